@@ -0,0 +1,46 @@
+// [[file:../optim.note::cfe19e45][cfe19e45]]
+//! Compare optimizer backends on structures in Lennard-Jones potential.
+//!
+//! Usage: cargo run --example benchmark -- [fmax] file1.xyz file2.xyz ...
+
+use gosh_core::*;
+use gut::prelude::*;
+
+use gchemol::prelude::*;
+use gchemol::Molecule;
+use gosh_model::LennardJones;
+use gosh_optim::{Backend, Benchmark};
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1).peekable();
+    let fmax = match args.peek().and_then(|s| s.parse::<f64>().ok()) {
+        Some(fmax) => {
+            args.next();
+            fmax
+        }
+        None => 0.01,
+    };
+
+    let mut bench = Benchmark::new(fmax, 1000);
+    for backend in Backend::all() {
+        bench = bench.backend(backend);
+    }
+    for path in args {
+        let mol = Molecule::from_file(&path)?;
+        bench = bench.case(&path, mol);
+    }
+
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let table = bench.run(&mut lj);
+    for r in table.records.iter() {
+        println!(
+            "{:<12} {:<30} converged = {:5} ncalls = {:5} energy = {:-12.6}",
+            r.backend, r.case, r.converged, r.ncalls, r.energy
+        );
+    }
+    println!("\n{table}");
+
+    Ok(())
+}
+// cfe19e45 ends here
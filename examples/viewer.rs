@@ -0,0 +1,35 @@
+// [[file:../optim.note::7a8949b7][7a8949b7]]
+//! A minimal reference consumer for structures streamed by `LiveViewer`.
+//!
+//! Run this example first, then connect from an optimization using
+//! `Optimizer::default().viewer(LiveViewer::connect("127.0.0.1:6666")?)`.
+
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::ViewerFrame;
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+
+fn main() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:6666")?;
+    println!("listening on {} ...", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        println!("connected from {}", stream.peer_addr()?);
+        for line in BufReader::new(stream).lines() {
+            let frame: ViewerFrame = serde_json::from_str(&line?)?;
+            // a real viewer would render the structure here
+            println!(
+                "step {:5}: {} atoms, energy = {:?}",
+                frame.step,
+                frame.positions.len(),
+                frame.energy
+            );
+        }
+        println!("disconnected.");
+    }
+
+    Ok(())
+}
+// 7a8949b7 ends here
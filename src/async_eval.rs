@@ -0,0 +1,88 @@
+// [[file:../optim.note::f6735b68][f6735b68]]
+use super::*;
+use crate::sd::GradientDescent;
+use crate::vars::Vars;
+
+use std::future::Future;
+// f6735b68 ends here
+
+// [[file:../optim.note::b6787d72][b6787d72]]
+/// Trait for potential evaluation that could be awaited, such as potentials
+/// backed by remote services, without blocking threads.
+pub trait AsyncEvaluatePotential<U> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> impl Future<Output = Result<U>>;
+}
+
+impl<T, F> AsyncEvaluatePotential<()> for T
+where
+    T: FnMut(Vec<f64>) -> F, // position => (energy, force)
+    F: Future<Output = Result<(f64, Vec<f64>)>>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> impl Future<Output = Result<()>> {
+        let computed = self(position.to_vec());
+        async move {
+            let (energy, force) = computed.await?;
+            ensure!(
+                force.len() == output.force.len(),
+                "invalid size of forces: {}",
+                force.len()
+            );
+            output.energy = energy;
+            output.force = force;
+            Ok(())
+        }
+    }
+}
+
+/// Optimize `position` in `potential` evaluated asynchronously, until max
+/// force below `fmax` or `nmax` evaluations. `on_step` will be called with
+/// progress of each evaluation. Return progress of the final evaluation.
+///
+/// Steepest descent with Barzilai–Borwein step sizes is used, as line search
+/// based optimizers require blocking evaluations.
+///
+/// # Examples
+///
+/// ```ignore
+/// let remote = |x: Vec<f64>| async move { client.compute(&x).await };
+/// let last = optimize_async(&mut remote, &mut x, 0.01, 500, |p| println!("{}", p.fmax)).await?;
+/// ```
+pub async fn optimize_async<U, P>(
+    potential: &mut P,
+    position: &mut [f64],
+    fmax: f64,
+    nmax: usize,
+    mut on_step: impl FnMut(&OptimProgress<U>),
+) -> Result<OptimProgress<U>>
+where
+    P: AsyncEvaluatePotential<U>,
+{
+    ensure!(nmax > 0, "invalid max number of evaluations: {nmax}");
+    let vars = Vars::from_env();
+    let mut sd = GradientDescent::new(vars.step_size_rule, vars.initial_step_size, vars.max_step_size);
+    let mut output = PotentialOutput {
+        energy: std::f64::NAN,
+        force: vec![0.0; position.len()],
+        stress: None,
+    };
+    let mut ncalls = 0;
+    loop {
+        output.stress = None;
+        let extra = potential.evaluate(position, &mut output).await?;
+        ncalls += 1;
+        let progress = OptimProgress {
+            ncalls,
+            fmax: fmax_(&output.force),
+            energy: output.energy,
+            extra,
+        };
+        on_step(&progress);
+        if progress.fmax < fmax || ncalls >= nmax {
+            return Ok(progress);
+        }
+        let gradient = output.force.iter().map(|f| -f).collect_vec();
+        let x = sd.step(position, &gradient);
+        position.copy_from_slice(&x);
+    }
+}
+// b6787d72 ends here
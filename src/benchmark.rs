@@ -0,0 +1,230 @@
+// [[file:../optim.note::1db9ae39][1db9ae39]]
+use super::*;
+
+use gchemol::Molecule;
+use std::time::{Duration, Instant};
+// 1db9ae39 ends here
+
+// [[file:../optim.note::fae15a03][fae15a03]]
+/// Optimization backends compared in `Benchmark`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// L-BFGS in Cartesian coordinates.
+    Lbfgs,
+    /// FIRE in Cartesian coordinates.
+    Fire,
+    /// Steepest descent in Cartesian coordinates.
+    SteepestDescent,
+    /// Quasi-Newton steps in redundant internal coordinates.
+    Redundant,
+    /// Quasi-Newton steps in delocalized internal coordinates.
+    Delocalized,
+}
+
+impl Backend {
+    /// All available backends.
+    pub fn all() -> [Backend; 5] {
+        [
+            Self::Lbfgs,
+            Self::Fire,
+            Self::SteepestDescent,
+            Self::Redundant,
+            Self::Delocalized,
+        ]
+    }
+
+    /// Return the optimizer using this backend.
+    pub fn optimizer(&self, fmax: f64, nmax: usize) -> Optimizer {
+        let opt = Optimizer::new(fmax, nmax);
+        match self {
+            Self::Lbfgs => opt.algorithm("LBFGS"),
+            Self::Fire => opt.algorithm("FIRE"),
+            Self::SteepestDescent => opt.algorithm("SD"),
+            Self::Redundant => opt.coordinate_system(CoordinateSystem::Redundant),
+            Self::Delocalized => opt.coordinate_system(CoordinateSystem::Delocalized),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Lbfgs => "L-BFGS",
+            Self::Fire => "FIRE",
+            Self::SteepestDescent => "SD",
+            Self::Redundant => "Redundant",
+            Self::Delocalized => "Delocalized",
+        };
+        f.pad(name)
+    }
+}
+
+/// The result of one structure optimized using one backend in `Benchmark`.
+#[derive(Debug, Clone)]
+pub struct BenchmarkRecord {
+    pub backend: Backend,
+    /// The name of structure.
+    pub case: String,
+    /// True if forces converged within max iterations.
+    pub converged: bool,
+    /// The number of iterations in optimization loop.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Final fmax criterion of forces.
+    pub fmax: f64,
+    /// Final energy.
+    pub energy: f64,
+    /// Wall time of the run.
+    pub wall_time: Duration,
+}
+
+/// Run a set of structures through multiple optimizer backends with identical
+/// convergence criteria, for choosing algorithms based on data.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    fmax: f64,
+    nmax: usize,
+    backends: Vec<Backend>,
+    cases: Vec<(String, Molecule)>,
+}
+
+impl Benchmark {
+    /// New benchmark with max force (fmax) and max step (nmax) in iterations
+    /// as convergence criteria for all backends.
+    pub fn new(fmax: f64, nmax: usize) -> Self {
+        assert!(fmax.is_sign_positive(), "invalid value of fmax: {:?}", fmax);
+        Self {
+            fmax,
+            nmax,
+            backends: vec![],
+            cases: vec![],
+        }
+    }
+
+    /// Add `backend` for comparison.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backends.push(backend);
+        self
+    }
+
+    /// Add structure `mol` named as `name` into benchmark set.
+    pub fn case(mut self, name: &str, mol: Molecule) -> Self {
+        self.cases.push((name.into(), mol));
+        self
+    }
+
+    /// Optimize all structures using all backends in potential provided by
+    /// `model`. A failed evaluation is recorded as a failed run.
+    pub fn run<M, U>(&self, model: &mut M) -> BenchmarkTable
+    where
+        M: OptimizeMolecule<U>,
+    {
+        let mut records = vec![];
+        for backend in self.backends.iter() {
+            let opt = backend.optimizer(self.fmax, self.nmax);
+            for (name, mol) in self.cases.iter() {
+                info!("benchmark {name} using {backend} ...");
+                let mut mol = mol.clone();
+                let timer = Instant::now();
+                let mut record = BenchmarkRecord {
+                    backend: *backend,
+                    case: name.to_owned(),
+                    converged: false,
+                    niter: 0,
+                    ncalls: 0,
+                    fmax: std::f64::NAN,
+                    energy: std::f64::NAN,
+                    wall_time: Duration::default(),
+                };
+                let steps = match opt.optimize_geometry_iter(&mut mol, model) {
+                    Ok(steps) => steps,
+                    Err(e) => {
+                        warn!("failed to optimize {name} using {backend}: {e:?}");
+                        Box::new(std::iter::empty())
+                    }
+                };
+                for (progress, i) in steps.take(self.nmax).zip(1..) {
+                    record.niter = i;
+                    record.ncalls = progress.ncalls;
+                    record.fmax = progress.fmax;
+                    record.energy = progress.energy;
+                    if progress.fmax < self.fmax {
+                        record.converged = true;
+                        break;
+                    }
+                }
+                record.wall_time = timer.elapsed();
+                records.push(record);
+            }
+        }
+        BenchmarkTable { records }
+    }
+}
+// fae15a03 ends here
+
+// [[file:../optim.note::64fffa59][64fffa59]]
+/// Summary of benchmark runs using one backend.
+#[derive(Debug, Clone)]
+pub struct BackendSummary {
+    pub backend: Backend,
+    /// Fraction of converged runs.
+    pub success_rate: f64,
+    /// Mean number of potential evaluations in converged runs.
+    pub mean_ncalls: f64,
+    /// Mean number of iterations in converged runs.
+    pub mean_niter: f64,
+    /// Total wall time of all runs.
+    pub wall_time: Duration,
+}
+
+/// Results of all runs in `Benchmark`.
+#[derive(Debug, Clone)]
+pub struct BenchmarkTable {
+    pub records: Vec<BenchmarkRecord>,
+}
+
+impl BenchmarkTable {
+    /// Summarize runs for each backend in the order of first appearance.
+    pub fn summary(&self) -> Vec<BackendSummary> {
+        let backends = self.records.iter().map(|r| r.backend).unique().collect_vec();
+        backends
+            .into_iter()
+            .map(|backend| {
+                let runs = self.records.iter().filter(|r| r.backend == backend).collect_vec();
+                let converged = runs.iter().filter(|r| r.converged).collect_vec();
+                let n = converged.len().max(1) as f64;
+                BackendSummary {
+                    backend,
+                    success_rate: converged.len() as f64 / runs.len() as f64,
+                    mean_ncalls: converged.iter().map(|r| r.ncalls as f64).sum::<f64>() / n,
+                    mean_niter: converged.iter().map(|r| r.niter as f64).sum::<f64>() / n,
+                    wall_time: runs.iter().map(|r| r.wall_time).sum(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for BenchmarkTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>10} {:>10} {:>12}",
+            "backend", "success", "ncalls", "niter", "wall time/s"
+        )?;
+        for s in self.summary() {
+            writeln!(
+                f,
+                "{:<12} {:>7.0}% {:>10.1} {:>10.1} {:>12.3}",
+                s.backend,
+                s.success_rate * 100.0,
+                s.mean_ncalls,
+                s.mean_niter,
+                s.wall_time.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+// 64fffa59 ends here
@@ -0,0 +1,77 @@
+// [[file:../optim.note::643a2723][643a2723]]
+use super::*;
+// 643a2723 ends here
+
+// [[file:../optim.note::56066410][56066410]]
+/// Output of potential with a bias.
+#[derive(Debug, Clone)]
+pub struct Biased<U> {
+    /// The bias energy included in the total energy.
+    pub bias_energy: f64,
+    /// The energy of the wrapped physical potential, without bias.
+    pub physical_energy: f64,
+    /// Extra data from the wrapped potential.
+    pub extra: U,
+}
+
+/// A potential wrapper adding an arbitrary user defined bias energy and
+/// forces on top of any `EvaluatePotential`.
+///
+/// # Examples
+///
+/// ```ignore
+/// // a harmonic wall pushing x0 below 2.0
+/// let wall = |x: &[f64], f: &mut [f64]| {
+///     let d = (x[0] - 2.0).max(0.0);
+///     f[0] = -2.0 * d;
+///     Ok(d * d)
+/// };
+/// let mut dynamics = Dynamics::new(&x, BiasedPotential::new(potential, wall));
+/// for p in optimize(&mut dynamics).take(100) {
+///     println!("{} {}", p.energy, p.extra.bias_energy);
+/// }
+/// ```
+pub struct BiasedPotential<P, B> {
+    potential: P,
+    bias: B,
+    force: Vec<f64>,
+}
+
+impl<P, B> BiasedPotential<P, B>
+where
+    B: FnMut(&[f64], &mut [f64]) -> Result<f64>, // position, bias force => bias energy
+{
+    /// Add `bias` to `potential`. In closure `bias`, the first parameter is
+    /// the position, the second is the bias force to be updated, and the
+    /// return value is the bias energy.
+    pub fn new(potential: P, bias: B) -> Self {
+        Self {
+            potential,
+            bias,
+            force: vec![],
+        }
+    }
+}
+
+impl<U, P, B> EvaluatePotential<Biased<U>> for BiasedPotential<P, B>
+where
+    P: EvaluatePotential<U>,
+    B: FnMut(&[f64], &mut [f64]) -> Result<f64>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Biased<U>> {
+        let extra = self.potential.evaluate(position, output)?;
+        let physical_energy = output.energy;
+        self.force.clear();
+        self.force.resize(position.len(), 0.0);
+        let bias_energy = (self.bias)(position, &mut self.force)?;
+        output.energy += bias_energy;
+        output.force.vecadd(&self.force, 1.0);
+
+        Ok(Biased {
+            bias_energy,
+            physical_energy,
+            extra,
+        })
+    }
+}
+// 56066410 ends here
@@ -0,0 +1,261 @@
+// [[file:../optim.note::592bb330][592bb330]]
+use super::*;
+
+use crate::dynamics::KB;
+use crate::internals::{distance, stretch_b};
+// 592bb330 ends here
+
+// [[file:../optim.note::7382098b][7382098b]]
+/// Output of potential boosted by a hyperdynamics bias.
+#[derive(Debug, Clone)]
+pub struct Boosted<U> {
+    /// The boost energy added to the physical potential.
+    pub boost_energy: f64,
+    /// The boost factor exp(ΔV/kT) for rescaling the simulation time.
+    pub boost_factor: f64,
+    /// Extra data from the wrapped potential.
+    pub extra: U,
+}
+
+/// The bond-boost bias potential for hyperdynamics, which is applied only
+/// when all monitored bonds are near their equilibrium lengths.
+///
+/// # Reference
+///
+/// Miron, R. A.; Fichthorn, K. A. J. Chem. Phys. 2003, 119, 6210.
+pub struct BondBoost<P> {
+    potential: P,
+    // monitored bonds with equilibrium bond lengths
+    bonds: Vec<(usize, usize, f64)>,
+    // the max boost energy
+    dv_max: f64,
+    // the threshold of bond strain for boosting
+    q: f64,
+    // the curvature parameter of envelope function, in [0, 1)
+    p1: f64,
+    // the temperature for boost factor
+    temperature: f64,
+}
+
+impl<P> BondBoost<P> {
+    /// Boost `potential` on bonds between atom pairs in `bonds` (indexed
+    /// from 0), with equilibrium bond lengths taken from `reference` positions
+    /// (usually a minimum).
+    pub fn new(potential: P, bonds: &[(usize, usize)], reference: &[f64]) -> Self {
+        let positions = reference.as_3d();
+        let bonds = bonds
+            .iter()
+            .map(|&(i, j)| {
+                assert!(i != j, "invalid bond: {i}-{j}");
+                let r_eq = distance(positions[i], positions[j]);
+                (i, j, r_eq)
+            })
+            .collect();
+
+        Self {
+            potential,
+            bonds,
+            dv_max: 0.5,
+            q: 0.2,
+            p1: 0.9,
+            temperature: 300.0,
+        }
+    }
+
+    /// Set the max boost energy (ΔV_max).
+    pub fn with_max_boost(mut self, dv_max: f64) -> Self {
+        assert!(dv_max.is_sign_positive(), "invalid max boost: {dv_max}");
+        self.dv_max = dv_max;
+        self
+    }
+
+    /// Set the threshold of relative bond strain (q) beyond which boost is
+    /// turned off.
+    pub fn with_strain_threshold(mut self, q: f64) -> Self {
+        assert!(q > 0.0, "invalid strain threshold: {q}");
+        self.q = q;
+        self
+    }
+
+    /// Set the curvature parameter (P1) of envelope function, in [0, 1).
+    pub fn with_curvature(mut self, p1: f64) -> Self {
+        assert!((0.0..1.0).contains(&p1), "invalid curvature parameter: {p1}");
+        self.p1 = p1;
+        self
+    }
+
+    /// Set the temperature (in K) for computing boost factors.
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        assert!(temperature > 0.0, "invalid temperature: {temperature}");
+        self.temperature = temperature;
+        self
+    }
+
+    /// Compute boost energy at `position`, and add the bias forces into
+    /// `force`.
+    fn boost(&self, position: &[f64], force: &mut [f64]) -> f64 {
+        let positions = position.as_3d();
+        let nb = self.bonds.len();
+        if nb == 0 {
+            return 0.0;
+        }
+        let strains = self
+            .bonds
+            .iter()
+            .map(|&(i, j, r_eq)| (distance(positions[i], positions[j]) - r_eq) / r_eq)
+            .collect_vec();
+        let (m, eps_max) = strains
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().partial_cmp(&b.1.abs()).expect("found invalid float numbers"))
+            .unwrap();
+        let e = eps_max.abs() / self.q;
+        if e >= 1.0 {
+            return 0.0;
+        }
+
+        // sum of bond boosts
+        let dv_bond = self.dv_max / nb as f64;
+        let sum: f64 = strains.iter().map(|eps| dv_bond * (1.0 - (eps / self.q).powi(2))).sum();
+        // envelope function and its derivative with respect to `e`
+        let p2 = self.p1 * self.p1;
+        let (u, w) = (1.0 - e * e, 1.0 - p2 * e * e);
+        let a = u * u / w;
+        let da = (-4.0 * e * u * w + 2.0 * p2 * e * u * u) / (w * w);
+
+        let mut add_bond_gradient = |k: usize, scale: f64| {
+            let (i, j, r_eq) = self.bonds[k];
+            let b = stretch_b(&positions[i].into(), &positions[j].into());
+            // force is the negative gradient
+            force[3 * i..3 * i + 3].vecadd(b[0].as_slice(), -scale / r_eq);
+            force[3 * j..3 * j + 3].vecadd(b[1].as_slice(), -scale / r_eq);
+        };
+        add_bond_gradient(m, da * sum * eps_max.signum() / self.q);
+        for (k, eps) in strains.iter().enumerate() {
+            add_bond_gradient(k, -a * 2.0 * dv_bond * eps / (self.q * self.q));
+        }
+
+        a * sum
+    }
+}
+
+impl<U, P> EvaluatePotential<Boosted<U>> for BondBoost<P>
+where
+    P: EvaluatePotential<U>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Boosted<U>> {
+        let extra = self.potential.evaluate(position, output)?;
+        let boost_energy = self.boost(position, &mut output.force);
+        output.energy += boost_energy;
+        let boost_factor = (boost_energy / (KB * self.temperature)).exp();
+
+        Ok(Boosted {
+            boost_energy,
+            boost_factor,
+            extra,
+        })
+    }
+}
+// 7382098b ends here
+
+// [[file:../optim.note::5c3aafe4][5c3aafe4]]
+/// Bookkeeping of the hyperdynamics time, accumulated from boost factors of
+/// each MD step.
+#[derive(Debug, Clone, Default)]
+pub struct HyperClock {
+    // elapsed MD time
+    md_time: f64,
+    // elapsed boosted time
+    hyper_time: f64,
+}
+
+impl HyperClock {
+    /// Advance the clock by an MD step of `dt` with `boost_factor`.
+    pub fn advance(&mut self, dt: f64, boost_factor: f64) {
+        assert!(boost_factor >= 1.0, "invalid boost factor: {boost_factor}");
+        self.md_time += dt;
+        self.hyper_time += dt * boost_factor;
+    }
+
+    /// The accumulated simulation time in MD.
+    pub fn md_time(&self) -> f64 {
+        self.md_time
+    }
+
+    /// The accumulated hyper time with boost factors.
+    pub fn hyper_time(&self) -> f64 {
+        self.hyper_time
+    }
+
+    /// The averaged boost factor over the MD time elapsed.
+    pub fn average_boost(&self) -> f64 {
+        if self.md_time > 0.0 {
+            self.hyper_time / self.md_time
+        } else {
+            1.0
+        }
+    }
+}
+// 5c3aafe4 ends here
+
+// [[file:../optim.note::f44d8a37][f44d8a37]]
+/// Hyperdynamics: molecular dynamics on a boosted potential such as
+/// `BondBoost`, with the hyper time accumulated on the fly from boost factors
+/// in each step. Escape from deep minima is accelerated, while the hyper time
+/// estimates the physical timescale of escape.
+///
+/// The temperature of thermostat should be the same as the one for boost
+/// factors set in the bias potential.
+///
+/// # Examples
+///
+/// ```ignore
+/// let boost = BondBoost::new(potential, &bonds, &minimum).with_temperature(300.0);
+/// let md = MoleculeDynamics::new(Dynamics::new(&minimum, boost), &masses).nvt(300.0);
+/// let mut hmd = HyperDynamics::new(md);
+/// hmd.propagate(10000)?;
+/// println!("hyper time: {} fs", hmd.clock().hyper_time());
+/// ```
+pub struct HyperDynamics<'a, U> {
+    md: MoleculeDynamics<'a, Boosted<U>>,
+    clock: HyperClock,
+}
+
+impl<'a, U> HyperDynamics<'a, U> {
+    /// Run hyperdynamics using `md` on a boosted potential.
+    pub fn new(md: MoleculeDynamics<'a, Boosted<U>>) -> Self {
+        Self {
+            md,
+            clock: HyperClock::default(),
+        }
+    }
+
+    /// Propagate `nsteps` steps, advancing the hyper clock by boost factor
+    /// at the end of each step.
+    pub fn propagate(&mut self, nsteps: usize) -> Result<()> {
+        for _ in 0..nsteps {
+            let t0 = self.md.time();
+            self.md.propagate(1)?;
+            let boost_factor = self.md.dynamics().get_extra()?.boost_factor;
+            self.clock.advance(self.md.time() - t0, boost_factor);
+        }
+        Ok(())
+    }
+
+    /// Return current boost energy.
+    pub fn boost_energy(&mut self) -> Result<f64> {
+        Ok(self.md.dynamics().get_extra()?.boost_energy)
+    }
+
+    /// Return the hyper clock.
+    pub fn clock(&self) -> &HyperClock {
+        &self.clock
+    }
+
+    /// Return the underlying molecular dynamics.
+    pub fn md(&mut self) -> &mut MoleculeDynamics<'a, Boosted<U>> {
+        &mut self.md
+    }
+}
+// f44d8a37 ends here
@@ -0,0 +1,402 @@
+// [[file:../optim.note::b13dd1f6][b13dd1f6]]
+use super::*;
+
+use gchemol::{Lattice, Molecule};
+use vecfx::nalgebra as na;
+// b13dd1f6 ends here
+
+// [[file:../optim.note::b9034c15][b9034c15]]
+/// 1 eV/Å^3 in GPa
+pub(crate) const EV_PER_A3_TO_GPA: f64 = 160.21766208;
+
+/// Convert stress in Voigt order (xx, yy, zz, yz, xz, xy) into a symmetric
+/// matrix.
+pub(crate) fn voigt_to_matrix(s: [f64; 6]) -> na::Matrix3<f64> {
+    na::Matrix3::new(s[0], s[5], s[4], s[5], s[1], s[3], s[4], s[3], s[2])
+}
+
+/// Return lattice vectors of `mol` as columns of a matrix.
+pub(crate) fn cell_matrix(mol: &Molecule) -> Option<na::Matrix3<f64>> {
+    mol.lattice.as_ref().map(|lat| {
+        let mat = lat.matrix();
+        na::Matrix3::from_fn(|i, j| mat[(i, j)])
+    })
+}
+
+/// Degrees of freedom of unit cell for relaxing lattice vectors together with
+/// atoms, minimizing the enthalpy H = E + pV at a target pressure.
+///
+/// The cell is deformed from its reference by the deformation gradient F, and
+/// atoms are moved along with the cell: x = F r. The 9 components of F are
+/// optimized together with atoms in scaled variables F * `cell_factor` to
+/// balance with atomic coordinates.
+///
+/// # Reference
+///
+/// Tadmor, E. B.; Smith, G. S.; Bernstein, N.; Kaxiras, E. Phys. Rev. B 1999,
+/// 59, 235.
+#[derive(Debug, Clone)]
+pub(crate) struct CellFilter {
+    // lattice vectors as columns in reference
+    reference: na::Matrix3<f64>,
+    // target pressure in eV/Å^3
+    pressure: f64,
+    cell_factor: f64,
+    constraints: Vec<CellConstraint>,
+}
+
+impl CellFilter {
+    /// Construct the filter for periodic `mol` at `pressure` in eV/Å^3.
+    pub fn new(mol: &Molecule, pressure: f64) -> Result<Self> {
+        let reference = cell_matrix(mol).ok_or(format_err!("variable-cell optimization requires a lattice"))?;
+        Ok(Self {
+            reference,
+            pressure,
+            cell_factor: mol.natoms() as f64,
+            constraints: vec![],
+        })
+    }
+
+    /// Restrict cell degrees of freedom using `constraints`.
+    pub fn with_constraints(mut self, constraints: &[CellConstraint]) -> Self {
+        self.constraints = constraints.to_vec();
+        self
+    }
+
+    /// Return the number of cell variables.
+    pub fn nparams(&self) -> usize {
+        9
+    }
+
+    /// Return cell variables in reference.
+    pub fn initial_params(&self) -> Vec<f64> {
+        (na::Matrix3::identity() * self.cell_factor)
+            .transpose()
+            .as_slice()
+            .to_vec()
+    }
+
+    /// Return the deformation gradient from cell variables `params` in row
+    /// major.
+    pub fn deformation(&self, params: &[f64]) -> na::Matrix3<f64> {
+        let identity = na::Matrix3::identity();
+        let mut f = na::Matrix3::from_row_slice(params) / self.cell_factor;
+        for c in self.constraints.iter() {
+            match c.subspace(&self.reference) {
+                Some(basis) => f = identity + project(f - identity, &basis),
+                None => f /= f.determinant().cbrt(),
+            }
+        }
+        f
+    }
+
+    /// Deform flattened `positions` in place by cell variables `params`, and
+    /// update lattice of `mol` accordingly.
+    pub fn apply(&self, params: &[f64], positions: &mut [f64], mol: &mut Molecule) {
+        let f = self.deformation(params);
+        for p in positions.as_mut_3d() {
+            *p = (f * Vector3f::from(*p)).into();
+        }
+        set_cell(mol, &(f * self.reference));
+    }
+
+    /// Return the volume of deformed cell.
+    pub fn volume(&self, params: &[f64]) -> f64 {
+        (self.deformation(params) * self.reference).determinant().abs()
+    }
+
+    /// Return the pV term of enthalpy.
+    pub fn pv(&self, params: &[f64]) -> f64 {
+        self.pressure * self.volume(params)
+    }
+
+    /// Transform Cartesian gradient `gx` of deformed positions in place into
+    /// gradient of positions in reference frame.
+    pub fn transform_gradient(&self, params: &[f64], gx: &mut [f64]) {
+        let ft = self.deformation(params).transpose();
+        for g in gx.as_mut_3d() {
+            *g = (ft * Vector3f::from(*g)).into();
+        }
+    }
+
+    /// Return the gradient of enthalpy with respect to cell variables, given
+    /// `stress` in Voigt order.
+    pub fn gradient(&self, params: &[f64], stress: [f64; 6]) -> Vec<f64> {
+        let f = self.deformation(params);
+        let f_inv_t = f.try_inverse().expect("invalid deformation").transpose();
+        let sigma = voigt_to_matrix(stress) + na::Matrix3::identity() * self.pressure;
+        let mut g = sigma * f_inv_t * (self.volume(params) / self.cell_factor);
+        for c in self.constraints.iter() {
+            match c.subspace(&self.reference) {
+                Some(basis) => g = project(g, &basis),
+                // remove the component changing volume: dV/dF = V F^-T
+                None => g -= f_inv_t * (g.dot(&f_inv_t) / f_inv_t.norm_squared()),
+            }
+        }
+        g.transpose().as_slice().to_vec()
+    }
+}
+// b9034c15 ends here
+
+// [[file:../optim.note::ba55264c][ba55264c]]
+/// Constraints on cell degrees of freedom in variable-cell optimization.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellConstraint {
+    /// Scale the cell isotropically only.
+    Isotropic,
+    /// Keep angles between lattice vectors fixed, allowing their lengths to
+    /// change.
+    FixedAngles,
+    /// Keep the cell volume fixed.
+    FixedVolume,
+    /// Allow only lengths of selected lattice vectors (a, b, c) to change,
+    /// such as `[false, false, true]` for relaxing only c-axis of a slab.
+    Axes([bool; 3]),
+}
+
+impl CellConstraint {
+    // Return the basis of allowed changes in deformation gradient as a linear
+    // subspace, for cell with lattice vectors in columns of `reference`. None
+    // for nonlinear constraint of fixed volume.
+    fn subspace(&self, reference: &na::Matrix3<f64>) -> Option<Vec<na::Matrix3<f64>>> {
+        let inv = reference.try_inverse().expect("invalid cell");
+        // scaling lattice vector k only
+        let scale = |k: usize| {
+            let mut e = na::Matrix3::zeros();
+            e[(k, k)] = 1.0;
+            reference * e * inv
+        };
+        match *self {
+            Self::Isotropic => Some(vec![na::Matrix3::identity()]),
+            Self::FixedAngles => Some((0..3).map(scale).collect()),
+            Self::Axes(mask) => Some((0..3).filter(|&k| mask[k]).map(scale).collect()),
+            Self::FixedVolume => None,
+        }
+    }
+}
+
+// Orthogonal projection of `m` onto span of `basis` in Frobenius inner product
+fn project(m: na::Matrix3<f64>, basis: &[na::Matrix3<f64>]) -> na::Matrix3<f64> {
+    let n = basis.len();
+    if n == 0 {
+        return na::Matrix3::zeros();
+    }
+    let gram = na::DMatrix::from_fn(n, n, |i, j| basis[i].dot(&basis[j]));
+    let rhs = na::DVector::from_fn(n, |i, _| basis[i].dot(&m));
+    let coeffs = gram.lu().solve(&rhs).expect("invalid basis of cell constraint");
+    basis.iter().zip(coeffs.iter()).map(|(b, c)| b * *c).sum()
+}
+// ba55264c ends here
+
+// [[file:../optim.note::69e67062][69e67062]]
+/// Scaled fractional coordinates of periodic molecule as optimization
+/// variables.
+///
+/// Fractional coordinates are multiplied by the cubic root of cell volume to
+/// have similar magnitude with Cartesian coordinates, so that step sizes in
+/// Å remain meaningful.
+#[derive(Debug, Clone)]
+pub(crate) struct ScaledCoords {
+    // lattice vectors as columns
+    cell: na::Matrix3<f64>,
+    inv: na::Matrix3<f64>,
+    scale: f64,
+}
+
+impl ScaledCoords {
+    /// Construct from the cell of periodic `mol`.
+    pub fn new(mol: &Molecule) -> Result<Self> {
+        let cell = cell_matrix(mol).ok_or(format_err!("fractional coordinates require a lattice"))?;
+        let inv = cell.try_inverse().ok_or(format_err!("invalid cell"))?;
+        Ok(Self {
+            cell,
+            inv,
+            scale: cell.determinant().abs().cbrt(),
+        })
+    }
+
+    /// Convert Cartesian `positions` into scaled coordinates.
+    pub fn to_scaled(&self, positions: &[f64]) -> Vec<f64> {
+        let m = self.inv * self.scale;
+        positions
+            .as_3d()
+            .iter()
+            .flat_map(|p| <[f64; 3]>::from(m * Vector3f::from(*p)))
+            .collect()
+    }
+
+    /// Convert scaled coordinates `scaled` into Cartesian positions.
+    pub fn to_cartesian(&self, scaled: &[f64]) -> Vec<f64> {
+        let m = self.cell / self.scale;
+        scaled
+            .as_3d()
+            .iter()
+            .flat_map(|s| <[f64; 3]>::from(m * Vector3f::from(*s)))
+            .collect()
+    }
+
+    /// Transform Cartesian gradient `gx` in place into gradient of scaled
+    /// coordinates.
+    pub fn transform_gradient(&self, gx: &mut [f64]) {
+        let mt = self.cell.transpose() / self.scale;
+        for g in gx.as_mut_3d() {
+            *g = (mt * Vector3f::from(*g)).into();
+        }
+    }
+}
+
+/// Wrap Cartesian `positions` in place into the unit cell with lattice vectors
+/// in columns of `cell`.
+pub(crate) fn wrap_positions(cell: &na::Matrix3<f64>, positions: &mut [f64]) {
+    let inv = cell.try_inverse().expect("invalid cell");
+    for p in positions.as_mut_3d() {
+        let x = Vector3f::from(*p);
+        let frac = (inv * x).map(|f| f - f.floor());
+        *p = (cell * frac).into();
+    }
+}
+// 69e67062 ends here
+
+// [[file:../optim.note::895f59fa][895f59fa]]
+/// Return the Niggli reduced cell of lattice vectors in columns of `cell`,
+/// and the integer transformation matrix M with reduced = cell * M, using the
+/// algorithm of Křivý and Gruber.
+///
+/// # Reference
+///
+/// Grosse-Kunstleve, R. W.; Sauter, N. K.; Adams, P. D. Acta Cryst. 2004, A60, 1.
+pub fn niggli_reduce(cell: na::Matrix3<f64>) -> Result<(na::Matrix3<f64>, na::Matrix3<f64>)> {
+    let eps = 1e-5 * cell.determinant().abs().cbrt();
+    let lt = |x: f64, y: f64| x < y - eps;
+    let gt = |x: f64, y: f64| lt(y, x);
+    let eq = |x: f64, y: f64| !lt(x, y) && !gt(x, y);
+    let sign = |x: f64| {
+        if gt(x, 0.0) {
+            1.0
+        } else if lt(x, 0.0) {
+            -1.0
+        } else {
+            0.0
+        }
+    };
+
+    let mut m = na::Matrix3::identity();
+    for _ in 0..10000 {
+        let l = cell * m;
+        let g = l.transpose() * l;
+        let (a, b, c) = (g[(0, 0)], g[(1, 1)], g[(2, 2)]);
+        let (xi, eta, zeta) = (2.0 * g[(1, 2)], 2.0 * g[(0, 2)], 2.0 * g[(0, 1)]);
+
+        // A1
+        if gt(a, b) || (eq(a, b) && gt(xi.abs(), eta.abs())) {
+            m *= na::Matrix3::new(0.0, -1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, -1.0);
+            continue;
+        }
+        // A2
+        if gt(b, c) || (eq(b, c) && gt(eta.abs(), zeta.abs())) {
+            m *= na::Matrix3::new(-1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, -1.0, 0.0);
+            continue;
+        }
+        // A3 and A4: make signs of xi, eta, zeta all positive, or all non-positive
+        let lmn = [sign(xi), sign(eta), sign(zeta)];
+        if lmn.iter().product::<f64>() == 1.0 {
+            let ijk = lmn.map(|x| if x < 0.0 { -1.0 } else { 1.0 });
+            m *= na::Matrix3::from_diagonal(&Vector3f::from(ijk));
+        } else {
+            let mut ijk = lmn.map(|x| if x > 0.0 { -1.0 } else { 1.0 });
+            if ijk.iter().product::<f64>() < 0.0 {
+                let p = lmn.iter().position(|&x| x == 0.0).ok_or(format_err!("invalid cell"))?;
+                ijk[p] = -1.0;
+            }
+            m *= na::Matrix3::from_diagonal(&Vector3f::from(ijk));
+        }
+        let l = cell * m;
+        let g = l.transpose() * l;
+        let (a, b) = (g[(0, 0)], g[(1, 1)]);
+        let (xi, eta, zeta) = (2.0 * g[(1, 2)], 2.0 * g[(0, 2)], 2.0 * g[(0, 1)]);
+
+        // A5
+        if gt(xi.abs(), b) || (eq(xi, b) && lt(2.0 * eta, zeta)) || (eq(xi, -b) && lt(zeta, 0.0)) {
+            let s = xi.signum();
+            m *= na::Matrix3::new(1.0, 0.0, 0.0, 0.0, 1.0, -s, 0.0, 0.0, 1.0);
+            continue;
+        }
+        // A6
+        if gt(eta.abs(), a) || (eq(eta, a) && lt(2.0 * xi, zeta)) || (eq(eta, -a) && lt(zeta, 0.0)) {
+            let s = eta.signum();
+            m *= na::Matrix3::new(1.0, 0.0, -s, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+            continue;
+        }
+        // A7
+        if gt(zeta.abs(), a) || (eq(zeta, a) && lt(2.0 * xi, eta)) || (eq(zeta, -a) && lt(eta, 0.0)) {
+            let s = zeta.signum();
+            m *= na::Matrix3::new(1.0, -s, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+            continue;
+        }
+        // A8
+        let sum = xi + eta + zeta + a + b;
+        if lt(sum, 0.0) || (eq(sum, 0.0) && gt(2.0 * (a + eta) + zeta, 0.0)) {
+            m *= na::Matrix3::new(1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0);
+            continue;
+        }
+        return Ok((cell * m, m));
+    }
+    bail!("Niggli reduction not converged");
+}
+
+/// Mapping of a periodic molecule into its Niggli reduced cell, with atoms
+/// wrapped into the reduced cell, which can be un-mapped back to the original
+/// setting after the cell is deformed.
+#[derive(Debug, Clone)]
+pub(crate) struct NiggliMapping {
+    original: na::Matrix3<f64>,
+    reduced: na::Matrix3<f64>,
+    // lattice translations of atoms for wrapping into reduced cell
+    shifts: Vec<Vector3f>,
+}
+
+impl NiggliMapping {
+    /// Map `mol` into its Niggli reduced cell in place.
+    pub fn apply(mol: &mut Molecule) -> Result<Self> {
+        let original = cell_matrix(mol).ok_or(format_err!("Niggli reduction requires a lattice"))?;
+        let (reduced, _) = niggli_reduce(original)?;
+        let inv = reduced.try_inverse().ok_or(format_err!("invalid cell"))?;
+        let mut shifts = vec![];
+        let positions = mol
+            .positions()
+            .map(|p| {
+                let x = Vector3f::from(p);
+                let shift = reduced * (-(inv * x).map(|f| f.floor()));
+                shifts.push(shift);
+                (x + shift).into()
+            })
+            .collect_vec();
+        set_cell(mol, &reduced);
+        mol.update_positions(positions);
+        Ok(Self {
+            original,
+            reduced,
+            shifts,
+        })
+    }
+
+    /// Map `mol` with deformed reduced cell back to the original setting.
+    pub fn restore(&self, mol: &mut Molecule) -> Result<()> {
+        let current = cell_matrix(mol).ok_or(format_err!("no lattice"))?;
+        let f = current * self.reduced.try_inverse().ok_or(format_err!("invalid cell"))?;
+        let positions = mol
+            .positions()
+            .zip(&self.shifts)
+            .map(|(p, s)| (Vector3f::from(p) - f * s).into())
+            .collect_vec();
+        set_cell(mol, &(f * self.original));
+        mol.update_positions(positions);
+        Ok(())
+    }
+}
+
+pub(crate) fn set_cell(mol: &mut Molecule, cell: &na::Matrix3<f64>) {
+    let vectors = [0, 1, 2].map(|i| [cell[(0, i)], cell[(1, i)], cell[(2, i)]]);
+    mol.set_lattice(Lattice::new(vectors));
+}
+// 895f59fa ends here
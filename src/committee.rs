@@ -0,0 +1,245 @@
+// [[file:../optim.note::6f8d4d06][6f8d4d06]]
+use super::*;
+// 6f8d4d06 ends here
+
+// [[file:../optim.note::b3ca0bbe][b3ca0bbe]]
+/// Error for predictions of committee members deviating more than the
+/// threshold set in `CommitteePotential::max_uncertainty`.
+#[derive(Debug, Clone)]
+pub struct Uncertain {
+    /// The uncertainty of predicted forces.
+    pub uncertainty: f64,
+    /// The threshold of uncertainty.
+    pub threshold: f64,
+}
+
+impl std::fmt::Display for Uncertain {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "uncertainty of committee {} exceeds threshold {}",
+            self.uncertainty, self.threshold
+        )
+    }
+}
+
+impl std::error::Error for Uncertain {}
+
+/// Output of committee potential.
+#[derive(Debug, Clone)]
+pub struct Committee<U> {
+    /// The energy predicted by each member.
+    pub energies: Vec<f64>,
+    /// The standard deviation of predicted energies.
+    pub energy_std: f64,
+    /// The max deviation of predicted forces over atoms, as the uncertainty
+    /// metric of committee.
+    pub force_std: f64,
+    /// Extra data from the fallback model, if evaluated because of high
+    /// uncertainty.
+    pub fallback: Option<U>,
+    /// Extra data from each member.
+    pub extra: Vec<U>,
+}
+
+impl<U> Committee<U> {
+    /// The uncertainty metric of committee predictions.
+    pub fn uncertainty(&self) -> f64 {
+        self.force_std
+    }
+
+    /// Return true if results are taken from the fallback model.
+    pub fn switched(&self) -> bool {
+        self.fallback.is_some()
+    }
+}
+
+type Member<'a, U> = Box<dyn EvaluatePotentialBatch<U> + 'a>;
+
+/// A committee (ensemble) of potentials, evaluating all members and averaging
+/// their energies and forces. The deviation of member predictions is returned
+/// in `Committee` as uncertainty, for active learning workflows.
+///
+/// The uncertainty is defined as the max over atoms of the root mean square
+/// deviation of predicted atomic forces from their mean.
+///
+/// # Examples
+///
+/// ```ignore
+/// let potential = CommitteePotential::new()
+///     .member(model1)
+///     .member(model2)
+///     .member(model3)
+///     .max_uncertainty(0.2)
+///     .fallback(dft);
+/// let mut dynamics = Dynamics::new(&position, potential);
+/// for progress in gosh_optim::optimize(&mut dynamics) {
+///     println!("uncertainty = {}", progress.extra.uncertainty());
+/// }
+/// ```
+pub struct CommitteePotential<'a, U> {
+    members: Vec<Member<'a, U>>,
+    threshold: Option<f64>,
+    fallback: Option<Member<'a, U>>,
+}
+
+impl<'a, U: 'a> CommitteePotential<'a, U> {
+    /// New committee without members.
+    pub fn new() -> Self {
+        Self {
+            members: vec![],
+            threshold: None,
+            fallback: None,
+        }
+    }
+
+    /// Add `potential` as a member of committee.
+    pub fn member(self, potential: impl EvaluatePotential<U> + 'a) -> Self {
+        self.member_batch(SerialBatch(potential))
+    }
+
+    /// Add `potential` evaluating many geometries per call as a member of
+    /// committee.
+    pub fn member_batch(mut self, potential: impl EvaluatePotentialBatch<U> + 'a) -> Self {
+        self.members.push(Box::new(potential));
+        self
+    }
+
+    /// Abort evaluation with `Uncertain` error when uncertainty exceeds
+    /// `threshold`, unless a fallback model is set.
+    pub fn max_uncertainty(mut self, threshold: f64) -> Self {
+        assert!(threshold.is_sign_positive(), "invalid threshold: {threshold}");
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Switch to `potential`, such as a reference ab initio model, for
+    /// geometries with uncertainty over the threshold set in
+    /// `max_uncertainty`.
+    pub fn fallback(mut self, potential: impl EvaluatePotential<U> + 'a) -> Self {
+        self.fallback = Some(Box::new(SerialBatch(potential)));
+        self
+    }
+
+    /// The number of members.
+    pub fn nmembers(&self) -> usize {
+        self.members.len()
+    }
+}
+
+impl<'a, U: 'a> Default for CommitteePotential<'a, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Average `predictions` into `output`, returning member energies, deviation
+// of energies and deviation of forces.
+fn average(predictions: &[&PotentialOutput], output: &mut PotentialOutput) -> (Vec<f64>, f64, f64) {
+    let m = predictions.len() as f64;
+    let energies = predictions.iter().map(|p| p.energy).collect_vec();
+    output.energy = energies.iter().sum::<f64>() / m;
+    let energy_std = (energies.iter().map(|e| (e - output.energy).powi(2)).sum::<f64>() / m).sqrt();
+    output.force.fill(0.0);
+    for p in predictions {
+        output.force.vecadd(&p.force, 1.0 / m);
+    }
+    let mut deviations = vec![0.0; output.force.len()];
+    for p in predictions {
+        for (d, (f, f_mean)) in deviations.iter_mut().zip(p.force.iter().zip(&output.force)) {
+            *d += (f - f_mean).powi(2) / m;
+        }
+    }
+    let force_std = deviations
+        .chunks(3)
+        .map(|d| d.iter().sum::<f64>().sqrt())
+        .fold(0.0, f64::max);
+    output.stress = predictions
+        .iter()
+        .map(|p| p.stress)
+        .try_fold([0.0; 6], |s, p| p.map(|p| std::array::from_fn(|i| s[i] + p[i] / m)));
+    (energies, energy_std, force_std)
+}
+
+impl<'a, U> EvaluatePotentialBatch<Committee<U>> for CommitteePotential<'a, U> {
+    fn evaluate_batch(&mut self, positions: &[&[f64]], outputs: &mut [PotentialOutput]) -> Result<Vec<Committee<U>>> {
+        assert_eq!(positions.len(), outputs.len(), "invalid size of outputs");
+        ensure!(!self.members.is_empty(), "no member in committee");
+        let new_outputs = || {
+            positions
+                .iter()
+                .map(|x| PotentialOutput {
+                    energy: std::f64::NAN,
+                    force: vec![0.0; x.len()],
+                    stress: None,
+                })
+                .collect_vec()
+        };
+        // predictions of each member at all positions
+        let mut predictions = vec![];
+        let mut extras = positions.iter().map(|_| vec![]).collect_vec();
+        for (i, member) in self.members.iter_mut().enumerate() {
+            let mut outs = new_outputs();
+            let extra = member
+                .evaluate_batch(positions, &mut outs)
+                .with_context(|| format!("failed to evaluate committee member {i}"))?;
+            ensure!(
+                extra.len() == positions.len(),
+                "invalid size of extra data from member {i}"
+            );
+            for (e, x) in extras.iter_mut().zip(extra) {
+                e.push(x);
+            }
+            predictions.push(outs);
+        }
+
+        let mut committees = vec![];
+        for (j, (output, extra)) in outputs.iter_mut().zip(extras).enumerate() {
+            let members = predictions.iter().map(|outs| &outs[j]).collect_vec();
+            let (energies, energy_std, force_std) = average(&members, output);
+            committees.push(Committee {
+                energies,
+                energy_std,
+                force_std,
+                fallback: None,
+                extra,
+            });
+        }
+
+        let Some(threshold) = self.threshold else {
+            return Ok(committees);
+        };
+        let uncertain = (0..positions.len())
+            .filter(|&j| committees[j].force_std > threshold)
+            .collect_vec();
+        if uncertain.is_empty() {
+            return Ok(committees);
+        }
+        let Some(fallback) = self.fallback.as_mut() else {
+            let uncertainty = committees[uncertain[0]].force_std;
+            return Err(Uncertain { uncertainty, threshold }.into());
+        };
+        info!("switch to fallback model for {} uncertain geometries", uncertain.len());
+        let xs = uncertain.iter().map(|&j| positions[j]).collect_vec();
+        let mut outs = uncertain.iter().map(|&j| outputs[j].clone()).collect_vec();
+        let extra = fallback
+            .evaluate_batch(&xs, &mut outs)
+            .context("failed to evaluate fallback model")?;
+        for ((j, out), x) in uncertain.into_iter().zip(outs).zip(extra) {
+            outputs[j] = out;
+            committees[j].fallback = Some(x);
+        }
+        Ok(committees)
+    }
+}
+
+impl<'a, U> EvaluatePotential<Committee<U>> for CommitteePotential<'a, U> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Committee<U>> {
+        let mut outputs = [output.clone()];
+        let mut committees = self.evaluate_batch(&[position], &mut outputs)?;
+        let [out] = outputs;
+        *output = out;
+        Ok(committees.pop().expect("no committee output"))
+    }
+}
+// b3ca0bbe ends here
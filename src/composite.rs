@@ -0,0 +1,103 @@
+// [[file:../optim.note::bfba43e6][bfba43e6]]
+use super::*;
+
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+// bfba43e6 ends here
+
+// [[file:../optim.note::75be7135][75be7135]]
+/// Output of composite potential.
+#[derive(Debug, Clone)]
+pub struct Composite {
+    /// The energy contribution of each term, in the order of terms added.
+    pub energies: Vec<(String, f64)>,
+}
+
+impl Composite {
+    /// Return the energy contribution of term named as `name`.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.energies.iter().find(|(n, _)| n == name).map(|(_, e)| *e)
+    }
+}
+
+type Term<'a> = Box<dyn FnMut(&[f64], &mut PotentialOutput) -> Result<()> + 'a>;
+
+/// A potential summing energies and forces from several potentials or
+/// chemical models, e.g. DFT with dispersion correction and external
+/// restraints.
+///
+/// Stress is summed only if provided by all terms.
+///
+/// # Examples
+///
+/// ```ignore
+/// let potential = CompositePotential::default()
+///     .model("dft", &mut dft, mol.clone())
+///     .model("d3", &mut d3, mol.clone())
+///     .term("wall", wall);
+/// let mut dynamics = Dynamics::new(&position, potential);
+/// ```
+#[derive(Default)]
+pub struct CompositePotential<'a> {
+    terms: Vec<(String, Term<'a>)>,
+}
+
+impl<'a> CompositePotential<'a> {
+    /// Add `potential` as a term named as `name`.
+    pub fn term<U>(mut self, name: &str, mut potential: impl EvaluatePotential<U> + 'a) -> Self {
+        let term = move |position: &[f64], output: &mut PotentialOutput| {
+            potential.evaluate(position, output)?;
+            Ok(())
+        };
+        self.terms.push((name.into(), Box::new(term)));
+        self
+    }
+
+    /// Add chemical `model` as a term named as `name`, evaluated on `mol`
+    /// with positions of all atoms updated from flattened coordinates.
+    pub fn model(self, name: &str, model: &'a mut impl ChemicalModel, mut mol: Molecule) -> Self {
+        let potential = move |position: &[f64], force: &mut [f64]| {
+            mol.update_positions(position.as_3d().into_iter().copied());
+            let mp = model.compute(&mol)?;
+            let f = mp.get_forces().ok_or(format_err!("no forces"))?;
+            let e = mp.get_energy().ok_or(format_err!("no energy"))?;
+            force.copy_from_slice(f.as_flat());
+            Ok(e)
+        };
+        self.term(name, potential)
+    }
+
+    /// The number of terms.
+    pub fn nterms(&self) -> usize {
+        self.terms.len()
+    }
+}
+
+impl<'a> EvaluatePotential<Composite> for CompositePotential<'a> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Composite> {
+        ensure!(!self.terms.is_empty(), "no term in composite potential");
+        let n = position.len();
+        let mut part = PotentialOutput {
+            energy: std::f64::NAN,
+            force: vec![0.0; n],
+            stress: None,
+        };
+        output.energy = 0.0;
+        output.force.fill(0.0);
+        let mut stress = Some([0.0; 6]);
+        let mut energies = vec![];
+        for (name, term) in self.terms.iter_mut() {
+            part.stress = None;
+            term(position, &mut part).with_context(|| format!("failed to evaluate term {name}"))?;
+            output.energy += part.energy;
+            output.force.vecadd(&part.force, 1.0);
+            stress = stress
+                .zip(part.stress)
+                .map(|(s, p)| std::array::from_fn(|i| s[i] + p[i]));
+            energies.push((name.clone(), part.energy));
+        }
+        output.stress = stress;
+        Ok(Composite { energies })
+    }
+}
+// 75be7135 ends here
@@ -0,0 +1,585 @@
+// [[file:../optim.note::8e7a5b0e][8e7a5b0e]]
+use super::*;
+
+use crate::internals::Coordinate;
+use vecfx::nalgebra as na;
+// 8e7a5b0e ends here
+
+// [[file:../optim.note::08137053][08137053]]
+/// Geometric constraints held fixed during optimization. Atoms are indexed
+/// from 0 in the order of positions.
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// Fix the distance between atom `i` and atom `j` at `r0`.
+    Bond(usize, usize, f64),
+    /// Fix the angle i-j-k at `theta0` in degree, with atom `j` as the
+    /// vertex.
+    Angle(usize, usize, usize, f64),
+    /// Fix the dihedral angle i-j-k-l at `phi0` in degree.
+    Dihedral(usize, usize, usize, usize, f64),
+    /// Constrain atom `i` to move within the plane with `normal` vector.
+    FixedPlane(usize, [f64; 3]),
+    /// Constrain atom `i` to move along the line in `direction`.
+    FixedLine(usize, [f64; 3]),
+    /// Constrain atom `i` within `tolerance` of `position`.
+    FixedPosition(usize, [f64; 3], f64),
+}
+
+impl Constraint {
+    /// Return the constrained internal coordinate and its target value, or
+    /// None for Cartesian constraints.
+    fn coordinate(&self) -> Option<(Coordinate, f64)> {
+        let c = match *self {
+            Self::Bond(i, j, r0) => (Coordinate::Distance(i, j), r0),
+            Self::Angle(i, j, k, theta0) => (Coordinate::Angle(i, j, k), theta0.to_radians()),
+            Self::Dihedral(i, j, k, l, phi0) => (Coordinate::Dihedral(i, j, k, l), phi0.to_radians()),
+            _ => return None,
+        };
+        Some(c)
+    }
+
+    /// Return the deviation of current value from target value at `positions`.
+    fn deviation(&self, positions: &[[f64; 3]]) -> f64 {
+        let (coord, target) = self.coordinate().expect("internal coordinate");
+        coord.difference(coord.value(positions), target)
+    }
+
+    /// Return the gradient of the constraint function in pairs of (atom index,
+    /// partial derivatives).
+    fn gradient(&self, positions: &[[f64; 3]]) -> Vec<(usize, [f64; 3])> {
+        self.coordinate().expect("internal coordinate").0.gradient(positions)
+    }
+
+    /// Correct `positions` in place for Cartesian constraints, with
+    /// `reference` positions the atoms moving from.
+    fn correct_cartesian(&self, reference: &[[f64; 3]], positions: &mut [[f64; 3]]) {
+        let x = |p: &[[f64; 3]], i: usize| Vector3f::from(p[i]);
+        match *self {
+            Self::FixedPlane(i, normal) => {
+                let n = Vector3f::from(normal).normalize();
+                let d = x(positions, i) - x(reference, i);
+                positions[i] = (x(reference, i) + d - n * n.dot(&d)).into();
+            }
+            Self::FixedLine(i, direction) => {
+                let u = Vector3f::from(direction).normalize();
+                let d = x(positions, i) - x(reference, i);
+                positions[i] = (x(reference, i) + u * u.dot(&d)).into();
+            }
+            Self::FixedPosition(i, center, tol) => {
+                let d = x(positions, i) - Vector3f::from(center);
+                let r = d.norm();
+                if r > tol {
+                    positions[i] = (Vector3f::from(center) + d * (tol / r)).into();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Remove forces on atom violating Cartesian constraints.
+    fn project_cartesian(&self, positions: &[[f64; 3]], forces: &mut [[f64; 3]]) {
+        let f = |i: usize, forces: &[[f64; 3]]| Vector3f::from(forces[i]);
+        match *self {
+            Self::FixedPlane(i, normal) => {
+                let n = Vector3f::from(normal).normalize();
+                let fi = f(i, forces);
+                forces[i] = (fi - n * n.dot(&fi)).into();
+            }
+            Self::FixedLine(i, direction) => {
+                let d = Vector3f::from(direction).normalize();
+                let fi = f(i, forces);
+                forces[i] = (d * d.dot(&fi)).into();
+            }
+            Self::FixedPosition(i, center, tol) => {
+                let d = Vector3f::from(positions[i]) - Vector3f::from(center);
+                let r = d.norm();
+                // remove outward force on the boundary
+                if r > 0.0 && r >= tol * (1.0 - 1e-8) {
+                    let u = d / r;
+                    let fi = f(i, forces);
+                    let fr = u.dot(&fi);
+                    if fr > 0.0 {
+                        forces[i] = (fi - u * fr).into();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check(&self, natoms: usize) -> Result<()> {
+        match *self {
+            Self::Bond(_, _, r0) => ensure!(r0 > 0.0, "invalid bond length in constraint: {r0}"),
+            Self::Angle(_, _, _, theta0) => {
+                ensure!((0.0..=180.0).contains(&theta0), "invalid angle in constraint: {theta0}")
+            }
+            Self::Dihedral(..) => {}
+            Self::FixedPlane(i, v) | Self::FixedLine(i, v) => {
+                ensure!(i < natoms, "invalid atom in constraint: {self:?}");
+                ensure!(v.vec2norm() > 0.0, "invalid vector in constraint: {self:?}");
+            }
+            Self::FixedPosition(i, _, tol) => {
+                ensure!(i < natoms, "invalid atom in constraint: {self:?}");
+                ensure!(tol.is_sign_positive(), "invalid tolerance in constraint: {self:?}");
+            }
+        }
+        if let Some((coord, _)) = self.coordinate() {
+            coord.check(natoms)?;
+        }
+        Ok(())
+    }
+}
+// 08137053 ends here
+
+// [[file:../optim.note::26766373][26766373]]
+/// Trait for constraints applied in optimizers and MD integrators.
+///
+/// Positions, forces and steps are flattened vectors of all atoms.
+pub trait EnforceConstraint: std::fmt::Debug {
+    /// Project `forces` at `positions` in place, removing components that
+    /// violate the constraint.
+    fn project_forces(&self, positions: &[f64], forces: &mut [f64]) -> Result<()>;
+
+    /// Adjust `step` proposed at `positions` in place, so that the constraint
+    /// is satisfied at `positions + step`.
+    fn adjust_step(&self, positions: &[f64], step: &mut [f64]) -> Result<()>;
+
+    /// Project `velocities` at `positions` in place for MD integrators,
+    /// removing components that violate the constraint.
+    ///
+    /// The default implementation is the same as `project_forces`, which is
+    /// not correct for constraints weighted by masses.
+    fn project_velocities(&self, positions: &[f64], velocities: &mut [f64]) -> Result<()> {
+        self.project_forces(positions, velocities)
+    }
+
+    /// Return the number of degrees of freedom removed by the constraint, for
+    /// computing temperature in MD. The default is zero.
+    fn ndof_removed(&self) -> usize {
+        0
+    }
+}
+
+/// Correct `positions` in place by Newton iterations to satisfy constraints
+/// on internal coordinates in `constraints`.
+fn correct_internals(
+    constraints: &[&Constraint],
+    positions: &mut [f64],
+    tol: f64,
+    max_iterations: usize,
+) -> Result<()> {
+    if constraints.is_empty() {
+        return Ok(());
+    }
+    for _ in 0..max_iterations {
+        let dev = constraints.iter().map(|c| c.deviation(positions.as_3d())).collect_vec();
+        if dev.iter().all(|x| x.abs() < tol) {
+            return Ok(());
+        }
+        // Newton step: x -= G^T (G G^T)^-1 c
+        let grads = gradients(constraints, positions.as_3d());
+        let a_inv = metric_inverse(&grads)?;
+        let lambda = a_inv * na::DVector::from_vec(dev);
+        for (g, l) in grads.iter().zip(lambda.iter()) {
+            positions.vecadd(g, -l);
+        }
+    }
+    bail!("constraints not satisfied in {max_iterations} iterations");
+}
+
+/// Project `forces` in place onto the tangent space of constraints on
+/// internal coordinates in `constraints`.
+fn project_internals(constraints: &[&Constraint], positions: &[f64], forces: &mut [f64]) -> Result<()> {
+    if constraints.is_empty() {
+        return Ok(());
+    }
+    let grads = gradients(constraints, positions.as_3d());
+    let a_inv = metric_inverse(&grads)?;
+    let gf = na::DVector::from_iterator(grads.len(), grads.iter().map(|g| g.vecdot(forces)));
+    let lambda = a_inv * gf;
+    for (g, l) in grads.iter().zip(lambda.iter()) {
+        forces.vecadd(g, -l);
+    }
+    Ok(())
+}
+
+/// Return the gradients of constraints on internal coordinates, as dense
+/// vectors.
+fn gradients(constraints: &[&Constraint], positions: &[[f64; 3]]) -> Vec<Vec<f64>> {
+    constraints
+        .iter()
+        .map(|c| {
+            let mut g = vec![0.0; positions.len() * 3];
+            for (i, gi) in c.gradient(positions) {
+                g[3 * i..3 * i + 3].vecadd(&gi, 1.0);
+            }
+            g
+        })
+        .collect()
+}
+
+/// Correct `target` positions in place by SHAKE, moving along constraint
+/// gradients at `reference` positions weighted by inverse masses `weights`,
+/// to satisfy constraints on internal coordinates in `constraints`.
+///
+/// Ryckaert, J.-P.; Ciccotti, G.; Berendsen, H. J. C. J. Comput. Phys. 1977, 23, 327.
+fn shake_internals(
+    constraints: &[&Constraint],
+    reference: &[f64],
+    target: &mut [f64],
+    weights: &[f64],
+    tol: f64,
+    max_iterations: usize,
+) -> Result<()> {
+    if constraints.is_empty() {
+        return Ok(());
+    }
+    let directions = weighted(&gradients(constraints, reference.as_3d()), weights);
+    for _ in 0..max_iterations {
+        let dev = constraints.iter().map(|c| c.deviation(target.as_3d())).collect_vec();
+        if dev.iter().all(|x| x.abs() < tol) {
+            return Ok(());
+        }
+        let grads = gradients(constraints, target.as_3d());
+        let lambda = solve_multipliers(&grads, &directions, dev)?;
+        for (d, l) in directions.iter().zip(lambda.iter()) {
+            target.vecadd(d, -l);
+        }
+    }
+    bail!("SHAKE not converged in {max_iterations} iterations");
+}
+
+/// Remove velocity components along constraint gradients at `positions` in
+/// place by RATTLE, weighted by inverse masses `weights`.
+///
+/// Andersen, H. C. J. Comput. Phys. 1983, 52, 24.
+fn rattle_internals(
+    constraints: &[&Constraint],
+    positions: &[f64],
+    velocities: &mut [f64],
+    weights: &[f64],
+) -> Result<()> {
+    if constraints.is_empty() {
+        return Ok(());
+    }
+    let grads = gradients(constraints, positions.as_3d());
+    let directions = weighted(&grads, weights);
+    let gv = grads.iter().map(|g| g.vecdot(velocities)).collect_vec();
+    let lambda = solve_multipliers(&grads, &directions, gv)?;
+    for (d, l) in directions.iter().zip(lambda.iter()) {
+        velocities.vecadd(d, -l);
+    }
+    Ok(())
+}
+
+fn weighted(grads: &[Vec<f64>], weights: &[f64]) -> Vec<Vec<f64>> {
+    grads
+        .iter()
+        .map(|g| g.iter().zip(weights).map(|(x, w)| x * w).collect())
+        .collect()
+}
+
+// Solve (G D^T) lambda = rhs for constraint gradients G and directions D
+fn solve_multipliers(grads: &[Vec<f64>], directions: &[Vec<f64>], rhs: Vec<f64>) -> Result<na::DVector<f64>> {
+    let m = grads.len();
+    let a = na::DMatrix::from_fn(m, m, |k, l| grads[k].vecdot(&directions[l]));
+    let a_inv = a.pseudo_inverse(1e-10).map_err(|e| format_err!("{e}"))?;
+    Ok(a_inv * na::DVector::from_vec(rhs))
+}
+
+// (G G^T)^-1 for constraint gradients G
+fn metric_inverse(grads: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
+    let m = grads.len();
+    let a = na::DMatrix::from_fn(m, m, |k, l| grads[k].vecdot(&grads[l]));
+    let a_inv = a.pseudo_inverse(1e-10).map_err(|e| format_err!("{e}"))?;
+    Ok(a_inv)
+}
+
+impl EnforceConstraint for Constraint {
+    /// `FixedPosition` is an inequality constraint, removing no degree of
+    /// freedom.
+    fn ndof_removed(&self) -> usize {
+        match self {
+            Self::FixedPlane(..) => 1,
+            Self::FixedLine(..) => 2,
+            Self::FixedPosition(..) => 0,
+            _ => 1,
+        }
+    }
+
+    fn project_forces(&self, positions: &[f64], forces: &mut [f64]) -> Result<()> {
+        self.check(positions.len() / 3)?;
+        if self.coordinate().is_some() {
+            project_internals(&[self], positions, forces)?;
+        } else {
+            self.project_cartesian(positions.as_3d(), forces.as_mut_3d());
+        }
+        Ok(())
+    }
+
+    fn adjust_step(&self, positions: &[f64], step: &mut [f64]) -> Result<()> {
+        self.check(positions.len() / 3)?;
+        let mut target = positions.to_vec();
+        target.vecadd(step, 1.0);
+        if self.coordinate().is_some() {
+            correct_internals(&[self], &mut target, 1e-6, 100)?;
+        } else {
+            self.correct_cartesian(positions.as_3d(), target.as_mut_3d());
+        }
+        for (s, (t, x)) in step.iter_mut().zip(target.iter().zip(positions)) {
+            *s = t - x;
+        }
+        Ok(())
+    }
+}
+// 26766373 ends here
+
+// [[file:../optim.note::e4ead145][e4ead145]]
+/// A set of constraints. Built-in constraints on internal coordinates are
+/// enforced jointly by SHAKE-like projection: positions are corrected onto
+/// the constraint surface, and forces are projected onto its tangent space.
+/// Cartesian and custom constraints are applied after in turn.
+#[derive(Debug, Clone)]
+pub struct Constraints {
+    items: Vec<Constraint>,
+    custom: Vec<std::sync::Arc<dyn EnforceConstraint>>,
+    tolerance: f64,
+    max_iterations: usize,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            custom: vec![],
+            tolerance: 1e-6,
+            max_iterations: 100,
+        }
+    }
+}
+
+impl Constraints {
+    /// Add a constraint.
+    pub fn add(&mut self, constraint: Constraint) {
+        self.items.push(constraint);
+    }
+
+    /// Add a user defined constraint.
+    pub fn add_custom(&mut self, constraint: impl EnforceConstraint + 'static) {
+        self.custom.push(std::sync::Arc::new(constraint));
+    }
+
+    /// Return true if there is no constraint.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.custom.is_empty()
+    }
+
+    /// Iterate over all constraints, as trait objects.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn EnforceConstraint> {
+        let items = self.items.iter().map(|c| c as &dyn EnforceConstraint);
+        items.chain(self.custom.iter().map(|c| c.as_ref()))
+    }
+
+    /// Set the tolerance in deviation for position correction.
+    pub fn set_tolerance(&mut self, tol: f64) {
+        assert!(tol.is_sign_positive(), "invalid tolerance: {tol}");
+        self.tolerance = tol;
+    }
+
+    /// Return constraints on internal coordinates.
+    fn internals(&self) -> Vec<&Constraint> {
+        self.items.iter().filter(|c| c.coordinate().is_some()).collect()
+    }
+
+    fn check(&self, natoms: usize) -> Result<()> {
+        for c in self.items.iter() {
+            c.check(natoms)?;
+        }
+        Ok(())
+    }
+
+    /// Correct flattened `positions` in place to satisfy built-in constraints.
+    pub fn enforce(&self, positions: &mut [f64]) -> Result<()> {
+        self.check(positions.len() / 3)?;
+        let reference = positions.to_vec();
+        for c in self.items.iter() {
+            c.correct_cartesian(reference.as_3d(), positions.as_mut_3d());
+        }
+        correct_internals(&self.internals(), positions, self.tolerance, self.max_iterations)
+    }
+
+    /// Adjust `step` from `positions` in place for MD integrators, as
+    /// `adjust_step` but with internal coordinates corrected by SHAKE using
+    /// inverse masses `weights` of flattened coordinates.
+    pub(crate) fn shake(&self, positions: &[f64], step: &mut [f64], weights: &[f64]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check(positions.len() / 3)?;
+        let mut target = positions.to_vec();
+        target.vecadd(step, 1.0);
+        for c in self.items.iter() {
+            c.correct_cartesian(positions.as_3d(), target.as_mut_3d());
+        }
+        shake_internals(
+            &self.internals(),
+            positions,
+            &mut target,
+            weights,
+            self.tolerance,
+            self.max_iterations,
+        )?;
+        for (s, (t, x)) in step.iter_mut().zip(target.iter().zip(positions)) {
+            *s = t - x;
+        }
+        for c in self.custom.iter() {
+            c.adjust_step(positions, step)?;
+        }
+        Ok(())
+    }
+
+    /// Project `velocities` at `positions` in place onto the tangent space of
+    /// the constraint surface for MD integrators, with internal coordinates
+    /// handled by RATTLE using inverse masses `weights`.
+    pub(crate) fn rattle(&self, positions: &[f64], velocities: &mut [f64], weights: &[f64]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check(positions.len() / 3)?;
+        rattle_internals(&self.internals(), positions, velocities, weights)?;
+        for c in self.items.iter() {
+            c.project_cartesian(positions.as_3d(), velocities.as_mut_3d());
+        }
+        for c in self.custom.iter() {
+            c.project_velocities(positions, velocities)?;
+        }
+        Ok(())
+    }
+}
+
+impl EnforceConstraint for Constraints {
+    /// Project flattened `forces` in place onto the tangent space of the
+    /// constraint surface at `positions`.
+    fn project_forces(&self, positions: &[f64], forces: &mut [f64]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check(positions.len() / 3)?;
+        project_internals(&self.internals(), positions, forces)?;
+        for c in self.items.iter() {
+            c.project_cartesian(positions.as_3d(), forces.as_mut_3d());
+        }
+        for c in self.custom.iter() {
+            c.project_forces(positions, forces)?;
+        }
+        Ok(())
+    }
+
+    fn adjust_step(&self, positions: &[f64], step: &mut [f64]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        self.check(positions.len() / 3)?;
+        let mut target = positions.to_vec();
+        target.vecadd(step, 1.0);
+        for c in self.items.iter() {
+            c.correct_cartesian(positions.as_3d(), target.as_mut_3d());
+        }
+        correct_internals(&self.internals(), &mut target, self.tolerance, self.max_iterations)?;
+        for (s, (t, x)) in step.iter_mut().zip(target.iter().zip(positions)) {
+            *s = t - x;
+        }
+        for c in self.custom.iter() {
+            c.adjust_step(positions, step)?;
+        }
+        Ok(())
+    }
+
+    /// Return the number of degrees of freedom removed by all constraints.
+    fn ndof_removed(&self) -> usize {
+        self.iter().map(|c| c.ndof_removed()).sum()
+    }
+}
+// e4ead145 ends here
+
+// [[file:../optim.note::295c0013][295c0013]]
+/// Return atomic masses of atoms in `mol`.
+pub(crate) fn atom_masses(mol: &gchemol::Molecule) -> Result<Vec<f64>> {
+    mol.atoms()
+        .map(|(i, a)| a.get_mass().ok_or(format_err!("no mass for atom {i}")))
+        .collect()
+}
+
+/// Keep the center of mass fixed, by removing the net translational force in
+/// each evaluation and the net translation in each step. This prevents drift
+/// when forces from the model do not sum exactly to zero.
+#[derive(Debug, Clone)]
+pub struct FixedCenterOfMass {
+    masses: Vec<f64>,
+}
+
+impl FixedCenterOfMass {
+    /// Construct with atomic `masses`.
+    pub fn new(masses: &[f64]) -> Self {
+        assert!(masses.iter().all(|&m| m > 0.0), "invalid masses: {masses:?}");
+        Self {
+            masses: masses.to_vec(),
+        }
+    }
+
+    /// Construct from atomic masses in `mol`.
+    pub fn from_molecule(mol: &gchemol::Molecule) -> Result<Self> {
+        let masses = atom_masses(mol)?;
+        Ok(Self::new(&masses))
+    }
+
+    fn check(&self, natoms: usize) -> Result<()> {
+        ensure!(
+            natoms == self.masses.len(),
+            "expect {} atoms for fixed center of mass, but found {natoms}",
+            self.masses.len()
+        );
+        Ok(())
+    }
+}
+
+impl EnforceConstraint for FixedCenterOfMass {
+    /// Remove net force, keeping the acceleration of center of mass zero:
+    /// F_i -= m_i / M Σ F_j
+    fn project_forces(&self, positions: &[f64], forces: &mut [f64]) -> Result<()> {
+        self.check(positions.len() / 3)?;
+        let total: f64 = self.masses.iter().sum();
+        let mut net = [0.0; 3];
+        for f in forces.as_3d() {
+            net.vecadd(f, 1.0);
+        }
+        for (f, m) in forces.as_mut_3d().iter_mut().zip(&self.masses) {
+            f.vecadd(&net, -m / total);
+        }
+        Ok(())
+    }
+
+    /// Remove displacement of center of mass from `step`.
+    fn adjust_step(&self, positions: &[f64], step: &mut [f64]) -> Result<()> {
+        self.check(positions.len() / 3)?;
+        let total: f64 = self.masses.iter().sum();
+        let mut shift = [0.0; 3];
+        for (s, m) in step.as_3d().iter().zip(&self.masses) {
+            shift.vecadd(s, m / total);
+        }
+        for s in step.as_mut_3d() {
+            s.vecadd(&shift, -1.0);
+        }
+        Ok(())
+    }
+
+    /// Remove velocity of center of mass: v_i -= Σ m_j v_j / M
+    fn project_velocities(&self, positions: &[f64], velocities: &mut [f64]) -> Result<()> {
+        self.adjust_step(positions, velocities)
+    }
+
+    fn ndof_removed(&self) -> usize {
+        3
+    }
+}
+// 295c0013 ends here
@@ -0,0 +1,102 @@
+// [[file:../optim.note::266d4138][266d4138]]
+use super::*;
+
+use crate::cell::EV_PER_A3_TO_GPA;
+use crate::dynamics::MoleculeDynamics;
+use vecfx::nalgebra as na;
+// 266d4138 ends here
+
+// [[file:../optim.note::26727974][26727974]]
+/// Homogeneous deformation applied to the simulation cell at constant
+/// engineering strain rate (in 1/fs).
+#[derive(Debug, Clone, Copy)]
+pub enum Deformation {
+    /// Uniaxial tension (or compression with negative `rate`) along
+    /// Cartesian `axis` (0, 1, 2 for x, y, z).
+    Tension { axis: usize, rate: f64 },
+    /// Simple shear: displacement along `axis` proportional to coordinate
+    /// along `normal`.
+    Shear { axis: usize, normal: usize, rate: f64 },
+}
+
+impl Deformation {
+    // Velocity gradient tensor.
+    fn velocity_gradient(&self) -> Result<na::Matrix3<f64>> {
+        let mut l = na::Matrix3::zeros();
+        match *self {
+            Self::Tension { axis, rate } => {
+                ensure!(axis < 3, "invalid tension axis: {axis}");
+                l[(axis, axis)] = rate;
+            }
+            Self::Shear { axis, normal, rate } => {
+                ensure!(axis < 3 && normal < 3, "invalid shear axes: {axis}, {normal}");
+                ensure!(axis != normal, "shear axis and normal must differ");
+                l[(axis, normal)] = rate;
+            }
+        }
+        Ok(l)
+    }
+
+    /// Return the deformation gradient `F = I + L t` after `time` in fs, with
+    /// `L` the velocity gradient of this deformation.
+    pub fn gradient(&self, time: f64) -> Result<na::Matrix3<f64>> {
+        let f = na::Matrix3::identity() + self.velocity_gradient()? * time;
+        ensure!(f.determinant() > 0.0, "cell collapsed in deformation after {time} fs");
+        Ok(f)
+    }
+
+    /// Return the engineering strain (shear strain for `Shear`) after `time`
+    /// in fs.
+    pub fn strain(&self, time: f64) -> f64 {
+        match *self {
+            Self::Tension { rate, .. } | Self::Shear { rate, .. } => rate * time,
+        }
+    }
+}
+
+/// Record of one step of deformation dynamics.
+#[derive(Debug, Clone)]
+pub struct DeformationRecord {
+    /// MD step number.
+    pub step: usize,
+    /// Engineering strain accumulated since deformation started.
+    pub strain: f64,
+    /// Stress tensor in Voigt order (xx, yy, zz, yz, xz, xy) in GPa, as
+    /// provided by the potential.
+    pub stress: [f64; 6],
+    /// Instantaneous temperature in K.
+    pub temperature: f64,
+}
+// 26727974 ends here
+
+// [[file:../optim.note::e26454e4][e26454e4]]
+impl<'a, U> MoleculeDynamics<'a, U> {
+    /// Propagate `nsteps` steps while deforming the cell at constant
+    /// engineering strain rate. Atoms are mapped affinely with the cell after
+    /// each step. The potential must provide stress, for periodic molecule.
+    pub fn deform(&mut self, deformation: Deformation, nsteps: usize) -> Result<Vec<DeformationRecord>> {
+        let mut f = deformation.gradient(0.0)?;
+        let mut records = Vec::with_capacity(nsteps);
+        for k in 1..=nsteps {
+            self.propagate(1)?;
+            // F grows linearly in time, so apply F_new F_old^-1 to the cell
+            let time = k as f64 * self.dt();
+            let f_new = deformation.gradient(time)?;
+            let f_old_inv = f.try_inverse().ok_or(format_err!("singular deformation gradient"))?;
+            self.deform_cell(&(f_new * f_old_inv))?;
+            f = f_new;
+            let stress = self
+                .dynamics()
+                .get_stress()?
+                .ok_or(format_err!("no stress for deformation"))?;
+            records.push(DeformationRecord {
+                step: self.nstep(),
+                strain: deformation.strain(time),
+                stress: stress.map(|s| s * EV_PER_A3_TO_GPA),
+                temperature: self.temperature(),
+            });
+        }
+        Ok(records)
+    }
+}
+// e26454e4 ends here
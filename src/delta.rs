@@ -0,0 +1,125 @@
+// [[file:../optim.note::14fee8da][14fee8da]]
+use super::*;
+// 14fee8da ends here
+
+// [[file:../optim.note::70406145][70406145]]
+/// Output of delta-learning potential.
+#[derive(Debug, Clone)]
+pub struct Delta<U> {
+    /// The energy of baseline potential.
+    pub baseline_energy: f64,
+    /// The correction energy included in the total energy, which is
+    /// extrapolated linearly from the last refresh if not refreshed.
+    pub correction_energy: f64,
+    /// True if correction was evaluated at this position.
+    pub refreshed: bool,
+    /// The number of calls for baseline potential.
+    pub nbaseline: usize,
+    /// The number of calls for correction potential.
+    pub ncorrection: usize,
+    /// Extra data from baseline potential.
+    pub extra: U,
+}
+
+// correction evaluated at position
+struct Correction {
+    position: Vec<f64>,
+    energy: f64,
+    force: Vec<f64>,
+}
+
+/// A potential for the "baseline + correction" (delta-learning) pattern: a
+/// cheap `baseline` potential provides most of the potential energy surface,
+/// and a second `correction` potential (e.g. a machine learning model)
+/// provides the difference to the target level.
+///
+/// The correction could be refreshed less frequently than the baseline. In
+/// between, the correction is extrapolated to first order using its last
+/// evaluated energy and forces. Stress, if any, is taken from the baseline
+/// only.
+///
+/// # Examples
+///
+/// ```ignore
+/// let potential = DeltaPotential::new(xtb, ml_correction).refresh_every(5);
+/// let mut dynamics = Dynamics::new(&x, potential);
+/// ```
+pub struct DeltaPotential<P, C> {
+    baseline: P,
+    correction: C,
+    refresh_every: usize,
+    nbaseline: usize,
+    ncorrection: usize,
+    last: Option<Correction>,
+}
+
+impl<P, C> DeltaPotential<P, C> {
+    /// Correct `baseline` potential by `correction` on each evaluation.
+    pub fn new(baseline: P, correction: C) -> Self {
+        Self {
+            baseline,
+            correction,
+            refresh_every: 1,
+            nbaseline: 0,
+            ncorrection: 0,
+            last: None,
+        }
+    }
+
+    /// Refresh correction every `n` evaluations of baseline.
+    pub fn refresh_every(mut self, n: usize) -> Self {
+        assert!(n > 0, "invalid refresh interval: {n}");
+        self.refresh_every = n;
+        self
+    }
+
+    /// The number of calls for baseline potential.
+    pub fn nbaseline(&self) -> usize {
+        self.nbaseline
+    }
+
+    /// The number of calls for correction potential.
+    pub fn ncorrection(&self) -> usize {
+        self.ncorrection
+    }
+}
+
+impl<U, V, P, C> EvaluatePotential<Delta<U>> for DeltaPotential<P, C>
+where
+    P: EvaluatePotential<U>,
+    C: EvaluatePotential<V>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Delta<U>> {
+        let refreshed = self.last.is_none() || self.nbaseline % self.refresh_every == 0;
+        if refreshed {
+            self.correction.evaluate(position, output)?;
+            self.ncorrection += 1;
+            self.last = Some(Correction {
+                position: position.to_vec(),
+                energy: output.energy,
+                force: output.force.clone(),
+            });
+        }
+        let last = self.last.as_ref().unwrap();
+        // E(x) = E(x0) - F(x0) . (x - x0)
+        let dx = position.iter().zip(&last.position).map(|(x, x0)| x - x0).collect_vec();
+        let correction_energy = last.energy - last.force.vecdot(&dx);
+
+        output.stress = None;
+        let extra = self.baseline.evaluate(position, output)?;
+        self.nbaseline += 1;
+        let baseline_energy = output.energy;
+        output.energy += correction_energy;
+        output.force.vecadd(&last.force, 1.0);
+
+        Ok(Delta {
+            baseline_energy,
+            correction_energy,
+            refreshed,
+            nbaseline: self.nbaseline,
+            ncorrection: self.ncorrection,
+            extra,
+        })
+    }
+}
+// 70406145 ends here
@@ -0,0 +1,1286 @@
+// [[file:../optim.note::f7e5e791][f7e5e791]]
+use super::*;
+
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+use serde::*;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::cell::{cell_matrix, set_cell, EV_PER_A3_TO_GPA};
+use vecfx::nalgebra as na;
+// f7e5e791 ends here
+
+// [[file:../optim.note::625c84f3][625c84f3]]
+/// Conversion factor from eV/Å/amu to Å/fs², for accelerations from forces.
+const ACCELERATION_UNIT: f64 = 9.648533212e-3;
+
+/// The Boltzmann constant in eV/K.
+pub(crate) const KB: f64 = 8.617333262e-5;
+
+/// Threshold of principal moments of inertia (amu·Å²) for vanishing rotation.
+const INERTIA_EPS: f64 = 1e-10;
+
+/// Thermostat for constant temperature (NVT) molecular dynamics.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Thermostat {
+    /// Langevin dynamics with target `temperature` in K and `friction`
+    /// coefficient in 1/fs, integrated using BAOAB splitting.
+    Langevin { temperature: f64, friction: f64 },
+    /// Berendsen weak coupling to `temperature` in K with time constant
+    /// `tau` in fs, for fast equilibration. Note that it does not sample
+    /// canonical ensemble.
+    Berendsen { temperature: f64, tau: f64 },
+    /// Bussi stochastic velocity rescaling to `temperature` in K with time
+    /// constant `tau` in fs, sampling canonical ensemble for production runs.
+    Bussi { temperature: f64, tau: f64 },
+}
+
+impl Thermostat {
+    // Set target temperature in K.
+    fn set_temperature(&mut self, t: f64) {
+        match self {
+            Self::Langevin { temperature, .. } => *temperature = t,
+            Self::Berendsen { temperature, .. } => *temperature = t,
+            Self::Bussi { temperature, .. } => *temperature = t,
+        }
+    }
+
+    /// Return target temperature in K.
+    pub fn temperature(&self) -> f64 {
+        match self {
+            Self::Langevin { temperature, .. } => *temperature,
+            Self::Berendsen { temperature, .. } => *temperature,
+            Self::Bussi { temperature, .. } => *temperature,
+        }
+    }
+}
+
+/// Barostat for constant pressure (NPT) molecular dynamics of periodic
+/// systems. The potential must provide stress.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Barostat {
+    /// Berendsen weak coupling to `pressure` in GPa with time constant `tau`
+    /// in fs, scaling the cell isotropically. `compressibility` is in 1/GPa.
+    Berendsen {
+        pressure: f64,
+        tau: f64,
+        compressibility: f64,
+    },
+}
+
+/// Actions when energy drift exceeds tolerance in NVE dynamics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftPolicy {
+    /// Warn once and continue.
+    Warn,
+    /// Abort dynamics with an error.
+    Abort,
+}
+
+/// Thermodynamic observables of one MD step.
+#[derive(Debug, Clone)]
+pub struct MdProgress {
+    /// The number of steps propagated.
+    pub nstep: usize,
+    /// Simulation time in fs.
+    pub time: f64,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Potential energy in eV.
+    pub potential_energy: f64,
+    /// Kinetic energy in eV.
+    pub kinetic_energy: f64,
+    /// Total energy in eV, the sum of potential and kinetic energy.
+    pub total_energy: f64,
+    /// Instantaneous temperature in K.
+    pub temperature: f64,
+    /// Instantaneous pressure in GPa, if stress is available for periodic
+    /// molecule.
+    pub pressure: Option<f64>,
+}
+
+/// Trait for MD integrators propagating `MoleculeDynamics` by one step,
+/// using its `kick` and `drift` operations.
+pub trait Integrator<U> {
+    /// Propagate `md` by one step of `dt` in fs.
+    fn step(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()>;
+}
+
+/// Velocity Verlet integrator, with velocities synchronized with positions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VelocityVerlet;
+
+impl<U> Integrator<U> for VelocityVerlet {
+    fn step(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()> {
+        md.kick(0.5 * dt)?;
+        md.drift(dt)?;
+        md.kick(0.5 * dt)
+    }
+}
+
+/// Leapfrog integrator. Velocities are at half steps, lagging behind
+/// positions by half a timestep, and so is the kinetic energy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Leapfrog;
+
+impl<U> Integrator<U> for Leapfrog {
+    fn step(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()> {
+        md.kick(dt)?;
+        md.drift(dt)
+    }
+}
+
+/// Multiple time step integrator (r-RESPA), splitting the potential into a
+/// fast part in `MoleculeDynamics`, and a slow part evaluated only once in
+/// each step. The fast part is integrated by velocity Verlet in `nsub`
+/// substeps. Note that the potential energy of `MoleculeDynamics` is the
+/// fast part only.
+///
+/// # Reference
+///
+/// Tuckerman, M.; Berne, B. J.; Martyna, G. J. J. Chem. Phys. 1992, 97, 1990.
+pub struct Respa<'a, P, V> {
+    potential: Option<P>,
+    // created in the first step
+    slow: Option<Dynamics<'a, V>>,
+    nsub: usize,
+}
+
+impl<'a, P, V> Respa<'a, P, V>
+where
+    P: EvaluatePotential<V> + 'a,
+{
+    /// Construct with `slow` part of the potential, and the fast part
+    /// integrated in `nsub` substeps of each step.
+    pub fn new(slow: P, nsub: usize) -> Self {
+        assert!(nsub > 0, "invalid number of substeps: {nsub}");
+        Self {
+            potential: Some(slow),
+            slow: None,
+            nsub,
+        }
+    }
+
+    // Update velocities of `md` using slow forces in time `dt`.
+    fn slow_kick<U>(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()> {
+        let x = md.position();
+        if self.slow.is_none() {
+            let potential = self.potential.take().expect("no slow potential");
+            self.slow = Some(Dynamics::new(x, potential));
+        }
+        let slow = self.slow.as_mut().unwrap();
+        slow.set_position(x);
+        let force = slow.get_force()?.to_vec();
+        md.kick_by(&force, dt)
+    }
+}
+
+impl<'a, U, P, V> Integrator<U> for Respa<'a, P, V>
+where
+    P: EvaluatePotential<V> + 'a,
+{
+    fn step(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()> {
+        self.slow_kick(md, 0.5 * dt)?;
+        let h = dt / self.nsub as f64;
+        for _ in 0..self.nsub {
+            md.kick(0.5 * h)?;
+            md.drift(h)?;
+            md.kick(0.5 * h)?;
+        }
+        self.slow_kick(md, 0.5 * dt)
+    }
+}
+
+/// Molecular dynamics of atoms moving on the potential surface provided by
+/// `Dynamics`, integrated using velocity Verlet algorithm by default (NVE),
+/// or coupled to a `Thermostat` (NVT).
+///
+/// Units are Å for positions, eV for energy, amu for masses, and fs for
+/// time, so velocities are in Å/fs.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut md = MoleculeDynamics::from_chemical_model(&mut model, mol)?.timestep(0.5);
+/// md.propagate(1000)?;
+/// println!("T = {}", md.temperature());
+///
+/// // Langevin dynamics at 300 K
+/// let mut md = MoleculeDynamics::from_chemical_model(&mut model, mol)?.nvt(300.0);
+/// md.propagate(1000)?;
+///
+/// // NPT dynamics at 300 K and 1 GPa, with a model providing stress
+/// let barostat = Barostat::Berendsen { pressure: 1.0, tau: 100.0, compressibility: 0.01 };
+/// let mut md = MoleculeDynamics::from_model(&mut model, mol)?.nvt(300.0).barostat(barostat);
+/// md.propagate(1000)?;
+/// let lattice = md.molecule().unwrap().lattice;
+/// ```
+pub struct MoleculeDynamics<'a, U> {
+    dynamics: Dynamics<'a, U>,
+    // atomic masses
+    masses: Vec<f64>,
+    // true for freezing coordinate, which is never moved
+    frozen: Vec<bool>,
+    velocities: Vec<f64>,
+    timestep: f64,
+    nstep: usize,
+    thermostat: Option<Thermostat>,
+    barostat: Option<Barostat>,
+    rng: crate::random::Rng,
+    // the molecule shared with potential, with lattice updated in NPT
+    molecule: Option<Rc<RefCell<Molecule>>>,
+    constraints: Constraints,
+    // intervals in steps for removing net translation and rotation
+    translation_removal: Option<usize>,
+    rotation_removal: Option<usize>,
+    writer: Option<TrajectoryWriter>,
+    schedule: Option<TemperatureSchedule>,
+    // taken out temporarily during integration
+    integrator: Option<Box<dyn Integrator<U> + 'a>>,
+    observer: Option<(usize, Box<dyn FnMut(&MdProgress) + 'a>)>,
+    restraints: Restraints,
+    // tolerance of energy drift per atom, with total energy in reference
+    drift_check: Option<(f64, DriftPolicy)>,
+    reference_energy: Option<f64>,
+    drift_warned: bool,
+}
+
+/// Default friction coefficient in 1/fs for Langevin dynamics.
+const LANGEVIN_FRICTION: f64 = 0.01;
+
+/// Atomic mass of deuterium in amu, for isotope substitution of hydrogen.
+pub const DEUTERIUM_MASS: f64 = 2.014101778;
+
+impl<'a, U> MoleculeDynamics<'a, U> {
+    /// Construct MD for atoms with `masses` in potential `dynamics`, whose
+    /// position is flattened Cartesian coordinates of atoms. Initial velocities
+    /// are zero, with timestep of 1 fs.
+    ///
+    /// Displacements smaller than epsilon of `dynamics` are not ignored in MD
+    /// (`SmallStep::ForceReevaluate`), so that positions always follow the
+    /// propagated velocities.
+    pub fn new(mut dynamics: Dynamics<'a, U>, masses: &[f64]) -> Self {
+        let n = dynamics.position().len();
+        assert_eq!(n, 3 * masses.len(), "invalid number of masses: {}", masses.len());
+        assert!(masses.iter().all(|&m| m > 0.0), "invalid masses: {masses:?}");
+        dynamics.set_small_step(SmallStep::ForceReevaluate);
+        Self {
+            dynamics,
+            masses: masses.to_vec(),
+            frozen: vec![false; n],
+            velocities: vec![0.0; n],
+            timestep: 1.0,
+            nstep: 0,
+            thermostat: None,
+            barostat: None,
+            rng: crate::random::Rng::new(0),
+            molecule: None,
+            constraints: Constraints::default(),
+            translation_removal: None,
+            rotation_removal: None,
+            writer: None,
+            schedule: None,
+            integrator: Some(Box::new(VelocityVerlet)),
+            observer: None,
+            restraints: Restraints::default(),
+            drift_check: None,
+            reference_energy: None,
+            drift_warned: false,
+        }
+    }
+
+    /// Set integration timestep in fs.
+    pub fn timestep(mut self, dt: f64) -> Self {
+        assert!(dt > 0.0, "invalid timestep: {dt}");
+        self.timestep = dt;
+        self
+    }
+
+    /// Override masses in amu of atoms indexed from 0 in `atoms`, e.g. for
+    /// isotope substitution such as deuteration using `DEUTERIUM_MASS`.
+    ///
+    /// # Panics
+    ///
+    /// * when `atoms` out of range, or `mass` is not positive.
+    pub fn isotope(mut self, atoms: &[usize], mass: f64) -> Self {
+        assert!(mass > 0.0, "invalid mass: {mass}");
+        for &i in atoms {
+            assert!(i < self.masses.len(), "invalid atom index: {i}");
+            self.masses[i] = mass;
+        }
+        self
+    }
+
+    /// Propagate using `integrator`, instead of velocity Verlet. Ignored
+    /// for Langevin thermostat, which has its own integrator.
+    pub fn integrator(mut self, integrator: impl Integrator<U> + 'a) -> Self {
+        self.integrator = Some(Box::new(integrator));
+        self
+    }
+
+    /// Couple to `thermostat` for constant temperature dynamics.
+    pub fn thermostat(mut self, thermostat: Thermostat) -> Self {
+        assert!(thermostat.temperature() >= 0.0, "invalid temperature: {thermostat:?}");
+        match thermostat {
+            Thermostat::Langevin { friction, .. } => assert!(friction >= 0.0, "invalid friction: {friction}"),
+            Thermostat::Berendsen { tau, .. } | Thermostat::Bussi { tau, .. } => {
+                assert!(tau > 0.0, "invalid time constant: {tau}")
+            }
+        }
+        self.thermostat = Some(thermostat);
+        self
+    }
+
+    /// Run NVT dynamics at `temperature` in K using Langevin thermostat with
+    /// default friction coefficient of 0.01/fs.
+    pub fn nvt(self, temperature: f64) -> Self {
+        self.thermostat(Thermostat::Langevin {
+            temperature,
+            friction: LANGEVIN_FRICTION,
+        })
+    }
+
+    /// Change target temperature of the active thermostat following
+    /// `schedule` over steps, such as for simulated annealing.
+    pub fn temperature_schedule(mut self, schedule: TemperatureSchedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Couple to `barostat` for constant pressure dynamics. Only supported
+    /// for periodic molecule, see `from_model`.
+    pub fn barostat(mut self, barostat: Barostat) -> Self {
+        let Barostat::Berendsen {
+            tau, compressibility, ..
+        } = barostat;
+        assert!(tau > 0.0, "invalid time constant: {tau}");
+        assert!(compressibility >= 0.0, "invalid compressibility: {compressibility}");
+        self.barostat = Some(barostat);
+        self
+    }
+
+    /// Freeze atoms with serial numbers in `serials` (counting from 1, see
+    /// `Freezing::freeze_atoms`) during dynamics, such as bottom layers of a
+    /// slab, in addition to freezing flags set on the molecule. Without
+    /// molecule atoms are numbered by their order. Return error when selected
+    /// atoms do not exist.
+    pub fn freeze_atoms(mut self, serials: &[usize]) -> Result<Self> {
+        let numbers = match &self.molecule {
+            Some(mol) => mol.borrow().numbers().collect_vec(),
+            None => (1..=self.masses.len()).collect(),
+        };
+        for n in serials {
+            let i = numbers
+                .iter()
+                .position(|m| m == n)
+                .ok_or_else(|| format_err!("invalid atom for freezing: {n}"))?;
+            self.frozen[3 * i..3 * i + 3].fill(true);
+        }
+        self.fix_frozen();
+        Ok(self)
+    }
+
+    /// Freeze coordinates using `mask` over flattened coordinates of all atoms
+    /// during dynamics. `true` for freezing the coordinate. Return error when
+    /// `mask` has a wrong size.
+    pub fn freeze_coords(mut self, mask: &[bool]) -> Result<Self> {
+        ensure!(
+            mask.len() == self.frozen.len(),
+            "invalid size of coords mask: {} != {}",
+            mask.len(),
+            self.frozen.len()
+        );
+        for (x, &c) in self.frozen.iter_mut().zip(mask) {
+            *x |= c;
+        }
+        self.fix_frozen();
+        Ok(self)
+    }
+
+    /// Hold `constraint` fixed during dynamics, such as `Constraint::Bond`
+    /// for X-H bonds to allow larger timestep. Constraints on internal
+    /// coordinates are enforced using SHAKE/RATTLE.
+    pub fn constrain(mut self, constraint: Constraint) -> Self {
+        self.constraints.add(constraint);
+        self
+    }
+
+    /// Add bias energy and forces from `restraint` during dynamics.
+    pub fn restrain(mut self, restraint: Restraint) -> Self {
+        self.restraints.add(restraint);
+        self
+    }
+
+    /// Replace restraints during dynamics with `restraints`, such as for
+    /// switching umbrella windows. Lambda schedule of `restraints` counts
+    /// MD steps.
+    pub fn set_restraints(&mut self, restraints: Restraints) {
+        self.restraints = restraints;
+    }
+
+    /// Hold a user defined `constraint` during dynamics.
+    pub fn constrain_with(mut self, constraint: impl EnforceConstraint + 'static) -> Self {
+        self.constraints.add_custom(constraint);
+        self
+    }
+
+    /// Remove net translation of all atoms every `nevery` steps, avoiding the
+    /// whole system flying away due to small inconsistency in forces. Ignored
+    /// when any atom is frozen.
+    pub fn remove_translation(mut self, nevery: usize) -> Self {
+        assert!(nevery > 0, "invalid interval: {nevery}");
+        self.translation_removal = Some(nevery);
+        self
+    }
+
+    /// Remove net rotation of all atoms around center of mass every `nevery`
+    /// steps, for gas-phase clusters only. Ignored when any atom is frozen.
+    pub fn remove_rotation(mut self, nevery: usize) -> Self {
+        assert!(nevery > 0, "invalid interval: {nevery}");
+        self.rotation_removal = Some(nevery);
+        self
+    }
+
+    /// Call `observer` with progress every `nevery` steps during dynamics.
+    pub fn observe(mut self, nevery: usize, observer: impl FnMut(&MdProgress) + 'a) -> Self {
+        assert!(nevery > 0, "invalid interval: {nevery}");
+        self.observer = Some((nevery, Box::new(observer)));
+        self
+    }
+
+    /// Monitor drift of total energy from the start of NVE dynamics, and
+    /// take action by `policy` when it exceeds `tolerance` in eV/atom, which
+    /// indicates too large timestep or inconsistent forces. Ignored if
+    /// thermostat or barostat is active.
+    pub fn drift_tolerance(mut self, tolerance: f64, policy: DriftPolicy) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.drift_check = Some((tolerance, policy));
+        self
+    }
+
+    /// Write trajectory frames during `propagate` using `writer`.
+    pub fn trajectory(mut self, writer: TrajectoryWriter) -> Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Set `seed` for random numbers used by stochastic thermostats.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = crate::random::Rng::new(seed);
+        self
+    }
+
+    /// Return current position.
+    pub fn position(&self) -> &[f64] {
+        self.dynamics.position()
+    }
+
+    /// Return current velocities in Å/fs.
+    pub fn velocities(&self) -> &[f64] {
+        &self.velocities
+    }
+
+    /// Set current velocities in Å/fs. Velocities of freezing coordinates
+    /// are always zero.
+    pub fn set_velocities(&mut self, velocities: &[f64]) {
+        assert_eq!(velocities.len(), self.velocities.len(), "invalid size of velocities");
+        self.velocities.copy_from_slice(velocities);
+        self.fix_frozen();
+    }
+
+    /// Change target temperature of thermostat to `temperature` in K, with
+    /// velocities rescaled from the old target, such as for swapping
+    /// temperatures in replica exchange.
+    ///
+    /// # Panics
+    ///
+    /// * when no thermostat set.
+    pub fn set_target_temperature(&mut self, temperature: f64) {
+        assert!(temperature > 0.0, "invalid temperature: {temperature}");
+        let thermostat = self.thermostat.as_mut().expect("no thermostat");
+        let scale = (temperature / thermostat.temperature()).sqrt();
+        thermostat.set_temperature(temperature);
+        self.velocities.iter_mut().for_each(|v| *v *= scale);
+    }
+
+    /// Draw velocities from Maxwell–Boltzmann distribution at `temperature`
+    /// in K, using random numbers set by `seed`. Net translation is removed
+    /// when no atom is frozen.
+    pub fn randomize_velocities(&mut self, temperature: f64) {
+        assert!(temperature >= 0.0, "invalid temperature: {temperature}");
+        let weights = self.inverse_masses();
+        for (v, w) in self.velocities.iter_mut().zip(weights) {
+            *v = (KB * temperature * ACCELERATION_UNIT * w).sqrt() * self.rng.normal();
+        }
+        if !self.frozen.contains(&true) {
+            self.zero_momentum();
+        }
+    }
+
+    /// Return atomic masses.
+    pub fn masses(&self) -> &[f64] {
+        &self.masses
+    }
+
+    /// Return elapsed simulation time in fs.
+    pub fn time(&self) -> f64 {
+        self.nstep as f64 * self.timestep
+    }
+
+    /// Return the number of steps propagated.
+    pub fn nstep(&self) -> usize {
+        self.nstep
+    }
+
+    /// Return the underlying potential.
+    pub fn dynamics(&mut self) -> &mut Dynamics<'a, U> {
+        &mut self.dynamics
+    }
+
+    /// Return potential energy at current position, including restraint
+    /// energy if any.
+    pub fn get_energy(&mut self) -> Result<f64> {
+        let energy = self.dynamics.get_energy()?;
+        Ok(energy + self.restraint_forces()?.0)
+    }
+
+    // Return restraint energy and forces at current position.
+    fn restraint_forces(&self) -> Result<(f64, Vec<f64>)> {
+        let x = self.dynamics.position();
+        let mut force = vec![0.0; x.len()];
+        let energy = if self.restraints.is_empty() {
+            0.0
+        } else {
+            self.restraints.apply(self.nstep, x, &mut force)?
+        };
+        Ok((energy, force))
+    }
+
+    /// Return kinetic energy in eV.
+    pub fn kinetic_energy(&self) -> f64 {
+        let mv2: f64 = self
+            .velocities
+            .chunks(3)
+            .zip(&self.masses)
+            .map(|(v, m)| m * v.vecdot(v))
+            .sum();
+        0.5 * mv2 / ACCELERATION_UNIT
+    }
+
+    /// Return instantaneous pressure in GPa, including kinetic contribution.
+    /// The potential must provide stress, for periodic molecule.
+    pub fn pressure(&mut self) -> Result<f64> {
+        let volume = self.cell()?.determinant().abs();
+        let stress = self
+            .dynamics
+            .get_stress()?
+            .ok_or(format_err!("no stress for computing pressure"))?;
+        let virial = -(stress[0] + stress[1] + stress[2]) / 3.0;
+        let kinetic = 2.0 * self.kinetic_energy() / (3.0 * volume);
+        Ok((virial + kinetic) * EV_PER_A3_TO_GPA)
+    }
+
+    /// Return the full dynamical state for restarting later.
+    pub fn restart(&self) -> MdRestart {
+        let cell = self.cell().map_or(vec![], |cell| cell.as_slice().to_vec());
+        MdRestart {
+            positions: self.position().to_vec(),
+            velocities: self.velocities.clone(),
+            cell,
+            timestep: self.timestep,
+            nstep: self.nstep,
+            thermostat: self.thermostat,
+            barostat: self.barostat,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Restore the dynamical state from `restart`, continuing dynamics
+    /// exactly as it was saved. Other settings such as constraints and
+    /// trajectory writer are kept as is.
+    pub fn restore(&mut self, restart: &MdRestart) -> Result<()> {
+        ensure!(
+            restart.positions.len() == self.velocities.len() && restart.velocities.len() == self.velocities.len(),
+            "restart file does not match {} atoms",
+            self.masses.len()
+        );
+        if !restart.cell.is_empty() {
+            ensure!(restart.cell.len() == 9, "invalid cell in restart file");
+            let mol = self
+                .molecule
+                .as_ref()
+                .ok_or(format_err!("no molecule for restoring lattice"))?;
+            set_cell(&mut mol.borrow_mut(), &na::Matrix3::from_column_slice(&restart.cell));
+        }
+        self.dynamics.set_position(&restart.positions);
+        self.dynamics.force_invalidate();
+        self.velocities.copy_from_slice(&restart.velocities);
+        self.timestep = restart.timestep;
+        self.nstep = restart.nstep;
+        self.thermostat = restart.thermostat;
+        self.barostat = restart.barostat;
+        self.rng = restart.rng.clone();
+        Ok(())
+    }
+
+    /// Return thermodynamic observables at current step.
+    pub fn progress(&mut self) -> Result<MdProgress> {
+        let potential_energy = self.get_energy()?;
+        let kinetic_energy = self.kinetic_energy();
+        let has_cell = self.cell().is_ok();
+        let pressure = if has_cell && self.dynamics.get_stress()?.is_some() {
+            Some(self.pressure()?)
+        } else {
+            None
+        };
+        Ok(MdProgress {
+            nstep: self.nstep,
+            time: self.time(),
+            ncalls: self.dynamics.ncalls(),
+            potential_energy,
+            kinetic_energy,
+            total_energy: potential_energy + kinetic_energy,
+            temperature: self.temperature(),
+            pressure,
+        })
+    }
+
+    /// Return the molecule with current positions and lattice, if
+    /// constructed from a molecule.
+    pub fn molecule(&self) -> Option<Molecule> {
+        self.molecule.as_ref().map(|mol| {
+            let mut mol = mol.borrow().clone();
+            mol.update_positions(self.position().as_3d().iter().copied());
+            mol
+        })
+    }
+
+    // Return current lattice vectors as columns.
+    fn cell(&self) -> Result<na::Matrix3<f64>> {
+        let mol = self.molecule.as_ref().ok_or(format_err!("no molecule for lattice"))?;
+        let cell = cell_matrix(&mol.borrow()).ok_or(format_err!("no lattice in molecule"))?;
+        Ok(cell)
+    }
+
+    /// Return instantaneous temperature in K.
+    pub fn temperature(&self) -> f64 {
+        2.0 * self.kinetic_energy() / (self.ndof() as f64 * KB)
+    }
+
+    // The number of degrees of freedom, excluding freezing coords,
+    // constrained ones and removed net motions.
+    fn ndof(&self) -> usize {
+        let nfrozen = self.frozen.iter().filter(|&&x| x).count();
+        let mut n = (self.velocities.len() - nfrozen).saturating_sub(self.constraints.ndof_removed());
+        if self.translation_removal.is_some() && nfrozen == 0 {
+            n = n.saturating_sub(3);
+        }
+        if self.rotation_removal.is_some() && nfrozen == 0 {
+            // linear molecule has only two rotational degrees of freedom
+            let (_, inertia) = self.inertia();
+            let rank = inertia.singular_values().iter().filter(|&&s| s > INERTIA_EPS).count();
+            n = n.saturating_sub(rank);
+        }
+        n.max(1)
+    }
+
+    // Inverse masses of flattened coordinates, which are zero for freezing
+    // coords as if they were infinitely heavy.
+    fn inverse_masses(&self) -> Vec<f64> {
+        self.masses
+            .iter()
+            .flat_map(|m| [1.0 / m; 3])
+            .zip(&self.frozen)
+            .map(|(w, &frozen)| if frozen { 0.0 } else { w })
+            .collect()
+    }
+
+    // Zero velocities of freezing coords.
+    fn fix_frozen(&mut self) {
+        for (v, &frozen) in self.velocities.iter_mut().zip(&self.frozen) {
+            if frozen {
+                *v = 0.0;
+            }
+        }
+    }
+
+    /// Propagate `nsteps` steps.
+    pub fn propagate(&mut self, nsteps: usize) -> Result<()> {
+        for _ in 0..nsteps {
+            self.advance()?;
+        }
+        Ok(())
+    }
+
+    /// Return an iterator over `nsteps` steps of dynamics, with
+    /// thermodynamic observables in each step.
+    pub fn run_iter<'b>(&'b mut self, nsteps: usize) -> Box<dyn Iterator<Item = Result<MdProgress>> + 'b> {
+        let steps = (0..nsteps).map(move |_| {
+            self.advance()?;
+            self.progress()
+        });
+        Box::new(steps)
+    }
+
+    // Propagate one step, with trajectory and observer handled.
+    fn advance(&mut self) -> Result<()> {
+        let microcanonical = self.thermostat.is_none() && self.barostat.is_none();
+        if self.drift_check.is_some() && microcanonical && self.reference_energy.is_none() {
+            self.reference_energy = Some(self.get_energy()? + self.kinetic_energy());
+        }
+        self.step()?;
+        if microcanonical {
+            self.check_drift()?;
+        }
+        self.write_frame()?;
+        if self.observer.as_ref().map_or(false, |(n, _)| self.nstep % n == 0) {
+            let progress = self.progress()?;
+            if let Some((_, observe)) = self.observer.as_mut() {
+                observe(&progress);
+            }
+        }
+        Ok(())
+    }
+
+    // Check drift of total energy against tolerance.
+    fn check_drift(&mut self) -> Result<()> {
+        let (Some((tolerance, policy)), Some(e0)) = (self.drift_check, self.reference_energy) else {
+            return Ok(());
+        };
+        let energy = self.get_energy()? + self.kinetic_energy();
+        let drift = (energy - e0).abs() / self.masses.len() as f64;
+        if drift > tolerance {
+            let msg = format!(
+                "energy drift {drift:.3e} eV/atom exceeds {tolerance:.3e} at step {}: \
+                 too large timestep or inconsistent forces?",
+                self.nstep
+            );
+            match policy {
+                DriftPolicy::Abort => bail!(msg),
+                DriftPolicy::Warn if !self.drift_warned => {
+                    warn!("{msg}");
+                    self.drift_warned = true;
+                }
+                DriftPolicy::Warn => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Write current frame into trajectory if required in this step.
+    fn write_frame(&mut self) -> Result<()> {
+        if !self.writer.as_ref().map_or(false, |w| self.nstep % w.nevery == 0) {
+            return Ok(());
+        }
+        let progress = self.progress()?;
+        let force = self.dynamics.get_force()?.to_vec();
+        let cell = self.cell().ok();
+        if let Some(writer) = self.writer.as_mut() {
+            let frame = Frame {
+                progress: &progress,
+                positions: self.dynamics.position(),
+                velocities: &self.velocities,
+                forces: &force,
+                cell,
+            };
+            writer.write(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<()> {
+        if let (Some(thermostat), Some(schedule)) = (self.thermostat.as_mut(), self.schedule.as_ref()) {
+            thermostat.set_temperature(schedule.temperature(self.nstep));
+        }
+        match self.thermostat {
+            None => self.integrate()?,
+            Some(Thermostat::Langevin { temperature, friction }) => self.baoab_step(temperature, friction)?,
+            Some(Thermostat::Berendsen { temperature, tau }) => {
+                self.integrate()?;
+                self.berendsen_rescale(temperature, tau);
+            }
+            Some(Thermostat::Bussi { temperature, tau }) => {
+                self.integrate()?;
+                self.bussi_rescale(temperature, tau);
+            }
+        }
+        if let Some(Barostat::Berendsen {
+            pressure,
+            tau,
+            compressibility,
+        }) = self.barostat
+        {
+            self.berendsen_scale_cell(pressure, tau, compressibility)?;
+        }
+        self.nstep += 1;
+        // net motion is not conserved with freezing atoms anyway
+        let free = !self.frozen.contains(&true);
+        if free && self.translation_removal.map_or(false, |n| self.nstep % n == 0) {
+            self.zero_momentum();
+        }
+        if free && self.rotation_removal.map_or(false, |n| self.nstep % n == 0) {
+            self.zero_angular_momentum();
+        }
+        Ok(())
+    }
+
+    // Remove velocity of center of mass.
+    fn zero_momentum(&mut self) {
+        let mtot: f64 = self.masses.iter().sum();
+        let mut vcom = [0.0; 3];
+        for (v, m) in self.velocities.chunks(3).zip(&self.masses) {
+            vcom.vecadd(v, m / mtot);
+        }
+        for v in self.velocities.chunks_mut(3) {
+            v.vecadd(&vcom, -1.0);
+        }
+    }
+
+    // Remove angular velocity of rigid rotation around center of mass.
+    fn zero_angular_momentum(&mut self) {
+        let (com, inertia) = self.inertia();
+        let positions = self.dynamics.position();
+        // angular momentum around center of mass
+        let mut l = Vector3f::zeros();
+        for ((r, v), &m) in positions.chunks(3).zip(self.velocities.chunks(3)).zip(&self.masses) {
+            let d = Vector3f::new(r[0], r[1], r[2]) - com;
+            l += m * d.cross(&Vector3f::new(v[0], v[1], v[2]));
+        }
+        // pseudo inverse for linear molecule
+        let Ok(inv) = inertia.pseudo_inverse(INERTIA_EPS) else {
+            return;
+        };
+        let omega = inv * l;
+        for (r, v) in positions.chunks(3).zip(self.velocities.chunks_mut(3)) {
+            let d = Vector3f::new(r[0], r[1], r[2]) - com;
+            let w = omega.cross(&d);
+            v.vecadd(w.as_slice(), -1.0);
+        }
+    }
+
+    // Return center of mass and inertia tensor around it.
+    fn inertia(&self) -> (Vector3f, na::Matrix3<f64>) {
+        let mtot: f64 = self.masses.iter().sum();
+        let positions = self.dynamics.position();
+        let mut com = [0.0; 3];
+        for (r, m) in positions.chunks(3).zip(&self.masses) {
+            com.vecadd(r, m / mtot);
+        }
+        let com = Vector3f::from(com);
+        let mut inertia = na::Matrix3::zeros();
+        for (r, &m) in positions.chunks(3).zip(&self.masses) {
+            let d = Vector3f::new(r[0], r[1], r[2]) - com;
+            inertia += m * (na::Matrix3::identity() * d.norm_squared() - d * d.transpose());
+        }
+        (com, inertia)
+    }
+
+    // One step using the integrator.
+    fn integrate(&mut self) -> Result<()> {
+        let dt = self.timestep;
+        let mut integrator = self.integrator.take().expect("no integrator");
+        let result = integrator.step(self, dt);
+        self.integrator = Some(integrator);
+        result
+    }
+
+    // One step of Langevin dynamics using BAOAB splitting.
+    //
+    // Leimkuhler, B.; Matthews, C. Appl. Math. Res. Express 2013, 34.
+    fn baoab_step(&mut self, temperature: f64, friction: f64) -> Result<()> {
+        let dt = self.timestep;
+        self.kick(0.5 * dt)?;
+        self.drift(0.5 * dt)?;
+        // exact solution of Ornstein-Uhlenbeck process for velocities
+        let c1 = (-friction * dt).exp();
+        let c2 = (1.0 - c1 * c1).sqrt();
+        for (v, w) in self.velocities.iter_mut().zip(self.inverse_masses()) {
+            let sigma = (KB * temperature * ACCELERATION_UNIT * w).sqrt();
+            *v = c1 * *v + c2 * sigma * self.rng.normal();
+        }
+        self.constrain_velocities()?;
+        self.drift(0.5 * dt)?;
+        self.kick(0.5 * dt)
+    }
+
+    // Rescale velocities toward `temperature` with time constant `tau`.
+    //
+    // Berendsen, H. J. C. et al. J. Chem. Phys. 1984, 81, 3684.
+    fn berendsen_rescale(&mut self, temperature: f64, tau: f64) {
+        let t = self.temperature();
+        if t > 0.0 {
+            let lambda = (1.0 + self.timestep / tau * (temperature / t - 1.0)).max(0.0).sqrt();
+            self.velocities.iter_mut().for_each(|v| *v *= lambda);
+        }
+    }
+
+    // Rescale velocities by stochastic kinetic energy drawn from canonical
+    // distribution with time constant `tau`.
+    //
+    // Bussi, G.; Donadio, D.; Parrinello, M. J. Chem. Phys. 2007, 126, 014101.
+    fn bussi_rescale(&mut self, temperature: f64, tau: f64) {
+        let ke = self.kinetic_energy();
+        if ke <= 0.0 {
+            return;
+        }
+        let ndof = self.ndof();
+        let ke_target = 0.5 * ndof as f64 * KB * temperature;
+        let c = (-self.timestep / tau).exp();
+        let r1 = self.rng.normal();
+        let r2: f64 = (1..ndof).map(|_| self.rng.normal().powi(2)).sum();
+        let ke_new = ke * c
+            + ke_target / ndof as f64 * (1.0 - c) * (r1 * r1 + r2)
+            + 2.0 * r1 * (c * (1.0 - c) * ke * ke_target / ndof as f64).sqrt();
+        let mut alpha = (ke_new / ke).sqrt();
+        if r1 + (c * ndof as f64 * ke / ((1.0 - c) * ke_target)).sqrt() < 0.0 {
+            alpha = -alpha;
+        }
+        self.velocities.iter_mut().for_each(|v| *v *= alpha);
+    }
+
+    // Scale cell and positions isotropically toward `pressure`.
+    //
+    // Berendsen, H. J. C. et al. J. Chem. Phys. 1984, 81, 3684.
+    fn berendsen_scale_cell(&mut self, pressure: f64, tau: f64, compressibility: f64) -> Result<()> {
+        let p = self.pressure()?;
+        let mu = (1.0 - compressibility * self.timestep / tau * (pressure - p)).cbrt();
+        self.deform_cell(&(na::Matrix3::identity() * mu))
+    }
+
+    // Return time step in fs.
+    pub(crate) fn dt(&self) -> f64 {
+        self.timestep
+    }
+
+    // Deform cell and positions affinely using deformation gradient `f`.
+    pub(crate) fn deform_cell(&mut self, f: &na::Matrix3<f64>) -> Result<()> {
+        let cell = f * self.cell()?;
+        if let Some(mol) = &self.molecule {
+            set_cell(&mut mol.borrow_mut(), &cell);
+        }
+        let mut x = self.position().to_vec();
+        for (p, frozen) in x.as_mut_3d().iter_mut().zip(self.frozen.chunks(3)) {
+            let q: [f64; 3] = (f * Vector3f::from(*p)).into();
+            for k in 0..3 {
+                if !frozen[k] {
+                    p[k] = q[k];
+                }
+            }
+        }
+        self.dynamics.set_position(&x);
+        // the potential changed with lattice anyway
+        self.dynamics.force_invalidate();
+        Ok(())
+    }
+
+    /// Update position using current velocities in time `dt`, with the step
+    /// corrected by SHAKE under constraints.
+    pub fn drift(&mut self, dt: f64) -> Result<()> {
+        let mut x = self.dynamics.position().to_vec();
+        let mut step = self.velocities.iter().map(|v| v * dt).collect_vec();
+        if !self.constraints.is_empty() {
+            self.constraints.shake(&x, &mut step, &self.inverse_masses())?;
+            self.velocities = step.iter().map(|s| s / dt).collect();
+        }
+        x.vecadd(&step, 1.0);
+        self.dynamics.set_position(&x);
+        Ok(())
+    }
+
+    /// Update velocities using forces at current position in time `dt`,
+    /// with constrained components removed by RATTLE.
+    pub fn kick(&mut self, dt: f64) -> Result<()> {
+        let weights = self.inverse_masses();
+        if !self.restraints.is_empty() {
+            let (_, force) = self.restraint_forces()?;
+            accelerate(&mut self.velocities, &weights, &force, dt);
+        }
+        let force = self.dynamics.get_force()?;
+        accelerate(&mut self.velocities, &weights, force, dt);
+        self.constrain_velocities()
+    }
+
+    /// Update velocities using external `force` in time `dt`, such as forces
+    /// from another part of the potential, with constrained components
+    /// removed by RATTLE.
+    pub fn kick_by(&mut self, force: &[f64], dt: f64) -> Result<()> {
+        ensure!(force.len() == self.velocities.len(), "invalid size of force");
+        let weights = self.inverse_masses();
+        accelerate(&mut self.velocities, &weights, force, dt);
+        self.constrain_velocities()
+    }
+
+    // Remove velocity components violating constraints by RATTLE.
+    fn constrain_velocities(&mut self) -> Result<()> {
+        if !self.constraints.is_empty() {
+            let weights = self.inverse_masses();
+            let x = self.dynamics.position();
+            self.constraints.rattle(x, &mut self.velocities, &weights)?;
+        }
+        Ok(())
+    }
+}
+
+// Update `velocities` by `force` in time `dt`, with `weights` of inverse
+// masses over flattened coords.
+fn accelerate(velocities: &mut [f64], weights: &[f64], force: &[f64], dt: f64) {
+    for ((v, f), w) in velocities.iter_mut().zip(force).zip(weights) {
+        *v += f * dt * ACCELERATION_UNIT * w;
+    }
+}
+
+/// Full dynamical state of `MoleculeDynamics` for restarting, including
+/// positions, velocities, cell, step counter, thermostat/barostat settings,
+/// and state of random number generator. Floats are stored in bits so that
+/// restarted dynamics continues bit-for-bit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MdRestart {
+    #[serde(with = "bits")]
+    positions: Vec<f64>,
+    #[serde(with = "bits")]
+    velocities: Vec<f64>,
+    // lattice vectors as columns in flattened matrix, empty if not periodic
+    #[serde(with = "bits")]
+    cell: Vec<f64>,
+    timestep: f64,
+    nstep: usize,
+    thermostat: Option<Thermostat>,
+    barostat: Option<Barostat>,
+    rng: crate::random::Rng,
+}
+
+impl VersionedState for MdRestart {
+    const KIND: &'static str = "md-restart";
+    const VERSION: u32 = 1;
+
+    // untagged restart files share the same layout
+    fn migrate(version: u32, state: serde_json::Value) -> Result<serde_json::Value> {
+        ensure!(version == 0, "no migration path for MD restart from version {version}");
+        Ok(state)
+    }
+}
+
+impl MdRestart {
+    /// Load restart file from `file`.
+    pub fn load<P: AsRef<std::path::Path>>(file: P) -> Result<Self> {
+        let file = file.as_ref();
+        Self::load_from_file(file).with_context(|| format!("load MD restart file {file:?}"))
+    }
+
+    /// Save restart file into `file`.
+    pub fn save<P: AsRef<std::path::Path>>(&self, file: P) -> Result<()> {
+        self.save_to_file(file)
+    }
+
+    /// Return the number of steps propagated.
+    pub fn nstep(&self) -> usize {
+        self.nstep
+    }
+}
+
+// Serialize floats by their bits for exact restart.
+mod bits {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(x: &[f64], s: S) -> Result<S::Ok, S::Error> {
+        x.iter().map(|v| v.to_bits()).collect::<Vec<_>>().serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<f64>, D::Error> {
+        let bits = Vec::<u64>::deserialize(d)?;
+        Ok(bits.into_iter().map(f64::from_bits).collect())
+    }
+}
+
+/// A writer dumping MD trajectory in extended xyz format, with positions,
+/// velocities, forces, energies and cell of each frame. For long runs,
+/// frames can be rotated into a series of files.
+#[derive(Debug, Clone)]
+pub struct TrajectoryWriter {
+    path: PathBuf,
+    symbols: Vec<String>,
+    nevery: usize,
+    max_frames: Option<usize>,
+    nframes: usize,
+}
+
+// Data of one trajectory frame.
+struct Frame<'a> {
+    progress: &'a MdProgress,
+    positions: &'a [f64],
+    velocities: &'a [f64],
+    forces: &'a [f64],
+    cell: Option<na::Matrix3<f64>>,
+}
+
+impl TrajectoryWriter {
+    /// Write trajectory of atoms in molecule `mol` into file `path`, every
+    /// step by default.
+    pub fn new<P: AsRef<std::path::Path>>(path: P, mol: &Molecule) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            symbols: mol.symbols().map(|s| s.to_string()).collect(),
+            nevery: 1,
+            max_frames: None,
+            nframes: 0,
+        }
+    }
+
+    /// Write one frame every `nevery` steps.
+    pub fn every(mut self, nevery: usize) -> Self {
+        assert!(nevery > 0, "invalid interval: {nevery}");
+        self.nevery = nevery;
+        self
+    }
+
+    /// Start a new file after every `max_frames` frames. Files are named
+    /// with a counter after the stem of `path`, such as "md-0000.xyz",
+    /// "md-0001.xyz", ...
+    pub fn rotate(mut self, max_frames: usize) -> Self {
+        assert!(max_frames > 0, "invalid number of frames: {max_frames}");
+        self.max_frames = Some(max_frames);
+        self
+    }
+
+    /// Return the number of frames written.
+    pub fn nframes(&self) -> usize {
+        self.nframes
+    }
+
+    // Return the file for writing current frame.
+    fn current_file(&self) -> PathBuf {
+        match self.max_frames {
+            Some(n) => {
+                let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("md");
+                let ext = self.path.extension().and_then(|s| s.to_str()).unwrap_or("xyz");
+                self.path
+                    .with_file_name(format!("{stem}-{:04}.{ext}", self.nframes / n))
+            }
+            None => self.path.clone(),
+        }
+    }
+
+    fn write(&mut self, frame: &Frame) -> Result<()> {
+        use std::io::Write;
+
+        let natoms = self.symbols.len();
+        ensure!(
+            frame.positions.len() == 3 * natoms,
+            "trajectory frame does not match {natoms} atoms"
+        );
+        // truncate file at the first frame
+        let append = match self.max_frames {
+            Some(n) => self.nframes % n != 0,
+            None => self.nframes != 0,
+        };
+        let file = self.current_file();
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&file)
+            .with_context(|| format!("open trajectory file {file:?}"))?;
+        f.write_all(self.format(frame).as_bytes())?;
+        self.nframes += 1;
+        Ok(())
+    }
+
+    // Format one frame in extended xyz format.
+    fn format(&self, frame: &Frame) -> String {
+        let p = frame.progress;
+        let mut comment = format!(
+            "Properties=species:S:1:pos:R:3:vel:R:3:forces:R:3 \
+             energy={} kinetic_energy={} temperature={} step={} time={}",
+            p.potential_energy, p.kinetic_energy, p.temperature, p.nstep, p.time
+        );
+        if let Some(pressure) = p.pressure {
+            comment.push_str(&format!(" pressure={pressure}"));
+        }
+        if let Some(cell) = frame.cell {
+            let lattice = (0..3).flat_map(|i| (0..3).map(move |k| cell[(k, i)])).join(" ");
+            comment.push_str(&format!(" Lattice=\"{lattice}\" pbc=\"T T T\""));
+        }
+        let mut lines = vec![self.symbols.len().to_string(), comment];
+        let data = frame
+            .positions
+            .chunks(3)
+            .zip(frame.velocities.chunks(3))
+            .zip(frame.forces.chunks(3));
+        for (s, ((x, v), f)) in self.symbols.iter().zip(data) {
+            let values = x.iter().chain(v).chain(f).map(|a| format!("{a:-18.8}")).join(" ");
+            lines.push(format!("{s:3} {values}"));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+impl<'a, U> MoleculeDynamics<'a, U> {
+    /// Construct MD for `mol` using `model`, with atomic masses taken from
+    /// `mol`, i.e. the mass set on each atom or the standard atomic weight of
+    /// its element. Use `isotope` to override masses of selected atoms.
+    ///
+    /// For NPT dynamics `mol` should be periodic and `model` should provide
+    /// stress in `Output`.
+    pub fn from_model<M: OptimizeMolecule<U>>(model: &'a mut M, mol: Molecule) -> Result<Self> {
+        let masses = crate::constraint::atom_masses(&mol)?;
+        let position = mol.positions().flatten().collect_vec();
+        let molecule = Rc::new(RefCell::new(mol));
+        let potential = MoleculePotential {
+            model,
+            mol: molecule.clone(),
+        };
+        let dynamics = Dynamics::new(&position, potential);
+        let mut md = Self::new(dynamics, &masses);
+        md.frozen = Freezing::default().coords_mask(&molecule.borrow())?.frozen().to_vec();
+        md.molecule = Some(molecule);
+        Ok(md)
+    }
+}
+
+impl<'a> MoleculeDynamics<'a, ()> {
+    /// Construct MD for `mol` using chemical `model`, with atomic masses
+    /// taken from `mol` as in `from_model`.
+    pub fn from_chemical_model(model: &'a mut impl ChemicalModel, mol: Molecule) -> Result<Self> {
+        let masses = crate::constraint::atom_masses(&mol)?;
+        let position = mol.positions().flatten().collect_vec();
+        let molecule = Rc::new(RefCell::new(mol));
+        let mol = molecule.clone();
+        let dynamics = Dynamics::new(&position, move |x: &[f64], force: &mut [f64]| {
+            let mut mol = mol.borrow_mut();
+            mol.update_positions(x.as_3d().iter().copied());
+            let mp = model.compute(&mol)?;
+            let f = mp.get_forces().ok_or(format_err!("no forces"))?;
+            let e = mp.get_energy().ok_or(format_err!("no energy"))?;
+            force.copy_from_slice(f.as_flat());
+            Ok(e)
+        });
+        let mut md = Self::new(dynamics, &masses);
+        md.frozen = Freezing::default().coords_mask(&molecule.borrow())?.frozen().to_vec();
+        md.molecule = Some(molecule);
+        Ok(md)
+    }
+}
+
+// Potential of molecule evaluated by `model`, with stress.
+struct MoleculePotential<'a, M> {
+    model: &'a mut M,
+    mol: Rc<RefCell<Molecule>>,
+}
+
+impl<'a, U, M: OptimizeMolecule<U>> EvaluatePotential<U> for MoleculePotential<'a, M> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<U> {
+        let mut mol = self.mol.borrow_mut();
+        mol.update_positions(position.as_3d().iter().copied());
+        let mut out = Output::default();
+        let extra = self.model.evaluate(&mol, &mut out)?;
+        output.energy = out.energy.ok_or(format_err!("no energy"))?;
+        let forces = out.forces.ok_or(format_err!("no forces"))?;
+        output.force.copy_from_slice(forces.as_flat());
+        output.stress = out.stress;
+        Ok(extra)
+    }
+}
+// 625c84f3 ends here
@@ -0,0 +1,182 @@
+// [[file:../optim.note::162d6533][162d6533]]
+use super::*;
+
+use crate::redundant::{covalent_radius, BOND_FACTOR};
+use gchemol::Molecule;
+use std::collections::BTreeSet;
+// 162d6533 ends here
+
+// [[file:../optim.note::8f711276][8f711276]]
+/// A reaction event detected from changes of connectivity in a trajectory.
+/// Atoms are indexed from 0 in the order of positions.
+#[derive(Debug, Clone)]
+pub struct ReactionEvent {
+    /// The last step with connectivity of reactant.
+    pub step_reactant: usize,
+    /// The first step with connectivity of product.
+    pub step_product: usize,
+    /// Atoms involved in broken or formed bonds.
+    pub atoms: Vec<usize>,
+    /// Bonds broken in the event.
+    pub broken: Vec<(usize, usize)>,
+    /// Bonds formed in the event.
+    pub formed: Vec<(usize, usize)>,
+    /// The snapshot of reactant, as initial image for NEB.
+    pub reactant: Molecule,
+    /// The snapshot of product, as final image for NEB.
+    pub product: Molecule,
+}
+
+/// Return bonds in `mol` by covalent radii.
+fn connectivity(mol: &Molecule) -> BTreeSet<(usize, usize)> {
+    let radii = mol.atoms().map(|(_, a)| covalent_radius(a.number())).collect_vec();
+    let positions = mol.positions().collect_vec();
+    let rmax = radii.iter().copied().float_max();
+    if positions.len() < 2 {
+        return BTreeSet::new();
+    }
+    crate::sparse::neighbor_pairs(&positions, 2.0 * BOND_FACTOR * rmax)
+        .into_iter()
+        .filter(|&(i, j)| crate::internals::distance(positions[i], positions[j]) < BOND_FACTOR * (radii[i] + radii[j]))
+        .collect()
+}
+
+// A connectivity change waiting for confirmation
+#[derive(Debug, Clone)]
+struct Pending {
+    bonds: BTreeSet<(usize, usize)>,
+    step: usize,
+    product: Molecule,
+    count: usize,
+}
+
+/// Streaming detector of reaction events from connectivity changes in MD
+/// trajectories (non-periodic).
+///
+/// A change of connectivity is accepted only if it persists over a number of
+/// frames, which filters out transient bond vibrations across the bond
+/// threshold. Simultaneous changes on unrelated atoms are reported as
+/// separate events.
+#[derive(Debug, Clone)]
+pub struct ReactionDetector {
+    // the number of frames a new connectivity must persist
+    persistence: usize,
+    // current stable connectivity with its last frame
+    stable: Option<(BTreeSet<(usize, usize)>, usize, Molecule)>,
+    pending: Option<Pending>,
+}
+
+impl Default for ReactionDetector {
+    fn default() -> Self {
+        Self {
+            persistence: 5,
+            stable: None,
+            pending: None,
+        }
+    }
+}
+
+impl ReactionDetector {
+    /// Accept a connectivity change only if it persists over `n` frames.
+    pub fn with_persistence(mut self, n: usize) -> Self {
+        assert!(n > 0, "invalid number of frames: {n}");
+        self.persistence = n;
+        self
+    }
+
+    /// Feed the frame `mol` at `step`, and return reaction events confirmed
+    /// in this frame.
+    pub fn push(&mut self, step: usize, mol: &Molecule) -> Vec<ReactionEvent> {
+        let bonds = connectivity(mol);
+        if self.stable.is_none() {
+            self.stable = Some((bonds, step, mol.clone()));
+            return vec![];
+        }
+        let (stable, stable_step, reactant) = self.stable.as_mut().unwrap();
+        if &bonds == stable {
+            *stable_step = step;
+            *reactant = mol.clone();
+            self.pending = None;
+            return vec![];
+        }
+
+        match self.pending.as_mut() {
+            Some(pending) if pending.bonds == bonds => pending.count += 1,
+            _ => {
+                self.pending = Some(Pending {
+                    bonds,
+                    step,
+                    product: mol.clone(),
+                    count: 1,
+                })
+            }
+        }
+        let pending = self.pending.as_ref().unwrap();
+        if pending.count < self.persistence {
+            return vec![];
+        }
+
+        let pending = self.pending.take().unwrap();
+        let events = split_events(stable, &pending.bonds)
+            .into_iter()
+            .map(|(atoms, broken, formed)| ReactionEvent {
+                step_reactant: *stable_step,
+                step_product: pending.step,
+                atoms,
+                broken,
+                formed,
+                reactant: reactant.clone(),
+                product: pending.product.clone(),
+            })
+            .collect();
+        self.stable = Some((pending.bonds, step, mol.clone()));
+        events
+    }
+}
+
+/// Detect reaction events post hoc from stored `frames` in pairs of step and
+/// molecule.
+pub fn detect_reactions<'a>(
+    frames: impl IntoIterator<Item = (usize, &'a Molecule)>,
+    detector: ReactionDetector,
+) -> Vec<ReactionEvent> {
+    let mut detector = detector;
+    frames
+        .into_iter()
+        .flat_map(|(step, mol)| detector.push(step, mol))
+        .collect()
+}
+
+// Group changed bonds between connectivity `old` and `new` into events of
+// atoms connected by changed bonds.
+fn split_events(
+    old: &BTreeSet<(usize, usize)>,
+    new: &BTreeSet<(usize, usize)>,
+) -> Vec<(Vec<usize>, Vec<(usize, usize)>, Vec<(usize, usize)>)> {
+    let broken = old.difference(new).copied().collect_vec();
+    let formed = new.difference(old).copied().collect_vec();
+    let changed = broken.iter().chain(formed.iter()).copied().collect_vec();
+
+    // union atoms connected by changed bonds
+    let atoms: BTreeSet<usize> = changed.iter().flat_map(|&(i, j)| [i, j]).collect();
+    let mut group: std::collections::HashMap<usize, usize> = atoms.iter().map(|&i| (i, i)).collect();
+    for &(i, j) in changed.iter() {
+        let (gi, gj) = (group[&i], group[&j]);
+        group.values_mut().filter(|g| **g == gj).for_each(|g| *g = gi);
+    }
+
+    let in_group = |g: usize, (i, _): &(usize, usize)| group[i] == g;
+    group
+        .values()
+        .copied()
+        .unique()
+        .sorted()
+        .map(|g| {
+            let atoms = atoms.iter().copied().filter(|i| group[i] == g).collect();
+            let broken = broken.iter().copied().filter(|b| in_group(g, b)).collect();
+            let formed = formed.iter().copied().filter(|b| in_group(g, b)).collect();
+            (atoms, broken, formed)
+        })
+        .collect()
+}
+// 8f711276 ends here
@@ -0,0 +1,133 @@
+// [[file:../optim.note::37ce99db][37ce99db]]
+use super::*;
+
+use crate::potential::{EvaluatePotentialBatch, NumericalHessian};
+// 37ce99db ends here
+
+// [[file:../optim.note::504e33e4][504e33e4]]
+// Return unfrozen coordinates with positions displaced by `step` along them,
+// in pairs of forward and backward displacements.
+fn displace(position: &[f64], step: f64, frozen: Option<&[bool]>) -> (Vec<usize>, Vec<Vec<f64>>) {
+    let n = position.len();
+    if let Some(frozen) = frozen {
+        assert_eq!(frozen.len(), n, "invalid size of frozen mask");
+    }
+    let coords = (0..n).filter(|&i| !frozen.map_or(false, |m| m[i])).collect_vec();
+    let mut positions = vec![];
+    for &j in &coords {
+        for s in [step, -step] {
+            let mut displaced = position.to_vec();
+            displaced[j] += s;
+            positions.push(displaced);
+        }
+    }
+    (coords, positions)
+}
+
+// Return Hessian columns by central difference of forces at positions
+// displaced by `step` in pairs.
+fn columns(forces: &[Vec<f64>], step: f64) -> Vec<Vec<f64>> {
+    forces
+        .chunks(2)
+        .map(|f| f[0].iter().zip(&f[1]).map(|(p, m)| -(p - m) / (2.0 * step)).collect())
+        .collect()
+}
+
+impl NumericalHessian {
+    /// Compute Hessian at `position` as in `Dynamics::numerical_hessian`,
+    /// with displaced positions evaluated concurrently, one thread for each
+    /// of `potentials`, such as separate `Dynamics` instances or clones of a
+    /// thread-safe model. Coordinates set true in `frozen` mask if any are
+    /// skipped, with rows and columns filled with zeros.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut models = (0..8).map(|_| make_potential()).collect_vec();
+    /// let h = NumericalHessian::parallel(&mut models, &x, 1e-3, None)?;
+    /// ```
+    pub fn parallel<P, U>(potentials: &mut [P], position: &[f64], step: f64, frozen: Option<&[bool]>) -> Result<Self>
+    where
+        P: EvaluatePotential<U> + Send,
+    {
+        assert!(step > 0.0, "invalid displacement: {step}");
+        let n = position.len();
+        let (coords, positions) = displace(position, step, frozen);
+        let forces = evaluate_chunked(potentials, &positions, |potential, x| {
+            let mut out = PotentialOutput {
+                energy: std::f64::NAN,
+                force: vec![0.0; n],
+                stress: None,
+            };
+            potential.evaluate(x, &mut out)?;
+            Ok(out.force)
+        })?;
+        Ok(Self::from_columns(n, &coords, &columns(&forces, step), forces.len()))
+    }
+
+    /// Compute Hessian as in `parallel`, with all displaced positions
+    /// evaluated in one call of batch `potential`.
+    pub fn batch<B, U>(potential: &mut B, position: &[f64], step: f64, frozen: Option<&[bool]>) -> Result<Self>
+    where
+        B: EvaluatePotentialBatch<U>,
+    {
+        assert!(step > 0.0, "invalid displacement: {step}");
+        let n = position.len();
+        let (coords, positions) = displace(position, step, frozen);
+        let positions = positions.iter().map(|x| x.as_slice()).collect_vec();
+        let mut outputs = vec![
+            PotentialOutput {
+                energy: std::f64::NAN,
+                force: vec![0.0; n],
+                stress: None,
+            };
+            positions.len()
+        ];
+        potential.evaluate_batch(&positions, &mut outputs)?;
+        let forces = outputs.into_iter().map(|o| o.force).collect_vec();
+        Ok(Self::from_columns(n, &coords, &columns(&forces, step), forces.len()))
+    }
+}
+// 504e33e4 ends here
+
+// [[file:../optim.note::60ae13c7][60ae13c7]]
+/// Evaluate all `items` using `workers`, one thread for each worker, and
+/// return the results in the order of items. Items are distributed to
+/// threads in contiguous chunks, and evaluated serially in current thread
+/// if there is only one worker.
+pub(crate) fn evaluate_chunked<W, I, T>(
+    workers: &mut [W],
+    items: &[I],
+    eval: impl Fn(&mut W, &I) -> Result<T> + Sync,
+) -> Result<Vec<T>>
+where
+    W: Send,
+    I: Sync,
+    T: Send,
+{
+    ensure!(!workers.is_empty(), "no worker for evaluation");
+    let nthreads = workers.len();
+    if nthreads == 1 || items.len() < 2 {
+        let worker = &mut workers[0];
+        return items.iter().map(|x| eval(worker, x)).collect();
+    }
+
+    let chunk_size = (items.len() + nthreads - 1) / nthreads;
+    let eval = &eval;
+    std::thread::scope(|s| {
+        let handles = items
+            .chunks(chunk_size)
+            .zip(workers.iter_mut())
+            .map(|(chunk, worker)| {
+                s.spawn(move || -> Result<Vec<T>> { chunk.iter().map(|x| eval(worker, x)).collect() })
+            })
+            .collect_vec();
+        let mut computed = vec![];
+        for h in handles {
+            let part = h.join().map_err(|_| format_err!("thread for evaluation panicked"))?;
+            computed.extend(part?);
+        }
+        Ok(computed)
+    })
+}
+// 60ae13c7 ends here
@@ -0,0 +1,136 @@
+// [[file:../optim.note::a3b35402][a3b35402]]
+use super::*;
+
+use gchemol::Molecule;
+// a3b35402 ends here
+
+// [[file:../optim.note::51c335e3][51c335e3]]
+/// Rule for switching to the next model in `MultiFidelity`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Switchover {
+    /// Switch when max atomic force from the model is below this value.
+    Fmax(f64),
+    /// Switch after the model has been called this many times.
+    Iterations(usize),
+}
+
+struct Level<'a, U> {
+    name: String,
+    model: Box<dyn OptimizeMolecule<U> + 'a>,
+    switchover: Switchover,
+    stats: EvalStats,
+}
+
+/// An ordered list of models from fast to accurate, switched during one
+/// optimization run, e.g. pre-relaxing with a force field and finishing
+/// with DFT.
+///
+/// When the switchover rule of current model is met, the next model is
+/// evaluated at the same geometry, and its results are used from then on.
+/// The switchover rule of the last model is ignored. Max atomic force for
+/// `Switchover::Fmax` is computed over all atoms including frozen ones, and
+/// its value should be larger than the convergence criterion of optimizer,
+/// otherwise the optimization could finish before switching. L-BFGS in
+/// `Optimizer` is restarted after switching, discarding the history from
+/// previous model.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut model = MultiFidelity::default()
+///     .model("xtb", xtb, Switchover::Fmax(0.3))
+///     .model("dft", dft, Switchover::Fmax(0.0));
+/// let optimized = Optimizer::new(0.05, 200).optimize_geometry(&mut mol, &mut model)?;
+/// assert!(model.is_final());
+/// for (name, stats) in model.stats() {
+///     println!("{name}: {stats}");
+/// }
+/// ```
+pub struct MultiFidelity<'a, U> {
+    levels: Vec<Level<'a, U>>,
+    current: usize,
+    // switched since last query by optimizer
+    switched: bool,
+}
+
+impl<'a, U> Default for MultiFidelity<'a, U> {
+    fn default() -> Self {
+        Self {
+            levels: vec![],
+            current: 0,
+            switched: false,
+        }
+    }
+}
+
+impl<'a, U> MultiFidelity<'a, U> {
+    /// Append `model` named as `name`, which will be switched to the next
+    /// one using `switchover` rule.
+    pub fn model(mut self, name: &str, model: impl OptimizeMolecule<U> + 'a, switchover: Switchover) -> Self {
+        self.levels.push(Level {
+            name: name.into(),
+            model: Box::new(model),
+            switchover,
+            stats: EvalStats::default(),
+        });
+        self
+    }
+
+    /// The name of model in use.
+    pub fn current(&self) -> &str {
+        &self.levels[self.current].name
+    }
+
+    /// Return true if the last (the most accurate) model is in use.
+    pub fn is_final(&self) -> bool {
+        self.current + 1 >= self.levels.len()
+    }
+
+    /// Call statistics of each model, in the order of models added.
+    pub fn stats(&self) -> impl Iterator<Item = (&str, &EvalStats)> {
+        self.levels.iter().map(|l| (l.name.as_str(), &l.stats))
+    }
+
+    /// Start over from the first model, clearing call statistics.
+    pub fn reset(&mut self) {
+        self.current = 0;
+        self.switched = false;
+        self.levels.iter_mut().for_each(|l| l.stats = EvalStats::default());
+    }
+}
+
+impl<'a, U> OptimizeMolecule<U> for MultiFidelity<'a, U> {
+    fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<U> {
+        ensure!(!self.levels.is_empty(), "no model for multi-fidelity evaluation");
+        let is_final = self.is_final();
+        let level = &mut self.levels[self.current];
+        let start = std::time::Instant::now();
+        let extra = level.model.evaluate(mol, out);
+        level.stats.record(start.elapsed());
+        let extra = extra.with_context(|| format!("failed to evaluate model {}", level.name))?;
+        if is_final {
+            return Ok(extra);
+        }
+        let switch = match level.switchover {
+            Switchover::Fmax(fmax) => {
+                let forces = out.forces.as_ref().ok_or(format_err!("no forces"))?;
+                forces.iter().map(|f| f.vec2norm()).fold(0.0, f64::max) < fmax
+            }
+            Switchover::Iterations(n) => level.stats.ncalls >= n,
+        };
+        if !switch {
+            return Ok(extra);
+        }
+        let ncalls = level.stats.ncalls;
+        self.current += 1;
+        self.switched = true;
+        info!("switch to model {} after {ncalls} calls", self.current());
+        self.evaluate(mol, out)
+    }
+
+    /// Switching to next model changes potential energy surface.
+    fn surface_changed(&mut self) -> bool {
+        std::mem::take(&mut self.switched)
+    }
+}
+// 51c335e3 ends here
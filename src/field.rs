@@ -0,0 +1,98 @@
+// [[file:../optim.note::7fff02b4][7fff02b4]]
+use super::*;
+
+use gchemol::Molecule;
+// 7fff02b4 ends here
+
+// [[file:../optim.note::21c1ea29][21c1ea29]]
+/// Coulomb constant e^2/(4πε0) in eV·Å
+const COULOMB: f64 = 14.399645;
+
+/// A potential term coupling fixed atomic charges to a uniform electric
+/// field and/or external point charges, for field dependent geometry
+/// relaxation. To be composed with the main model using
+/// `CompositePotential`.
+///
+/// Charges are in e, field in V/Å, and positions in Å, giving energy in eV.
+///
+/// # Examples
+///
+/// ```ignore
+/// let field = ExternalField::from_molecule(&mol).uniform([0.0, 0.0, 0.1]);
+/// let potential = CompositePotential::default()
+///     .model("dft", &mut dft, mol.clone())
+///     .term("field", field);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExternalField {
+    charges: Vec<f64>,
+    field: [f64; 3],
+    // external point charges with positions
+    point_charges: Vec<(f64, [f64; 3])>,
+}
+
+impl ExternalField {
+    /// Couple `charges` of atoms to external field, which is zero by
+    /// default.
+    pub fn new(charges: &[f64]) -> Self {
+        Self {
+            charges: charges.to_vec(),
+            field: [0.0; 3],
+            point_charges: vec![],
+        }
+    }
+
+    /// Couple partial charges of atoms in `mol` to external field. Atoms
+    /// without partial charge are treated as neutral.
+    pub fn from_molecule(mol: &Molecule) -> Self {
+        let charges = mol
+            .atoms()
+            .map(|(_, a)| a.get_partial_charge().unwrap_or(0.0))
+            .collect_vec();
+        Self::new(&charges)
+    }
+
+    /// Apply a uniform electric `field` in V/Å.
+    pub fn uniform(mut self, field: [f64; 3]) -> Self {
+        self.field = field;
+        self
+    }
+
+    /// Add an external point charge `q` in e at fixed `position`.
+    pub fn point_charge(mut self, q: f64, position: [f64; 3]) -> Self {
+        self.point_charges.push((q, position));
+        self
+    }
+}
+
+impl EvaluatePotential<()> for ExternalField {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        let positions = position.as_3d();
+        ensure!(
+            positions.len() == self.charges.len(),
+            "expect {} atoms for external field, but found {}",
+            self.charges.len(),
+            positions.len()
+        );
+        let forces = output.force.as_mut_3d();
+        let mut energy = 0.0;
+        for (i, (&q, r)) in self.charges.iter().zip(positions).enumerate() {
+            // E = -q field . r
+            energy -= q * r.vecdot(&self.field);
+            forces[i] = [q * self.field[0], q * self.field[1], q * self.field[2]];
+            for &(qj, rj) in self.point_charges.iter() {
+                let d = [r[0] - rj[0], r[1] - rj[1], r[2] - rj[2]];
+                let dist = d.vec2norm();
+                ensure!(dist > 1e-6, "atom {i} overlaps with external point charge");
+                let e = COULOMB * q * qj / dist;
+                energy += e;
+                for k in 0..3 {
+                    forces[i][k] += e * d[k] / (dist * dist);
+                }
+            }
+        }
+        output.energy = energy;
+        Ok(())
+    }
+}
+// 21c1ea29 ends here
@@ -0,0 +1,119 @@
+// [[file:../optim.note::45125e4b][45125e4b]]
+use super::*;
+
+use gchemol::Molecule;
+// 45125e4b ends here
+
+// [[file:../optim.note::bc1dec6d][bc1dec6d]]
+/// Per-run selection of freezing atoms or coordinates, in addition to the
+/// freezing flags already set on the `Molecule`. The molecule itself will not
+/// be mutated.
+#[derive(Debug, Clone, Default)]
+pub struct Freezing {
+    // atom serial numbers, counting from 1
+    serials: Vec<usize>,
+    // masks over flattened coordinates, true for freezing
+    coords: Vec<Vec<bool>>,
+}
+
+impl Freezing {
+    /// Freeze atoms with serial numbers in `serials`, as numbered in the
+    /// molecule (counting from 1), unlike the 0-based atom indices used in
+    /// `Constraint`.
+    pub fn freeze_atoms(mut self, serials: &[usize]) -> Self {
+        self.serials.extend_from_slice(serials);
+        self
+    }
+
+    /// Freeze coordinates using `mask` over flattened coordinates of all atoms
+    /// (x1, y1, z1, x2, ...). `true` for freezing the coordinate. Masks in
+    /// repeated calls are merged, as atoms in `freeze_atoms`.
+    pub fn freeze_coords(mut self, mask: &[bool]) -> Self {
+        self.coords.push(mask.to_vec());
+        self
+    }
+
+    /// Return true if no extra atoms or coords selected.
+    pub fn is_empty(&self) -> bool {
+        self.serials.is_empty() && !self.coords.iter().flatten().any(|&x| x)
+    }
+
+    /// Return the mask over flattened coordinates of `mol`, merging freezing
+    /// flags set on atoms with this selection. Return error when selected
+    /// atoms do not exist in `mol`, or a coords mask has a wrong size.
+    pub(crate) fn coords_mask(&self, mol: &Molecule) -> Result<CoordsMask> {
+        let numbers = mol.numbers().collect_vec();
+        let mut frozen = mol.atoms().flat_map(|(_, a)| a.freezing()).collect_vec();
+        for n in self.serials.iter() {
+            let i = numbers
+                .iter()
+                .position(|m| m == n)
+                .ok_or_else(|| format_err!("invalid atom for freezing: {n}"))?;
+            frozen[3 * i..3 * i + 3].fill(true);
+        }
+        for mask in self.coords.iter() {
+            ensure!(
+                mask.len() == frozen.len(),
+                "invalid size of coords mask: {} != {}",
+                mask.len(),
+                frozen.len()
+            );
+            for (x, &c) in frozen.iter_mut().zip(mask) {
+                *x |= c;
+            }
+        }
+        Ok(CoordsMask { frozen })
+    }
+}
+
+/// Mask over flattened coordinates for removing freezing coords.
+#[derive(Debug, Clone)]
+pub(crate) struct CoordsMask {
+    // true for freezing coordinate
+    frozen: Vec<bool>,
+}
+
+impl CoordsMask {
+    /// Return freezing flags of all coords.
+    pub fn frozen(&self) -> &[bool] {
+        &self.frozen
+    }
+
+    /// Freeze all coords of atoms indexed from 0 in `atoms`.
+    pub fn with_atoms(mut self, atoms: &[usize]) -> Self {
+        for &i in atoms {
+            self.frozen[3 * i..3 * i + 3].fill(true);
+        }
+        self
+    }
+
+    /// Return the values for coords not frozen.
+    pub fn apply<T: Copy>(&self, values: &[T]) -> Vec<T> {
+        assert_eq!(values.len(), self.frozen.len(), "invalid size of values");
+        values
+            .iter()
+            .zip(self.frozen.iter())
+            .filter_map(|(&v, &frozen)| if frozen { None } else { Some(v) })
+            .collect()
+    }
+
+    /// Recover values for all coords from `values` of coords not frozen, with
+    /// freezing coords filled with `fill`.
+    pub fn unmask<T: Copy>(&self, values: &[T], fill: T) -> Vec<T> {
+        let mut values = values.iter().copied();
+        let all = self
+            .frozen
+            .iter()
+            .map(|&frozen| {
+                if frozen {
+                    fill
+                } else {
+                    values.next().expect("invalid size of values")
+                }
+            })
+            .collect();
+        assert!(values.next().is_none(), "invalid size of values");
+        all
+    }
+}
+// bc1dec6d ends here
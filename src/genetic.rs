@@ -0,0 +1,357 @@
+// [[file:../optim.note::c8a2a0d2][c8a2a0d2]]
+use super::*;
+
+use crate::hopping::Minimum;
+use crate::minima::{fingerprint, fingerprint_difference};
+use crate::random::Rng;
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+use vecfx::nalgebra as na;
+// c8a2a0d2 ends here
+
+// [[file:../optim.note::f210508d][f210508d]]
+// Max number of trials for breeding a sensible offspring.
+const MAX_BREED_ATTEMPTS: usize = 100;
+
+/// Final result of `GeneticSearch`.
+#[derive(Debug, Clone)]
+pub struct GeneticSearched {
+    /// Final population in ascending order of energy, with the number of
+    /// times each structure was found in `nvisits`.
+    pub population: Vec<Minimum>,
+    /// The number of generations evolved.
+    pub ngenerations: usize,
+    /// The number of structures relaxed, including failed ones.
+    pub nrelaxed: usize,
+}
+
+impl GeneticSearched {
+    /// Return the lowest structure found.
+    pub fn best(&self) -> Option<&Minimum> {
+        self.population.first()
+    }
+}
+
+/// Genetic algorithm (GA) for cluster and surface structure search.
+///
+/// The initial population is relaxed from random structures generated by
+/// `RandomSearch`, using its optimizer and min distance between atoms. In each
+/// generation offspring are bred from parents selected by tournament, using
+/// cut-and-splice crossover, or mutations of rattle, permute or twist. Each
+/// offspring is relaxed locally, and replaces a similar structure in
+/// population (niching) only if lower in energy. Structures are similar if
+/// close in energy and in sorted interatomic distances. Atoms with freezing
+/// flags in template, such as slab atoms, are never moved.
+///
+/// # Examples
+///
+/// ```ignore
+/// let search = RandomSearch::new(Optimizer::new(0.01, 500), &template).min_distance(2.0);
+/// let ga = GeneticSearch::new(search).population(20).generations(50);
+/// let searched = ga.run(&mut model)?;
+/// println!("lowest energy: {:?}", searched.best().map(|m| m.energy));
+/// ```
+///
+/// # References
+///
+/// - Deaven, D. M.; Ho, K. M. Phys. Rev. Lett. 1995, 75, 288.
+/// - Johnston, R. L. Dalton Trans. 2003, 4193.
+pub struct GeneticSearch {
+    search: RandomSearch,
+    population: usize,
+    generations: usize,
+    mutation_rate: f64,
+    rattle_amplitude: f64,
+    similarity: f64,
+    energy_tolerance: f64,
+    seed: u64,
+}
+
+impl GeneticSearch {
+    /// Evolve structures generated and relaxed using `search`.
+    pub fn new(search: RandomSearch) -> Self {
+        Self {
+            search,
+            population: 10,
+            generations: 20,
+            mutation_rate: 0.2,
+            rattle_amplitude: 0.3,
+            similarity: 0.02,
+            energy_tolerance: 1e-3,
+            seed: 0,
+        }
+    }
+
+    /// Set the size of population, which is also the number of offspring in
+    /// each generation.
+    pub fn population(mut self, n: usize) -> Self {
+        assert!(n > 1, "invalid population size: {n}");
+        self.population = n;
+        self
+    }
+
+    /// Set the number of generations to evolve.
+    pub fn generations(mut self, n: usize) -> Self {
+        self.generations = n;
+        self
+    }
+
+    /// Breed offspring by mutation instead of crossover with probability
+    /// `rate`.
+    pub fn mutation_rate(mut self, rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&rate), "invalid mutation rate: {rate}");
+        self.mutation_rate = rate;
+        self
+    }
+
+    /// Displace atoms by at most `amplitude` in Å in each direction in rattle
+    /// mutation.
+    pub fn rattle_amplitude(mut self, amplitude: f64) -> Self {
+        assert!(amplitude > 0.0, "invalid amplitude: {amplitude}");
+        self.rattle_amplitude = amplitude;
+        self
+    }
+
+    /// Treat structures as similar if relative difference of their sorted
+    /// interatomic distances is below `threshold`, and their energy difference
+    /// is below `tolerance` in eV.
+    pub fn similarity(mut self, threshold: f64, tolerance: f64) -> Self {
+        assert!(threshold > 0.0, "invalid similarity threshold: {threshold}");
+        assert!(tolerance > 0.0, "invalid energy tolerance: {tolerance}");
+        self.similarity = threshold;
+        self.energy_tolerance = tolerance;
+        self
+    }
+
+    /// Set `seed` for random numbers in selection and breeding.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Evolve population in potential provided by `model`.
+    pub fn run<M: ChemicalModel>(&self, model: &mut M) -> Result<GeneticSearched> {
+        let mut rng = Rng::new(self.seed);
+        let mut population: Vec<Minimum> = vec![];
+        let mut nrelaxed = 0;
+        // initial population, which could be smaller if structures are too
+        // similar
+        for i in 0..2 * self.population {
+            if population.len() == self.population {
+                break;
+            }
+            let mol = self.search.random_structure(i)?;
+            nrelaxed += 1;
+            if let Some(relaxed) = self.relax(mol, model) {
+                self.niche(&mut population, relaxed);
+            }
+        }
+        ensure!(!population.is_empty(), "no structure relaxed for initial population");
+
+        for g in 0..self.generations {
+            for _ in 0..self.population {
+                let Some(child) = self.breed(&population, &mut rng) else {
+                    continue;
+                };
+                nrelaxed += 1;
+                if let Some(relaxed) = self.relax(child, model) {
+                    self.niche(&mut population, relaxed);
+                }
+            }
+            population.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+            population.truncate(self.population);
+            info!("generation {}: lowest E = {:-16.6}", g + 1, population[0].energy);
+        }
+        population.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+
+        Ok(GeneticSearched {
+            population,
+            ngenerations: self.generations,
+            nrelaxed,
+        })
+    }
+
+    // Relax `mol` locally, returning None if not converged.
+    fn relax<M: ChemicalModel>(&self, mut mol: Molecule, model: &mut M) -> Option<Minimum> {
+        let optimizer = self.search.optimizer();
+        let mut last = None;
+        let steps = optimizer
+            .optimize_geometry_iter(&mut mol, model)
+            .map_err(|e| warn!("failed to relax structure: {e:?}"))
+            .ok()?;
+        for progress in steps.take(optimizer.nmax()) {
+            let converged = progress.fmax < optimizer.fmax();
+            last = Some(progress);
+            if converged {
+                break;
+            }
+        }
+        let progress = last?;
+        if progress.fmax >= optimizer.fmax() {
+            warn!("offspring not converged in relaxation: fmax = {}", progress.fmax);
+            return None;
+        }
+        let molecule = progress.extra.get_molecule()?.clone();
+        Some(Minimum {
+            energy: progress.energy,
+            molecule,
+            nvisits: 1,
+        })
+    }
+
+    // Add `new` into `population` unless it is similar to any member, which
+    // is replaced if higher in energy.
+    fn niche(&self, population: &mut Vec<Minimum>, new: Minimum) {
+        let fp = fingerprint(&new.molecule);
+        let similar = population.iter_mut().find(|m| {
+            (m.energy - new.energy).abs() < self.energy_tolerance
+                && fingerprint_difference(&fingerprint(&m.molecule), &fp) < self.similarity
+        });
+        match similar {
+            Some(m) => {
+                m.nvisits += 1;
+                if new.energy < m.energy {
+                    m.energy = new.energy;
+                    m.molecule = new.molecule;
+                }
+            }
+            None => population.push(new),
+        }
+    }
+
+    // Breed an offspring from `population` with sensible distances between
+    // atoms.
+    fn breed(&self, population: &[Minimum], rng: &mut Rng) -> Option<Molecule> {
+        let template = self.search.template();
+        let numbers = template.atoms().map(|(_, a)| a.number()).collect_vec();
+        let mobile = template
+            .atoms()
+            .enumerate()
+            .filter_map(|(i, (_, a))| (!a.freezing().contains(&true)).then_some(i))
+            .collect_vec();
+        for _ in 0..MAX_BREED_ATTEMPTS {
+            let a = tournament(population, rng);
+            let pa = population[a].molecule.positions().collect_vec();
+            let positions = if rng.uniform() < self.mutation_rate {
+                match rng.index(3) {
+                    0 => self.rattle(pa, &mobile, rng),
+                    1 => match permute(pa.clone(), &mobile, &numbers, rng) {
+                        Some(positions) => positions,
+                        None => self.rattle(pa, &mobile, rng),
+                    },
+                    _ => twist(pa, &mobile, rng),
+                }
+            } else {
+                let b = tournament(population, rng);
+                let pb = population[b].molecule.positions().collect_vec();
+                cut_and_splice(&pa, &pb, &mobile, &numbers, rng)
+            };
+            let mut mol = template.clone();
+            mol.update_positions(positions);
+            if self.search.is_sensible(&mol) {
+                return Some(mol);
+            }
+        }
+        warn!("failed to breed sensible offspring after {MAX_BREED_ATTEMPTS} attempts");
+        None
+    }
+
+    // Displace `mobile` atoms randomly.
+    fn rattle(&self, mut positions: Vec<[f64; 3]>, mobile: &[usize], rng: &mut Rng) -> Vec<[f64; 3]> {
+        for &i in mobile {
+            for x in positions[i].iter_mut() {
+                *x += self.rattle_amplitude * (2.0 * rng.uniform() - 1.0);
+            }
+        }
+        positions
+    }
+}
+
+// Select the lower one of two random members in `population`.
+fn tournament(population: &[Minimum], rng: &mut Rng) -> usize {
+    let (i, j) = (rng.index(population.len()), rng.index(population.len()));
+    if population[i].energy <= population[j].energy {
+        i
+    } else {
+        j
+    }
+}
+
+// Geometric center of `atoms` in `positions`.
+fn center(positions: &[[f64; 3]], atoms: &[usize]) -> na::Vector3<f64> {
+    let sum: na::Vector3<f64> = atoms.iter().map(|&i| na::Vector3::from(positions[i])).sum();
+    sum / atoms.len().max(1) as f64
+}
+
+// Cut-and-splice crossover: combine `mobile` atoms of parent `pa` above a
+// random plane through its center with those of parent `pb` below the plane,
+// keeping the number of atoms of each element.
+fn cut_and_splice(
+    pa: &[[f64; 3]],
+    pb: &[[f64; 3]],
+    mobile: &[usize],
+    numbers: &[usize],
+    rng: &mut Rng,
+) -> Vec<[f64; 3]> {
+    let normal = na::Vector3::from_column_slice(&rng.unit_vector(3));
+    let (ca, cb) = (center(pa, mobile), center(pb, mobile));
+    let height = |p: [f64; 3], c: &na::Vector3<f64>| (na::Vector3::from(p) - c).dot(&normal);
+    let mut positions = pa.to_vec();
+    for z in mobile.iter().map(|&i| numbers[i]).unique().collect_vec() {
+        let atoms = mobile.iter().copied().filter(|&i| numbers[i] == z).collect_vec();
+        let nabove = atoms.iter().filter(|&&i| height(pa[i], &ca) > 0.0).count();
+        // the highest atoms of parent a, and the lowest ones of parent b
+        // moved onto the center of parent a
+        let from_a = atoms
+            .iter()
+            .map(|&i| pa[i])
+            .sorted_by(|p, q| height(*q, &ca).total_cmp(&height(*p, &ca)))
+            .take(nabove);
+        let from_b = atoms
+            .iter()
+            .map(|&i| pb[i])
+            .sorted_by(|p, q| height(*p, &cb).total_cmp(&height(*q, &cb)))
+            .take(atoms.len() - nabove)
+            .map(|p| (na::Vector3::from(p) - cb + ca).into());
+        for (&i, p) in atoms.iter().zip(from_a.chain(from_b)) {
+            positions[i] = p;
+        }
+    }
+    positions
+}
+
+// Swap positions of random pairs of `mobile` atoms in different elements.
+// Return None if all mobile atoms are the same element.
+fn permute(mut positions: Vec<[f64; 3]>, mobile: &[usize], numbers: &[usize], rng: &mut Rng) -> Option<Vec<[f64; 3]>> {
+    let pairs = mobile
+        .iter()
+        .copied()
+        .tuple_combinations()
+        .filter(|&(i, j)| numbers[i] != numbers[j])
+        .collect_vec();
+    if pairs.is_empty() {
+        return None;
+    }
+    for _ in 0..(mobile.len() / 5).max(1) {
+        let (i, j) = pairs[rng.index(pairs.len())];
+        positions.swap(i, j);
+    }
+    Some(positions)
+}
+
+// Rotate `mobile` atoms above a random plane through their center by a
+// random angle around the normal of the plane.
+fn twist(mut positions: Vec<[f64; 3]>, mobile: &[usize], rng: &mut Rng) -> Vec<[f64; 3]> {
+    let normal = na::Vector3::from_column_slice(&rng.unit_vector(3));
+    let c = center(&positions, mobile);
+    let angle = 2.0 * std::f64::consts::PI * rng.uniform();
+    let rotation = na::Rotation3::from_axis_angle(&na::Unit::new_normalize(normal), angle);
+    for &i in mobile {
+        let d = na::Vector3::from(positions[i]) - c;
+        if d.dot(&normal) > 0.0 {
+            positions[i] = (rotation * d + c).into();
+        }
+    }
+    positions
+}
+// f210508d ends here
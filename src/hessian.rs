@@ -0,0 +1,195 @@
+// [[file:../optim.note::067f1e60][067f1e60]]
+use super::*;
+
+use crate::internals::{bend_b, stretch_b, torsion_b};
+use crate::sparse::SparseHessian;
+use gchemol::Molecule;
+// 067f1e60 ends here
+
+// [[file:../optim.note::1bfe6be0][1bfe6be0]]
+// Bohr radius in Angstrom
+const BOHR: f64 = 0.52917721067;
+// Hartree in eV
+const HARTREE: f64 = 27.21138602;
+
+// Force constants for stretch, bend and torsion in Lindh model
+const K_R: f64 = 0.45;
+const K_PHI: f64 = 0.15;
+const K_TAU: f64 = 0.005;
+
+// Pairs with weight below this will be ignored in Lindh model
+const RHO_THRESHOLD: f64 = 1e-3;
+
+// Lindh parameters (in atomic units) indexed by periodic table rows
+const ALPHA: [[f64; 3]; 3] = [
+    [1.0000, 0.3949, 0.3949],
+    [0.3949, 0.2800, 0.2800],
+    [0.3949, 0.2800, 0.2800],
+];
+const R_REF: [[f64; 3]; 3] = [[1.35, 2.10, 2.53], [2.10, 2.87, 3.40], [2.53, 3.40, 3.40]];
+
+fn lindh_row(z: usize) -> usize {
+    match z {
+        0..=2 => 0,
+        3..=10 => 1,
+        _ => 2,
+    }
+}
+// 1bfe6be0 ends here
+
+// [[file:../optim.note::7007ad12][7007ad12]]
+// add k b b^T for an internal coordinate involving `atoms` into Hessian
+// entries by calling `add`, with `b` for its Wilson B-matrix row
+fn add_outer(add: &mut impl FnMut(usize, usize, f64), k: f64, atoms: &[usize], b: &[Vector3f]) {
+    for (&i, bi) in atoms.iter().zip(b) {
+        for (&j, bj) in atoms.iter().zip(b) {
+            for p in 0..3 {
+                for q in 0..3 {
+                    add(3 * i + p, 3 * j + q, k * bi[p] * bj[q]);
+                }
+            }
+        }
+    }
+}
+
+// The distance (in Bohr) beyond which all pair weights fall below
+// `RHO_THRESHOLD`.
+fn lindh_cutoff() -> f64 {
+    let mut r2max = 0.0f64;
+    for (alpha, r_ref) in ALPHA.iter().flatten().zip(R_REF.iter().flatten()) {
+        r2max = r2max.max(r_ref * r_ref - RHO_THRESHOLD.ln() / alpha);
+    }
+    r2max.sqrt()
+}
+
+// Assemble Lindh model Hessian of `mol` into entries by calling `add` with
+// (row, col, value) in eV/Å^2. Neighbors are searched using cell lists.
+fn assemble_lindh(mol: &Molecule, mut add: impl FnMut(usize, usize, f64)) {
+    let rows = mol.atoms().map(|(_, a)| lindh_row(a.number())).collect_vec();
+    // in atomic units
+    let positions = mol.positions().map(|p| p.map(|x| x / BOHR)).collect_vec();
+    let coords = positions.iter().map(|&p| Vector3f::from(p)).collect_vec();
+    let n = coords.len();
+
+    // pairwise weights
+    let mut rho = std::collections::HashMap::new();
+    let mut neighbors = vec![vec![]; n];
+    for (i, j) in crate::sparse::neighbor_pairs(&positions, lindh_cutoff()) {
+        let (ri, rj) = (rows[i], rows[j]);
+        let r2 = (coords[i] - coords[j]).norm_squared();
+        let w = (ALPHA[ri][rj] * (R_REF[ri][rj].powi(2) - r2)).exp();
+        if w > RHO_THRESHOLD {
+            rho.insert((i, j), w);
+            rho.insert((j, i), w);
+            neighbors[i].push(j);
+            neighbors[j].push(i);
+        }
+    }
+    let rho = |i: usize, j: usize| rho[&(i, j)];
+
+    // convert from Hartree/Bohr^2 to eV/Å^2
+    let factor = HARTREE / BOHR.powi(2);
+    let mut add = |i: usize, j: usize, v: f64| add(i, j, v * factor);
+    // stretches
+    for i in 0..n {
+        for &j in neighbors[i].iter().filter(|&&j| j < i) {
+            let b = stretch_b(&coords[i], &coords[j]);
+            add_outer(&mut add, K_R * rho(i, j), &[i, j], &b);
+        }
+    }
+    // bends with `j` as the center atom
+    for j in 0..n {
+        for (&i, &k) in neighbors[j].iter().tuple_combinations() {
+            let k_phi = K_PHI * rho(i, j) * rho(j, k);
+            if let Some(b) = bend_b(&coords[i], &coords[j], &coords[k]) {
+                add_outer(&mut add, k_phi, &[i, j, k], &b);
+            }
+        }
+    }
+    // torsions around `j-k` bond
+    for j in 0..n {
+        for &k in neighbors[j].iter().filter(|&&k| k > j) {
+            for &i in neighbors[j].iter().filter(|&&i| i != k) {
+                for &l in neighbors[k].iter().filter(|&&l| l != j && l != i) {
+                    let k_tau = K_TAU * rho(i, j) * rho(j, k) * rho(k, l);
+                    if let Some(b) = torsion_b(&coords[i], &coords[j], &coords[k], &coords[l]) {
+                        add_outer(&mut add, k_tau, &[i, j, k, l], &b);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Construct an approximate Hessian in Cartesian coordinates of `mol` using
+/// the model of Lindh et al. (Chem. Phys. Lett. 1995, 241, 423).
+///
+/// # Return
+///
+/// * the 3Nx3N Hessian matrix in row major, in units of eV/Å^2.
+pub fn lindh_hessian(mol: &Molecule) -> Vec<f64> {
+    let n = 3 * mol.natoms();
+    let mut hessian = vec![0.0; n * n];
+    assemble_lindh(mol, |i, j, v| hessian[i * n + j] += v);
+    hessian
+}
+
+/// Construct the Lindh model Hessian of `mol` in sparse storage, which scales
+/// linearly with the number of atoms.
+pub fn lindh_hessian_sparse(mol: &Molecule) -> SparseHessian {
+    let mut triplets = vec![];
+    assemble_lindh(mol, |i, j, v| triplets.push((i, j, v)));
+    SparseHessian::from_triplets(3 * mol.natoms(), triplets)
+}
+// 7007ad12 ends here
+
+// [[file:../optim.note::f38ee222][f38ee222]]
+/// Estimate a proper initial step size for quasi-Newton optimization from the
+/// mean curvature in `diag`, the diagonal of model Hessian in coordinates of
+/// variables, with frozen coordinates removed.
+pub(crate) fn initial_step_size_from_model_hessian(diag: &[f64]) -> Option<f64> {
+    if diag.is_empty() {
+        return None;
+    }
+    let mean = diag.iter().sum::<f64>() / diag.len() as f64;
+    if mean > 0.0 {
+        Some(1.0 / mean)
+    } else {
+        None
+    }
+}
+// f38ee222 ends here
+
+// [[file:../optim.note::031e7db8][031e7db8]]
+/// Return the mass-weighted Hessian H_ij / sqrt(m_i m_j) from Cartesian
+/// `hessian` (3N x 3N in row major) and atomic `masses`.
+pub fn mass_weighted_hessian(hessian: &[f64], masses: &[f64]) -> Vec<f64> {
+    let n = 3 * masses.len();
+    assert_eq!(hessian.len(), n * n, "invalid size of Hessian");
+    let w = masses.iter().flat_map(|m| [m.sqrt(); 3]).collect_vec();
+    (0..n * n).map(|k| hessian[k] / (w[k / n] * w[k % n])).collect()
+}
+
+/// Compute normal modes from Cartesian `hessian` (3N x 3N in row major) and
+/// atomic `masses`, by diagonalizing the mass-weighted Hessian.
+///
+/// # Return
+///
+/// * pairs of eigenvalue and normalized eigenvector in mass-weighted
+///   coordinates, in ascending order of eigenvalues. Negative eigenvalues
+///   correspond to imaginary frequencies.
+pub fn normal_modes(hessian: &[f64], masses: &[f64]) -> Vec<(f64, Vec<f64>)> {
+    use vecfx::nalgebra as na;
+
+    let n = 3 * masses.len();
+    let h = na::DMatrix::from_row_slice(n, n, &mass_weighted_hessian(hessian, masses));
+    let eigen = h.symmetric_eigen();
+    eigen
+        .eigenvalues
+        .iter()
+        .zip(eigen.eigenvectors.column_iter())
+        .map(|(&e, v)| (e, v.iter().copied().collect_vec()))
+        .sorted_by(|a, b| a.0.partial_cmp(&b.0).expect("found invalid float numbers"))
+        .collect()
+}
+// 031e7db8 ends here
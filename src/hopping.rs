@@ -0,0 +1,239 @@
+// [[file:../optim.note::34cf417a][34cf417a]]
+use super::*;
+
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+// 34cf417a ends here
+
+// [[file:../optim.note::f4f34bad][f4f34bad]]
+// factors for adjusting MD temperature when escape trial ends up in the same
+// minimum, an old minimum, or a new minimum.
+const BETA_SAME: f64 = 1.05;
+const BETA_OLD: f64 = 1.05;
+const BETA_NEW: f64 = 1.0 / 1.05;
+// factors for adjusting energy threshold when a hop is accepted or rejected
+const ALPHA_ACCEPT: f64 = 1.0 / 1.02;
+const ALPHA_REJECT: f64 = 1.02;
+
+/// A local minimum visited in structure search.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Minimum {
+    /// Energy of the minimum.
+    pub energy: f64,
+    /// The optimized structure.
+    pub molecule: Molecule,
+    /// The number of times the minimum was found.
+    pub nvisits: usize,
+}
+
+/// Final result of `MinimaHopping`.
+#[derive(Debug, Clone)]
+pub struct MinimaHopped {
+    /// All distinct minima visited, in order of discovery.
+    pub minima: Vec<Minimum>,
+    /// The number of MD escape trials.
+    pub nescapes: usize,
+    /// The number of accepted hops.
+    pub naccepted: usize,
+    /// Final MD temperature in K.
+    pub temperature: f64,
+    /// Final energy threshold in eV for accepting hops.
+    pub ediff: f64,
+}
+
+impl MinimaHopped {
+    /// Return the lowest minimum found.
+    pub fn best(&self) -> &Minimum {
+        self.minima
+            .iter()
+            .min_by(|a, b| a.energy.total_cmp(&b.energy))
+            .expect("no minimum")
+    }
+}
+
+/// Minima hopping for global structure search of clusters. Each escape trial
+/// runs short MD from current minimum at temperature adjusted on the fly,
+/// followed by a quench using `Optimizer`. The new minimum is accepted if its
+/// energy is not higher than current one by more than a threshold, which is
+/// also adjusted on the fly. Minima are identified by their energies.
+///
+/// # Examples
+///
+/// ```ignore
+/// let hopping = MinimaHopping::new(Optimizer::new(0.01, 500)).temperature(1000.0);
+/// let hopped = hopping.run(&mol, &mut model, 100)?;
+/// println!("lowest energy: {}", hopped.best().energy);
+/// ```
+///
+/// # References
+///
+/// - Goedecker, S. J. Chem. Phys. 2004, 120, 9911.
+pub struct MinimaHopping {
+    optimizer: Optimizer,
+    temperature: f64,
+    ediff: f64,
+    // the number of minima of potential energy crossed in MD escape
+    mdmin: usize,
+    timestep: f64,
+    max_md_steps: usize,
+    energy_tolerance: f64,
+    seed: u64,
+}
+
+impl MinimaHopping {
+    /// Quench structures after MD escape using `optimizer`.
+    pub fn new(optimizer: Optimizer) -> Self {
+        Self {
+            optimizer,
+            temperature: 1000.0,
+            ediff: 0.5,
+            mdmin: 2,
+            timestep: 1.0,
+            max_md_steps: 1000,
+            energy_tolerance: 1e-3,
+            seed: 0,
+        }
+    }
+
+    /// Set initial MD temperature in K for escape trials.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        assert!(temperature > 0.0, "invalid temperature: {temperature}");
+        self.temperature = temperature;
+        self
+    }
+
+    /// Set initial energy threshold in eV for accepting hops.
+    pub fn ediff(mut self, ediff: f64) -> Self {
+        assert!(ediff > 0.0, "invalid energy threshold: {ediff}");
+        self.ediff = ediff;
+        self
+    }
+
+    /// Stop MD escape after crossing `n` minima of potential energy, or
+    /// `nmax` steps at most.
+    pub fn mdmin(mut self, n: usize, nmax: usize) -> Self {
+        assert!(n > 0, "invalid number of minima: {n}");
+        self.mdmin = n;
+        self.max_md_steps = nmax;
+        self
+    }
+
+    /// Set MD timestep in fs for escape trials.
+    pub fn timestep(mut self, dt: f64) -> Self {
+        assert!(dt > 0.0, "invalid timestep: {dt}");
+        self.timestep = dt;
+        self
+    }
+
+    /// Treat minima with energy difference below `tolerance` in eV as the
+    /// same one.
+    pub fn energy_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.energy_tolerance = tolerance;
+        self
+    }
+
+    /// Set `seed` for random initial velocities in MD escape.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Run `nescapes` escape trials starting from `mol` in potential
+    /// provided by `model`. `mol` is quenched first.
+    pub fn run<M: ChemicalModel>(&self, mol: &Molecule, model: &mut M, nescapes: usize) -> Result<MinimaHopped> {
+        let mut current = mol.clone();
+        let optimized = self
+            .optimizer
+            .optimize_geometry(&mut current, model)
+            .context("initial quench")?;
+        let mut energy = quenched_energy(&optimized)?;
+        let mut minima = vec![Minimum {
+            energy,
+            molecule: current.clone(),
+            nvisits: 1,
+        }];
+        let mut temperature = self.temperature;
+        let mut ediff = self.ediff;
+        let mut naccepted = 0;
+        for i in 0..nescapes {
+            let seed = self.seed.wrapping_add(i as u64);
+            let mut trial = self.escape(&current, model, temperature, seed)?;
+            let optimized = self
+                .optimizer
+                .optimize_geometry(&mut trial, model)
+                .with_context(|| format!("quench failed in escape trial {i}"))?;
+            let e = quenched_energy(&optimized)?;
+            let tol = self.energy_tolerance;
+            if (e - energy).abs() < tol {
+                info!("escape trial {i}: same minimum at T = {temperature:.1}");
+                temperature *= BETA_SAME;
+                continue;
+            }
+            match minima.iter_mut().find(|m| (m.energy - e).abs() < tol) {
+                Some(m) => {
+                    m.nvisits += 1;
+                    temperature *= BETA_OLD;
+                }
+                None => {
+                    minima.push(Minimum {
+                        energy: e,
+                        molecule: trial.clone(),
+                        nvisits: 1,
+                    });
+                    temperature *= BETA_NEW;
+                }
+            }
+            if e - energy < ediff {
+                info!("escape trial {i}: accepted minimum E = {e:-16.6}");
+                current = trial;
+                energy = e;
+                ediff *= ALPHA_ACCEPT;
+                naccepted += 1;
+            } else {
+                info!("escape trial {i}: rejected minimum E = {e:-16.6}");
+                ediff *= ALPHA_REJECT;
+            }
+        }
+
+        Ok(MinimaHopped {
+            minima,
+            nescapes,
+            naccepted,
+            temperature,
+            ediff,
+        })
+    }
+
+    // Run MD from `mol` with random velocities at `temperature` until
+    // crossing `mdmin` minima of potential energy, and return the final
+    // structure.
+    fn escape<M: ChemicalModel>(&self, mol: &Molecule, model: &mut M, temperature: f64, seed: u64) -> Result<Molecule> {
+        let mut md = MoleculeDynamics::from_chemical_model(model, mol.clone())?
+            .timestep(self.timestep)
+            .seed(seed);
+        md.randomize_velocities(temperature);
+        let mut nminima = 0;
+        // potential energies in last two steps
+        let mut last = [f64::NAN; 2];
+        for progress in md.run_iter(self.max_md_steps) {
+            let e = progress?.potential_energy;
+            if last[1] < last[0] && last[1] < e {
+                nminima += 1;
+                if nminima >= self.mdmin {
+                    break;
+                }
+            }
+            last = [last[1], e];
+        }
+        md.molecule().ok_or(format_err!("no molecule in MD"))
+    }
+}
+
+fn quenched_energy(optimized: &Optimized) -> Result<f64> {
+    optimized
+        .computed
+        .get_energy()
+        .ok_or(format_err!("no energy in quenched structure"))
+}
+// f4f34bad ends here
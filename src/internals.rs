@@ -0,0 +1,158 @@
+// [[file:../optim.note::817306fc][817306fc]]
+use super::*;
+// 817306fc ends here
+
+// [[file:../optim.note::00c7d0a1][00c7d0a1]]
+/// Distance between points `pi` and `pj`.
+pub(crate) fn distance(pi: [f64; 3], pj: [f64; 3]) -> f64 {
+    (Vector3f::from(pi) - Vector3f::from(pj)).norm()
+}
+
+/// Angle i-j-k in radian, with `pj` as the vertex.
+pub(crate) fn angle(pi: [f64; 3], pj: [f64; 3], pk: [f64; 3]) -> f64 {
+    let u = Vector3f::from(pi) - Vector3f::from(pj);
+    let v = Vector3f::from(pk) - Vector3f::from(pj);
+    let cos = u.dot(&v) / (u.norm() * v.norm());
+    cos.clamp(-1.0, 1.0).acos()
+}
+
+/// Dihedral angle i-j-k-l in radian, in range of (-pi, pi].
+pub(crate) fn dihedral(pi: [f64; 3], pj: [f64; 3], pk: [f64; 3], pl: [f64; 3]) -> f64 {
+    let [xi, xj, xk, xl] = [pi, pj, pk, pl].map(Vector3f::from);
+    let f = xi - xj;
+    let g = xj - xk;
+    let h = xl - xk;
+    let a = f.cross(&g);
+    let b = h.cross(&g);
+    b.cross(&a).dot(&g).atan2(a.dot(&b) * g.norm())
+}
+
+/// Wrap angle `x` in radian into range of (-pi, pi].
+pub(crate) fn wrap_angle(x: f64) -> f64 {
+    use std::f64::consts::PI;
+    let y = (x + PI).rem_euclid(2.0 * PI) - PI;
+    if y == -PI {
+        PI
+    } else {
+        y
+    }
+}
+
+/// Wilson B-matrix row of bond stretch i-j.
+pub(crate) fn stretch_b(xi: &Vector3f, xj: &Vector3f) -> [Vector3f; 2] {
+    let u = (xi - xj).normalize();
+    [u, -u]
+}
+
+/// Wilson B-matrix row of angle bend i-j-k. Return None for linear bend.
+pub(crate) fn bend_b(xi: &Vector3f, xj: &Vector3f, xk: &Vector3f) -> Option<[Vector3f; 3]> {
+    let u = xi - xj;
+    let v = xk - xj;
+    let (lu, lv) = (u.norm(), v.norm());
+    let cos = u.dot(&v) / (lu * lv);
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    // skip linear bends
+    if sin < 1e-6 {
+        return None;
+    }
+    let bi = (cos * u / lu - v / lv) / (lu * sin);
+    let bk = (cos * v / lv - u / lu) / (lv * sin);
+    Some([bi, -bi - bk, bk])
+}
+
+/// Wilson B-matrix row of torsion i-j-k-l, consistent with `dihedral`. Return
+/// None if any of the bends is linear.
+///
+/// Reference: Blondel, A.; Karplus, M. J. Comput. Chem. 1996, 17, 1132.
+pub(crate) fn torsion_b(xi: &Vector3f, xj: &Vector3f, xk: &Vector3f, xl: &Vector3f) -> Option<[Vector3f; 4]> {
+    let f = xi - xj;
+    let g = xj - xk;
+    let h = xl - xk;
+    let a = f.cross(&g);
+    let b = h.cross(&g);
+    let (a2, b2, lg) = (a.norm_squared(), b.norm_squared(), g.norm());
+    // skip torsions with linear bends
+    if a2 < 1e-12 || b2 < 1e-12 {
+        return None;
+    }
+    let bi = -lg / a2 * a;
+    let bl = lg / b2 * b;
+    let fg = f.dot(&g) / (a2 * lg);
+    let hg = h.dot(&g) / (b2 * lg);
+    let bj = -bi + fg * a - hg * b;
+    let bk = -bl - fg * a + hg * b;
+    Some([bi, bj, bk, bl])
+}
+// 00c7d0a1 ends here
+
+// [[file:../optim.note::4922096d][4922096d]]
+/// Internal coordinates defined on atoms indexed from 0 in the order of
+/// positions. Distances are in the unit of positions, and angles in radian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coordinate {
+    /// The distance between atom `i` and atom `j`.
+    Distance(usize, usize),
+    /// The angle i-j-k with atom `j` as the vertex.
+    Angle(usize, usize, usize),
+    /// The dihedral angle i-j-k-l.
+    Dihedral(usize, usize, usize, usize),
+}
+
+impl Coordinate {
+    /// Return indices of atoms involved.
+    pub fn atoms(&self) -> Vec<usize> {
+        match *self {
+            Self::Distance(i, j) => vec![i, j],
+            Self::Angle(i, j, k) => vec![i, j, k],
+            Self::Dihedral(i, j, k, l) => vec![i, j, k, l],
+        }
+    }
+
+    /// Return true if the coordinate is an angle or dihedral angle.
+    pub fn is_angular(&self) -> bool {
+        !matches!(self, Self::Distance(..))
+    }
+
+    /// Return the value of coordinate at `positions`.
+    pub fn value(&self, positions: &[[f64; 3]]) -> f64 {
+        let p = |i: usize| positions[i];
+        match *self {
+            Self::Distance(i, j) => distance(p(i), p(j)),
+            Self::Angle(i, j, k) => angle(p(i), p(j), p(k)),
+            Self::Dihedral(i, j, k, l) => dihedral(p(i), p(j), p(k), p(l)),
+        }
+    }
+
+    /// Return the difference between coordinate values `a` and `b`, taking
+    /// periodicity of dihedral angle into account.
+    pub fn difference(&self, a: f64, b: f64) -> f64 {
+        match self {
+            Self::Dihedral(..) => wrap_angle(a - b),
+            _ => a - b,
+        }
+    }
+
+    /// Return the gradient of coordinate at `positions` in pairs of (atom
+    /// index, partial derivatives). The gradient of a linear angle is
+    /// treated as zero.
+    pub fn gradient(&self, positions: &[[f64; 3]]) -> Vec<(usize, [f64; 3])> {
+        let x = |i: usize| Vector3f::from(positions[i]);
+        let b = match *self {
+            Self::Distance(i, j) => stretch_b(&x(i), &x(j)).to_vec(),
+            Self::Angle(i, j, k) => bend_b(&x(i), &x(j), &x(k)).map(|b| b.to_vec()).unwrap_or_default(),
+            Self::Dihedral(i, j, k, l) => torsion_b(&x(i), &x(j), &x(k), &x(l))
+                .map(|b| b.to_vec())
+                .unwrap_or_default(),
+        };
+        self.atoms().into_iter().zip(b).map(|(i, bi)| (i, bi.into())).collect()
+    }
+
+    /// Check if atoms in coordinate are valid for a system of `natoms`.
+    pub(crate) fn check(&self, natoms: usize) -> Result<()> {
+        let atoms = self.atoms();
+        ensure!(atoms.iter().all(|&i| i < natoms), "invalid atoms in {self:?}");
+        ensure!(atoms.iter().all_unique(), "duplicated atoms in {self:?}");
+        Ok(())
+    }
+}
+// 4922096d ends here
@@ -0,0 +1,143 @@
+// [[file:../optim.note::399f9040][399f9040]]
+use super::*;
+
+use crate::hopping::Minimum;
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+// 399f9040 ends here
+
+// [[file:../optim.note::c80d484e][c80d484e]]
+/// Final result of `KickSearch`.
+#[derive(Debug, Clone)]
+pub struct KickSearched {
+    /// Distinct minima found, with the re-optimized reference structure as
+    /// the first one.
+    pub minima: MinimaDb,
+    /// The number of kicked structures failed in re-optimization or not
+    /// converged.
+    pub nfailed: usize,
+}
+
+impl KickSearched {
+    /// Return the reference minimum optimized from the input structure.
+    pub fn reference(&self) -> &Minimum {
+        &self.minima.minima()[0]
+    }
+
+    /// Return true if all kicked structures returned to the reference.
+    pub fn is_stable(&self) -> bool {
+        self.minima.len() == 1
+    }
+
+    /// Return minima lower in energy than the reference.
+    pub fn lower(&self) -> Vec<&Minimum> {
+        let e0 = self.reference().energy;
+        self.minima.sorted().into_iter().filter(|m| m.energy < e0).collect()
+    }
+}
+
+/// Perturb-and-reoptimize workflow: kick a converged structure repeatedly
+/// with random displacements and re-optimize, collecting distinct structures
+/// found. This is a cheap check that a minimum is not an artifact of the
+/// optimizer, such as a saddle point with tiny forces.
+///
+/// # Examples
+///
+/// ```ignore
+/// let kicked = KickSearch::new(Optimizer::new(0.01, 500), 0.1).run(&mol, &mut model, 10)?;
+/// if !kicked.is_stable() {
+///     println!("found {} lower minima", kicked.lower().len());
+/// }
+/// ```
+pub struct KickSearch {
+    optimizer: Optimizer,
+    magnitude: f64,
+    minima: MinimaDb,
+    seed: u64,
+}
+
+impl KickSearch {
+    /// Re-optimize structures kicked with atomic displacements up to
+    /// `magnitude` in Å using `optimizer`.
+    pub fn new(optimizer: Optimizer, magnitude: f64) -> Self {
+        assert!(magnitude > 0.0, "invalid kick magnitude: {magnitude}");
+        Self {
+            optimizer,
+            magnitude,
+            minima: MinimaDb::default(),
+            seed: 0,
+        }
+    }
+
+    /// Identify distinct structures using tolerances set in `minima`.
+    pub fn minima_db(mut self, minima: MinimaDb) -> Self {
+        self.minima = minima;
+        self
+    }
+
+    /// Set `seed` for random kicks.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Kick `mol` for `nkicks` times in potential provided by `model`, each
+    /// from the reference structure re-optimized from `mol`. Atoms with
+    /// freezing flags are not kicked.
+    pub fn run<M: ChemicalModel>(&self, mol: &Molecule, model: &mut M, nkicks: usize) -> Result<KickSearched> {
+        let mut reference = mol.clone();
+        let optimized = self
+            .optimizer
+            .optimize_geometry(&mut reference, model)
+            .context("optimize reference")?;
+        ensure!(
+            optimized.fmax < self.optimizer.fmax(),
+            "reference structure not converged: fmax = {}",
+            optimized.fmax
+        );
+        let energy = optimized.computed.get_energy().ok_or(format_err!("no energy"))?;
+        let mut minima = self.minima.clone();
+        minima.insert(energy, &reference);
+
+        let mut rng = crate::random::Rng::new(self.seed);
+        let mut nfailed = 0;
+        for i in 0..nkicks {
+            let mut kicked = reference.clone();
+            let positions = reference
+                .atoms()
+                .map(|(_, a)| {
+                    let mut p = a.position();
+                    if !a.freezing().contains(&true) {
+                        let r = self.magnitude * rng.uniform().cbrt();
+                        p.vecadd(&rng.unit_vector(3), r);
+                    }
+                    p
+                })
+                .collect_vec();
+            kicked.update_positions(positions);
+            let energy = match self.optimizer.optimize_geometry(&mut kicked, model) {
+                Ok(o) if o.fmax < self.optimizer.fmax() => o.computed.get_energy(),
+                Ok(o) => {
+                    warn!("kicked structure {i} not converged: fmax = {}", o.fmax);
+                    None
+                }
+                Err(e) => {
+                    warn!("kicked structure {i} failed in re-optimization: {e:?}");
+                    None
+                }
+            };
+            match energy {
+                Some(energy) => {
+                    let (k, new) = minima.insert(energy, &kicked);
+                    if new {
+                        info!("kick {i}: new minimum {k} with E = {energy:-16.6}");
+                    }
+                }
+                None => nfailed += 1,
+            }
+        }
+
+        Ok(KickSearched { minima, nfailed })
+    }
+}
+// c80d484e ends here
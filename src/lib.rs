@@ -6,10 +6,56 @@ use gut::prelude::*;
 // a74a585a ends here
 
 // [[file:../optim.note::2e984082][2e984082]]
+mod async_eval;
+mod benchmark;
+mod bias;
+mod boost;
+mod cell;
+mod committee;
+mod composite;
+mod constraint;
+mod deform;
+mod delta;
+mod dynamics;
+mod events;
+mod fd;
+mod fidelity;
+mod field;
+mod freeze;
+mod genetic;
+mod hessian;
+mod hopping;
+mod internals;
+mod kick;
+mod metadynamics;
+mod minima;
+mod neb;
 mod opt;
 mod optimization;
 mod potential;
+mod random;
+mod redundant;
+mod replica;
+mod report;
+mod restart;
+mod restraint;
+mod rigid;
+mod rss;
+mod saddle;
+mod schedule;
+mod sd;
+mod sparse;
+mod staged;
+mod state;
+mod swarm;
+mod symmetry;
+mod timeout;
+mod toy;
+mod ts;
+mod umbrella;
+mod units;
 mod vars;
+mod viewer;
 // 2e984082 ends here
 
 // [[file:../optim.note::135c17fa][135c17fa]]
@@ -24,13 +70,73 @@ fn f3max_<'a>(values: impl IntoIterator<Item = &'a [f64]>) -> f64 {
     assert!(!fmax.is_nan(), "found invalid float numbers");
     fmax
 }
+
+// Scale `v` down to norm of `max` if it is longer.
+fn cap_norm(v: &mut [f64], max: f64) {
+    let norm = v.vec2norm();
+    if norm > max {
+        v.iter_mut().for_each(|x| *x *= max / norm);
+    }
+}
 // 135c17fa ends here
 
 // [[file:../optim.note::33bebce4][33bebce4]]
+pub use async_eval::{optimize_async, AsyncEvaluatePotential};
+pub use benchmark::{Backend, BackendSummary, Benchmark, BenchmarkRecord, BenchmarkTable};
+pub use bias::{Biased, BiasedPotential};
+pub use boost::{BondBoost, Boosted, HyperClock, HyperDynamics};
+pub use cell::{niggli_reduce, CellConstraint};
+pub use committee::{Committee, CommitteePotential, Uncertain};
+pub use composite::{Composite, CompositePotential};
+pub use constraint::{Constraint, Constraints, EnforceConstraint, FixedCenterOfMass};
+pub use deform::{Deformation, DeformationRecord};
+pub use delta::{Delta, DeltaPotential};
+pub use dynamics::{
+    Barostat, DriftPolicy, Integrator, Leapfrog, MdProgress, MdRestart, MoleculeDynamics, Respa, Thermostat,
+    TrajectoryWriter, VelocityVerlet, DEUTERIUM_MASS,
+};
+pub use events::{detect_reactions, ReactionDetector, ReactionEvent};
+pub use fidelity::{MultiFidelity, Switchover};
+pub use field::ExternalField;
+pub use freeze::Freezing;
+pub use genetic::{GeneticSearch, GeneticSearched};
+pub use hessian::{lindh_hessian, lindh_hessian_sparse, mass_weighted_hessian, normal_modes};
+pub use hopping::{MinimaHopped, MinimaHopping, Minimum};
+pub use kick::{KickSearch, KickSearched};
+pub use metadynamics::{Hill, HillStore, Metadynamics, Metadynamized};
+pub use minima::MinimaDb;
+pub use neb::{BandLayout, BandWriter, NebCheckpoint, NebOptimized, NudgedElasticBand, Path};
 pub use opt::*;
-pub use potential::{Dynamics, EvaluatePotential, PotentialOutput};
+pub use potential::{
+    Dynamics, DynamicsSnapshot, DynamicsSync, EvalHook, EvaluatePotential, EvaluatePotentialBatch, Noise,
+    NumericalForces, NumericalHessian, PotentialOutput, RetryPolicy, SerialBatch, SharedMolecule, SharedSnapshot,
+    SmallStep,
+};
+pub use redundant::{DelocalizedInternals, RedundantInternals};
+pub use replica::{ExchangeStats, Replica, ReplicaExchange, ReplicaServer, ReplicaWorker};
 
-pub use optimization::{optimize, OptimProgress};
+pub use internals::Coordinate;
+pub use optimization::{
+    optimize, optimize_constrained, optimize_fold, optimize_mecp, OptimConstrained, OptimCrossing, OptimFolded,
+    OptimProgress, ScalarConstraint,
+};
+pub use report::{EvalStats, ForceStats, RunReport, StepStats};
+pub use restraint::{Restrained, RestrainedPotential, Restraint, Restraints};
+pub use rss::{RandomSearch, RandomSearched};
+pub use saddle::{ArtConfig, ArtSaddle, ArtSearch, DimerConfig, DimerProgress, DimerRotation, DimerSearch};
+pub use schedule::{LambdaSchedule, TemperatureSchedule};
+pub use sd::StepSizeRule;
+pub use sparse::SparseHessian;
+pub use staged::{Stage, StagedOptimized, StagedOptimizer};
+pub use state::VersionedState;
+pub use swarm::{SwarmSearch, SwarmSearched};
+pub use symmetry::Symmetry;
+pub use timeout::{Cancelled, Canceller, Timeout, TimeoutPotential};
+pub use toy::{EckartBarrier, HarmonicLattice, LepsHarmonic, MullerBrown, Rosenbrock};
+pub use ts::{refine as refine_ts, TsConfig, TsRefined};
+pub use umbrella::{CvSeries, UmbrellaSampling, UmbrellaWindow};
+pub use units::{UnitSystem, Units, UnitsPotential};
+pub use viewer::{LiveViewer, ViewerFrame};
 // 33bebce4 ends here
 
 // [[file:../optim.note::242ad86a][242ad86a]]
@@ -48,27 +154,46 @@ pub mod docs {
     export_doc!(potential);
     export_doc!(opt);
     export_doc!(vars);
+    export_doc!(report);
+    export_doc!(state);
+    export_doc!(hessian);
+    export_doc!(freeze);
+    export_doc!(viewer);
+    export_doc!(constraint);
+    export_doc!(metadynamics);
+    export_doc!(boost);
+    export_doc!(restraint);
+    export_doc!(schedule);
+    export_doc!(internals);
+    export_doc!(redundant);
+    export_doc!(sparse);
+    export_doc!(events);
+    export_doc!(cell);
+    export_doc!(toy);
+    export_doc!(symmetry);
+    export_doc!(benchmark);
+    export_doc!(staged);
+    export_doc!(composite);
+    export_doc!(bias);
+    export_doc!(delta);
+    export_doc!(field);
+    export_doc!(timeout);
+    export_doc!(async_eval);
+    export_doc!(units);
+    export_doc!(committee);
+    export_doc!(fidelity);
+    export_doc!(neb);
+    export_doc!(saddle);
+    export_doc!(ts);
+    export_doc!(dynamics);
+    export_doc!(deform);
+    export_doc!(umbrella);
+    export_doc!(hopping);
+    export_doc!(rss);
+    export_doc!(genetic);
+    export_doc!(swarm);
+    export_doc!(replica);
+    export_doc!(minima);
+    export_doc!(kick);
 }
 // 242ad86a ends here
-
-// [[file:../optim.note::5e6f19c8][5e6f19c8]]
-pub use dimer::Dimer;
-
-impl<'a, U> dimer::EvaluateDimer for Dynamics<'a, U> {
-    fn position(&self) -> &[f64] {
-        self.position()
-    }
-
-    fn set_position(&mut self, position: &[f64]) {
-        self.set_position(position);
-    }
-
-    fn get_energy(&mut self) -> Result<f64> {
-        self.get_energy()
-    }
-
-    fn get_force(&mut self) -> Result<&[f64]> {
-        self.get_force()
-    }
-}
-// 5e6f19c8 ends here
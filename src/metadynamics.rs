@@ -0,0 +1,204 @@
+// [[file:../optim.note::d23e4897][d23e4897]]
+use super::*;
+
+use std::sync::{Arc, RwLock};
+// d23e4897 ends here
+
+// [[file:../optim.note::8ad7acc4][8ad7acc4]]
+/// A Gaussian hill deposited in collective variable space.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Hill {
+    /// The center of hill, as values of collective variables.
+    pub center: Vec<f64>,
+    /// The height of hill in energy unit.
+    pub height: f64,
+    /// The index of walker depositing the hill.
+    pub walker: usize,
+}
+
+/// Hills shared by all walkers of metadynamics. Cloning the handle shares the
+/// same store, so walkers running in different threads build one common
+/// bias potential.
+#[derive(Debug, Clone, Default)]
+pub struct HillStore {
+    hills: Arc<RwLock<Vec<Hill>>>,
+}
+
+impl HillStore {
+    /// Return a copy of all deposited hills.
+    pub fn hills(&self) -> Vec<Hill> {
+        self.hills.read().expect("poisoned hills lock").clone()
+    }
+
+    /// The number of deposited hills.
+    pub fn len(&self) -> usize {
+        self.hills.read().expect("poisoned hills lock").len()
+    }
+
+    /// Return true if no hill deposited.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&self, hill: Hill) {
+        self.hills.write().expect("poisoned hills lock").push(hill);
+    }
+
+    // Bias energy at `s` and its gradient with respect to `s`, for Gaussian
+    // hills of `widths`.
+    fn bias(&self, s: &[f64], widths: &[f64]) -> (f64, Vec<f64>) {
+        let mut energy = 0.0;
+        let mut gradient = vec![0.0; s.len()];
+        for hill in self.hills.read().expect("poisoned hills lock").iter() {
+            let e = hill.height
+                * s.iter()
+                    .zip(&hill.center)
+                    .zip(widths)
+                    .map(|((x, c), w)| (-(x - c).powi(2) / (2.0 * w * w)).exp())
+                    .product::<f64>();
+            energy += e;
+            for ((g, (x, c)), w) in gradient.iter_mut().zip(s.iter().zip(&hill.center)).zip(widths) {
+                *g -= e * (x - c) / (w * w);
+            }
+        }
+        (energy, gradient)
+    }
+}
+
+/// Output of metadynamics bias.
+#[derive(Debug, Clone)]
+pub struct Metadynamized<U> {
+    /// Values of collective variables at evaluated position.
+    pub cv: Vec<f64>,
+    /// Bias energy from deposited hills.
+    pub bias_energy: f64,
+    /// Energy of the unbiased potential.
+    pub physical_energy: f64,
+    /// Extra data from the unbiased potential.
+    pub extra: U,
+}
+
+type Cv<'a> = Box<dyn FnMut(&[f64]) -> (f64, Vec<f64>) + 'a>;
+
+/// A potential wrapper for metadynamics, adding a history-dependent bias of
+/// Gaussian hills deposited along collective variables (CVs) to escape free
+/// energy minima.
+///
+/// Several walkers can share one bias by constructing each with the same
+/// `HillStore`, e.g. one walker per thread. The free energy surface is
+/// estimated by the negative of bias from all hills.
+///
+/// # Examples
+///
+/// ```ignore
+/// // bond distance between the first two atoms as CV
+/// let store = HillStore::default();
+/// let walker = |i| {
+///     Metadynamics::new(potential.clone(), 0.05)
+///         .cv(distance, 0.1)
+///         .stride(50)
+///         .walker(i, &store)
+/// };
+/// let mut dynamics = Dynamics::new(&x, walker(0));
+/// ```
+///
+/// # References
+///
+/// - Laio, A.; Parrinello, M. Proc. Natl. Acad. Sci. 2002, 99, 12562.
+/// - Raiteri, P. et al. J. Phys. Chem. B 2006, 110, 3533.
+pub struct Metadynamics<'a, P> {
+    potential: P,
+    cvs: Vec<Cv<'a>>,
+    widths: Vec<f64>,
+    height: f64,
+    stride: usize,
+    neval: usize,
+    walker: usize,
+    store: HillStore,
+}
+
+impl<'a, P> Metadynamics<'a, P> {
+    /// Bias `potential` using hills of `height`. Hills are deposited every
+    /// 100 evaluations by default, for a single walker.
+    pub fn new(potential: P, height: f64) -> Self {
+        assert!(height.is_sign_positive(), "invalid height of hills: {height}");
+        Self {
+            potential,
+            cvs: vec![],
+            widths: vec![],
+            height,
+            stride: 100,
+            neval: 0,
+            walker: 0,
+            store: HillStore::default(),
+        }
+    }
+
+    /// Add a collective variable with Gaussian width `width`. `cv` returns
+    /// the value of CV and its gradient with respect to position.
+    pub fn cv(mut self, cv: impl FnMut(&[f64]) -> (f64, Vec<f64>) + 'a, width: f64) -> Self {
+        assert!(width > 0.0, "invalid width of hills: {width}");
+        self.cvs.push(Box::new(cv));
+        self.widths.push(width);
+        self
+    }
+
+    /// Deposit a hill every `stride` evaluations.
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0, "invalid stride: {stride}");
+        self.stride = stride;
+        self
+    }
+
+    /// Run as walker indexed by `index`, sharing hills in `store` with
+    /// other walkers.
+    pub fn walker(mut self, index: usize, store: &HillStore) -> Self {
+        self.walker = index;
+        self.store = store.clone();
+        self
+    }
+
+    /// Return the store of hills shared by all walkers.
+    pub fn hills(&self) -> &HillStore {
+        &self.store
+    }
+
+    /// Return the free energy estimate at CV values `s`, as the negative of
+    /// bias from all deposited hills.
+    pub fn free_energy(&self, s: &[f64]) -> f64 {
+        assert_eq!(s.len(), self.widths.len(), "invalid number of CVs");
+        -self.store.bias(s, &self.widths).0
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U>> EvaluatePotential<Metadynamized<U>> for Metadynamics<'a, P> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Metadynamized<U>> {
+        ensure!(!self.cvs.is_empty(), "no collective variable for metadynamics");
+        let extra = self.potential.evaluate(position, output)?;
+        let physical_energy = output.energy;
+        let (cv, gradients): (Vec<_>, Vec<_>) = self.cvs.iter_mut().map(|cv| cv(position)).unzip();
+        let (bias_energy, ds) = self.store.bias(&cv, &self.widths);
+        // chain rule for forces on atoms
+        for (g, d) in gradients.iter().zip(&ds) {
+            ensure!(g.len() == position.len(), "invalid size of CV gradient: {}", g.len());
+            output.force.vecadd(g, -d);
+        }
+        output.energy += bias_energy;
+
+        self.neval += 1;
+        if self.neval % self.stride == 0 {
+            self.store.push(Hill {
+                center: cv.clone(),
+                height: self.height,
+                walker: self.walker,
+            });
+        }
+        Ok(Metadynamized {
+            cv,
+            bias_energy,
+            physical_energy,
+            extra,
+        })
+    }
+}
+// 8ad7acc4 ends here
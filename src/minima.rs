@@ -0,0 +1,273 @@
+// [[file:../optim.note::afb285e6][afb285e6]]
+use super::*;
+
+use crate::cell::cell_matrix;
+use crate::hopping::Minimum;
+use crate::rss::image_distance;
+use gchemol::Molecule;
+use gosh_database::prelude::Collection;
+use gosh_database::DbConnection;
+use serde::*;
+use vecfx::nalgebra as na;
+// afb285e6 ends here
+
+// [[file:../optim.note::ab732b6d][ab732b6d]]
+// Max number of iterations for matching atoms in RMSD.
+const MAX_MATCH_ITERATIONS: usize = 10;
+
+/// A store of distinct minima found in global structure search, so that
+/// repeatedly found basins are counted as visits instead of new minima.
+///
+/// A structure duplicates a stored minimum if they are close in energy and
+/// in fingerprint of sorted interatomic distances, and their RMSD after
+/// superposition is small. Atoms of the same element are matched by nearest
+/// neighbors after superposition for RMSD, so that permuted copies are
+/// detected too. RMSD is not checked for periodic structures, which rely on
+/// fingerprint in minimum image convention only.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut db = MinimaDb::default();
+/// db.extend(&hopped.minima);
+/// db.extend(&searched.minima);
+/// let conn = DbConnection::connect("minima.sqlite")?;
+/// db.save(&conn, "LJ38")?;
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinimaDb {
+    minima: Vec<Minimum>,
+    fingerprints: Vec<Vec<f64>>,
+    energy_tolerance: f64,
+    fingerprint_tolerance: f64,
+    rmsd_tolerance: f64,
+}
+
+impl Default for MinimaDb {
+    fn default() -> Self {
+        Self {
+            minima: vec![],
+            fingerprints: vec![],
+            energy_tolerance: 1e-3,
+            fingerprint_tolerance: 0.02,
+            rmsd_tolerance: 0.1,
+        }
+    }
+}
+
+impl Collection for MinimaDb {
+    fn collection_name() -> String {
+        "MinimaDb".into()
+    }
+}
+
+impl MinimaDb {
+    /// Treat structures with energy difference below `tolerance` in eV as
+    /// candidates of duplicates.
+    pub fn energy_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.energy_tolerance = tolerance;
+        self
+    }
+
+    /// Treat structures with relative difference of sorted interatomic
+    /// distances below `tolerance` as candidates of duplicates.
+    pub fn fingerprint_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.fingerprint_tolerance = tolerance;
+        self
+    }
+
+    /// Treat candidates with RMSD below `tolerance` in Å as duplicates.
+    pub fn rmsd_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.rmsd_tolerance = tolerance;
+        self
+    }
+
+    /// Add minimum `mol` with `energy`. Return the index of stored minimum,
+    /// and true if it is new.
+    pub fn insert(&mut self, energy: f64, mol: &Molecule) -> (usize, bool) {
+        self.insert_visits(energy, mol, 1)
+    }
+
+    /// Add all `minima` found in other searches, with their visits counted.
+    pub fn extend(&mut self, minima: &[Minimum]) {
+        for m in minima {
+            self.insert_visits(m.energy, &m.molecule, m.nvisits);
+        }
+    }
+
+    fn insert_visits(&mut self, energy: f64, mol: &Molecule, nvisits: usize) -> (usize, bool) {
+        let fp = fingerprint(mol);
+        if let Some(i) = self.find_duplicate(energy, mol, &fp) {
+            let m = &mut self.minima[i];
+            m.nvisits += nvisits;
+            // keep the lower one
+            if energy < m.energy {
+                m.energy = energy;
+                m.molecule = mol.clone();
+                self.fingerprints[i] = fp;
+            }
+            return (i, false);
+        }
+        self.minima.push(Minimum {
+            energy,
+            molecule: mol.clone(),
+            nvisits,
+        });
+        self.fingerprints.push(fp);
+        (self.minima.len() - 1, true)
+    }
+
+    /// Return the index of stored minimum duplicated by `mol` with `energy`.
+    pub fn find(&self, energy: f64, mol: &Molecule) -> Option<usize> {
+        self.find_duplicate(energy, mol, &fingerprint(mol))
+    }
+
+    fn find_duplicate(&self, energy: f64, mol: &Molecule, fp: &[f64]) -> Option<usize> {
+        let periodic = mol.lattice.is_some();
+        let positions = mol.positions().collect_vec();
+        let numbers = atomic_numbers(mol);
+        let composition = numbers.iter().sorted().collect_vec();
+        self.minima.iter().zip(&self.fingerprints).position(|(m, fp_m)| {
+            (m.energy - energy).abs() < self.energy_tolerance
+                && fp_m.len() == fp.len()
+                && fingerprint_difference(fp_m, fp) < self.fingerprint_tolerance
+                && {
+                    // fingerprint ignores elements
+                    let numbers_m = atomic_numbers(&m.molecule);
+                    numbers_m.iter().sorted().eq(composition.iter().copied())
+                        && (periodic || {
+                            let other = m.molecule.positions().collect_vec();
+                            superposed_rmsd(&other, &numbers_m, &positions, &numbers) < self.rmsd_tolerance
+                        })
+                }
+        })
+    }
+
+    /// Return all stored minima in order of discovery.
+    pub fn minima(&self) -> &[Minimum] {
+        &self.minima
+    }
+
+    /// Return stored minima in ascending order of energy.
+    pub fn sorted(&self) -> Vec<&Minimum> {
+        self.minima
+            .iter()
+            .sorted_by(|a, b| a.energy.total_cmp(&b.energy))
+            .collect()
+    }
+
+    /// Return the number of distinct minima.
+    pub fn len(&self) -> usize {
+        self.minima.len()
+    }
+
+    /// Return true if no minimum stored.
+    pub fn is_empty(&self) -> bool {
+        self.minima.is_empty()
+    }
+
+    /// Save into database `db` with `key`.
+    pub fn save(&self, db: &DbConnection, key: &str) -> Result<()> {
+        self.put_into_collection(db, key)
+            .with_context(|| format!("save minima db with key {key}"))
+    }
+
+    /// Load from database `db` with `key`.
+    pub fn load(db: &DbConnection, key: &str) -> Result<Self> {
+        Self::get_from_collection(db, key).with_context(|| format!("load minima db with key {key}"))
+    }
+}
+
+/// Return sorted interatomic distances of `mol`, in minimum image convention
+/// if periodic.
+pub(crate) fn fingerprint(mol: &Molecule) -> Vec<f64> {
+    let cell = cell_matrix(mol);
+    let positions = mol.positions().collect_vec();
+    (0..positions.len())
+        .tuple_combinations()
+        .map(|(i, j)| image_distance(cell.as_ref(), positions[i], positions[j]))
+        .sorted_by(|a, b| a.total_cmp(b))
+        .collect()
+}
+
+/// Return sum of absolute differences between fingerprints `a` and `b`
+/// relative to the sum of `a`.
+pub(crate) fn fingerprint_difference(a: &[f64], b: &[f64]) -> f64 {
+    let norm: f64 = a.iter().sum::<f64>().max(1e-8);
+    a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum::<f64>() / norm
+}
+
+fn atomic_numbers(mol: &Molecule) -> Vec<usize> {
+    mol.atoms().map(|(_, a)| a.number()).collect()
+}
+
+// RMSD between positions `a` and `b` of atoms with atomic numbers `numbers_a`
+// and `numbers_b` after optimal superposition, with atoms of the same element
+// matched iteratively to their nearest neighbors. Infinite if the atoms cannot
+// be matched.
+//
+// Kabsch, W. Acta Cryst. 1976, A32, 922.
+fn superposed_rmsd(a: &[[f64; 3]], numbers_a: &[usize], b: &[[f64; 3]], numbers_b: &[usize]) -> f64 {
+    let n = a.len();
+    if n == 0 || b.len() != n {
+        return f64::INFINITY;
+    }
+    let a = centered(a);
+    let b = centered(b);
+    // index of atom in `b` matched to each atom in `a`
+    let Some(mut order) = match_nearest(&a, numbers_a, &b, numbers_b) else {
+        return f64::INFINITY;
+    };
+    let mut best = f64::INFINITY;
+    for _ in 0..MAX_MATCH_ITERATIONS {
+        let matched = order.iter().map(|&j| b[j]).collect_vec();
+        let rotation = kabsch(&a, &matched);
+        let rotated = b.iter().map(|p| rotation * p).collect_vec();
+        let sd: f64 = (0..n).map(|i| (a[i] - rotated[order[i]]).norm_squared()).sum();
+        best = best.min((sd / n as f64).sqrt());
+        match match_nearest(&a, numbers_a, &rotated, numbers_b) {
+            Some(new_order) if new_order != order => order = new_order,
+            _ => break,
+        }
+    }
+    best
+}
+
+fn centered(positions: &[[f64; 3]]) -> Vec<na::Vector3<f64>> {
+    let points = positions.iter().map(|p| na::Vector3::from(*p)).collect_vec();
+    let center: na::Vector3<f64> = points.iter().sum::<na::Vector3<f64>>() / points.len() as f64;
+    points.into_iter().map(|p| p - center).collect()
+}
+
+// Rotation superposing `b` onto `a`.
+fn kabsch(a: &[na::Vector3<f64>], b: &[na::Vector3<f64>]) -> na::Matrix3<f64> {
+    let h: na::Matrix3<f64> = b.iter().zip(a).map(|(p, q)| p * q.transpose()).sum();
+    let svd = h.svd(true, true);
+    let (u, v_t) = (svd.u.expect("svd u"), svd.v_t.expect("svd v_t"));
+    let d = (v_t.transpose() * u.transpose()).determinant().signum();
+    v_t.transpose() * na::Matrix3::from_diagonal(&na::Vector3::new(1.0, 1.0, d)) * u.transpose()
+}
+
+// Match each atom in `a` greedily to the nearest unmatched atom of the same
+// element in `b`. Return None if any atom is left unmatched.
+fn match_nearest(
+    a: &[na::Vector3<f64>],
+    numbers_a: &[usize],
+    b: &[na::Vector3<f64>],
+    numbers_b: &[usize],
+) -> Option<Vec<usize>> {
+    let mut used = vec![false; b.len()];
+    (0..a.len())
+        .map(|i| {
+            let j = (0..b.len())
+                .filter(|&j| !used[j] && numbers_b[j] == numbers_a[i])
+                .min_by(|&j, &k| (a[i] - b[j]).norm().total_cmp(&(a[i] - b[k]).norm()))?;
+            used[j] = true;
+            Some(j)
+        })
+        .collect()
+}
+// ab732b6d ends here
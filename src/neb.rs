@@ -0,0 +1,846 @@
+// [[file:../optim.note::fd1e39ab][fd1e39ab]]
+use super::*;
+
+use fire::fire;
+use serde::*;
+use std::path::PathBuf;
+// fd1e39ab ends here
+
+// [[file:../optim.note::cd9e6c0c][cd9e6c0c]]
+/// A reaction path as a chain of images, each represented as flattened
+/// positions. The first and last images are the fixed end points.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Path {
+    images: Vec<Vec<f64>>,
+}
+
+impl Path {
+    /// Construct a path from `images` including both end points.
+    pub fn new(images: Vec<Vec<f64>>) -> Self {
+        assert!(images.len() >= 2, "path requires at least two images");
+        let n = images[0].len();
+        assert!(images.iter().all(|x| x.len() == n), "images differ in dimension");
+        Self { images }
+    }
+
+    /// Interpolate linearly between `reactant` and `product` with `n` images
+    /// in total including both end points.
+    pub fn interpolate(reactant: &[f64], product: &[f64], n: usize) -> Self {
+        assert!(n >= 2, "invalid number of images: {n}");
+        assert_eq!(reactant.len(), product.len(), "end points differ in dimension");
+        let images = (0..n)
+            .map(|i| {
+                let t = i as f64 / (n - 1) as f64;
+                reactant.iter().zip(product).map(|(a, b)| a + t * (b - a)).collect()
+            })
+            .collect();
+        Self { images }
+    }
+
+    /// Return all images including end points.
+    pub fn images(&self) -> &[Vec<f64>] {
+        &self.images
+    }
+
+    /// Return the number of images including end points.
+    pub fn nimages(&self) -> usize {
+        self.images.len()
+    }
+}
+// cd9e6c0c ends here
+
+// [[file:../optim.note::245e9344][245e9344]]
+/// The nudged elastic band (NEB) method for finding minimum energy paths,
+/// with optional climbing image (CI-NEB) for converging the highest-energy
+/// image onto the saddle point.
+///
+/// # References
+///
+/// - Henkelman, G.; Jónsson, H. J. Chem. Phys. 2000, 113, 9978.
+/// - Henkelman, G.; Uberuaga, B. P.; Jónsson, H. J. Chem. Phys. 2000, 113, 9901.
+///
+/// # Examples
+///
+/// ```ignore
+/// let neb = NudgedElasticBand::default()
+///     .variable_spring(0.05, 0.5)
+///     .climbing_after(0.5);
+/// let optimized = neb.optimize_path(&mut path, &mut potential, 0.05, 500)?;
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NudgedElasticBand {
+    spring: f64,
+    max_step: f64,
+    // switch on climbing when band fmax drops below this value
+    climb: Option<f64>,
+    // k_min and k_max for energy-weighted springs
+    spring_range: Option<(f64, f64)>,
+    // reference energy for energy-weighted springs
+    spring_reference: Option<f64>,
+    // file for checkpointing band in each iteration
+    #[serde(skip)]
+    checkpoint: Option<PathBuf>,
+    // writer for band trajectory
+    #[serde(skip)]
+    writer: Option<BandWriter>,
+}
+
+impl Default for NudgedElasticBand {
+    fn default() -> Self {
+        Self {
+            spring: 0.1,
+            max_step: 0.1,
+            climb: None,
+            spring_range: None,
+            spring_reference: None,
+            checkpoint: None,
+            writer: None,
+        }
+    }
+}
+
+/// Results of band optimization in `NudgedElasticBand`.
+#[derive(Debug, Clone)]
+pub struct NebOptimized {
+    /// The number of iterations in optimization loop.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Final fmax criterion of NEB forces on intermediate images.
+    pub fmax: f64,
+    /// Energies of all images including end points.
+    pub energies: Vec<f64>,
+    /// The index of the climbing image, if climbing was switched on.
+    pub climbing_image: Option<usize>,
+}
+
+impl NebOptimized {
+    /// Return the index and energy of the highest image.
+    pub fn highest_image(&self) -> (usize, f64) {
+        let (i, e) = self
+            .energies
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .expect("no image");
+        (i, *e)
+    }
+}
+
+impl NudgedElasticBand {
+    /// Set spring constant between neighboring images.
+    pub fn spring(mut self, k: f64) -> Self {
+        assert!(k > 0.0, "invalid spring constant: {k}");
+        self.spring = k;
+        self
+    }
+
+    /// Use energy-weighted variable spring constants between `k_min` and
+    /// `k_max`, which stiffen springs near the barrier top for better
+    /// resolution around the saddle point. Springs between images below the
+    /// reference energy use `k_min`.
+    pub fn variable_spring(mut self, k_min: f64, k_max: f64) -> Self {
+        assert!(0.0 < k_min && k_min <= k_max, "invalid spring range: {k_min}, {k_max}");
+        self.spring_range = Some((k_min, k_max));
+        self
+    }
+
+    /// Set the reference energy for variable springs. By default, the
+    /// higher energy of the two end points is used.
+    pub fn spring_reference(mut self, energy: f64) -> Self {
+        self.spring_reference = Some(energy);
+        self
+    }
+
+    /// Return spring constants of all segments between neighboring images.
+    fn spring_constants(&self, energies: &[f64]) -> Vec<f64> {
+        let n = energies.len();
+        let Some((k_min, k_max)) = self.spring_range else {
+            return vec![self.spring; n - 1];
+        };
+        let e_ref = self
+            .spring_reference
+            .unwrap_or_else(|| energies[0].max(energies[n - 1]));
+        let e_max = energies.iter().copied().float_max();
+        (1..n)
+            .map(|i| {
+                let e = energies[i].max(energies[i - 1]);
+                if e > e_ref && e_max > e_ref {
+                    k_max - (k_max - k_min) * (e_max - e) / (e_max - e_ref)
+                } else {
+                    k_min
+                }
+            })
+            .collect()
+    }
+
+    /// Save images with optimizer state into `file` in each iteration, for
+    /// resuming band optimization using `NebCheckpoint`.
+    pub fn checkpoint<P: AsRef<std::path::Path>>(mut self, file: P) -> Self {
+        self.checkpoint = Some(file.as_ref().to_owned());
+        self
+    }
+
+    /// Write the band in each iteration using `writer`.
+    pub fn band_writer(mut self, writer: BandWriter) -> Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Set max displacement of images in each step.
+    pub fn max_step(mut self, max_step: f64) -> Self {
+        assert!(max_step > 0.0, "invalid max step: {max_step}");
+        self.max_step = max_step;
+        self
+    }
+
+    /// Let the highest-energy image climb along the band tangent from the
+    /// beginning. The climbing image is selected automatically in each
+    /// iteration.
+    pub fn climbing(mut self) -> Self {
+        self.climb = Some(std::f64::MAX);
+        self
+    }
+
+    /// Switch on climbing image only after the band is partially converged,
+    /// that is, when fmax of NEB forces drops below `fmax`.
+    pub fn climbing_after(mut self, fmax: f64) -> Self {
+        assert!(fmax > 0.0, "invalid fmax for climbing: {fmax}");
+        self.climb = Some(fmax);
+        self
+    }
+
+    /// Compute NEB forces on intermediate images, given `energies` and true
+    /// `forces` of intermediate images. `ci` is the index of the climbing
+    /// image in the band.
+    fn band_forces(
+        &self,
+        images: &[Vec<f64>],
+        energies: &[f64],
+        forces: &[Vec<f64>],
+        ci: Option<usize>,
+    ) -> Vec<Vec<f64>> {
+        let n = images.len();
+        let springs = self.spring_constants(energies);
+        (1..n - 1)
+            .map(|i| {
+                let tau = improved_tangent(&images[i - 1], &images[i], &images[i + 1], &energies[i - 1..=i + 1]);
+                let f = &forces[i - 1];
+                let f_par = f.vecdot(&tau);
+                if ci == Some(i) {
+                    // invert the parallel component without springs
+                    f.iter().zip(&tau).map(|(fi, ti)| fi - 2.0 * f_par * ti).collect()
+                } else {
+                    let d_next = distance(&images[i + 1], &images[i]);
+                    let d_prev = distance(&images[i], &images[i - 1]);
+                    let f_spring = springs[i] * d_next - springs[i - 1] * d_prev;
+                    f.iter()
+                        .zip(&tau)
+                        .map(|(fi, ti)| fi - f_par * ti + f_spring * ti)
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    /// Optimize intermediate images of `path` in `potential` until fmax of NEB
+    /// forces is below `fmax` or `nmax` iterations reached. If climbing is
+    /// enabled, the band is converged only with the climbing image switched
+    /// on.
+    pub fn optimize_path<U>(
+        &self,
+        path: &mut Path,
+        potential: &mut impl EvaluatePotential<U>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized> {
+        self.optimize_path_from(path, BandState::default(), potential, fmax, nmax)
+    }
+
+    fn optimize_path_from<U>(
+        &self,
+        path: &mut Path,
+        start: BandState,
+        potential: &mut impl EvaluatePotential<U>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized> {
+        let dim = path.images[0].len();
+        let mut out = PotentialOutput {
+            energy: std::f64::NAN,
+            force: vec![0.0; dim],
+            stress: None,
+        };
+        self.optimize_band(
+            path,
+            start,
+            |images| {
+                images
+                    .iter()
+                    .map(|(_, x)| {
+                        potential.evaluate(x, &mut out)?;
+                        Ok((out.energy, out.force.clone()))
+                    })
+                    .collect()
+            },
+            fmax,
+            nmax,
+        )
+    }
+
+    /// Optimize `path` like `optimize_path`, but evaluate all images to be
+    /// updated in one call of batch `potential` in each iteration.
+    pub fn optimize_path_batch<U>(
+        &self,
+        path: &mut Path,
+        potential: &mut impl EvaluatePotentialBatch<U>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized> {
+        let dim = path.images[0].len();
+        self.optimize_band(
+            path,
+            BandState::default(),
+            |images| {
+                let positions = images.iter().map(|(_, x)| *x).collect_vec();
+                let mut outputs = vec![
+                    PotentialOutput {
+                        energy: std::f64::NAN,
+                        force: vec![0.0; dim],
+                        stress: None,
+                    };
+                    images.len()
+                ];
+                potential.evaluate_batch(&positions, &mut outputs)?;
+                Ok(outputs.into_iter().map(|o| (o.energy, o.force)).collect())
+            },
+            fmax,
+            nmax,
+        )
+    }
+
+    /// Optimize `path` like `optimize_path`, but evaluate images concurrently
+    /// in threads, one for each potential in `potentials`, such as separate
+    /// `Dynamics` instances or clones of a thread-safe model. The level of
+    /// parallelism is the number of `potentials`. Images are distributed to
+    /// potentials in contiguous chunks.
+    pub fn optimize_path_parallel<P, U>(
+        &self,
+        path: &mut Path,
+        potentials: &mut [P],
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized>
+    where
+        P: EvaluatePotential<U> + Send,
+    {
+        ensure!(!potentials.is_empty(), "no potential for evaluation of images");
+        let dim = path.images[0].len();
+        self.optimize_band(
+            path,
+            BandState::default(),
+            |images| {
+                crate::fd::evaluate_chunked(potentials, images, |potential, (_, x)| {
+                    let mut out = PotentialOutput {
+                        energy: std::f64::NAN,
+                        force: vec![0.0; dim],
+                        stress: None,
+                    };
+                    potential.evaluate(x, &mut out)?;
+                    Ok((out.energy, out.force))
+                })
+            },
+            fmax,
+            nmax,
+        )
+    }
+
+    // Optimize band with images evaluated in batch by `evaluate`, which
+    // takes image indices and positions, and returns energies and forces.
+    fn optimize_band(
+        &self,
+        path: &mut Path,
+        start: BandState,
+        mut evaluate: impl FnMut(&[(usize, &[f64])]) -> Result<Vec<(f64, Vec<f64>)>>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized> {
+        let n = path.nimages();
+        ensure!(n >= 3, "NEB requires at least one intermediate image");
+        let dim = path.images[0].len();
+        let first = path.images[0].clone();
+        let last = path.images[n - 1].clone();
+        let computed = evaluate(&[(0, &first), (n - 1, &last)])?;
+        let (e_first, e_last) = (computed[0].0, computed[1].0);
+
+        let mut ncalls = start.ncalls + 2;
+        let mut climbing = start.climbing;
+        let x_init = path.images[1..n - 1].concat();
+        let steps = fire().with_max_step(self.max_step).with_max_cycles(nmax).minimize_iter(
+            x_init,
+            |x: &[f64], o: &mut fire::Output| {
+                let mut images = vec![first.clone()];
+                images.extend(x.chunks(dim).map(|c| c.to_vec()));
+                images.push(last.clone());
+                let batch = (1..n - 1).map(|i| (i, images[i].as_slice())).collect_vec();
+                let (mut energies, forces): (Vec<_>, Vec<_>) = evaluate(&batch)?.into_iter().unzip();
+                energies.insert(0, e_first);
+                energies.push(e_last);
+                ncalls += n - 2;
+
+                let ci = if climbing {
+                    (1..n - 1).max_by(|&i, &j| energies[i].total_cmp(&energies[j]))
+                } else {
+                    None
+                };
+                let band_forces = self.band_forces(&images, &energies, &forces, ci).concat();
+                let fmax_band = fmax_(&band_forces);
+                if let Some(fmax_climb) = self.climb {
+                    if !climbing && fmax_band < fmax_climb {
+                        info!("switch on climbing image at fmax = {fmax_band}");
+                        climbing = true;
+                    }
+                }
+                o.fx = energies[1..n - 1].iter().sum();
+                o.gx.vecncpy(&band_forces);
+                Ok(NebProgress {
+                    ncalls,
+                    fmax: fmax_band,
+                    energies,
+                    climbing_image: ci,
+                    climbing,
+                    positions: x.to_vec(),
+                })
+            },
+        );
+
+        let mut result = None;
+        for (progress, niter) in steps.map(|p| p.extra).zip(start.niter + 1..) {
+            debug!(
+                "{niter:5} fmax = {:-10.4} Emax = {:-16.6} climbing = {:?}",
+                progress.fmax,
+                progress.energies[1..n - 1].iter().copied().float_max(),
+                progress.climbing_image
+            );
+            let band = || {
+                let mut images = vec![first.clone()];
+                images.extend(progress.positions.chunks(dim).map(|c| c.to_vec()));
+                images.push(last.clone());
+                Path { images }
+            };
+            if let Some(writer) = &self.writer {
+                writer.write(niter, &band(), &progress.energies)?;
+            }
+            if let Some(file) = &self.checkpoint {
+                let ckpt = NebCheckpoint {
+                    neb: self.clone(),
+                    path: band(),
+                    niter,
+                    ncalls: progress.ncalls,
+                    climbing: progress.climbing,
+                };
+                ckpt.save(file)?;
+            }
+            let converged = progress.fmax < fmax && (self.climb.is_none() || progress.climbing_image.is_some());
+            result = Some((niter, progress));
+            if converged {
+                break;
+            }
+        }
+        let (niter, progress) = result.ok_or(format_err!("band optimization failed"))?;
+        for (image, x) in path.images[1..n - 1].iter_mut().zip(progress.positions.chunks(dim)) {
+            image.clone_from_slice(x);
+        }
+
+        Ok(NebOptimized {
+            niter,
+            ncalls: progress.ncalls,
+            fmax: progress.fmax,
+            energies: progress.energies,
+            climbing_image: progress.climbing_image,
+        })
+    }
+}
+
+// Information on band in each iteration.
+struct NebProgress {
+    ncalls: usize,
+    fmax: f64,
+    energies: Vec<f64>,
+    climbing_image: Option<usize>,
+    // climbing switched on for next iteration
+    climbing: bool,
+    positions: Vec<f64>,
+}
+
+// Counters and switches of band optimization to start with.
+#[derive(Debug, Clone, Default)]
+struct BandState {
+    niter: usize,
+    ncalls: usize,
+    climbing: bool,
+}
+// 245e9344 ends here
+
+// [[file:../optim.note::90d4852b][90d4852b]]
+impl NudgedElasticBand {
+    /// Grow `path` from its end points toward the middle until it has `n`
+    /// images in total, in the spirit of the growing string method. New
+    /// images are added next to the two frontier images by interpolation,
+    /// and the partial path is relaxed loosely to 10 times of `fmax` before
+    /// growing again. The complete path is then optimized as a full band,
+    /// with climbing image if enabled. `nmax` limits iterations in each
+    /// relaxation.
+    ///
+    /// Compared to a full pre-interpolated band, fewer images are evaluated
+    /// far from the converged path.
+    pub fn grow_path<U>(
+        &self,
+        path: &mut Path,
+        n: usize,
+        potential: &mut impl EvaluatePotential<U>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<NebOptimized> {
+        ensure!(n >= 3, "invalid number of images: {n}");
+        ensure!(path.nimages() <= n, "path has more than {n} images");
+        let growing = Self {
+            climb: None,
+            ..self.clone()
+        };
+        let (mut niter, mut ncalls) = (0, 0);
+        // the number of images grown from reactant side, including reactant
+        let mut nleft = (path.nimages() + 1) / 2;
+        while path.nimages() < n {
+            let m = n - path.nimages();
+            let left = &path.images[nleft - 1];
+            let right = &path.images[nleft];
+            let t = 1.0 / (m + 1) as f64;
+            let interpolate = |t: f64| left.iter().zip(right).map(|(a, b)| a + t * (b - a)).collect_vec();
+            if m == 1 {
+                let middle = interpolate(0.5);
+                path.images.insert(nleft, middle);
+                nleft += 1;
+            } else {
+                let (new_left, new_right) = (interpolate(t), interpolate(1.0 - t));
+                path.images.insert(nleft, new_right);
+                path.images.insert(nleft, new_left);
+                nleft += 1;
+            }
+            info!("growing path: {} images", path.nimages());
+            let optimized = growing.optimize_path(path, potential, fmax * 10.0, nmax)?;
+            niter += optimized.niter;
+            ncalls += optimized.ncalls;
+        }
+        let mut optimized = self.optimize_path(path, potential, fmax, nmax)?;
+        optimized.niter += niter;
+        optimized.ncalls += ncalls;
+        Ok(optimized)
+    }
+}
+// 90d4852b ends here
+
+// [[file:../optim.note::f8a0dac9][f8a0dac9]]
+/// Checkpoint of band optimization in `NudgedElasticBand`, including all
+/// images, iteration counters and band settings.
+///
+/// The internal state of the FIRE optimizer (velocities, time step and
+/// mixing parameter) is not checkpointed: resumed optimization restarts
+/// FIRE from zero velocities with the initial time step.
+///
+/// # Examples
+///
+/// ```ignore
+/// let neb = NudgedElasticBand::default().climbing().checkpoint("neb.json");
+/// // interrupted run
+/// neb.optimize_path(&mut path, &mut potential, 0.05, 500)?;
+/// // resume from where it stopped
+/// let (path, optimized) = NebCheckpoint::load("neb.json")?.resume(&mut potential, 0.05, 500)?;
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NebCheckpoint {
+    neb: NudgedElasticBand,
+    path: Path,
+    niter: usize,
+    ncalls: usize,
+    climbing: bool,
+}
+
+impl VersionedState for NebCheckpoint {
+    const KIND: &'static str = "neb-checkpoint";
+    const VERSION: u32 = 1;
+
+    // untagged checkpoints share the same layout
+    fn migrate(version: u32, state: serde_json::Value) -> Result<serde_json::Value> {
+        ensure!(
+            version == 0,
+            "no migration path for NEB checkpoint from version {version}"
+        );
+        Ok(state)
+    }
+}
+
+impl NebCheckpoint {
+    /// Load checkpoint from `file`.
+    pub fn load<P: AsRef<std::path::Path>>(file: P) -> Result<Self> {
+        let file = file.as_ref();
+        let mut ckpt = Self::load_from_file(file).with_context(|| format!("load NEB checkpoint {file:?}"))?;
+        // keep checkpointing into the same file
+        ckpt.neb.checkpoint = Some(file.to_owned());
+        Ok(ckpt)
+    }
+
+    fn save(&self, file: &std::path::Path) -> Result<()> {
+        self.save_to_file(file)
+    }
+
+    /// Return the checkpointed path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the number of iterations already done.
+    pub fn niter(&self) -> usize {
+        self.niter
+    }
+
+    /// Resume band optimization in `potential` with checkpointed settings
+    /// and iteration counters, for at most `nmax` more iterations.
+    pub fn resume<U>(
+        self,
+        potential: &mut impl EvaluatePotential<U>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<(Path, NebOptimized)> {
+        let Self {
+            neb,
+            mut path,
+            niter,
+            ncalls,
+            climbing,
+        } = self;
+        info!("resume band optimization from iteration {niter}, with FIRE state reset");
+        let start = BandState {
+            niter,
+            ncalls,
+            climbing,
+        };
+        let optimized = neb.optimize_path_from(&mut path, start, potential, fmax, nmax)?;
+        Ok((path, optimized))
+    }
+}
+// f8a0dac9 ends here
+
+// [[file:../optim.note::16b4cdc7][16b4cdc7]]
+/// Layouts of band trajectory files written by `BandWriter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandLayout {
+    /// One multi-frame file for each iteration, containing all images.
+    PerIteration,
+    /// One multi-frame file for each image, appended in each iteration.
+    PerImage,
+}
+
+/// A writer dumping the full band in extended xyz format in each iteration,
+/// together with a summary of image energies relative to the first image in
+/// "energies.dat", so that barrier profiles can be plotted as the
+/// calculation proceeds.
+#[derive(Debug, Clone)]
+pub struct BandWriter {
+    dir: PathBuf,
+    layout: BandLayout,
+    symbols: Vec<String>,
+    lattice: Option<String>,
+}
+
+impl BandWriter {
+    /// Write band files into directory `dir` for images of molecule `mol`,
+    /// with positions of atoms flattened.
+    pub fn new<P: AsRef<std::path::Path>>(dir: P, mol: &gchemol::Molecule, layout: BandLayout) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(&dir).with_context(|| format!("create directory {dir:?}"))?;
+        let lattice = mol.lattice.as_ref().map(|lat| {
+            let mat = lat.matrix();
+            (0..3).flat_map(|i| (0..3).map(move |k| mat[(k, i)])).join(" ")
+        });
+        Ok(Self {
+            dir,
+            layout,
+            symbols: mol.symbols().map(|s| s.to_string()).collect(),
+            lattice,
+        })
+    }
+
+    /// Write band `path` with image `energies` at iteration `niter`.
+    pub fn write(&self, niter: usize, path: &Path, energies: &[f64]) -> Result<()> {
+        use std::io::Write;
+
+        let natoms = self.symbols.len();
+        ensure!(
+            path.images.iter().all(|x| x.len() == 3 * natoms),
+            "band images do not match {natoms} atoms"
+        );
+        let open = |name: String, append: bool| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(self.dir.join(&name))
+                .with_context(|| format!("open band file {name}"))
+        };
+        match self.layout {
+            BandLayout::PerIteration => {
+                let mut f = open(format!("band-{niter:05}.xyz"), false)?;
+                for (i, (image, e)) in path.images.iter().zip(energies).enumerate() {
+                    f.write_all(self.frame(niter, i, image, *e).as_bytes())?;
+                }
+            }
+            BandLayout::PerImage => {
+                for (i, (image, e)) in path.images.iter().zip(energies).enumerate() {
+                    let mut f = open(format!("image-{i:02}.xyz"), true)?;
+                    f.write_all(self.frame(niter, i, image, *e).as_bytes())?;
+                }
+            }
+        }
+
+        let mut f = open("energies.dat".to_owned(), true)?;
+        let e0 = energies[0];
+        let line = energies.iter().map(|e| format!("{:-14.6}", e - e0)).join(" ");
+        writeln!(f, "{niter:5} {line}")?;
+        Ok(())
+    }
+
+    // Format one image as extended xyz frame.
+    fn frame(&self, niter: usize, image: usize, positions: &[f64], energy: f64) -> String {
+        let mut comment = format!("Properties=species:S:1:pos:R:3 energy={energy} image={image} iteration={niter}");
+        if let Some(lattice) = &self.lattice {
+            comment.push_str(&format!(" Lattice=\"{lattice}\" pbc=\"T T T\""));
+        }
+        let mut lines = vec![self.symbols.len().to_string(), comment];
+        for (s, p) in self.symbols.iter().zip(positions.chunks(3)) {
+            lines.push(format!("{s:3} {:-18.8} {:-18.8} {:-18.8}", p[0], p[1], p[2]));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+// 16b4cdc7 ends here
+
+// [[file:../optim.note::a8a7b1be][a8a7b1be]]
+impl Path {
+    /// Interpolate between `reactant` and `product` with `n` images in total
+    /// using the image dependent pair potential (IDPP), for chemically
+    /// sensible initial paths avoiding atoms too close to each other.
+    /// Positions are flattened Cartesian coordinates of atoms. Periodic
+    /// images are not considered.
+    ///
+    /// The linearly interpolated path is optimized as a band on the IDPP
+    /// surface, in which pair distances of each image are restrained to the
+    /// values interpolated from end points.
+    ///
+    /// # Reference
+    ///
+    /// Smidstrup, S.; Pedersen, A.; Stokbro, K.; Jónsson, H. J. Chem. Phys. 2014, 140, 214106.
+    pub fn interpolate_idpp(reactant: &[f64], product: &[f64], n: usize) -> Result<Self> {
+        ensure!(
+            reactant.len() % 3 == 0,
+            "invalid dimension of positions: {}",
+            reactant.len()
+        );
+        let mut path = Self::interpolate(reactant, product, n);
+        if n < 3 {
+            return Ok(path);
+        }
+        let d_reactant = pair_distances(reactant);
+        let d_product = pair_distances(product);
+        let neb = NudgedElasticBand::default();
+        neb.optimize_band(
+            &mut path,
+            BandState::default(),
+            |images| {
+                images
+                    .iter()
+                    .map(|&(i, x)| {
+                        let t = i as f64 / (n - 1) as f64;
+                        let target = d_reactant
+                            .iter()
+                            .zip(&d_product)
+                            .map(|(a, b)| a + t * (b - a))
+                            .collect_vec();
+                        Ok(idpp(x, &target))
+                    })
+                    .collect()
+            },
+            0.1,
+            100,
+        )?;
+        Ok(path)
+    }
+}
+
+// All pair distances of atoms in flattened `positions`.
+fn pair_distances(positions: &[f64]) -> Vec<f64> {
+    let p = positions.as_3d();
+    let n = p.len();
+    (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .map(|(i, j)| distance(&p[i], &p[j]))
+        .collect()
+}
+
+// The IDPP objective and forces for `positions` with `target` pair distances:
+//
+// S = sum_{i<j} (d_ij^target - d_ij)^2 / d_ij^4
+fn idpp(positions: &[f64], target: &[f64]) -> (f64, Vec<f64>) {
+    let p = positions.as_3d();
+    let n = p.len();
+    let mut energy = 0.0;
+    let mut forces = vec![0.0; positions.len()];
+    let pairs = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j)));
+    for ((i, j), dt) in pairs.zip(target) {
+        let r = [p[i][0] - p[j][0], p[i][1] - p[j][1], p[i][2] - p[j][2]];
+        let d = r.vec2norm();
+        let w = d.powi(-4);
+        energy += w * (dt - d).powi(2);
+        // derivative of the pair term with respect to d
+        let de = -4.0 * w / d * (dt - d).powi(2) - 2.0 * w * (dt - d);
+        for k in 0..3 {
+            forces[3 * i + k] -= de * r[k] / d;
+            forces[3 * j + k] += de * r[k] / d;
+        }
+    }
+    (energy, forces)
+}
+// a8a7b1be ends here
+
+// [[file:../optim.note::3caea9cf][3caea9cf]]
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt()
+}
+
+// The improved tangent estimate using the higher energy neighbor, for
+// avoiding kinks in the band. `energies` are of the previous, current and
+// next images.
+fn improved_tangent(prev: &[f64], current: &[f64], next: &[f64], energies: &[f64]) -> Vec<f64> {
+    let (e_prev, e, e_next) = (energies[0], energies[1], energies[2]);
+    let tau_plus = next.iter().zip(current).map(|(a, b)| a - b).collect_vec();
+    let tau_minus = current.iter().zip(prev).map(|(a, b)| a - b).collect_vec();
+    let tau = if e_next > e && e > e_prev {
+        tau_plus
+    } else if e_next < e && e < e_prev {
+        tau_minus
+    } else {
+        // at extrema, weight by energy differences
+        let dv_max = (e_next - e).abs().max((e_prev - e).abs());
+        let dv_min = (e_next - e).abs().min((e_prev - e).abs());
+        let (wp, wm) = if e_next > e_prev {
+            (dv_max, dv_min)
+        } else {
+            (dv_min, dv_max)
+        };
+        tau_plus.iter().zip(&tau_minus).map(|(p, m)| wp * p + wm * m).collect()
+    };
+    let norm = tau.vec2norm();
+    tau.iter().map(|t| t / norm).collect()
+}
+// 3caea9cf ends here
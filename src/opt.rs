@@ -10,7 +10,30 @@ use vecfx::*;
 // a0979185 ends here
 
 // [[file:../optim.note::5f176b88][5f176b88]]
+use crate::cell::{CellConstraint, CellFilter, NiggliMapping, ScaledCoords, EV_PER_A3_TO_GPA};
+use crate::freeze::CoordsMask;
+use crate::redundant::{DelocalizedInternals, InternalStepper, RedundantInternals};
+use crate::restart::RunSignature;
+use crate::rigid::RigidFragments;
+use crate::sd::GradientDescent;
 use gosh_database::CheckpointDb;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Coordinate system in which optimization steps are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoordinateSystem {
+    /// Steps in Cartesian coordinates, using algorithm set in env vars.
+    #[default]
+    Cartesian,
+    /// Quasi-Newton steps in redundant internal coordinates generated from
+    /// connectivity, which is usually more efficient for floppy molecules.
+    Redundant,
+    /// Quasi-Newton steps in delocalized internal coordinates built from
+    /// redundant internals, with freezing atoms excluded. Bonds in periodic
+    /// systems are found using the minimum image convention.
+    Delocalized,
+}
 
 /// A generic interface for geometry optimization of Molecule.
 pub struct Optimizer {
@@ -18,6 +41,24 @@ pub struct Optimizer {
     nmax: usize,
     ckpt: Option<CheckpointDb>,
     vars: crate::vars::Vars,
+    freezing: Freezing,
+    constraints: Constraints,
+    restraints: Restraints,
+    viewer: Option<LiveViewer>,
+    coordinate_system: CoordinateSystem,
+    // rigid fragments in atom serial numbers
+    rigid: Vec<Vec<usize>>,
+    // target pressure in GPa for variable-cell optimization
+    pressure: Option<f64>,
+    niggli: bool,
+    cell_constraints: Vec<CellConstraint>,
+    fractional: bool,
+    // tolerance for detecting symmetry to be preserved
+    symprec: Option<f64>,
+    fixed_com: bool,
+    mass_weighted: bool,
+    // units of model outputs, and of `fmax`
+    units: Option<Units>,
 }
 
 impl Default for Optimizer {
@@ -27,6 +68,20 @@ impl Default for Optimizer {
             nmax: 100,
             ckpt: None,
             vars: crate::vars::Vars::from_env(),
+            freezing: Freezing::default(),
+            constraints: Constraints::default(),
+            restraints: Restraints::default(),
+            viewer: None,
+            coordinate_system: CoordinateSystem::default(),
+            rigid: vec![],
+            pressure: None,
+            niggli: false,
+            cell_constraints: vec![],
+            fractional: false,
+            symprec: None,
+            fixed_com: false,
+            mass_weighted: false,
+            units: None,
         }
     }
 }
@@ -43,11 +98,171 @@ impl Optimizer {
         }
     }
 
+    /// Return the convergence criterion on max force in eV/Å.
+    pub(crate) fn fmax(&self) -> f64 {
+        match &self.units {
+            Some(units) => Units::new(units.internal).force(self.fmax),
+            None => self.fmax,
+        }
+    }
+
+    /// Return the max number of iterations.
+    pub(crate) fn nmax(&self) -> usize {
+        self.nmax
+    }
+
     /// Set checkpoint for resuming optimization later
     pub fn checkpoint(mut self, ckpt: CheckpointDb) -> Self {
         self.ckpt = ckpt.into();
         self
     }
+
+    /// Use optimization `algorithm` in Cartesian coordinates: "LBFGS",
+    /// "FIRE" or "SD", overriding the setting in env vars.
+    pub fn algorithm(mut self, algorithm: &str) -> Self {
+        self.vars.algorithm = algorithm.into();
+        self
+    }
+
+    /// Take optimization steps in `coordinate_system`.
+    pub fn coordinate_system(mut self, coordinate_system: CoordinateSystem) -> Self {
+        self.coordinate_system = coordinate_system;
+        self
+    }
+
+    /// Treat atoms with serial numbers in `atoms` (counting from 1) as a rigid
+    /// fragment, optimizing only its translation and orientation. Only
+    /// supported in Cartesian coordinate system.
+    pub fn rigid_fragment(mut self, atoms: &[usize]) -> Self {
+        self.rigid.push(atoms.to_vec());
+        self
+    }
+
+    /// Relax lattice vectors together with atoms, minimizing the enthalpy at
+    /// target `pressure` in GPa. The model must provide stress in `Output`.
+    /// Only supported in Cartesian coordinate system.
+    pub fn variable_cell(mut self, pressure: f64) -> Self {
+        self.pressure = Some(pressure);
+        self
+    }
+
+    /// Restrict cell degrees of freedom in variable-cell optimization using
+    /// `constraint`.
+    pub fn constrain_cell(mut self, constraint: CellConstraint) -> Self {
+        self.cell_constraints.push(constraint);
+        self
+    }
+
+    /// Take optimization steps in scaled fractional coordinates of periodic
+    /// molecule, and wrap atoms back into the cell after each step, avoiding
+    /// artificial huge displacements across periodic boundaries. Only
+    /// supported in Cartesian coordinate system.
+    pub fn fractional_coords(mut self) -> Self {
+        self.fractional = true;
+        self
+    }
+
+    /// Take optimization steps in mass-weighted Cartesian coordinates
+    /// sqrt(m) x, with masses taken from the molecule. This improves
+    /// conditioning for systems mixing light and heavy atoms. Only supported
+    /// in Cartesian coordinate system, and not together with fractional
+    /// coordinates.
+    pub fn mass_weighted(mut self) -> Self {
+        self.mass_weighted = true;
+        self
+    }
+
+    /// Evaluate model in units declared in `units`, converting its energy,
+    /// forces and stress into eV and Å used in optimization. The convergence
+    /// criterion `fmax` is taken in the internal units of `units`, and
+    /// converted accordingly. Positions are always passed to the model in Å,
+    /// and `Optimized::computed` keeps the results in model units.
+    pub fn units(mut self, units: Units) -> Self {
+        self.units = Some(units);
+        self
+    }
+
+    /// Niggli-reduce the cell of periodic molecule before optimization, and
+    /// map the results back to the original setting at the end, preventing
+    /// optimization in pathological skewed cells. Only applied in
+    /// `optimize_geometry`.
+    pub fn niggli_reduce(mut self) -> Self {
+        self.niggli = true;
+        self
+    }
+
+    /// Preserve symmetry of the input structure during optimization, by
+    /// symmetrizing forces and steps in each iteration. The point or space
+    /// group is detected with tolerance `symprec` in distance, and small
+    /// symmetry breaking within the tolerance is removed before optimization.
+    /// To use known symmetry operations instead, pass a `Symmetry` object to
+    /// `constrain_with`.
+    pub fn preserve_symmetry(mut self, symprec: f64) -> Self {
+        self.symprec = Some(symprec);
+        self
+    }
+
+    /// Keep the center of mass fixed during optimization, removing the net
+    /// translational force and re-centering positions in each step.
+    pub fn fix_center_of_mass(mut self) -> Self {
+        self.fixed_com = true;
+        self
+    }
+
+    /// Hold `constraint` fixed during optimization.
+    pub fn constrain(mut self, constraint: Constraint) -> Self {
+        self.constraints.add(constraint);
+        self
+    }
+
+    /// Hold a user defined `constraint` during optimization.
+    pub fn constrain_with(mut self, constraint: impl EnforceConstraint + 'static) -> Self {
+        self.constraints.add_custom(constraint);
+        self
+    }
+
+    /// Add bias energy and forces from `restraint` during optimization.
+    pub fn restrain(mut self, restraint: Restraint) -> Self {
+        self.restraints.add(restraint);
+        self
+    }
+
+    /// Add bias energy and forces from `restraint` during optimization, with
+    /// its force constant scaled by lambda in `ramp`. Each evaluation counts
+    /// as a step.
+    pub fn restrain_ramped(mut self, restraint: Restraint, ramp: LambdaSchedule) -> Self {
+        self.restraints.add_ramped(restraint, ramp);
+        self
+    }
+
+    /// Switch restraints on or off smoothly according to lambda in
+    /// `schedule`. Each evaluation counts as a step.
+    pub fn restraint_schedule(mut self, schedule: LambdaSchedule) -> Self {
+        self.restraints.set_schedule(schedule);
+        self
+    }
+
+    /// Stream current geometries to a live viewer during optimization.
+    pub fn viewer(mut self, viewer: LiveViewer) -> Self {
+        self.viewer = viewer.into();
+        self
+    }
+
+    /// Freeze atoms with serial numbers in `serials` (counting from 1, see
+    /// `Freezing::freeze_atoms`) during optimization, without mutating the
+    /// molecule.
+    pub fn freeze_atoms(mut self, serials: &[usize]) -> Self {
+        self.freezing = self.freezing.freeze_atoms(serials);
+        self
+    }
+
+    /// Freeze coordinates using `mask` over flattened coordinates of all atoms
+    /// during optimization, without mutating the molecule. `true` for freezing
+    /// the coordinate.
+    pub fn freeze_coords(mut self, mask: &[bool]) -> Self {
+        self.freezing = self.freezing.freeze_coords(mask);
+        self
+    }
 }
 
 /// A helper struct containing information on optimization.
@@ -58,19 +273,35 @@ pub struct Optimized {
     pub fmax: f64,
     /// Final computed properties in ChemicalModel.
     pub computed: ModelProperties,
+    /// Statistics on final forces and last steps for judging convergence
+    /// quality.
+    pub report: RunReport,
 }
 // 5f176b88 ends here
 
 // [[file:../optim.note::41861a95][41861a95]]
 /// A helper struct represents the output data required for molecular geometry
-/// optimization.
+/// optimization. New fields may be added, so construct it with
+/// `Output::default()`.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct Output {
     pub energy: Option<f64>,
     pub forces: Option<Vec<[f64; 3]>>,
+    /// Stress tensor in Voigt order (xx, yy, zz, yz, xz, xy) in eV/Å^3,
+    /// required for variable-cell optimization.
+    pub stress: Option<[f64; 6]>,
 }
 
 pub trait OptimizeMolecule<U> {
     fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<U>;
+
+    /// Return true once after the potential energy surface of the model has
+    /// changed, e.g. switched to another model in `MultiFidelity`, so that
+    /// optimizers can restart their history. The default is false.
+    fn surface_changed(&mut self) -> bool {
+        false
+    }
 }
 
 impl<T> OptimizeMolecule<ModelProperties> for T
@@ -100,8 +331,14 @@ pub struct OptimizedIter<U> {
     pub ncalls: usize,
     /// Current fmax criterion of forces in optimization.
     pub fmax: f64,
-    /// Current energy in optimization.
+    /// Current energy in optimization, including restraint energy.
     pub energy: f64,
+    /// Current restraint energy in optimization.
+    pub restraint_energy: f64,
+    /// Current lambda scaling restraints in optimization.
+    pub restraint_lambda: f64,
+    /// Timing statistics on model evaluations so far.
+    pub stats: EvalStats,
     /// Extra data returned from user defined OptimizeMolecule trait method
     pub extra: U,
 }
@@ -115,103 +352,437 @@ pub struct OptimizedIter<U> {
 ///
 /// # Return
 ///
-/// Returns an iterator over optimization steps
+/// Returns an iterator over optimization steps, or an error if the
+/// optimization could not be set up for `mol`.
 pub fn optimize_geometry_iter<'a, M, U: 'a>(
     mol: &'a mut Molecule,
     model: &'a mut M,
-) -> Box<dyn Iterator<Item = OptimizedIter<U>> + 'a>
+) -> Result<Box<dyn Iterator<Item = OptimizedIter<U>> + 'a>>
 where
     M: OptimizeMolecule<U>,
 {
-    let vars = crate::vars::Vars::from_env();
-    dbg!(&vars);
-    let coords = mol.positions().collect_vec().concat();
-    let mask = mol.freezing_coords_mask();
-    let mut x_init_masked = mask.apply(&coords);
-
-    if vars.algorithm == "FIRE" {
-        info!("Optimizing using FIRE algorithm ...");
-        let mut opt = fire::fire()
-            .with_max_step(vars.max_step_size)
-            .with_max_cycles(vars.max_evaluations);
-
-        let steps = opt.minimize_iter(x_init_masked, move |x_masked: &[f64], o_masked: &mut fire::Output| {
-            let positions = mask.unmask(x_masked, 0.0).as_3d().to_owned();
-            mol.update_positions(positions);
-            let mut out = Output {
-                energy: None,
-                forces: None,
-            };
-            let extra = model.evaluate(&mol, &mut out)?;
-            let energy = out.energy.expect("evaluate: forget to set energy?");
-            let forces = out.forces.as_ref().expect("evaluate: forget to set forces?");
-            let forces = mask.apply(forces.as_flat());
-            trace!("opt: evaluate PES");
+    Optimizer::default().optimize_geometry_iter(mol, model)
+}
 
-            o_masked.gx.vecncpy(&forces);
-            o_masked.fx = energy;
+/// Evaluation of `mol` in masked coordinates shared by optimization backends.
+struct MaskedEvaluator<'a, M> {
+    mol: &'a mut Molecule,
+    model: &'a mut M,
+    mask: CoordsMask,
+    // positions for filling freezing coords
+    reference: Vec<f64>,
+    rigid: RigidFragments,
+    cell: Option<CellFilter>,
+    // variables of free atoms in scaled fractional coordinates
+    scaled: Option<ScaledCoords>,
+    // variables of free atoms in mass-weighted coordinates: sqrt(m) x
+    mass_weights: Option<Vec<f64>>,
+    constraints: Constraints,
+    restraints: Restraints,
+    neval: usize,
+    // positions in last evaluation, for adjusting steps under constraints
+    last_positions: Option<Vec<f64>>,
+    stats: EvalStats,
+    units: Option<Units>,
+    // the model reported a change of potential energy surface
+    surface_changed: bool,
+}
 
-            let fmax = f3max_(forces.chunks(3));
-            Ok((fmax, extra))
-        });
+/// Data on each evaluation in `MaskedEvaluator`.
+struct Evaluated<U> {
+    fmax: f64,
+    restraint_energy: f64,
+    restraint_lambda: f64,
+    stats: EvalStats,
+    extra: U,
+}
 
-        Box::new(steps.map(|progress| {
-            let (fmax, extra) = progress.extra;
-            OptimizedIter {
-                fmax,
-                extra,
-                ncalls: progress.ncalls,
-                energy: progress.fx,
-            }
-        }))
-    } else {
-        info!("Optimizing using L-BFGS algorithm ...");
-        let mut opt = lbfgs::lbfgs_iter()
-            .with_max_evaluations(vars.max_evaluations)
-            .with_initial_step_size(vars.initial_step_size)
-            .with_max_step_size(vars.max_step_size)
-            .with_max_linesearch(vars.max_linesearch)
-            .with_gradient_only()
-            .with_damping(true)
-            .with_linesearch_gtol(0.999);
-
-        let steps = opt
-            .minimize(x_init_masked, move |x_masked: &[f64], o_masked: &mut lbfgs::Output| {
-                let positions = mask.unmask(x_masked, 0.0).as_3d().to_owned();
-                mol.update_positions(positions);
-                let mut out = Output {
-                    energy: None,
-                    forces: None,
-                };
-                let extra = model.evaluate(&mol, &mut out)?;
-                let energy = out.energy.expect("evaluate: forget to set energy?");
-                let forces = out.forces.as_ref().expect("evaluate: forget to set forces?");
-                let forces = mask.apply(forces.as_flat());
-                trace!("opt: evaluate PES");
-
-                o_masked.gx.vecncpy(&forces);
-                o_masked.fx = energy;
+impl<U> Evaluated<U> {
+    fn into_progress(self, ncalls: usize, energy: f64) -> OptimizedIter<U> {
+        OptimizedIter {
+            ncalls,
+            fmax: self.fmax,
+            energy,
+            restraint_energy: self.restraint_energy,
+            restraint_lambda: self.restraint_lambda,
+            stats: self.stats,
+            extra: self.extra,
+        }
+    }
+}
 
-                let fmax = f3max_(forces.chunks(3));
-                Ok((fmax, extra))
-            })
-            .expect("optimize_geometry_iter");
-
-        Box::new(steps.map(|progress| {
-            let (fmax, extra) = progress.extra;
-            OptimizedIter {
-                fmax,
-                extra,
-                ncalls: progress.ncalls,
-                energy: progress.fx,
+impl<'a, M> MaskedEvaluator<'a, M> {
+    /// Evaluate energy and forces at masked position `x_masked`, with the
+    /// gradient in masked coords written into `gx`. Return total energy and
+    /// evaluated data. Variables of rigid fragments, and then variables of
+    /// cell are placed at the end of `x_masked`.
+    fn evaluate<U>(&mut self, x_masked: &[f64], gx: &mut [f64]) -> Result<(f64, Evaluated<U>)>
+    where
+        M: OptimizeMolecule<U>,
+    {
+        let nrigid = self.rigid.nparams();
+        let ncell = self.cell.as_ref().map_or(0, |cell| cell.nparams());
+        let nfree = x_masked.len() - nrigid - ncell;
+        let (x_masked, params) = x_masked.split_at(nfree);
+        let (params, cell_params) = params.split_at(nrigid);
+        let mut positions = self.mask.unmask(x_masked, 0.0);
+        for (x, (&r, &frozen)) in positions.iter_mut().zip(self.reference.iter().zip(self.mask.frozen())) {
+            if frozen {
+                *x = r;
+            }
+        }
+        if let Some(scaled) = &self.scaled {
+            positions = scaled.to_cartesian(&positions);
+        }
+        if let Some(weights) = &self.mass_weights {
+            positions.iter_mut().zip(weights).for_each(|(x, w)| *x /= w);
+        }
+        self.rigid.place(params, &mut positions);
+        if let Some(cell) = &self.cell {
+            cell.apply(cell_params, &mut positions, self.mol);
+        }
+        if let Some(last) = self.last_positions.as_deref() {
+            let mut step = positions.clone();
+            step.vecadd(last, -1.0);
+            self.constraints.adjust_step(last, &mut step)?;
+            positions.copy_from_slice(last);
+            positions.vecadd(&step, 1.0);
+        } else {
+            self.constraints.enforce(&mut positions)?;
+        }
+        self.last_positions = Some(positions.clone());
+        if self.scaled.is_some() {
+            // wrap into the current cell, which may be deformed
+            let cell =
+                crate::cell::cell_matrix(self.mol).ok_or(format_err!("fractional coordinates require a lattice"))?;
+            let mut wrapped = positions.clone();
+            crate::cell::wrap_positions(&cell, &mut wrapped);
+            self.mol.update_positions(wrapped.as_3d().to_owned());
+        } else {
+            self.mol.update_positions(positions.as_3d().to_owned());
+        }
+        let mut out = Output::default();
+        let start = std::time::Instant::now();
+        let extra = self.model.evaluate(&self.mol, &mut out);
+        self.stats.record(start.elapsed());
+        let extra = extra?;
+        self.surface_changed |= self.model.surface_changed();
+        if let Some(units) = &self.units {
+            out.energy = out.energy.map(|e| units.energy(e));
+            if let Some(forces) = out.forces.as_mut() {
+                forces.iter_mut().flatten().for_each(|f| *f = units.force(*f));
+            }
+            if let Some(stress) = out.stress.as_mut() {
+                stress.iter_mut().for_each(|s| *s = units.stress(*s));
             }
-        }))
+        }
+        let energy = out.energy.expect("evaluate: forget to set energy?");
+        let forces = out.forces.as_ref().expect("evaluate: forget to set forces?");
+        let mut forces = forces.as_flat().to_vec();
+        // each evaluation counts as a step in lambda schedule
+        let step = self.neval;
+        self.neval += 1;
+        let restraint_energy = self.restraints.apply(step, &positions, &mut forces)?;
+        let mut energy = energy + restraint_energy;
+        self.constraints.project_forces(&positions, &mut forces)?;
+        trace!("opt: evaluate PES");
+
+        let mut gradient = forces;
+        gradient.iter_mut().for_each(|x| *x = -*x);
+        let mut cell_gradient = vec![];
+        if let Some(cell) = &self.cell {
+            let stress = out
+                .stress
+                .ok_or(format_err!("no stress for variable-cell optimization"))?;
+            energy += cell.pv(cell_params);
+            cell_gradient = cell.gradient(cell_params, stress);
+            cell.transform_gradient(cell_params, &mut gradient);
+        }
+        gx[nfree..nfree + nrigid].copy_from_slice(&self.rigid.gradient(params, &gradient));
+        gx[nfree + nrigid..].copy_from_slice(&cell_gradient);
+        // judge convergence using Cartesian forces
+        let gfree = self.mask.apply(&gradient);
+        let fmax = f3max_(gfree.chunks(3).chain(gx[nfree..].chunks(3)));
+        if let Some(scaled) = &self.scaled {
+            scaled.transform_gradient(&mut gradient);
+        }
+        if let Some(weights) = &self.mass_weights {
+            gradient.iter_mut().zip(weights).for_each(|(g, w)| *g /= w);
+        }
+        gx[..nfree].copy_from_slice(&self.mask.apply(&gradient));
+        let evaluated = Evaluated {
+            fmax,
+            restraint_energy,
+            restraint_lambda: self.restraints.lambda(step),
+            stats: self.stats.clone(),
+            extra,
+        };
+        Ok((energy, evaluated))
     }
 }
 // b17504d6 ends here
 
 // [[file:../optim.note::315bd793][315bd793]]
+/// The number of last steps included in `RunReport`.
+const NSTEPS_REPORT: usize = 10;
+
 impl Optimizer {
+    /// Optimize geometry of `mol` in potential provided by `model` (iterator
+    /// version), using settings of this optimizer.
+    ///
+    /// # Return
+    ///
+    /// Returns an iterator over optimization steps, or an error if the
+    /// optimization could not be set up for `mol`.
+    pub fn optimize_geometry_iter<'a, M, U: 'a>(
+        &self,
+        mol: &'a mut Molecule,
+        model: &'a mut M,
+    ) -> Result<Box<dyn Iterator<Item = OptimizedIter<U>> + 'a>>
+    where
+        M: OptimizeMolecule<U>,
+    {
+        let vars = &self.vars;
+        let mut constraints = self.constraints.clone();
+        if let Some(symprec) = self.symprec {
+            let symmetry = Symmetry::detect(mol, symprec).context("detect symmetry for optimization")?;
+            info!("detected {} symmetry operations", symmetry.nops());
+            let mut positions = mol.positions().collect_vec().concat();
+            symmetry.symmetrize_positions(&mut positions);
+            mol.update_positions(positions.as_3d().to_owned());
+            constraints.add_custom(symmetry);
+        }
+        if self.fixed_com {
+            let com = FixedCenterOfMass::from_molecule(mol).context("fix center of mass")?;
+            constraints.add_custom(com);
+        }
+        let coords = mol.positions().collect_vec().concat();
+        let numbers = mol.numbers().collect_vec();
+        let rigid: Vec<_> = self
+            .rigid
+            .iter()
+            .map(|fragment| {
+                fragment
+                    .iter()
+                    .map(|n| {
+                        let i = numbers.iter().position(|m| m == n);
+                        i.ok_or(format_err!("invalid atom in rigid fragment: {n}"))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<_>>()?;
+        let rigid = RigidFragments::new(&rigid, &coords);
+        let mask = self.freezing.coords_mask(mol)?.with_atoms(&rigid.atoms());
+        let scaled = self.fractional.then(|| ScaledCoords::new(mol)).transpose()?;
+        ensure!(
+            !(self.mass_weighted && self.fractional),
+            "mass-weighted coordinates not supported with fractional coordinates"
+        );
+        let mass_weights = if self.mass_weighted {
+            let masses = crate::constraint::atom_masses(mol).context("mass-weighted coordinates")?;
+            Some(masses.iter().flat_map(|m| [m.sqrt(); 3]).collect_vec())
+        } else {
+            None
+        };
+        // frozen coords are filled in the same coordinates as variables
+        let reference = match (&scaled, &mass_weights) {
+            (Some(scaled), _) => scaled.to_scaled(&coords),
+            (None, Some(weights)) => coords.iter().zip(weights).map(|(x, w)| x * w).collect(),
+            (None, None) => coords.clone(),
+        };
+        let mut x_init_masked = mask.apply(&reference);
+        x_init_masked.extend(rigid.initial_params(&coords));
+        let cell = match self.pressure {
+            Some(p) => {
+                let cell = CellFilter::new(mol, p / EV_PER_A3_TO_GPA)?.with_constraints(&self.cell_constraints);
+                x_init_masked.extend(cell.initial_params());
+                Some(cell)
+            }
+            None => None,
+        };
+        // diagonal of model Hessian in variables of unfrozen coords, not
+        // available in fractional coords
+        let model_diag = (vars.model_hessian_scaling && scaled.is_none()).then(|| {
+            let mut diag = crate::hessian::lindh_hessian_sparse(mol).diagonal();
+            if let Some(weights) = &mass_weights {
+                diag.iter_mut().zip(weights).for_each(|(d, w)| *d /= w * w);
+            }
+            mask.apply(&diag)
+        });
+        // inverse diagonal of model Hessian for preconditioning steepest descent
+        let preconditioner = match &model_diag {
+            Some(diag) if vars.algorithm == "SD" => {
+                let mean = diag.iter().sum::<f64>() / diag.len().max(1) as f64;
+                let mut inv_diag = diag.iter().map(|&d| 1.0 / d.max(0.1 * mean)).collect_vec();
+                inv_diag.resize(x_init_masked.len(), 1.0 / mean);
+                Some(inv_diag).filter(|_| mean > 0.0)
+            }
+            _ => None,
+        };
+        let initial_step_size = model_diag.as_deref().and_then(|diag| {
+            let step_size = crate::hessian::initial_step_size_from_model_hessian(diag)?;
+            info!("initial step size from Lindh model Hessian: {step_size}");
+            Some(step_size)
+        });
+
+        let mut evaluator = MaskedEvaluator {
+            mol,
+            model,
+            mask,
+            reference,
+            rigid,
+            cell,
+            scaled,
+            mass_weights,
+            constraints,
+            restraints: self.restraints.clone(),
+            neval: 0,
+            stats: EvalStats::default(),
+            last_positions: None,
+            // model units into eV and Å
+            units: self.units.map(|units| Units::new(units.model)),
+            surface_changed: false,
+        };
+        let steps: Box<dyn Iterator<Item = _> + 'a> = if self.coordinate_system != CoordinateSystem::Cartesian {
+            ensure!(
+                evaluator.rigid.is_empty()
+                    && evaluator.cell.is_none()
+                    && evaluator.scaled.is_none()
+                    && evaluator.mass_weights.is_none(),
+                "rigid fragments, variable cell, fractional or mass-weighted coords only supported in Cartesian coordinate system"
+            );
+            let internals = RedundantInternals::from_molecule(evaluator.mol).with_frozen(evaluator.mask.frozen());
+            info!("generated {} redundant internal coordinates", internals.coords().len());
+            let mut stepper = if self.coordinate_system == CoordinateSystem::Delocalized {
+                info!("Optimizing in delocalized internal coordinates ...");
+                let dlc = DelocalizedInternals::new(internals, &evaluator.reference)?;
+                info!("constructed {} delocalized internal coordinates", dlc.dim());
+                InternalStepper::delocalized(dlc, vars.max_step_size)
+            } else {
+                info!("Optimizing in redundant internal coordinates ...");
+                InternalStepper::new(internals, vars.max_step_size)
+            };
+            // Lindh model Hessian counts no bonds across cell boundaries
+            if evaluator.mol.lattice.is_none() {
+                let hx = crate::hessian::lindh_hessian(evaluator.mol);
+                stepper = stepper.with_model_hessian(&evaluator.reference, &hx)?;
+                info!("initial Hessian in internal coordinates from Lindh model Hessian");
+            }
+            let mut x_masked = x_init_masked;
+            let steps = std::iter::from_fn(move || {
+                let mut gx = vec![0.0; x_masked.len()];
+                let (energy, evaluated) = evaluator
+                    .evaluate(&x_masked, &mut gx)
+                    .map_err(|e| error!("failed to evaluate: {e:?}"))
+                    .ok()?;
+                let gx = evaluator.mask.unmask(&gx, 0.0);
+                let positions = evaluator.last_positions.as_deref().expect("no positions evaluated");
+                let new = stepper
+                    .step(positions, &gx)
+                    .map_err(|e| error!("failed to step in internal coordinates: {e:?}"))
+                    .ok()?;
+                x_masked = evaluator.mask.apply(&new);
+                Some(evaluated.into_progress(evaluator.neval, energy))
+            });
+            let steps: Box<dyn Iterator<Item = _>> = if vars.max_evaluations > 0 {
+                Box::new(steps.take(vars.max_evaluations))
+            } else {
+                Box::new(steps)
+            };
+            steps
+        } else if vars.algorithm == "SD" {
+            info!(
+                "Optimizing using steepest descent with {:?} step size ...",
+                vars.step_size_rule
+            );
+            let mut sd = match preconditioner {
+                Some(p) => GradientDescent::new(vars.step_size_rule, 1.0, vars.max_step_size).with_preconditioner(p),
+                None => GradientDescent::new(vars.step_size_rule, vars.initial_step_size, vars.max_step_size),
+            };
+            let mut x_masked = x_init_masked;
+            let steps = std::iter::from_fn(move || {
+                let mut gx = vec![0.0; x_masked.len()];
+                let (energy, evaluated) = evaluator
+                    .evaluate(&x_masked, &mut gx)
+                    .map_err(|e| error!("failed to evaluate: {e:?}"))
+                    .ok()?;
+                x_masked = sd.step(&x_masked, &gx);
+                Some(evaluated.into_progress(evaluator.neval, energy))
+            });
+            let steps: Box<dyn Iterator<Item = _>> = if vars.max_evaluations > 0 {
+                Box::new(steps.take(vars.max_evaluations))
+            } else {
+                Box::new(steps)
+            };
+            steps
+        } else if vars.algorithm == "FIRE" {
+            info!("Optimizing using FIRE algorithm ...");
+            let mut opt = fire::fire()
+                .with_max_step(vars.max_step_size)
+                .with_max_cycles(vars.max_evaluations);
+
+            let steps = opt.minimize_iter(x_init_masked, move |x_masked: &[f64], o_masked: &mut fire::Output| {
+                let (energy, evaluated) = evaluator.evaluate(x_masked, &mut o_masked.gx)?;
+                o_masked.fx = energy;
+                Ok(evaluated)
+            });
+
+            Box::new(steps.map(|progress| progress.extra.into_progress(progress.ncalls, progress.fx)))
+        } else {
+            info!("Optimizing using L-BFGS algorithm ...");
+            let initial_step_size = initial_step_size.unwrap_or(vars.initial_step_size);
+            let (max_evaluations, max_step_size, max_linesearch) =
+                (vars.max_evaluations, vars.max_step_size, vars.max_linesearch);
+            // shared by restarted runs when the potential energy surface
+            // changed, as the history of L-BFGS is then invalid
+            let evaluator = Rc::new(RefCell::new(evaluator));
+            let last_x = Rc::new(RefCell::new(x_init_masked.clone()));
+            let start = {
+                let evaluator = evaluator.clone();
+                let last_x = last_x.clone();
+                move |x_init: Vec<f64>,
+                      max_evaluations: usize|
+                      -> Result<Box<dyn Iterator<Item = (Evaluated<U>, f64)> + 'a>> {
+                    let mut opt = lbfgs::lbfgs_iter()
+                        .with_max_evaluations(max_evaluations)
+                        .with_initial_step_size(initial_step_size)
+                        .with_max_step_size(max_step_size)
+                        .with_max_linesearch(max_linesearch)
+                        .with_gradient_only()
+                        .with_damping(true)
+                        .with_linesearch_gtol(0.999);
+                    let (evaluator, last_x) = (evaluator.clone(), last_x.clone());
+                    let steps = opt.minimize(x_init, move |x_masked: &[f64], o_masked: &mut lbfgs::Output| {
+                        last_x.borrow_mut().clone_from_slice(x_masked);
+                        let (energy, evaluated) = evaluator.borrow_mut().evaluate(x_masked, &mut o_masked.gx)?;
+                        o_masked.fx = energy;
+                        Ok(evaluated)
+                    })?;
+                    Ok(Box::new(steps.map(|progress| (progress.extra, progress.fx))))
+                }
+            };
+
+            let mut steps = start(x_init_masked, max_evaluations)?;
+            Box::new(std::iter::from_fn(move || {
+                let (evaluated, energy) = steps.next()?;
+                let neval = evaluator.borrow().neval;
+                if std::mem::take(&mut evaluator.borrow_mut().surface_changed) {
+                    info!("potential energy surface changed: restart L-BFGS");
+                    let remaining = max_evaluations.saturating_sub(neval);
+                    steps = if max_evaluations > 0 && remaining == 0 {
+                        Box::new(std::iter::empty())
+                    } else {
+                        start(last_x.borrow().clone(), remaining)
+                            .map_err(|e| error!("failed to restart L-BFGS: {e:?}"))
+                            .ok()?
+                    };
+                }
+                Some(evaluated.into_progress(neval, energy))
+            }))
+        };
+        Ok(steps)
+    }
+
     /// Optimize geometry of `mol` in potential provided by `model`.
     ///
     /// # Parameters
@@ -223,40 +794,94 @@ impl Optimizer {
     ///
     /// Returns the computed `ModelProperties` on success in final geometry.
     ///
-    pub fn optimize_geometry<M: ChemicalModel>(&self, mol: &mut Molecule, model: &mut M) -> Result<Optimized> {
+    pub fn optimize_geometry<M>(&self, mol: &mut Molecule, model: &mut M) -> Result<Optimized>
+    where
+        M: OptimizeMolecule<ModelProperties>,
+    {
         // restore Molecule from ckpt
         if let Some(ckpt) = &self.ckpt {
+            let signature = RunSignature::new(mol, &self.freezing.coords_mask(mol)?, &self.constraints);
             ckpt.restore(mol).context("restore optimized molecule from ckpt")?;
+            signature.check_restart(mol)?;
+            signature.store(mol);
         }
 
-        let steps = self::optimize_geometry_iter(mol, model);
+        let niggli = if self.niggli && mol.lattice.is_some() {
+            info!("optimize in Niggli reduced cell");
+            Some(NiggliMapping::apply(mol)?)
+        } else {
+            None
+        };
+
+        // for excluding forces on freezing coords in final report
+        let mask = self.freezing.coords_mask(mol)?;
+        let steps = self.optimize_geometry_iter(mol, model)?;
 
         let mut computed = None;
         let mut niter = 0;
         let mut fmax = std::f64::NAN;
+        let mut last_position: Option<Vec<f64>> = None;
+        let mut last_steps = std::collections::VecDeque::with_capacity(NSTEPS_REPORT);
+        let mut stats = EvalStats::default();
+        let fmax_converged = self.fmax();
         for (progress, i) in steps.take(self.nmax).zip(1..) {
+            let mol = progress.extra.get_molecule().expect("no mol in mp");
             // checkpointing
             if let Some(ckpt) = &self.ckpt {
-                let mol = progress.extra.get_molecule().expect("no mol in mp");
                 ckpt.commit(mol);
             }
 
+            if let Some(viewer) = &self.viewer {
+                if let Err(e) = viewer.observe(i, mol, Some(progress.energy)) {
+                    warn!("failed to send structure to viewer: {e:?}");
+                }
+            }
+
+            // record step sizes of the last iterations
+            let position = mol.positions().flatten().collect_vec();
+            if let Some(last) = last_position.as_ref() {
+                let step_size = (position.as_vector_slice() - last.as_vector_slice()).norm();
+                if last_steps.len() == NSTEPS_REPORT {
+                    last_steps.pop_front();
+                }
+                last_steps.push_back(step_size);
+            }
+            last_position = Some(position);
+
             niter = i;
             fmax = progress.fmax;
+            stats = progress.stats;
             computed = progress.extra.into();
             println!("iter {:4}\tEnergy = {:-12.4}\tfmax={}", i, progress.energy, fmax);
-            if fmax < self.fmax {
+            if fmax < fmax_converged {
                 info!("forces converged: {}", fmax);
                 break;
             }
         }
 
+        if let Some(niggli) = niggli {
+            niggli.restore(mol)?;
+        }
+
         // FIXME: it is better to use `OptimizedIter`?
-        let mp = computed.ok_or(format_err!("model was not computed"))?;
+        let mp: ModelProperties = computed.ok_or(format_err!("model was not computed"))?;
+        let forces = mp.get_forces().ok_or(format_err!("no forces"))?;
+        let mut forces = mask.unmask(&mask.apply(forces.as_flat()), 0.0);
+        if let Some(units) = &self.units {
+            let units = Units::new(units.model);
+            forces.iter_mut().for_each(|f| *f = units.force(*f));
+        }
+        let report = RunReport {
+            forces: ForceStats::from_forces(forces.as_3d()),
+            steps: StepStats::from_steps(last_steps),
+            evaluations: stats,
+        };
+        info!("optimization report:\n{report}");
         let optimized = Optimized {
             niter,
             fmax,
             computed: mp,
+            report,
         };
 
         Ok(optimized)
@@ -27,6 +27,15 @@ pub fn optimize<'a, U: 'a>(potential: &'a mut Dynamics<U>) -> Box<dyn Iterator<I
 where
     U: Clone,
 {
+    optimize_with(potential, |extra| extra.clone())
+}
+
+// Optimize `potential` with payload in `OptimProgress` extracted from user
+// data of each evaluation by calling `extract`.
+fn optimize_with<'a, U: 'a, T: 'a>(
+    potential: &'a mut Dynamics<U>,
+    mut extract: impl FnMut(&U) -> T + 'a,
+) -> Box<dyn Iterator<Item = OptimProgress<T>> + 'a> {
     let vars = Vars::from_env();
     if vars.algorithm == "FIRE" {
         info!("Optimizing using FIRE algorithm ...");
@@ -40,8 +49,8 @@ where
             let force = potential.get_force()?;
             o.fx = energy;
             o.gx.vecncpy(force);
-            let fmax = fmax_(force);
-            let extra = potential.get_extra()?.clone();
+            let fmax = potential.fmax()?;
+            let extra = extract(potential.get_extra()?);
             let ncalls = potential.ncalls();
             let progress = OptimProgress {
                 ncalls,
@@ -71,9 +80,9 @@ where
                 let force = potential.get_force()?;
                 o.fx = energy;
                 o.gx.vecncpy(force);
-                let fmax = fmax_(force);
+                let fmax = potential.fmax()?;
                 let ncalls = potential.ncalls();
-                let extra = potential.get_extra()?.clone();
+                let extra = extract(potential.get_extra()?);
                 let progress = OptimProgress {
                     ncalls,
                     fmax,
@@ -87,3 +96,259 @@ where
     }
 }
 // fe25e584 ends here
+
+// [[file:../optim.note::b18ac88e][b18ac88e]]
+/// Final result of `optimize_fold`.
+#[derive(Debug, Clone)]
+pub struct OptimFolded<A> {
+    /// The number of iterations in optimization loop.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Final fmax criterion of forces.
+    pub fmax: f64,
+    /// Final energy.
+    pub energy: f64,
+    /// User data accumulated over all evaluations.
+    pub aggregate: A,
+}
+
+/// Optimize `potential` until max force below `fmax` or `nmax` iterations,
+/// accumulating user data of each evaluation by calling `fold` with the
+/// aggregate so far, starting from `init`.
+///
+/// User data is passed by reference, so no per-step clone is required, such
+/// as averaging dipoles or collecting charges from large payloads.
+pub fn optimize_fold<U, A>(
+    potential: &mut Dynamics<U>,
+    fmax: f64,
+    nmax: usize,
+    init: A,
+    mut fold: impl FnMut(A, &U) -> A,
+) -> Result<OptimFolded<A>> {
+    let mut aggregate = Some(init);
+    let mut last = None;
+    {
+        let steps = optimize_with(potential, |extra| {
+            let acc = aggregate.take().expect("aggregate");
+            aggregate = Some(fold(acc, extra));
+        });
+        for (progress, niter) in steps.take(nmax).zip(1..) {
+            let converged = progress.fmax < fmax;
+            last = Some((niter, progress));
+            if converged {
+                break;
+            }
+        }
+    }
+    let (niter, progress) = last.ok_or(format_err!("no optimization step"))?;
+    Ok(OptimFolded {
+        niter,
+        ncalls: progress.ncalls,
+        fmax: progress.fmax,
+        energy: progress.energy,
+        aggregate: aggregate.expect("aggregate"),
+    })
+}
+// b18ac88e ends here
+
+// [[file:../optim.note::6610e557][6610e557]]
+/// A scalar constraint c(x) = 0, returning the value at position (the first
+/// parameter) with its gradient written into the second parameter.
+pub type ScalarConstraint<'a> = &'a dyn Fn(&[f64], &mut [f64]) -> f64;
+
+/// Final result of `optimize_constrained`.
+#[derive(Debug, Clone)]
+pub struct OptimConstrained {
+    /// The number of inner iterations in total.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Final fmax of the gradient of Lagrangian.
+    pub fmax: f64,
+    /// Final energy, excluding constraint terms.
+    pub energy: f64,
+    /// Lagrange multipliers of constraints.
+    pub multipliers: Vec<f64>,
+    /// The largest absolute value of constraint functions.
+    pub violation: f64,
+}
+
+/// The tolerance on constraint violation |c(x)| for convergence.
+const CONSTRAINT_TOLERANCE: f64 = 1e-6;
+
+/// The max number of updates of Lagrange multipliers.
+const MAX_OUTER_ITERATIONS: usize = 50;
+
+/// Minimize `potential` subject to `constraints` c_i(x) = 0 using the
+/// augmented Lagrangian method:
+///
+/// L(x) = E(x) - Σ λ_i c_i(x) + μ/2 Σ c_i(x)^2
+///
+/// L is minimized using L-BFGS until its fmax below `fmax`, and then
+/// multipliers are updated as λ_i ← λ_i - μ c_i, with the penalty μ increased
+/// when the violation does not decrease enough. Stop when all constraints are
+/// satisfied, or after `nmax` inner iterations in total.
+pub fn optimize_constrained<U>(
+    potential: &mut Dynamics<U>,
+    constraints: &[ScalarConstraint],
+    fmax: f64,
+    nmax: usize,
+) -> Result<OptimConstrained> {
+    let vars = Vars::from_env();
+    let n = potential.position().len();
+    let mut lambda = vec![0.0; constraints.len()];
+    let mut mu = 10.0;
+    let mut niter = 0;
+    let mut last_violation = std::f64::INFINITY;
+    let eval_constraints = |x: &[f64]| constraints.iter().map(|c| c(x, &mut vec![0.0; n])).collect_vec();
+    for _ in 0..MAX_OUTER_ITERATIONS {
+        let mut fmax_l = std::f64::NAN;
+        {
+            let mut opt = lbfgs_iter()
+                .with_max_evaluations(vars.max_evaluations)
+                .with_initial_step_size(vars.initial_step_size)
+                .with_max_step_size(vars.max_step_size)
+                .with_max_linesearch(vars.max_linesearch)
+                .with_gradient_only()
+                .with_damping(true)
+                .with_linesearch_gtol(0.999);
+            let x_init = potential.position().to_vec();
+            let lambda = &lambda;
+            let steps = opt.minimize(x_init, |x: &[f64], o: &mut lbfgs::Output| {
+                potential.set_position(x);
+                let mut fx = potential.get_energy()?;
+                o.gx.vecncpy(potential.get_force()?);
+                let mut gc = vec![0.0; n];
+                for (c, l) in constraints.iter().zip(lambda) {
+                    let ci = c(x, &mut gc);
+                    fx += -l * ci + 0.5 * mu * ci * ci;
+                    o.gx.vecadd(&gc, mu * ci - l);
+                }
+                o.fx = fx;
+                Ok(fmax_(o.gx.iter()))
+            })?;
+            for progress in steps.take(nmax - niter) {
+                niter += 1;
+                fmax_l = progress.extra;
+                if fmax_l < fmax {
+                    break;
+                }
+            }
+        }
+
+        let c = eval_constraints(potential.position());
+        let violation = fmax_(&c);
+        if fmax_l < fmax && violation < CONSTRAINT_TOLERANCE || niter >= nmax {
+            return Ok(OptimConstrained {
+                niter,
+                ncalls: potential.ncalls(),
+                fmax: fmax_l,
+                energy: potential.get_energy()?,
+                multipliers: lambda,
+                violation,
+            });
+        }
+        for (l, ci) in lambda.iter_mut().zip(&c) {
+            *l -= mu * ci;
+        }
+        if violation > 0.25 * last_violation {
+            mu *= 10.0;
+        }
+        last_violation = violation;
+    }
+    bail!("constrained optimization not converged in {MAX_OUTER_ITERATIONS} updates of multipliers");
+}
+// 6610e557 ends here
+
+// [[file:../optim.note::9f06c851][9f06c851]]
+/// Final result of `optimize_mecp`.
+#[derive(Debug, Clone)]
+pub struct OptimCrossing {
+    /// The number of iterations in optimization loop.
+    pub niter: usize,
+    /// The number of calls for potential evaluation on both states.
+    pub ncalls: usize,
+    /// Final fmax of the effective gradient.
+    pub fmax: f64,
+    /// Final energy of the first state.
+    pub energy1: f64,
+    /// Final energy of the second state.
+    pub energy2: f64,
+    /// The geometry on the crossing seam.
+    pub position: Vec<f64>,
+}
+
+/// Locate the minimum energy crossing point (MECP) between two potential
+/// surfaces, such as singlet and triplet states, using the gradient
+/// projection scheme of Harvey et al. The effective gradient is
+///
+/// G = (E1 - E2) x + (g1 - (g1·x) x), x = (g1 - g2) / |g1 - g2|
+///
+/// which drives the energy gap to zero while minimizing E1 on the seam. Both
+/// `state1` and `state2` start from the position of `state1`, and end at the
+/// crossing point. Stop when fmax of G is below `fmax` or `nmax` iterations
+/// reached.
+///
+/// # Reference
+///
+/// Harvey, J. N.; Aschi, M.; Schwarz, H.; Koch, W. Theor. Chem. Acc. 1998, 99, 95.
+pub fn optimize_mecp<U1, U2>(
+    state1: &mut Dynamics<U1>,
+    state2: &mut Dynamics<U2>,
+    fmax: f64,
+    nmax: usize,
+) -> Result<OptimCrossing> {
+    ensure!(
+        state1.position().len() == state2.position().len(),
+        "two states differ in dimension"
+    );
+    let vars = Vars::from_env();
+    let mut opt = lbfgs_iter()
+        .with_max_evaluations(vars.max_evaluations)
+        .with_initial_step_size(vars.initial_step_size)
+        .with_max_step_size(vars.max_step_size)
+        .with_max_linesearch(vars.max_linesearch)
+        .with_gradient_only()
+        .with_damping(true)
+        .with_linesearch_gtol(0.999);
+    let x_init = state1.position().to_vec();
+    let steps = opt.minimize(x_init, |x: &[f64], o: &mut lbfgs::Output| {
+        state1.set_position(x);
+        state2.set_position(x);
+        let e1 = state1.get_energy()?;
+        let e2 = state2.get_energy()?;
+        let f1 = state1.get_force()?;
+        let f2 = state2.get_force()?;
+        // unit vector along the gradient difference
+        let mut dg: Vec<f64> = f2.iter().zip(f1).map(|(a, b)| a - b).collect();
+        let norm = dg.vec2norm();
+        ensure!(norm > 0.0, "degenerate gradients of two states");
+        dg.iter_mut().for_each(|v| *v /= norm);
+        let g_par = -f1.vecdot(&dg);
+        o.gx.vecncpy(f1);
+        o.gx.vecadd(&dg, e1 - e2 - g_par);
+        o.fx = e1;
+        Ok((e1, e2, fmax_(o.gx.iter())))
+    })?;
+
+    let mut result = None;
+    for (progress, niter) in steps.take(nmax).zip(1..) {
+        let (e1, e2, fmax_g) = progress.extra;
+        debug!("{niter:5} E1 = {e1:-16.6} E2 = {e2:-16.6} fmax = {fmax_g:-10.4}");
+        result = Some((niter, e1, e2, fmax_g));
+        if fmax_g < fmax {
+            break;
+        }
+    }
+    let (niter, energy1, energy2, fmax) = result.ok_or(format_err!("MECP optimization failed"))?;
+    Ok(OptimCrossing {
+        niter,
+        ncalls: state1.ncalls() + state2.ncalls(),
+        fmax,
+        energy1,
+        energy2,
+        position: state1.position().to_vec(),
+    })
+}
+// 9f06c851 ends here
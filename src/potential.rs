@@ -19,18 +19,28 @@ use super::*;
 // cc2b4eb6 ends here
 
 // [[file:../optim.note::0aa9588b][0aa9588b]]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 /// A helper struct represents the output data required for dynamics simulation.
 pub struct PotentialOutput {
     /// evaluated potential energy
     pub energy: f64,
     /// evaluated force, the negative of the gradient of potential
     pub force: Vec<f64>,
+    /// evaluated stress tensor in Voigt order (xx, yy, zz, yz, xz, xy), as
+    /// the derivative of energy with respect to strain divided by volume.
+    /// Required for variable-cell optimization only.
+    pub stress: Option<[f64; 6]>,
 }
 
 /// Trait for potential evaluation in dynamics simulation
 pub trait EvaluatePotential<U> {
     fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<U>;
+
+    /// Set time `limit` for each evaluation, or no limit if `None`. Return
+    /// false if the limit cannot be enforced by the potential, as by default.
+    fn set_time_limit(&mut self, _limit: Option<Duration>) -> bool {
+        false
+    }
 }
 
 impl<T> EvaluatePotential<()> for T
@@ -46,7 +56,7 @@ where
 // 0aa9588b ends here
 
 // [[file:../optim.note::9e96c6e5][9e96c6e5]]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 struct State {
     position: Vec<f64>,
     evaluated: Option<PotentialOutput>,
@@ -62,8 +72,13 @@ impl State {
 }
 
 /// A potential walker for dynamic simulation
-pub struct Dynamics<'a, U> {
-    f: Box<dyn EvaluatePotential<U> + 'a>,
+///
+/// The potential is boxed as a trait object `P`, which could be bounded with
+/// `Send` and `Sync` as in `DynamicsSync` for driving from worker threads,
+/// and so are the hooks `H` registered by `on_evaluated`.
+pub struct Dynamics<'a, U, P: ?Sized = dyn EvaluatePotential<U> + 'a, H: ?Sized = dyn EvalHook + 'a> {
+    f: Box<P>,
+    _potential: std::marker::PhantomData<&'a ()>,
 
     state: State,
     // cache previous point
@@ -72,25 +87,164 @@ pub struct Dynamics<'a, U> {
 
     // user returned data in `evaluate` method of `EvaluatePotential` trait
     user_data: Option<U>,
+
+    // published snapshots for readers in other threads
+    shared: Option<SharedSnapshot>,
+
+    // evaluated results of recently visited positions
+    cache: Option<EvalCache<U>>,
+
+    // the last evaluated states for rollback
+    history: VecDeque<DynamicsSnapshot>,
+    history_size: usize,
+
+    // policy for retrying failed evaluations
+    retry: Option<Retry>,
+
+    // time limit checked after each evaluation, if not enforced by potential
+    timeout: Option<Duration>,
+
+    // timing and call statistics
+    stats: crate::report::EvalStats,
+
+    // how to handle displacements smaller than epsilon
+    small_step: SmallStep,
+    // size of rejected small step, reported in next evaluation
+    rejected: Option<f64>,
+
+    // user callbacks after each successful evaluation
+    hooks: Vec<Box<H>>,
+
+    // the number of repeated evaluations averaged for noisy potentials
+    nsamples: usize,
+    noise: Option<Noise>,
+}
+
+/// Statistics on repeated evaluations of a noisy potential at the same
+/// position, averaged in `Dynamics`.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    /// The number of evaluations averaged.
+    pub nsamples: usize,
+    /// The sample variance of energies.
+    pub energy_variance: f64,
+    /// The sample variance of each component of forces.
+    pub force_variance: Vec<f64>,
+}
+
+impl Noise {
+    // Average `samples` into `output`, returning the variances.
+    fn average(samples: &[PotentialOutput], output: &mut PotentialOutput) -> Self {
+        let n = samples.len() as f64;
+        output.energy = samples.iter().map(|s| s.energy).sum::<f64>() / n;
+        output.force.fill(0.0);
+        for s in samples {
+            output.force.vecadd(&s.force, 1.0 / n);
+        }
+        output.stress = samples
+            .iter()
+            .map(|s| s.stress)
+            .try_fold([0.0; 6], |acc, s| s.map(|s| std::array::from_fn(|i| acc[i] + s[i] / n)));
+
+        let energy_variance = samples.iter().map(|s| (s.energy - output.energy).powi(2)).sum::<f64>() / (n - 1.0);
+        let mut force_variance = vec![0.0; output.force.len()];
+        for s in samples {
+            for (v, (f, f_mean)) in force_variance.iter_mut().zip(s.force.iter().zip(&output.force)) {
+                *v += (f - f_mean).powi(2) / (n - 1.0);
+            }
+        }
+        Self {
+            nsamples: samples.len(),
+            energy_variance,
+            force_variance,
+        }
+    }
+
+    /// The standard error of averaged energy.
+    pub fn energy_error(&self) -> f64 {
+        (self.energy_variance / self.nsamples as f64).sqrt()
+    }
 }
+
+/// Callback on evaluated position, energy, force and the number of calls.
+pub trait EvalHook: FnMut(&[f64], f64, &[f64], usize) {}
+
+impl<T: FnMut(&[f64], f64, &[f64], usize) + ?Sized> EvalHook for T {}
 // 9e96c6e5 ends here
 
 // [[file:../optim.note::c39f75c1][c39f75c1]]
-impl<'a, U> Dynamics<'a, U> {
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
     /// evaluate potential at current position
     fn eval(&mut self) -> Result<&PotentialOutput> {
+        if let Some(step_size) = self.rejected.take() {
+            bail!("step size is too small: {step_size}");
+        }
         let n = self.state.position.len();
         let evaluated = self.state.evaluated.get_or_insert(PotentialOutput {
             energy: std::f64::NAN,
             force: vec![0.0; n],
+            stress: None,
         });
-        let extra = self.f.evaluate(&self.state.position, evaluated)?;
+        if let Some(cache) = self.cache.as_mut() {
+            if let Some((output, extra)) = cache.get(&self.state.position, self.epsilon) {
+                *evaluated = output;
+                self.stats.ncached += 1;
+                self.noise = None;
+                self.user_data = extra.into();
+                self.record();
+                self.publish();
+                return Ok(self.state.evaluated.as_ref().unwrap());
+            }
+        }
+        // repeat evaluations at the same position for averaging out noise
+        let mut samples = vec![];
+        let result = loop {
+            evaluated.stress = None;
+            let start = std::time::Instant::now();
+            let result = match self.retry.as_mut() {
+                Some(retry) => retry.evaluate(&mut *self.f, &self.state.position, evaluated, &mut self.stats.nretries),
+                None => self.f.evaluate(&self.state.position, evaluated),
+            };
+            let elapsed = start.elapsed();
+            self.stats.record(elapsed);
+            let result = match self.timeout {
+                Some(limit) if result.is_ok() && elapsed > limit => Err(Timeout { limit }.into()),
+                _ => result,
+            };
+            match result {
+                Ok(_) if samples.len() + 1 < self.nsamples => samples.push(evaluated.clone()),
+                result => break result,
+            }
+        };
+        let extra = match result {
+            Ok(extra) => extra,
+            Err(e) => {
+                // discard partially updated output
+                self.state.evaluated = None;
+                return Err(e);
+            }
+        };
+        self.noise = None;
+        if !samples.is_empty() {
+            samples.push(evaluated.clone());
+            self.noise = Some(Noise::average(&samples, evaluated));
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(&self.state.position, self.epsilon, evaluated, &extra);
+        }
         self.user_data = extra.into();
-        self.neval += 1;
+        self.neval += samples.len().max(1);
+        for hook in self.hooks.iter_mut() {
+            hook(&self.state.position, evaluated.energy, &evaluated.force, self.neval);
+        }
+        self.record();
+        self.publish();
 
-        Ok(evaluated)
+        Ok(self.state.evaluated.as_ref().unwrap())
     }
+}
 
+impl<'a, U> Dynamics<'a, U> {
     /// Construct a Dynamics
     ///
     /// # Parameters
@@ -102,13 +256,32 @@ impl<'a, U> Dynamics<'a, U> {
     ///      - the second is the force to be updated
     ///      - the return value is the energy
     pub fn new(x: &[f64], f: impl EvaluatePotential<U> + 'a) -> Self {
+        Self::from_boxed(x, Box::new(f))
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    fn from_boxed(x: &[f64], f: Box<P>) -> Self {
         Self {
-            f: Box::new(f),
+            f,
+            _potential: std::marker::PhantomData,
             epsilon: 1e-8,
             neval: 0,
 
             state: State::new(x),
             user_data: None,
+            shared: None,
+            cache: None,
+            history: VecDeque::new(),
+            history_size: 0,
+            retry: None,
+            timeout: None,
+            stats: Default::default(),
+            small_step: SmallStep::default(),
+            rejected: None,
+            hooks: vec![],
+            nsamples: 1,
+            noise: None,
         }
     }
 
@@ -158,6 +331,52 @@ impl<'a, U> Dynamics<'a, U> {
         }
     }
 
+    /// Return stress at current position, if provided by the potential.
+    ///
+    /// The potential will be evaluated when necessary.
+    pub fn get_stress(&mut self) -> Result<Option<[f64; 6]>> {
+        match self.state.evaluated.as_ref() {
+            Some(v) => Ok(v.stress),
+            None => Ok(self.eval()?.stress),
+        }
+    }
+
+    /// Return the max absolute component of current force, the convergence
+    /// criterion used in `optimize`.
+    ///
+    /// The potential will be evaluated when necessary.
+    pub fn fmax(&mut self) -> Result<f64> {
+        Ok(fmax_(self.get_force()?))
+    }
+
+    /// Return the max norm of atomic forces, treating current force as
+    /// flattened 3D vectors of atoms.
+    ///
+    /// The potential will be evaluated when necessary.
+    pub fn f3max(&mut self) -> Result<f64> {
+        let force = self.get_force()?;
+        ensure!(
+            force.len() % 3 == 0,
+            "invalid dimension for atomic forces: {}",
+            force.len()
+        );
+        Ok(f3max_(force.chunks(3)))
+    }
+
+    /// Return the norm of force on each atom, treating current force as
+    /// flattened 3D vectors of atoms.
+    ///
+    /// The potential will be evaluated when necessary.
+    pub fn force_norms(&mut self) -> Result<Vec<f64>> {
+        let force = self.get_force()?;
+        ensure!(
+            force.len() % 3 == 0,
+            "invalid dimension for atomic forces: {}",
+            force.len()
+        );
+        Ok(force.chunks(3).map(|f| f.vec2norm()).collect())
+    }
+
     /// Return a reference to current position.
     pub fn position(&self) -> &[f64] {
         &self.state.position
@@ -172,16 +391,79 @@ impl<'a, U> Dynamics<'a, U> {
     pub fn recount(&mut self) {
         self.neval = 0;
     }
+
+    /// Evaluate the potential `nsamples` times at each position and average
+    /// energy, forces and stress, for potentials with stochastic noise such
+    /// as quantum Monte Carlo. Each evaluation counts as one call.
+    pub fn set_averaging(&mut self, nsamples: usize) {
+        assert!(nsamples > 0, "invalid number of samples: {nsamples}");
+        self.nsamples = nsamples;
+    }
+
+    /// Fail evaluations taking longer than `limit` with `Timeout` error, or
+    /// no limit if `None`. A hung call is interrupted only if the potential
+    /// enforces the limit itself, as `TimeoutPotential` running in a worker
+    /// thread. Otherwise, e.g. for borrowed models, the limit is checked
+    /// after each evaluation returns.
+    pub fn set_timeout(&mut self, limit: impl Into<Option<Duration>>) {
+        let limit = limit.into();
+        self.timeout = limit.filter(|_| !self.f.set_time_limit(limit));
+    }
+
+    /// Return statistics on noise in the last evaluation, if averaged over
+    /// more than one sample.
+    pub fn noise(&self) -> Option<&Noise> {
+        self.noise.as_ref()
+    }
+
+    /// Return timing and call statistics on potential evaluations, including
+    /// failed ones.
+    pub fn stats(&self) -> &crate::report::EvalStats {
+        &self.stats
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized> Dynamics<'a, U, P> {
+    /// Register `hook` called after every successful evaluation of the
+    /// potential with position, energy, force and the number of calls, e.g.
+    /// for live logging or storing results into database. Results served
+    /// from evaluation cache are not reported.
+    pub fn on_evaluated(&mut self, hook: impl FnMut(&[f64], f64, &[f64], usize) + 'a) {
+        self.hooks.push(Box::new(hook));
+    }
 }
 // c39f75c1 ends here
 
 // [[file:../optim.note::1a2ff40a][1a2ff40a]]
-impl<'a, U> Dynamics<'a, U> {
+/// Policy for displacements smaller than epsilon in `Dynamics::set_position`
+/// and `Dynamics::step_toward`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmallStep {
+    /// Keep the position unchanged, reusing the last evaluation.
+    #[default]
+    Ignore,
+    /// Update the position and re-evaluate the potential, bypassing any
+    /// cached results.
+    ForceReevaluate,
+    /// Keep the position unchanged, and fail the next request of energy or
+    /// forces with an error.
+    Error,
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
     /// Set epsilon for determining if structure has any substantial changes. If
     /// so, the potential will be re-evaluated automatically.
     pub fn set_epsilon(&mut self, eps: f64) {
         assert!(eps.is_sign_positive(), "invalid eps: {:?}", eps);
+        assert!(
+            eps > 0.0 || self.cache.is_none(),
+            "zero eps is not allowed with evaluation cache enabled"
+        );
         self.epsilon = eps;
+        // positions in cache are keyed using epsilon
+        if let Some(cache) = self.cache.as_mut() {
+            cache.entries.clear();
+        }
     }
 
     /// The threshold for updating current position.
@@ -189,6 +471,12 @@ impl<'a, U> Dynamics<'a, U> {
         self.epsilon
     }
 
+    /// Set `policy` for handling displacements smaller than epsilon in
+    /// `set_position` and `step_toward`. Small steps are ignored by default.
+    pub fn set_small_step(&mut self, policy: SmallStep) {
+        self.small_step = policy;
+    }
+
     /// Update position `x` with a prescribed displacement.
     ///
     /// x += displacement
@@ -196,12 +484,11 @@ impl<'a, U> Dynamics<'a, U> {
         // position changed
         let step_size = displacement.as_vector_slice().norm();
         assert!(!step_size.is_nan(), "found invalid float numbers: {displacement:?}");
-        if step_size > self.epsilon {
+        if self.accept_step(step_size) {
             // update position vector with the displacement
             self.state.position.vecadd(displacement, 1.0);
             self.state.evaluated = None;
-        } else {
-            info!("step size is too small: {step_size}, ignored.");
+            self.publish();
         }
     }
 
@@ -214,30 +501,234 @@ impl<'a, U> Dynamics<'a, U> {
             "found invalid float numbers: {position:?} or {:?}",
             self.state.position
         );
-        if step_size > self.epsilon {
+        if self.accept_step(step_size) {
             self.state.position.clone_from_slice(position);
             self.state.evaluated = None;
-        } else {
-            info!("step size is too small: {step_size}, ignored.");
+            self.publish();
+        }
+    }
+
+    // Return true if position should be updated with a step of `step_size`,
+    // following the small step policy.
+    fn accept_step(&mut self, step_size: f64) -> bool {
+        self.rejected = None;
+        if step_size > self.epsilon {
+            return true;
+        }
+        match self.small_step {
+            SmallStep::Ignore => {
+                info!("step size is too small: {step_size}, ignored.");
+                false
+            }
+            SmallStep::ForceReevaluate => {
+                self.force_invalidate();
+                true
+            }
+            SmallStep::Error => {
+                self.rejected = Some(step_size);
+                self.state.evaluated = None;
+                false
+            }
+        }
+    }
+
+    /// Discard cached evaluation, so that the potential will be re-evaluated
+    /// at current position, e.g. when the potential has been changed
+    /// externally. Results in the evaluation cache are also discarded.
+    pub fn force_invalidate(&mut self) {
+        self.state.evaluated = None;
+        // cached results are also out of date
+        if let Some(cache) = self.cache.as_mut() {
+            cache.entries.clear();
         }
     }
 }
 // 1a2ff40a ends here
 
+// [[file:../optim.note::aa9d368a][aa9d368a]]
+use std::sync::{Arc, RwLock};
+
+/// An immutable snapshot of `Dynamics` state, which can be shared cheaply
+/// across threads for read-mostly analysis, or persisted (see
+/// `VersionedState`) for resuming later using `Dynamics::restore`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DynamicsSnapshot {
+    position: Vec<f64>,
+    evaluated: Option<PotentialOutput>,
+    ncalls: usize,
+}
+
+impl DynamicsSnapshot {
+    /// Return position in snapshot.
+    pub fn position(&self) -> &[f64] {
+        &self.position
+    }
+
+    /// Return energy evaluated at position in snapshot, if any.
+    pub fn energy(&self) -> Option<f64> {
+        self.evaluated.as_ref().map(|e| e.energy)
+    }
+
+    /// Return force evaluated at position in snapshot, if any.
+    pub fn force(&self) -> Option<&[f64]> {
+        self.evaluated.as_ref().map(|e| e.force.as_slice())
+    }
+
+    /// Return stress evaluated at position in snapshot, if any.
+    pub fn stress(&self) -> Option<[f64; 6]> {
+        self.evaluated.as_ref().and_then(|e| e.stress)
+    }
+
+    /// The number of function calls when the snapshot was taken.
+    pub fn ncalls(&self) -> usize {
+        self.ncalls
+    }
+}
+
+impl VersionedState for DynamicsSnapshot {
+    const KIND: &'static str = "dynamics-snapshot";
+    const VERSION: u32 = 1;
+}
+
+/// A handle to the latest snapshot published by `Dynamics`, which can be sent
+/// to observer threads. The lock is only held for swapping or cloning the
+/// `Arc` pointer, so readers never block the simulation loop for long.
+#[derive(Debug, Clone)]
+pub struct SharedSnapshot {
+    latest: Arc<RwLock<Arc<DynamicsSnapshot>>>,
+}
+
+impl SharedSnapshot {
+    /// Return the latest published snapshot.
+    pub fn latest(&self) -> Arc<DynamicsSnapshot> {
+        self.latest.read().expect("poisoned snapshot lock").clone()
+    }
+
+    fn publish(&self, snapshot: DynamicsSnapshot) {
+        let snapshot = Arc::new(snapshot);
+        *self.latest.write().expect("poisoned snapshot lock") = snapshot;
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Return an immutable snapshot of current state.
+    pub fn snapshot(&self) -> Arc<DynamicsSnapshot> {
+        Arc::new(self.take_snapshot())
+    }
+
+    /// Restore position, evaluated energy and forces, and the number of calls
+    /// from `snapshot`, so that a persisted run can be resumed exactly
+    /// without evaluating the potential again. Extra data returned by the
+    /// potential is not kept in snapshot, and will be evaluated on request.
+    pub fn restore(&mut self, snapshot: &DynamicsSnapshot) -> Result<()> {
+        let n = self.state.position.len();
+        ensure!(
+            snapshot.position.len() == n,
+            "invalid dimension of snapshot: {} != {n}",
+            snapshot.position.len()
+        );
+        self.state.position.clone_from(&snapshot.position);
+        self.state.evaluated = snapshot.evaluated.clone();
+        self.neval = snapshot.ncalls;
+        self.user_data = None;
+        self.publish();
+        Ok(())
+    }
+
+    /// Return a handle for reading snapshots from other threads. Snapshots
+    /// will be published on each evaluation or change of position.
+    pub fn share(&mut self) -> SharedSnapshot {
+        if self.shared.is_none() {
+            self.shared = Some(SharedSnapshot {
+                latest: Arc::new(RwLock::new(self.snapshot())),
+            });
+        }
+        self.shared.clone().unwrap()
+    }
+
+    fn take_snapshot(&self) -> DynamicsSnapshot {
+        DynamicsSnapshot {
+            position: self.state.position.clone(),
+            evaluated: self.state.evaluated.clone(),
+            ncalls: self.neval,
+        }
+    }
+
+    fn publish(&self) {
+        if let Some(shared) = &self.shared {
+            shared.publish(self.take_snapshot());
+        }
+    }
+}
+// aa9d368a ends here
+
 // [[file:../optim.note::b4c9a7de][b4c9a7de]]
+/// A handle to the molecule evaluated by `Dynamics` created using
+/// `Dynamics::from_chemical_model_shared`, with positions updated in each
+/// evaluation, so that the optimized structure can be written out.
+#[derive(Clone)]
+pub struct SharedMolecule {
+    inner: Arc<RwLock<gchemol::Molecule>>,
+}
+
+impl SharedMolecule {
+    /// Return a copy of the molecule in the last evaluation.
+    pub fn get(&self) -> gchemol::Molecule {
+        self.inner.read().expect("poisoned molecule lock").clone()
+    }
+}
+
 impl<'a> Dynamics<'a, ()> {
     /// Create `Dynamics` for molecule simulation using chemical model `model`.
-    pub fn from_chemical_model(
+    pub fn from_chemical_model(model: &'a mut impl gosh_model::ChemicalModel, mol: gchemol::Molecule) -> Dynamics<()> {
+        // no extra atoms or coords selected, which never fails
+        Self::from_chemical_model_freezing(model, mol, &Freezing::default()).expect("no freezing selected")
+    }
+
+    /// Create `Dynamics` for molecule simulation using chemical model `model`,
+    /// with extra atoms/coords frozen as selected in `freezing`. Return error
+    /// when the selection is invalid for `mol`.
+    pub fn from_chemical_model_freezing(
         model: &'a mut impl gosh_model::ChemicalModel,
-        mut mol: gchemol::Molecule,
-    ) -> Dynamics<()> {
+        mol: gchemol::Molecule,
+        freezing: &Freezing,
+    ) -> Result<Self> {
+        Ok(Self::from_chemical_model_shared(model, mol, freezing)?.0)
+    }
+
+    /// Create `Dynamics` as in `from_chemical_model_freezing`, returning also
+    /// a handle to the working molecule updated in each evaluation.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let (mut dynamics, mol) = Dynamics::from_chemical_model_shared(&mut model, mol, &Freezing::default())?;
+    /// gosh_optim::optimize(&mut dynamics).take_while(|p| p.fmax > 0.05).last();
+    /// mol.get().to_file("optimized.xyz")?;
+    /// ```
+    pub fn from_chemical_model_shared(
+        model: &'a mut impl gosh_model::ChemicalModel,
+        mol: gchemol::Molecule,
+        freezing: &Freezing,
+    ) -> Result<(Self, SharedMolecule)> {
         // handle freezing atoms/coords
         let position = mol.positions().flatten().collect_vec();
-        let mask = mol.freezing_coords_mask();
+        let mask = freezing.coords_mask(&mol)?;
         let position_opt = mask.apply(&position);
         info!("Removed {} freezing coordinates", position.len() - position_opt.len());
-        Self::new(&position_opt, move |x_masked: &[f64], force: &mut [f64]| {
-            let x = mask.unmask(x_masked, 0.0);
+        let shared = SharedMolecule {
+            inner: Arc::new(RwLock::new(mol)),
+        };
+        let inner = shared.inner.clone();
+        let dynamics = Self::new(&position_opt, move |x_masked: &[f64], force: &mut [f64]| {
+            let mut x = mask.unmask(x_masked, 0.0);
+            // keep freezing coords in place
+            for (x, (&r, &frozen)) in x.iter_mut().zip(position.iter().zip(mask.frozen())) {
+                if frozen {
+                    *x = r;
+                }
+            }
+            let mut mol = inner.write().expect("poisoned molecule lock");
             mol.update_positions(x.as_3d().into_iter().copied());
             let mp = model.compute(&mol)?;
             let f = mp.get_forces().ok_or(format_err!("no forces"))?;
@@ -248,7 +739,542 @@ impl<'a> Dynamics<'a, ()> {
             force.copy_from_slice(&f_masked);
 
             Ok(e)
-        })
+        });
+        Ok((dynamics, shared))
     }
 }
 // b4c9a7de ends here
+
+// [[file:../optim.note::ec2baf3f][ec2baf3f]]
+/// A potential adapter computing forces by central difference of energies,
+/// for models without analytic gradients.
+///
+/// Each evaluation costs `2 * n + 1` energy calls for `n` coordinates, which
+/// could be evaluated concurrently in threads using `parallelism`.
+///
+/// # Examples
+///
+/// ```ignore
+/// // f(x) = sum of x^2, energy only
+/// let f = |x: &[f64]| Ok(x.iter().map(|v| v * v).sum());
+/// let pot = NumericalForces::new(f).displacement(1e-4).parallelism(4);
+/// let mut dynamics = Dynamics::new(&x, pot);
+/// let force = dynamics.get_force()?;
+/// ```
+pub struct NumericalForces<F> {
+    // one energy function for each thread
+    f: Vec<F>,
+    displacement: f64,
+    ncalls: usize,
+    // evaluate energies at positions, in parallel threads if possible
+    energies: fn(&mut [F], &[Vec<f64>]) -> Result<Vec<f64>>,
+}
+
+impl<F> NumericalForces<F>
+where
+    F: FnMut(&[f64]) -> Result<f64>, // position => energy
+{
+    /// Compute forces numerically from energies returned by `f`.
+    pub fn new(f: F) -> Self {
+        Self {
+            f: vec![f],
+            displacement: 1e-3,
+            ncalls: 0,
+            energies: |f, positions| positions.iter().map(|x| (f[0])(x.as_slice())).collect(),
+        }
+    }
+
+    /// Set displacement of each coordinate for central difference. The
+    /// default is 1e-3.
+    pub fn displacement(mut self, d: f64) -> Self {
+        assert!(d > 0.0, "invalid displacement: {d}");
+        self.displacement = d;
+        self
+    }
+
+    /// Evaluate displaced positions concurrently in `n` threads, each with a
+    /// clone of the energy function. Positions will be evaluated serially in
+    /// current thread if `n` is 1, as by default.
+    pub fn parallelism(mut self, n: usize) -> Self
+    where
+        F: Clone + Send,
+    {
+        assert!(n > 0, "invalid parallelism: {n}");
+        self.f.resize(n, self.f[0].clone());
+        self.energies = |f, positions| crate::fd::evaluate_chunked(f, positions, |f, x| f(x.as_slice()));
+        self
+    }
+
+    /// The number of calls for energy evaluation, including those for
+    /// displaced positions.
+    pub fn ncalls(&self) -> usize {
+        self.ncalls
+    }
+}
+
+impl<F> EvaluatePotential<()> for NumericalForces<F>
+where
+    F: FnMut(&[f64]) -> Result<f64>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        let d = self.displacement;
+        let mut positions = vec![position.to_vec()];
+        for i in 0..position.len() {
+            for s in [d, -d] {
+                let mut displaced = position.to_vec();
+                displaced[i] += s;
+                positions.push(displaced);
+            }
+        }
+        let energies = (self.energies)(&mut self.f, &positions)?;
+        self.ncalls += positions.len();
+        output.energy = energies[0];
+        for (f, e) in output.force.iter_mut().zip(energies[1..].chunks(2)) {
+            *f = -(e[0] - e[1]) / (2.0 * d);
+        }
+        Ok(())
+    }
+}
+// ec2baf3f ends here
+
+// [[file:../optim.note::803eb877][803eb877]]
+/// Hessian computed by central difference of forces.
+#[derive(Debug, Clone)]
+pub struct NumericalHessian {
+    /// The symmetric Hessian matrix (n x n in row major) for all n
+    /// coordinates. Rows and columns of frozen coordinates are zero.
+    pub hessian: Vec<f64>,
+    /// The number of calls for potential evaluation at displaced positions.
+    pub ncalls: usize,
+}
+
+impl NumericalHessian {
+    // Assemble symmetric Hessian of `n` coordinates from `columns` along
+    // `coords`.
+    pub(crate) fn from_columns(n: usize, coords: &[usize], columns: &[Vec<f64>], ncalls: usize) -> Self {
+        let mut hessian = vec![0.0; n * n];
+        for (&j, column) in coords.iter().zip(columns) {
+            for &i in coords {
+                hessian[i * n + j] = column[i];
+            }
+        }
+        for &i in coords {
+            for &j in coords.iter().filter(|&&j| j < i) {
+                let h = 0.5 * (hessian[i * n + j] + hessian[j * n + i]);
+                hessian[i * n + j] = h;
+                hessian[j * n + i] = h;
+            }
+        }
+        Self { hessian, ncalls }
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Compute Hessian at current position by displacing each coordinate by
+    /// `step` in both directions and differentiating the forces, which costs
+    /// `2 * n` calls for `n` coordinates.
+    ///
+    /// Coordinates frozen in `from_chemical_model_freezing` have already been
+    /// removed from `Dynamics` and will not appear in the Hessian.
+    pub fn numerical_hessian(&mut self, step: f64) -> Result<NumericalHessian> {
+        let n = self.state.position.len();
+        self.numerical_hessian_masked(step, &vec![false; n])
+    }
+
+    /// Compute Hessian as in `numerical_hessian`, skipping coordinates set
+    /// true in `frozen` mask. Rows and columns of frozen coordinates are
+    /// filled with zeros.
+    pub fn numerical_hessian_masked(&mut self, step: f64, frozen: &[bool]) -> Result<NumericalHessian> {
+        let n = self.state.position.len();
+        assert_eq!(frozen.len(), n, "invalid size of frozen mask");
+        let coords = (0..n).filter(|&i| !frozen[i]).collect_vec();
+        let ncalls = self.neval;
+        let columns = self.fd_columns(step, &coords)?;
+        Ok(NumericalHessian::from_columns(
+            n,
+            &coords,
+            &columns,
+            self.neval - ncalls,
+        ))
+    }
+
+    /// Return columns of Hessian along `coords` at current position, by
+    /// central difference of forces displaced by `step`. Current position
+    /// and its evaluated results will be restored afterwards.
+    pub(crate) fn fd_columns(&mut self, step: f64, coords: &[usize]) -> Result<Vec<Vec<f64>>> {
+        assert!(step > self.epsilon, "too small displacement: {step}");
+        let x = self.state.position.clone();
+        self.probe(|dynamics| {
+            coords
+                .iter()
+                .map(|&j| {
+                    let mut displaced = x.clone();
+                    displaced[j] = x[j] + step;
+                    dynamics.set_position(&displaced);
+                    let fp = dynamics.get_force()?.to_vec();
+                    displaced[j] = x[j] - step;
+                    dynamics.set_position(&displaced);
+                    let fm = dynamics.get_force()?;
+                    Ok(fp.iter().zip(fm).map(|(p, m)| -(p - m) / (2.0 * step)).collect())
+                })
+                .collect()
+        })
+    }
+
+    // Call `f` for evaluations at positions around current one, which will
+    // be restored afterwards with its evaluated results. The probed positions
+    // are not recorded in history.
+    fn probe<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.state.clone();
+        let history_size = std::mem::replace(&mut self.history_size, 0);
+        let probed = f(self);
+        self.history_size = history_size;
+        self.state = saved;
+        // user data is invalid for the restored position
+        self.user_data = None;
+        self.publish();
+        probed
+    }
+}
+// 803eb877 ends here
+
+// [[file:../optim.note::a57ac1df][a57ac1df]]
+use std::collections::VecDeque;
+
+// A least recently used cache of evaluated results keyed by a hash of the
+// position rounded to a resolution of `epsilon`.
+struct EvalCache<U> {
+    capacity: usize,
+    entries: VecDeque<(u64, Vec<i64>, PotentialOutput, U)>,
+    nhits: usize,
+    // user data may be not cloneable in general
+    clone: fn(&U) -> U,
+}
+
+impl<U> EvalCache<U> {
+    fn key(position: &[f64], epsilon: f64) -> (u64, Vec<i64>) {
+        use std::hash::{Hash, Hasher};
+
+        let rounded = position.iter().map(|x| (x / epsilon).round() as i64).collect_vec();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        rounded.hash(&mut hasher);
+        (hasher.finish(), rounded)
+    }
+
+    fn get(&mut self, position: &[f64], epsilon: f64) -> Option<(PotentialOutput, U)> {
+        let (hash, rounded) = Self::key(position, epsilon);
+        let i = self.entries.iter().position(|(h, r, ..)| *h == hash && *r == rounded)?;
+        // move to the most recently used end
+        let entry = self.entries.remove(i)?;
+        let found = (entry.2.clone(), (self.clone)(&entry.3));
+        self.entries.push_back(entry);
+        self.nhits += 1;
+        Some(found)
+    }
+
+    fn insert(&mut self, position: &[f64], epsilon: f64, output: &PotentialOutput, extra: &U) {
+        let (hash, rounded) = Self::key(position, epsilon);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries
+            .push_back((hash, rounded, output.clone(), (self.clone)(extra)));
+    }
+}
+
+impl<'a, U: Clone, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Cache evaluated results of the last `capacity` distinct positions, so
+    /// that revisiting a position within `epsilon` (rounded to the grid of
+    /// `epsilon` in each coordinate) will not call the potential again. Set
+    /// `capacity` to 0 to disable the cache. Epsilon must be positive for
+    /// the cache enabled.
+    pub fn set_cache(&mut self, capacity: usize) {
+        assert!(
+            capacity == 0 || self.epsilon > 0.0,
+            "evaluation cache requires positive eps: {}",
+            self.epsilon
+        );
+        self.cache = (capacity > 0).then(|| EvalCache {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            nhits: 0,
+            clone: U::clone,
+        });
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// The number of evaluations served from the cache set by `set_cache`.
+    pub fn cache_hits(&self) -> usize {
+        self.cache.as_ref().map_or(0, |c| c.nhits)
+    }
+}
+// a57ac1df ends here
+
+// [[file:../optim.note::98f8a1f1][98f8a1f1]]
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Keep the last `n` evaluated states in history for `rollback`. Set `n`
+    /// to 0 to disable the history.
+    pub fn set_history(&mut self, n: usize) {
+        self.history_size = n;
+        while self.history.len() > n {
+            self.history.pop_front();
+        }
+    }
+
+    /// Return the evaluated states in history, from the oldest to the latest.
+    pub fn history(&self) -> impl Iterator<Item = &DynamicsSnapshot> {
+        self.history.iter()
+    }
+
+    /// Return to the `k`-th latest evaluated state in history, counting from
+    /// 0 for the latest one, and discard all states after it. For example,
+    /// `rollback(1)` rejects the last evaluated step.
+    ///
+    /// Extra data from the potential is not kept in history, and will be
+    /// re-evaluated when requested.
+    pub fn rollback(&mut self, k: usize) -> Result<()> {
+        let n = self.history.len();
+        ensure!(k < n, "cannot rollback {k} states with {n} in history");
+        self.history.truncate(n - k);
+        let last = self.history.back().unwrap();
+        self.state.position.clone_from(&last.position);
+        self.state.evaluated = last.evaluated.clone();
+        self.user_data = None;
+        self.publish();
+        Ok(())
+    }
+
+    fn record(&mut self) {
+        if self.history_size > 0 {
+            if self.history.len() >= self.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.take_snapshot());
+        }
+    }
+}
+// 98f8a1f1 ends here
+
+// [[file:../optim.note::1f0c0a56][1f0c0a56]]
+use std::time::Duration;
+
+/// Policy for retrying failed potential evaluations, e.g. for external
+/// quantum chemistry jobs failed transiently.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The max number of retries before giving up.
+    pub max_retries: usize,
+    /// The waiting time before the first retry, which is doubled for each
+    /// further retry.
+    pub backoff: Duration,
+    /// Standard deviation of random displacements applied to a copy of the
+    /// position on each retry, which could help with SCF convergence
+    /// problems. The results at the displaced position are taken for the
+    /// original one, which is kept unchanged. No displacement if zero.
+    pub jitter: f64,
+    /// Random seed for the displacements.
+    pub seed: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_secs(1),
+            jitter: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+struct Retry {
+    policy: RetryPolicy,
+    rng: crate::random::Rng,
+}
+
+impl Retry {
+    // Evaluate `f` at `position`, or at a displaced copy on retry, counting
+    // retries in `nretries`.
+    fn evaluate<U, F>(
+        &mut self,
+        f: &mut F,
+        position: &[f64],
+        output: &mut PotentialOutput,
+        nretries: &mut usize,
+    ) -> Result<U>
+    where
+        F: EvaluatePotential<U> + ?Sized,
+    {
+        let mut displaced: Option<Vec<f64>> = None;
+        let mut k = 0;
+        loop {
+            output.stress = None;
+            match f.evaluate(displaced.as_deref().unwrap_or(position), output) {
+                Ok(extra) => return Ok(extra),
+                Err(e) if k < self.policy.max_retries => {
+                    k += 1;
+                    *nretries += 1;
+                    warn!("potential evaluation failed: {e:?}");
+                    let wait = self.policy.backoff * 2u32.saturating_pow(k as u32 - 1);
+                    info!("retry {k}/{} in {wait:?} ...", self.policy.max_retries);
+                    std::thread::sleep(wait);
+                    if self.policy.jitter > 0.0 {
+                        let jitter = self.policy.jitter;
+                        displaced = Some(position.iter().map(|x| x + jitter * self.rng.normal()).collect());
+                    }
+                }
+                Err(e) => return Err(e).with_context(|| format!("potential evaluation failed after {k} retries")),
+            }
+        }
+    }
+}
+
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Retry failed evaluations following `policy` before propagating the
+    /// error. Set `None` to disable retrying. The number of retries is
+    /// counted in `stats`.
+    pub fn set_retry(&mut self, policy: impl Into<Option<RetryPolicy>>) {
+        self.retry = policy.into().map(|policy| Retry {
+            rng: crate::random::Rng::new(policy.seed),
+            policy,
+        });
+    }
+}
+// 1f0c0a56 ends here
+
+// [[file:../optim.note::dfc3c1e9][dfc3c1e9]]
+/// Trait for evaluating potential of multiple positions in one call, which
+/// could be much faster for machine learning or GPU backed models.
+pub trait EvaluatePotentialBatch<U> {
+    /// Evaluate potential at each of `positions`, updating corresponding
+    /// `outputs` and returning extra data in the same order.
+    fn evaluate_batch(&mut self, positions: &[&[f64]], outputs: &mut [PotentialOutput]) -> Result<Vec<U>>;
+}
+
+impl<T> EvaluatePotentialBatch<()> for T
+where
+    T: FnMut(&[&[f64]], &mut [Vec<f64>]) -> Result<Vec<f64>>, // positions, forces => energies
+{
+    fn evaluate_batch(&mut self, positions: &[&[f64]], outputs: &mut [PotentialOutput]) -> Result<Vec<()>> {
+        assert_eq!(positions.len(), outputs.len(), "invalid size of outputs");
+        let mut forces = outputs.iter_mut().map(|o| std::mem::take(&mut o.force)).collect_vec();
+        let energies = self(positions, &mut forces);
+        for (o, f) in outputs.iter_mut().zip(forces) {
+            o.force = f;
+        }
+        let energies = energies?;
+        ensure!(
+            energies.len() == positions.len(),
+            "expect {} energies, but found {}",
+            positions.len(),
+            energies.len()
+        );
+        for (o, e) in outputs.iter_mut().zip(energies) {
+            o.energy = e;
+        }
+        Ok(vec![(); positions.len()])
+    }
+}
+
+/// An adapter for evaluating positions in batch one by one using any
+/// `EvaluatePotential`.
+pub struct SerialBatch<P>(pub P);
+
+impl<U, P> EvaluatePotentialBatch<U> for SerialBatch<P>
+where
+    P: EvaluatePotential<U>,
+{
+    fn evaluate_batch(&mut self, positions: &[&[f64]], outputs: &mut [PotentialOutput]) -> Result<Vec<U>> {
+        assert_eq!(positions.len(), outputs.len(), "invalid size of outputs");
+        positions
+            .iter()
+            .zip(outputs)
+            .map(|(x, o)| self.0.evaluate(x, o))
+            .collect()
+    }
+}
+// dfc3c1e9 ends here
+
+// [[file:../optim.note::e558a380][e558a380]]
+/// A `Dynamics` with potential bounded by `Send` and `Sync` and hooks
+/// bounded by `Send`, which could be moved into worker threads, e.g. one
+/// instance for each thread in `NudgedElasticBand::optimize_path_parallel`.
+pub type DynamicsSync<'a, U> = Dynamics<'a, U, dyn EvaluatePotential<U> + Send + Sync + 'a, dyn EvalHook + Send + 'a>;
+
+impl<'a, U> DynamicsSync<'a, U> {
+    /// Construct a `DynamicsSync` as in `Dynamics::new`, with a thread-safe
+    /// potential `f`.
+    pub fn new_sync(x: &[f64], f: impl EvaluatePotential<U> + Send + Sync + 'a) -> Self {
+        Self::from_boxed(x, Box::new(f))
+    }
+
+    /// Register `hook` as in `Dynamics::on_evaluated`, which must be `Send`
+    /// for driving from worker threads.
+    pub fn on_evaluated(&mut self, hook: impl FnMut(&[f64], f64, &[f64], usize) + Send + 'a) {
+        self.hooks.push(Box::new(hook));
+    }
+}
+
+impl<'a, U, P, H> EvaluatePotential<U> for Dynamics<'a, U, P, H>
+where
+    U: Clone,
+    P: EvaluatePotential<U> + ?Sized,
+    H: EvalHook + ?Sized,
+{
+    /// Evaluate potential at `position` through `Dynamics`, making use of
+    /// its cache, history and retry policy.
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<U> {
+        self.set_position(position);
+        output.energy = self.get_energy()?;
+        output.force.clone_from_slice(self.get_force()?);
+        output.stress = self.get_stress()?;
+        Ok(self.get_extra()?.clone())
+    }
+
+    fn set_time_limit(&mut self, limit: Option<Duration>) -> bool {
+        self.set_timeout(limit);
+        true
+    }
+}
+// e558a380 ends here
+
+// [[file:../optim.note::93f7b6a9][93f7b6a9]]
+impl<'a, U, P: EvaluatePotential<U> + ?Sized, H: EvalHook + ?Sized> Dynamics<'a, U, P, H> {
+    /// Check consistency of forces with energies at current position, for
+    /// validating new potentials or chemical models. Along each of
+    /// `ndirections` random unit directions, the directional derivative from
+    /// central difference of energies displaced by `step` is compared with
+    /// the one from forces.
+    ///
+    /// # Return
+    ///
+    /// * relative errors in each direction, which should be small for
+    ///   consistent forces.
+    pub fn check_forces(&mut self, step: f64, ndirections: usize) -> Result<Vec<f64>> {
+        assert!(step > self.epsilon, "too small displacement: {step}");
+        let x = self.state.position.clone();
+        let force = self.get_force()?.to_vec();
+        let mut rng = crate::random::Rng::new(ndirections as u64);
+        self.probe(|dynamics| {
+            (0..ndirections)
+                .map(|_| {
+                    let d = rng.unit_vector(x.len());
+                    let analytic = -force.vecdot(&d);
+                    let mut displaced = x.clone();
+                    displaced.vecadd(&d, step);
+                    dynamics.set_position(&displaced);
+                    let ep = dynamics.get_energy()?;
+                    displaced.vecadd(&d, -2.0 * step);
+                    dynamics.set_position(&displaced);
+                    let em = dynamics.get_energy()?;
+                    let numerical = (ep - em) / (2.0 * step);
+                    let error = (numerical - analytic).abs() / analytic.abs().max(numerical.abs()).max(1e-8);
+                    debug!("directional derivative: analytic = {analytic}, numerical = {numerical}");
+                    Ok(error)
+                })
+                .collect()
+        })
+    }
+}
+// 93f7b6a9 ends here
@@ -0,0 +1,461 @@
+// [[file:../optim.note::a6e5cf6d][a6e5cf6d]]
+use super::*;
+
+use crate::internals::{angle, distance, Coordinate};
+use gchemol::Molecule;
+use vecfx::nalgebra as na;
+// a6e5cf6d ends here
+
+// [[file:../optim.note::140a4dee][140a4dee]]
+// Covalent radii in Å for elements up to Xe (Cordero et al. Dalton Trans.
+// 2008, 2832), indexed by atomic number.
+const COVALENT_RADII: [f64; 55] = [
+    0.00, 0.31, 0.28, 1.28, 0.96, 0.84, 0.76, 0.71, 0.66, 0.57, 0.58, 1.66, 1.41, 1.21, 1.11, 1.07, 1.05, 1.02, 1.06,
+    2.03, 1.76, 1.70, 1.60, 1.53, 1.39, 1.39, 1.32, 1.26, 1.24, 1.32, 1.22, 1.22, 1.20, 1.19, 1.20, 1.20, 1.16, 2.20,
+    1.95, 1.90, 1.75, 1.64, 1.54, 1.47, 1.46, 1.42, 1.39, 1.45, 1.44, 1.42, 1.39, 1.39, 1.38, 1.39, 1.40,
+];
+
+// Atoms are bonded if closer than this factor times the sum of covalent radii
+pub(crate) const BOND_FACTOR: f64 = 1.3;
+
+// Angles beyond this value (in degree) are treated as linear, and excluded
+const LINEAR_ANGLE: f64 = 175.0;
+
+// Diagonal force constants of the initial Hessian in eV/Å^2 or eV/rad^2, when
+// not seeded from the Lindh model Hessian
+const K_STRETCH: f64 = 30.0;
+const K_BEND: f64 = 5.0;
+const K_TORSION: f64 = 0.5;
+
+// Max number of iterations in back-transformation to Cartesian coordinates
+const MAX_BACK_ITERATIONS: usize = 25;
+
+pub(crate) fn covalent_radius(z: usize) -> f64 {
+    COVALENT_RADII.get(z).copied().filter(|&r| r > 0.0).unwrap_or(1.5)
+}
+
+/// Redundant internal coordinates (bonds, angles and dihedrals) generated
+/// from connectivity of atoms. For periodic systems, bonds are searched using
+/// the minimum image convention, and coordinates involving periodic images of
+/// atoms are evaluated with their image shifts.
+///
+/// # Reference
+///
+/// Peng, C.; Ayala, P. Y.; Schlegel, H. B.; Frisch, M. J. J. Comput. Chem.
+/// 1996, 17, 49.
+#[derive(Debug, Clone)]
+pub struct RedundantInternals {
+    coords: Vec<Coordinate>,
+    // Cartesian shifts of periodic images for atoms in each coordinate
+    shifts: Vec<Vec<Vector3f>>,
+    // freezing flags over flattened coords
+    frozen: Vec<bool>,
+}
+
+impl RedundantInternals {
+    /// Generate redundant internal coordinates for `mol`, from bonds by
+    /// covalent radii. Disconnected fragments are linked by their closest
+    /// atom pairs.
+    pub fn from_molecule(mol: &Molecule) -> Self {
+        let radii = mol.atoms().map(|(_, a)| covalent_radius(a.number())).collect_vec();
+        let positions = mol.positions().collect_vec();
+        let cell = mol.lattice.as_ref().map(|lat| {
+            let mat = lat.matrix();
+            let v = |i: usize| Vector3f::new(mat[(0, i)], mat[(1, i)], mat[(2, i)]);
+            na::Matrix3::from_columns(&[v(0), v(1), v(2)])
+        });
+        Self::generate(&positions, &radii, cell)
+    }
+
+    /// Exclude coords flagged true in `frozen` (over flattened coords) from
+    /// Wilson B-matrix, so that freezing atoms are never displaced.
+    pub fn with_frozen(mut self, frozen: &[bool]) -> Self {
+        self.frozen = frozen.to_vec();
+        self
+    }
+
+    fn generate(positions: &[[f64; 3]], radii: &[f64], cell: Option<na::Matrix3<f64>>) -> Self {
+        let n = positions.len();
+        let x = positions.iter().map(|&p| Vector3f::from(p)).collect_vec();
+        // the shortest vector from atom i to atom j, with the image shift of j
+        let cell_inv = cell.map(|m| m.try_inverse().expect("invalid cell"));
+        let image = |i: usize, j: usize| {
+            let d = x[j] - x[i];
+            match (cell, cell_inv) {
+                (Some(m), Some(m_inv)) => {
+                    let shift = -m * (m_inv * d).map(|f| f.round());
+                    (d + shift, shift)
+                }
+                _ => (d, Vector3f::zeros()),
+            }
+        };
+
+        // neighbors with image shifts relative to the center atom
+        let mut neighbors: Vec<Vec<(usize, Vector3f)>> = vec![vec![]; n];
+        let mut internals = Self {
+            coords: vec![],
+            shifts: vec![],
+            frozen: vec![false; 3 * n],
+        };
+        let mut add_bond = |i: usize, j: usize, shift: Vector3f, internals: &mut Self| {
+            neighbors[i].push((j, shift));
+            neighbors[j].push((i, -shift));
+            internals.push(Coordinate::Distance(i, j), vec![Vector3f::zeros(), shift]);
+        };
+        let mut fragment = (0..n).collect_vec();
+        for (i, j) in (0..n).tuple_combinations() {
+            let (d, shift) = image(i, j);
+            if d.norm() < BOND_FACTOR * (radii[i] + radii[j]) {
+                add_bond(i, j, shift, &mut internals);
+                let (fi, fj) = (fragment[i], fragment[j]);
+                fragment.iter_mut().filter(|f| **f == fj).for_each(|f| *f = fi);
+            }
+        }
+
+        // link disconnected fragments by their closest atom pairs
+        loop {
+            let closest = (0..n)
+                .tuple_combinations()
+                .filter(|&(i, j)| fragment[i] != fragment[j])
+                .map(|(i, j)| (i, j, image(i, j)))
+                .min_by(|a, b| {
+                    a.2 .0
+                        .norm()
+                        .partial_cmp(&b.2 .0.norm())
+                        .expect("found invalid float numbers")
+                });
+            let Some((i, j, (_, shift))) = closest else {
+                break;
+            };
+            let (fi, fj) = (fragment[i], fragment[j]);
+            fragment.iter_mut().filter(|f| **f == fj).for_each(|f| *f = fi);
+            add_bond(i, j, shift, &mut internals);
+        }
+
+        let is_linear = |pi: Vector3f, pj: Vector3f, pk: Vector3f| {
+            angle(pi.into(), pj.into(), pk.into()).to_degrees() > LINEAR_ANGLE
+        };
+        // bends with `j` as the center atom
+        for j in 0..n {
+            for (&(i, si), &(k, sk)) in neighbors[j].iter().tuple_combinations() {
+                if i != k && !is_linear(x[i] + si, x[j], x[k] + sk) {
+                    internals.push(Coordinate::Angle(i, j, k), vec![si, Vector3f::zeros(), sk]);
+                }
+            }
+        }
+        // torsions around `j-k` bond
+        for j in 0..n {
+            for &(k, sk) in neighbors[j].iter().filter(|&&(k, _)| k > j) {
+                for &(i, si) in neighbors[j].iter().filter(|&&(i, _)| i != k) {
+                    for &(l, sl) in neighbors[k].iter().filter(|&&(l, _)| l != j && l != i) {
+                        let (pi, pj, pk, pl) = (x[i] + si, x[j], x[k] + sk, x[l] + sk + sl);
+                        if !is_linear(pi, pj, pk) && !is_linear(pj, pk, pl) {
+                            let shifts = vec![si, Vector3f::zeros(), sk, sk + sl];
+                            internals.push(Coordinate::Dihedral(i, j, k, l), shifts);
+                        }
+                    }
+                }
+            }
+        }
+
+        internals
+    }
+
+    fn push(&mut self, coord: Coordinate, shifts: Vec<Vector3f>) {
+        self.coords.push(coord);
+        self.shifts.push(shifts);
+    }
+
+    // Return the coordinate `k` on local atoms and their positions with image
+    // shifts
+    fn local(&self, k: usize, positions: &[f64]) -> (Coordinate, Vec<[f64; 3]>) {
+        let c = self.coords[k];
+        let local = match c {
+            Coordinate::Distance(..) => Coordinate::Distance(0, 1),
+            Coordinate::Angle(..) => Coordinate::Angle(0, 1, 2),
+            Coordinate::Dihedral(..) => Coordinate::Dihedral(0, 1, 2, 3),
+        };
+        let p = positions.as_3d();
+        let local_positions = c
+            .atoms()
+            .into_iter()
+            .zip(&self.shifts[k])
+            .map(|(i, s)| (Vector3f::from(p[i]) + s).into())
+            .collect();
+        (local, local_positions)
+    }
+
+    /// Return all internal coordinates.
+    pub fn coords(&self) -> &[Coordinate] {
+        &self.coords
+    }
+
+    /// Return values of internal coordinates at flattened `positions`.
+    pub fn values(&self, positions: &[f64]) -> Vec<f64> {
+        (0..self.coords.len())
+            .map(|k| {
+                let (c, p) = self.local(k, positions);
+                c.value(&p)
+            })
+            .collect()
+    }
+
+    /// Return differences between internal coordinate values `a` and `b`,
+    /// taking periodicity of dihedral angles into account.
+    pub fn differences(&self, a: &[f64], b: &[f64]) -> Vec<f64> {
+        self.coords
+            .iter()
+            .zip(a.iter().zip(b))
+            .map(|(c, (&a, &b))| c.difference(a, b))
+            .collect()
+    }
+
+    /// Return the Wilson B-matrix (nq x 3N) at flattened `positions`. Columns
+    /// of freezing coords are zero.
+    pub fn wilson_b(&self, positions: &[f64]) -> na::DMatrix<f64> {
+        let mut b = na::DMatrix::zeros(self.coords.len(), positions.len());
+        for (k, c) in self.coords.iter().enumerate() {
+            let (local, p) = self.local(k, positions);
+            let atoms = c.atoms();
+            for (m, g) in local.gradient(&p) {
+                for q in 0..3 {
+                    b[(k, 3 * atoms[m] + q)] += g[q];
+                }
+            }
+        }
+        for (col, &frozen) in self.frozen.iter().enumerate() {
+            if frozen {
+                b.column_mut(col).fill(0.0);
+            }
+        }
+        b
+    }
+
+    /// Transform the Cartesian gradient `gx` at `positions` into internal
+    /// coordinates: gq = (B B^T)^- B gx.
+    pub fn gradient_to_internals(&self, positions: &[f64], gx: &[f64]) -> Result<Vec<f64>> {
+        let b = self.wilson_b(positions);
+        let g_inv = pseudo_inverse(&b * b.transpose())?;
+        let gq = g_inv * (&b * na::DVector::from_column_slice(gx));
+        Ok(gq.as_slice().to_vec())
+    }
+
+    /// Return new Cartesian positions displaced from `positions` by `dq` in
+    /// internal coordinates, using iterative back-transformation. Fall back to
+    /// the first order step if iterations do not converge.
+    pub fn back_transform(&self, positions: &[f64], dq: &[f64]) -> Result<Vec<f64>> {
+        let target = self.values(positions).iter().zip(dq).map(|(q, d)| q + d).collect_vec();
+        let mut x = positions.to_vec();
+        let mut first_order = None;
+        for _ in 0..MAX_BACK_ITERATIONS {
+            let dq = self.differences(&target, &self.values(&x));
+            let b = self.wilson_b(&x);
+            let g_inv = pseudo_inverse(&b * b.transpose())?;
+            let dx = b.transpose() * (g_inv * na::DVector::from_vec(dq));
+            x.vecadd(dx.as_slice(), 1.0);
+            if first_order.is_none() {
+                first_order = Some(x.clone());
+            }
+            if dx.norm() / (dx.len() as f64).sqrt() < 1e-6 {
+                return Ok(x);
+            }
+        }
+        warn!("back-transformation not converged, use first order step");
+        first_order.ok_or(format_err!("no internal coordinates"))
+    }
+
+    // Diagonal of the initial Hessian
+    fn model_hessian_diagonal(&self) -> na::DVector<f64> {
+        let diag = self.coords.iter().map(|c| match c {
+            Coordinate::Distance(..) => K_STRETCH,
+            Coordinate::Angle(..) => K_BEND,
+            Coordinate::Dihedral(..) => K_TORSION,
+        });
+        na::DVector::from_iterator(self.coords.len(), diag)
+    }
+}
+
+fn pseudo_inverse(m: na::DMatrix<f64>) -> Result<na::DMatrix<f64>> {
+    m.pseudo_inverse(1e-10).map_err(|e| format_err!("{e}"))
+}
+// 140a4dee ends here
+
+// [[file:../optim.note::08334871][08334871]]
+/// Delocalized internal coordinates (DLC), as non-redundant combinations of
+/// redundant internals spanning the space of Cartesian displacements of
+/// atoms not frozen.
+///
+/// # Reference
+///
+/// Baker, J.; Kessi, A.; Delley, B. J. Chem. Phys. 1996, 105, 192.
+#[derive(Debug, Clone)]
+pub struct DelocalizedInternals {
+    internals: RedundantInternals,
+    // columns of the transformation from redundant internals
+    u: na::DMatrix<f64>,
+}
+
+impl DelocalizedInternals {
+    /// Construct DLC from `internals` at flattened `positions`, using the
+    /// eigenvectors of G = B B^T with nonzero eigenvalues.
+    pub fn new(internals: RedundantInternals, positions: &[f64]) -> Result<Self> {
+        let b = internals.wilson_b(positions);
+        let eigen = (&b * b.transpose()).symmetric_eigen();
+        let columns = eigen
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .filter(|(_, &e)| e > 1e-6)
+            .map(|(i, _)| eigen.eigenvectors.column(i).into_owned())
+            .collect_vec();
+        ensure!(!columns.is_empty(), "no delocalized internal coordinates");
+        let u = na::DMatrix::from_columns(&columns);
+        Ok(Self { internals, u })
+    }
+
+    /// Return the number of delocalized coordinates.
+    pub fn dim(&self) -> usize {
+        self.u.ncols()
+    }
+
+    /// Return the underlying redundant internals.
+    pub fn internals(&self) -> &RedundantInternals {
+        &self.internals
+    }
+
+    /// Return values of delocalized coordinates at flattened `positions`.
+    pub fn values(&self, positions: &[f64]) -> Vec<f64> {
+        let q = na::DVector::from_vec(self.internals.values(positions));
+        (self.u.transpose() * q).as_slice().to_vec()
+    }
+}
+// 08334871 ends here
+
+// [[file:../optim.note::aefa97cd][aefa97cd]]
+/// Quasi-Newton steps in internal coordinates, with BFGS update of the
+/// Hessian. Steps in redundant internals are projected onto the non-redundant
+/// space; steps in DLC are taken along columns of the transformation `u`.
+pub(crate) struct InternalStepper {
+    internals: RedundantInternals,
+    // transformation from redundant to delocalized internals
+    u: Option<na::DMatrix<f64>>,
+    hessian: na::DMatrix<f64>,
+    // max displacement of any atom in a step
+    max_step: f64,
+    // redundant internals and gradient in working coordinates in last step
+    last: Option<(Vec<f64>, na::DVector<f64>)>,
+}
+
+impl InternalStepper {
+    pub fn new(internals: RedundantInternals, max_step: f64) -> Self {
+        let hessian = na::DMatrix::from_diagonal(&internals.model_hessian_diagonal());
+        Self {
+            internals,
+            u: None,
+            hessian,
+            max_step,
+            last: None,
+        }
+    }
+
+    pub fn delocalized(dlc: DelocalizedInternals, max_step: f64) -> Self {
+        let h = na::DMatrix::from_diagonal(&dlc.internals.model_hessian_diagonal());
+        let hessian = dlc.u.transpose() * h * &dlc.u;
+        Self {
+            internals: dlc.internals,
+            u: Some(dlc.u),
+            hessian,
+            max_step,
+            last: None,
+        }
+    }
+
+    /// Seed the Hessian from the Cartesian model Hessian `hx` (3N x 3N in row
+    /// major) at flattened `positions`, transformed into working coordinates
+    /// as G^- B Hx B^T G^- using Wilson B-matrix, instead of the constant
+    /// diagonal.
+    pub fn with_model_hessian(mut self, positions: &[f64], hx: &[f64]) -> Result<Self> {
+        let n = positions.len();
+        ensure!(hx.len() == n * n, "invalid size of model Hessian: {}", hx.len());
+        let b = self.internals.wilson_b(positions);
+        let b = match &self.u {
+            Some(u) => u.transpose() * b,
+            None => b,
+        };
+        let g_inv = pseudo_inverse(&b * b.transpose())?;
+        let bg = b.transpose() * g_inv;
+        self.hessian = bg.transpose() * na::DMatrix::from_row_slice(n, n, hx) * bg;
+        Ok(self)
+    }
+
+    // transform vector in redundant internals into working coordinates
+    fn to_working(&self, v: na::DVector<f64>) -> na::DVector<f64> {
+        match &self.u {
+            Some(u) => u.transpose() * v,
+            None => v,
+        }
+    }
+
+    /// Return new positions stepping from `positions` with Cartesian gradient
+    /// `gx`.
+    pub fn step(&mut self, positions: &[f64], gx: &[f64]) -> Result<Vec<f64>> {
+        ensure!(!self.internals.coords().is_empty(), "no internal coordinates");
+        let b = self.internals.wilson_b(positions);
+        let b = match &self.u {
+            Some(u) => u.transpose() * b,
+            None => b,
+        };
+        let nw = b.nrows();
+        let g = &b * b.transpose();
+        let g_inv = pseudo_inverse(g.clone())?;
+        let gw = &g_inv * (&b * na::DVector::from_column_slice(gx));
+        let q = self.internals.values(positions);
+
+        // BFGS update of the Hessian
+        if let Some((q_last, gw_last)) = self.last.take() {
+            let s = self.to_working(na::DVector::from_vec(self.internals.differences(&q, &q_last)));
+            let y = &gw - gw_last;
+            let sy = s.dot(&y);
+            let hs = &self.hessian * &s;
+            let shs = s.dot(&hs);
+            if sy > 1e-8 && shs > 1e-8 {
+                self.hessian += &y * y.transpose() / sy - &hs * hs.transpose() / shs;
+            }
+        }
+        self.last = Some((q, gw.clone()));
+
+        // Newton step with Hessian projected onto non-redundant space
+        let p = &g * &g_inv;
+        let identity = na::DMatrix::identity(nw, nw);
+        let h = &p * &self.hessian * &p + (identity - &p) * 1000.0;
+        let pg = &p * gw;
+        let mut dw = -pseudo_inverse(h)? * &pg;
+        if dw.dot(&pg) > 0.0 {
+            debug!("uphill step in internals, switch to steepest descent");
+            dw = -pg / K_STRETCH;
+        }
+
+        let back_transform = |dw: &na::DVector<f64>| {
+            let dq = match &self.u {
+                Some(u) => u * dw,
+                None => dw.clone(),
+            };
+            self.internals.back_transform(positions, dq.as_slice())
+        };
+        let mut new = back_transform(&dw)?;
+        let dmax = max_displacement(positions, &new);
+        if dmax > self.max_step {
+            dw *= self.max_step / dmax;
+            new = back_transform(&dw)?;
+        }
+        Ok(new)
+    }
+}
+
+fn max_displacement(a: &[f64], b: &[f64]) -> f64 {
+    a.chunks(3)
+        .zip(b.chunks(3))
+        .map(|(x, y)| distance([x[0], x[1], x[2]], [y[0], y[1], y[2]]))
+        .float_max()
+}
+// aefa97cd ends here
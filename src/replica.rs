@@ -0,0 +1,369 @@
+// [[file:../optim.note::12a8600c][12a8600c]]
+use super::*;
+
+use crate::dynamics::KB;
+use crate::random::Rng;
+// 12a8600c ends here
+
+// [[file:../optim.note::dbd5edbe][dbd5edbe]]
+/// A replica simulated at its own temperature in `ReplicaExchange`.
+pub trait Replica {
+    /// Propagate dynamics by `nsteps` steps.
+    fn propagate(&mut self, nsteps: usize) -> Result<()>;
+
+    /// Return potential energy in eV at current step.
+    fn potential_energy(&mut self) -> Result<f64>;
+
+    /// Change target temperature to `temperature` in K, with velocities
+    /// rescaled accordingly.
+    fn set_temperature(&mut self, temperature: f64);
+}
+
+impl<'a, U> Replica for MoleculeDynamics<'a, U> {
+    fn propagate(&mut self, nsteps: usize) -> Result<()> {
+        self.propagate(nsteps)
+    }
+
+    fn potential_energy(&mut self) -> Result<f64> {
+        self.get_energy()
+    }
+
+    fn set_temperature(&mut self, temperature: f64) {
+        self.set_target_temperature(temperature);
+    }
+}
+
+/// Statistics of exchange trials between neighboring temperatures.
+#[derive(Debug, Clone)]
+pub struct ExchangeStats {
+    temperatures: Vec<f64>,
+    /// The number of exchange trials between temperature k and k + 1.
+    pub attempts: Vec<usize>,
+    /// The number of accepted exchanges between temperature k and k + 1.
+    pub accepted: Vec<usize>,
+}
+
+impl ExchangeStats {
+    /// Return acceptance ratio of exchanges between temperature `k` and `k +
+    /// 1`, or NaN if never attempted.
+    pub fn acceptance(&self, k: usize) -> f64 {
+        self.accepted[k] as f64 / self.attempts[k] as f64
+    }
+}
+
+impl std::fmt::Display for ExchangeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10} {:>10} {:>10}",
+            "T1", "T2", "attempts", "accepted", "ratio"
+        )?;
+        for k in 0..self.attempts.len() {
+            writeln!(
+                f,
+                "{:>10.2} {:>10.2} {:>10} {:>10} {:>10.3}",
+                self.temperatures[k],
+                self.temperatures[k + 1],
+                self.attempts[k],
+                self.accepted[k],
+                self.acceptance(k)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Replica exchange molecular dynamics (parallel tempering): replicas are
+/// propagated at different temperatures, and temperatures of neighboring
+/// replicas are swapped periodically by Metropolis criterion, alternating
+/// between even and odd pairs.
+///
+/// `MoleculeDynamics` coupled to a thermostat is a `Replica`, propagated in
+/// turn in `run`. Replicas that can be sent across threads could be
+/// propagated in parallel using `run_parallel`. `MoleculeDynamics` is not
+/// `Send`, but can be constructed and kept in its own thread by
+/// `ReplicaWorker` for parallel propagation.
+///
+/// # Examples
+///
+/// ```ignore
+/// let temperatures = [300.0, 350.0, 410.0, 480.0];
+/// let replicas = models
+///     .iter_mut()
+///     .map(|model| MoleculeDynamics::from_chemical_model(model, mol.clone()).map(|md| md.nvt(300.0)))
+///     .collect::<Result<Vec<_>>>()?;
+/// let mut remd = ReplicaExchange::new(replicas, &temperatures).exchange_every(100);
+/// remd.run(1000)?;
+/// println!("{}", remd.stats());
+/// ```
+///
+/// # References
+///
+/// - Sugita, Y.; Okamoto, Y. Chem. Phys. Lett. 1999, 314, 141.
+pub struct ReplicaExchange<R> {
+    replicas: Vec<R>,
+    // temperatures in ascending order
+    temperatures: Vec<f64>,
+    // index of replica at each temperature
+    ladder: Vec<usize>,
+    nsteps: usize,
+    nexchanges: usize,
+    rng: Rng,
+    stats: ExchangeStats,
+}
+
+impl<R: Replica> ReplicaExchange<R> {
+    /// Simulate `replicas` at `temperatures` in K in ascending order, one for
+    /// each replica.
+    ///
+    /// # Panics
+    ///
+    /// * when the number of temperatures does not match, or temperatures are
+    ///   not ascending.
+    pub fn new(mut replicas: Vec<R>, temperatures: &[f64]) -> Self {
+        let n = replicas.len();
+        assert_eq!(temperatures.len(), n, "invalid number of temperatures");
+        assert!(temperatures[0] > 0.0, "invalid temperatures: {temperatures:?}");
+        assert!(
+            temperatures.windows(2).all(|t| t[0] < t[1]),
+            "temperatures are not ascending: {temperatures:?}"
+        );
+        for (replica, &t) in replicas.iter_mut().zip(temperatures) {
+            replica.set_temperature(t);
+        }
+        let npairs = n.saturating_sub(1);
+        Self {
+            replicas,
+            temperatures: temperatures.to_vec(),
+            ladder: (0..n).collect(),
+            nsteps: 100,
+            nexchanges: 0,
+            rng: Rng::new(0),
+            stats: ExchangeStats {
+                temperatures: temperatures.to_vec(),
+                attempts: vec![0; npairs],
+                accepted: vec![0; npairs],
+            },
+        }
+    }
+
+    /// Attempt exchanges every `nsteps` steps of dynamics.
+    pub fn exchange_every(mut self, nsteps: usize) -> Self {
+        assert!(nsteps > 0, "invalid interval: {nsteps}");
+        self.nsteps = nsteps;
+        self
+    }
+
+    /// Set `seed` for random numbers in Metropolis criterion.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Return the replica currently at the `k`-th temperature.
+    pub fn replica_at(&self, k: usize) -> &R {
+        &self.replicas[self.ladder[k]]
+    }
+
+    /// Return all replicas in their original order.
+    pub fn replicas(&self) -> &[R] {
+        &self.replicas
+    }
+
+    /// Return statistics of exchanges.
+    pub fn stats(&self) -> &ExchangeStats {
+        &self.stats
+    }
+
+    /// Run `nexchanges` cycles of propagating all replicas in turn followed
+    /// by exchange trials.
+    pub fn run(&mut self, nexchanges: usize) -> Result<()> {
+        for _ in 0..nexchanges {
+            for replica in self.replicas.iter_mut() {
+                replica.propagate(self.nsteps)?;
+            }
+            self.exchange()?;
+        }
+        Ok(())
+    }
+
+    /// Run `nexchanges` cycles as in `run`, propagating replicas in parallel
+    /// threads.
+    pub fn run_parallel(&mut self, nexchanges: usize) -> Result<()>
+    where
+        R: Send,
+    {
+        let nsteps = self.nsteps;
+        for _ in 0..nexchanges {
+            std::thread::scope(|s| {
+                let handles = self
+                    .replicas
+                    .iter_mut()
+                    .map(|replica| s.spawn(move || replica.propagate(nsteps)))
+                    .collect_vec();
+                handles
+                    .into_iter()
+                    .map(|h| h.join().map_err(|_| format_err!("replica thread panicked"))?)
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            self.exchange()?;
+        }
+        Ok(())
+    }
+
+    // Attempt swaps of temperatures between neighboring replicas.
+    fn exchange(&mut self) -> Result<()> {
+        let energies = self
+            .replicas
+            .iter_mut()
+            .map(|r| r.potential_energy())
+            .collect::<Result<Vec<_>>>()?;
+        let offset = self.nexchanges % 2;
+        for k in (offset..self.temperatures.len().saturating_sub(1)).step_by(2) {
+            let (i, j) = (self.ladder[k], self.ladder[k + 1]);
+            let (ti, tj) = (self.temperatures[k], self.temperatures[k + 1]);
+            let delta = (1.0 / (KB * ti) - 1.0 / (KB * tj)) * (energies[i] - energies[j]);
+            self.stats.attempts[k] += 1;
+            if delta >= 0.0 || self.rng.uniform() < delta.exp() {
+                self.stats.accepted[k] += 1;
+                self.ladder.swap(k, k + 1);
+                self.replicas[i].set_temperature(tj);
+                self.replicas[j].set_temperature(ti);
+            }
+        }
+        self.nexchanges += 1;
+        Ok(())
+    }
+}
+// dbd5edbe ends here
+
+// [[file:../optim.note::db757bd3][db757bd3]]
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+
+enum Command {
+    Propagate(usize),
+    PotentialEnergy,
+    SetTemperature(f64),
+}
+
+/// A `Replica` constructed and kept in its own worker thread, which can be
+/// sent across threads even if the replica itself cannot, such as
+/// `MoleculeDynamics` holding a borrowed model. All calls are forwarded to
+/// the worker thread, and the worker is stopped when dropped.
+///
+/// # Examples
+///
+/// ```ignore
+/// let workers = temperatures
+///     .iter()
+///     .map(|_| {
+///         let mol = mol.clone();
+///         ReplicaWorker::spawn(move |server| {
+///             let mut lj = LennardJones::default();
+///             let md = MoleculeDynamics::from_chemical_model(&mut lj, mol)?.nvt(300.0);
+///             server.serve(md)
+///         })
+///     })
+///     .collect_vec();
+/// let mut remd = ReplicaExchange::new(workers, &temperatures);
+/// remd.run_parallel(1000)?;
+/// ```
+pub struct ReplicaWorker {
+    commands: Option<Sender<Command>>,
+    results: Receiver<Result<f64>>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+/// The serving end of `ReplicaWorker` in worker thread.
+pub struct ReplicaServer {
+    commands: Receiver<Command>,
+    results: Sender<Result<f64>>,
+}
+
+impl ReplicaServer {
+    /// Serve calls on `replica` until the worker is dropped.
+    pub fn serve(self, mut replica: impl Replica) -> Result<()> {
+        for command in self.commands.iter() {
+            let result = match command {
+                Command::Propagate(nsteps) => replica.propagate(nsteps).map(|_| 0.0),
+                Command::PotentialEnergy => replica.potential_energy(),
+                Command::SetTemperature(t) => {
+                    replica.set_temperature(t);
+                    continue;
+                }
+            };
+            if self.results.send(result).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReplicaWorker {
+    /// Spawn a worker thread calling `build` with a `ReplicaServer`, which
+    /// constructs the replica and serves it.
+    pub fn spawn<F>(build: F) -> Self
+    where
+        F: FnOnce(ReplicaServer) -> Result<()> + Send + 'static,
+    {
+        let (commands, commands_rx) = channel();
+        let (results_tx, results) = channel();
+        let server = ReplicaServer {
+            commands: commands_rx,
+            results: results_tx,
+        };
+        let handle = std::thread::spawn(move || build(server));
+        Self {
+            commands: Some(commands),
+            results,
+            handle: Some(handle),
+        }
+    }
+
+    fn call(&mut self, command: Command) -> Result<f64> {
+        let sent = self.commands.as_ref().map_or(false, |tx| tx.send(command).is_ok());
+        if sent {
+            if let Ok(result) = self.results.recv() {
+                return result;
+            }
+        }
+        // the worker stopped: report its error
+        self.commands = None;
+        match self.handle.take().map(|h| h.join()) {
+            Some(Ok(Err(e))) => Err(e.context("replica worker failed")),
+            Some(Err(_)) => bail!("replica worker panicked"),
+            _ => bail!("replica worker stopped"),
+        }
+    }
+}
+
+impl Replica for ReplicaWorker {
+    fn propagate(&mut self, nsteps: usize) -> Result<()> {
+        self.call(Command::Propagate(nsteps))?;
+        Ok(())
+    }
+
+    fn potential_energy(&mut self) -> Result<f64> {
+        self.call(Command::PotentialEnergy)
+    }
+
+    fn set_temperature(&mut self, temperature: f64) {
+        // errors in worker will be reported in the next call
+        if let Some(tx) = &self.commands {
+            let _ = tx.send(Command::SetTemperature(temperature));
+        }
+    }
+}
+
+impl Drop for ReplicaWorker {
+    fn drop(&mut self) {
+        // closing the channel stops serving
+        self.commands = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+// db757bd3 ends here
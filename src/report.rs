@@ -0,0 +1,182 @@
+// [[file:../optim.note::15c98a06][15c98a06]]
+use super::*;
+
+use std::time::Duration;
+// 15c98a06 ends here
+
+// [[file:../optim.note::7a395ce5][7a395ce5]]
+/// Distribution of per-atom force norms at the end of an optimization run.
+#[derive(Debug, Clone, Default)]
+pub struct ForceStats {
+    /// The largest per-atom force norm.
+    pub max: f64,
+    /// Root mean square of per-atom force norms.
+    pub rms: f64,
+    /// Percentiles of per-atom force norms in pairs of (percentile, value).
+    pub percentiles: Vec<(f64, f64)>,
+    /// Atoms with the largest force norms in pairs of (atom number, force
+    /// norm), sorted in descending order.
+    pub worst_atoms: Vec<(usize, f64)>,
+}
+
+/// Statistics on step sizes of the last few iterations.
+#[derive(Debug, Clone, Default)]
+pub struct StepStats {
+    /// Step sizes (norm of displacement) in the last iterations, oldest first.
+    pub last: Vec<f64>,
+    /// The largest step size in `last`.
+    pub max: f64,
+    /// The mean step size in `last`.
+    pub mean: f64,
+}
+
+/// Timing and call statistics on potential evaluations, for cost accounting.
+#[derive(Debug, Clone, Default)]
+pub struct EvalStats {
+    /// The number of real evaluations of the potential.
+    pub ncalls: usize,
+    /// The number of evaluations served from cache.
+    pub ncached: usize,
+    /// The number of retries for failed evaluations, see `RetryPolicy`.
+    pub nretries: usize,
+    /// Total wall time spent in real evaluations.
+    pub total: Duration,
+    /// The shortest wall time of a single evaluation.
+    pub min: Duration,
+    /// The longest wall time of a single evaluation.
+    pub max: Duration,
+}
+
+/// A report on convergence quality of an optimization run, beyond the single
+/// fmax number.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    pub forces: ForceStats,
+    pub steps: StepStats,
+    pub evaluations: EvalStats,
+}
+// 7a395ce5 ends here
+
+// [[file:../optim.note::c4bf1de0][c4bf1de0]]
+/// The percentiles reported in `ForceStats`.
+const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+/// The number of worst atoms reported in `ForceStats`.
+const NWORST: usize = 5;
+
+// nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    debug_assert!(!sorted.is_empty());
+    let n = sorted.len();
+    let rank = (p / 100.0 * n as f64).ceil() as usize;
+    sorted[rank.clamp(1, n) - 1]
+}
+
+impl ForceStats {
+    /// Compute statistics from per-atom forces. Atoms are numbered from 1 in
+    /// the same order as `forces`.
+    pub fn from_forces(forces: &[[f64; 3]]) -> Self {
+        if forces.is_empty() {
+            return Self::default();
+        }
+
+        let norms = forces.iter().map(|f| f.vec2norm()).collect_vec();
+        let n = norms.len() as f64;
+        let rms = (norms.iter().map(|x| x * x).sum::<f64>() / n).sqrt();
+
+        let mut sorted = norms.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("found invalid float numbers"));
+        let percentiles = PERCENTILES.iter().map(|&p| (p, percentile(&sorted, p))).collect();
+
+        let worst_atoms = norms
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, f)| (i + 1, f))
+            .sorted_by(|a, b| b.1.partial_cmp(&a.1).expect("found invalid float numbers"))
+            .take(NWORST)
+            .collect();
+
+        Self {
+            max: sorted[sorted.len() - 1],
+            rms,
+            percentiles,
+            worst_atoms,
+        }
+    }
+}
+
+impl StepStats {
+    /// Compute statistics from step sizes of the last iterations.
+    pub fn from_steps(steps: impl IntoIterator<Item = f64>) -> Self {
+        let last = steps.into_iter().collect_vec();
+        if last.is_empty() {
+            return Self::default();
+        }
+        let max = last.iter().copied().float_max();
+        let mean = last.iter().sum::<f64>() / last.len() as f64;
+        Self { last, max, mean }
+    }
+}
+
+impl EvalStats {
+    /// The mean wall time of real evaluations.
+    pub fn mean(&self) -> Duration {
+        if self.ncalls == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.ncalls as u32
+        }
+    }
+
+    /// Record a real evaluation taking `elapsed` wall time.
+    pub(crate) fn record(&mut self, elapsed: Duration) {
+        self.min = if self.ncalls == 0 {
+            elapsed
+        } else {
+            self.min.min(elapsed)
+        };
+        self.max = self.max.max(elapsed);
+        self.total += elapsed;
+        self.ncalls += 1;
+    }
+}
+
+impl std::fmt::Display for EvalStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "evaluations: {} calls ({} cached, {} retried), wall time = {:.3?} (min = {:.3?}, mean = {:.3?}, max = {:.3?})",
+            self.ncalls,
+            self.ncached,
+            self.nretries,
+            self.total,
+            self.min,
+            self.mean(),
+            self.max
+        )
+    }
+}
+
+impl std::fmt::Display for RunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let forces = &self.forces;
+        writeln!(f, "force norms: max = {:.4}, rms = {:.4}", forces.max, forces.rms)?;
+        for (p, v) in forces.percentiles.iter() {
+            writeln!(f, "  {:>4.0}th percentile = {:.4}", p, v)?;
+        }
+        let worst = forces.worst_atoms.iter().map(|(i, v)| format!("{i}({v:.4})")).join(" ");
+        writeln!(f, "  worst atoms: {worst}")?;
+
+        let steps = &self.steps;
+        writeln!(
+            f,
+            "step sizes in last {} iterations: max = {:.4}, mean = {:.4}",
+            steps.last.len(),
+            steps.max,
+            steps.mean
+        )?;
+        write!(f, "{}", self.evaluations)
+    }
+}
+// c4bf1de0 ends here
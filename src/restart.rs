@@ -0,0 +1,98 @@
+// [[file:../optim.note::f5cbff2e][f5cbff2e]]
+use super::*;
+
+use crate::freeze::CoordsMask;
+use gchemol::Molecule;
+use serde::*;
+// f5cbff2e ends here
+
+// [[file:../optim.note::c8cc5c8c][c8cc5c8c]]
+// The key for storing run signature in molecule properties
+const SIGNATURE_KEY: &str = "gosh-optim/run-signature";
+
+/// The degrees of freedom of an optimization run, recorded along with the
+/// checkpointed molecule for detecting inconsistent restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RunSignature {
+    symbols: Vec<String>,
+    frozen: Vec<bool>,
+    constraints: Vec<String>,
+}
+
+impl RunSignature {
+    /// Construct signature of the run for `mol`.
+    pub fn new(mol: &Molecule, mask: &CoordsMask, constraints: &Constraints) -> Self {
+        Self {
+            symbols: mol.symbols().map(|s| s.to_string()).collect(),
+            frozen: mask.frozen().to_vec(),
+            constraints: constraints.iter().map(|c| format!("{c:?}")).collect(),
+        }
+    }
+
+    /// Record the signature in properties of `mol`.
+    pub fn store(&self, mol: &mut Molecule) {
+        mol.properties.store(SIGNATURE_KEY, self);
+    }
+
+    /// Load signature recorded in `mol`, if any.
+    pub fn load(mol: &Molecule) -> Option<Self> {
+        mol.properties.load(SIGNATURE_KEY).ok()
+    }
+
+    /// Describe differences of this run from the checkpointed one.
+    pub fn diff(&self, checkpointed: &Self) -> Vec<String> {
+        let mut diff = vec![];
+        let (n_new, n_old) = (self.symbols.len(), checkpointed.symbols.len());
+        if n_new != n_old {
+            diff.push(format!("number of atoms changed: {n_old} => {n_new}"));
+            return diff;
+        }
+
+        for (i, (new, old)) in self.symbols.iter().zip(checkpointed.symbols.iter()).enumerate() {
+            if new != old {
+                diff.push(format!("element of atom {} changed: {old} => {new}", i + 1));
+            }
+        }
+        // frozen may be empty for legacy checkpoints
+        if !checkpointed.frozen.is_empty() {
+            let frozen_new = self.frozen.chunks(3);
+            let frozen_old = checkpointed.frozen.chunks(3);
+            for (i, (new, old)) in frozen_new.zip(frozen_old).enumerate() {
+                if new != old {
+                    diff.push(format!("freezing coords of atom {} changed: {old:?} => {new:?}", i + 1));
+                }
+            }
+        }
+        for c in checkpointed.constraints.iter() {
+            if !self.constraints.contains(c) {
+                diff.push(format!("constraint removed: {c}"));
+            }
+        }
+        for c in self.constraints.iter() {
+            if !checkpointed.constraints.contains(c) {
+                diff.push(format!("constraint added: {c}"));
+            }
+        }
+        diff
+    }
+
+    /// Check if resuming from checkpointed molecule `mol` is consistent with
+    /// this run.
+    pub fn check_restart(&self, mol: &Molecule) -> Result<()> {
+        let checkpointed = Self::load(mol).unwrap_or_else(|| {
+            // checkpoints written without signature: only elements can be
+            // compared
+            Self {
+                symbols: mol.symbols().map(|s| s.to_string()).collect(),
+                frozen: vec![],
+                constraints: self.constraints.clone(),
+            }
+        });
+        let diff = self.diff(&checkpointed);
+        if !diff.is_empty() {
+            bail!("inconsistent restart from checkpoint:\n{}", diff.join("\n"));
+        }
+        Ok(())
+    }
+}
+// c8cc5c8c ends here
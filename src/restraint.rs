@@ -0,0 +1,249 @@
+// [[file:../optim.note::8eb80c6c][8eb80c6c]]
+use super::*;
+
+use crate::internals::Coordinate;
+// 8eb80c6c ends here
+
+// [[file:../optim.note::f077f343][f077f343]]
+/// Restraints adding bias energy and forces on top of the physical potential.
+#[derive(Debug, Clone)]
+pub enum Restraint {
+    /// Harmonic restraint E = k/2 (q - target)^2 on coordinate `coord`. For
+    /// angles, `target` is in degree, and `k` in energy per radian squared.
+    Harmonic { coord: Coordinate, k: f64, target: f64 },
+    /// Harmonic wall E = k/2 (r - radius)^2 on each atom outside a sphere,
+    /// with r the distance of the atom to `center`. This confines clusters
+    /// from evaporating.
+    SphericalWall { center: [f64; 3], radius: f64, k: f64 },
+    /// Harmonic wall E = k/2 d^2 on each atom outside an axis-aligned box
+    /// from `lower` to `upper` corners, with d the distance outside the box
+    /// along each Cartesian direction.
+    BoxWall { lower: [f64; 3], upper: [f64; 3], k: f64 },
+    /// Hookean restraint between atom `i` and atom `j`, which is zero below
+    /// the threshold distance `rt`, and harmonic E = k/2 (r - rt)^2 beyond
+    /// it. This prevents bonds from breaking unintentionally.
+    Hookean { i: usize, j: usize, k: f64, rt: f64 },
+}
+
+impl Restraint {
+    /// Compute restraint energy at `positions`, and add the restraint forces
+    /// into `forces`.
+    fn apply(&self, positions: &[[f64; 3]], forces: &mut [f64]) -> f64 {
+        match *self {
+            Self::Harmonic { coord, k, target } => {
+                let target = if coord.is_angular() {
+                    target.to_radians()
+                } else {
+                    target
+                };
+                let dq = coord.difference(coord.value(positions), target);
+                for (i, g) in coord.gradient(positions) {
+                    forces[3 * i..3 * i + 3].vecadd(&g, -k * dq);
+                }
+                0.5 * k * dq * dq
+            }
+            Self::SphericalWall { center, radius, k } => {
+                let mut energy = 0.0;
+                for (p, f) in positions.iter().zip(forces.as_mut_3d()) {
+                    let mut d = *p;
+                    d.vecadd(&center, -1.0);
+                    let r = d.vec2norm();
+                    if r > radius {
+                        energy += 0.5 * k * (r - radius).powi(2);
+                        f.vecadd(&d, -k * (r - radius) / r);
+                    }
+                }
+                energy
+            }
+            Self::Hookean { i, j, k, rt } => {
+                let coord = Coordinate::Distance(i, j);
+                let dr = coord.value(positions) - rt;
+                if dr <= 0.0 {
+                    return 0.0;
+                }
+                for (i, g) in coord.gradient(positions) {
+                    forces[3 * i..3 * i + 3].vecadd(&g, -k * dr);
+                }
+                0.5 * k * dr * dr
+            }
+            Self::BoxWall { lower, upper, k } => {
+                let mut energy = 0.0;
+                for (p, f) in positions.iter().zip(forces.as_mut_3d()) {
+                    for i in 0..3 {
+                        let d = (p[i] - upper[i]).max(0.0) + (p[i] - lower[i]).min(0.0);
+                        energy += 0.5 * k * d * d;
+                        f[i] -= k * d;
+                    }
+                }
+                energy
+            }
+        }
+    }
+
+    fn check(&self, natoms: usize) -> Result<()> {
+        match self {
+            Self::Harmonic { coord, k, .. } => {
+                coord.check(natoms)?;
+                ensure!(k.is_sign_positive(), "invalid force constant: {k}");
+            }
+            Self::SphericalWall { radius, k, .. } => {
+                ensure!(*radius > 0.0, "invalid radius of spherical wall: {radius}");
+                ensure!(k.is_sign_positive(), "invalid force constant: {k}");
+            }
+            Self::Hookean { i, j, k, rt } => {
+                Coordinate::Distance(*i, *j).check(natoms)?;
+                ensure!(*rt > 0.0, "invalid threshold distance: {rt}");
+                ensure!(k.is_sign_positive(), "invalid force constant: {k}");
+            }
+            Self::BoxWall { lower, upper, k } => {
+                ensure!(
+                    (0..3).all(|i| lower[i] < upper[i]),
+                    "invalid box wall: {lower:?}, {upper:?}"
+                );
+                ensure!(k.is_sign_positive(), "invalid force constant: {k}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A set of restraints, optionally switched on or off according to a lambda
+/// schedule.
+#[derive(Debug, Clone, Default)]
+pub struct Restraints {
+    items: Vec<Restraint>,
+    // schedules ramping force constant of each restraint
+    ramps: Vec<LambdaSchedule>,
+    schedule: LambdaSchedule,
+}
+
+impl Restraints {
+    /// Add a restraint.
+    pub fn add(&mut self, restraint: Restraint) {
+        self.add_ramped(restraint, LambdaSchedule::default());
+    }
+
+    /// Add a restraint with force constant scaled by lambda in `ramp`, such
+    /// as increasing strength gradually in pulling simulations. Steps are
+    /// counted in the same way as the schedule for all restraints.
+    pub fn add_ramped(&mut self, restraint: Restraint, ramp: LambdaSchedule) {
+        self.items.push(restraint);
+        self.ramps.push(ramp);
+    }
+
+    /// Return true if there is no restraint.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Scale restraints using lambda in `schedule`.
+    pub fn set_schedule(&mut self, schedule: LambdaSchedule) {
+        self.schedule = schedule;
+    }
+
+    /// Return the lambda for scaling restraints at `step`.
+    pub fn lambda(&self, step: usize) -> f64 {
+        self.schedule.lambda(step)
+    }
+
+    /// Compute total restraint energy at flattened `positions` for `step`,
+    /// and add the restraint forces into flattened `forces`. Restraints are
+    /// scaled by lambda in schedule.
+    pub fn apply(&self, step: usize, positions: &[f64], forces: &mut [f64]) -> Result<f64> {
+        let natoms = positions.len() / 3;
+        let lambda = self.lambda(step);
+        let mut energy = 0.0;
+        let mut restraint_forces = vec![0.0; forces.len()];
+        let mut f = vec![0.0; forces.len()];
+        for (r, ramp) in self.items.iter().zip(&self.ramps) {
+            r.check(natoms)?;
+            let scale = ramp.lambda(step);
+            f.iter_mut().for_each(|x| *x = 0.0);
+            energy += scale * r.apply(positions.as_3d(), &mut f);
+            restraint_forces.vecadd(&f, scale);
+        }
+        forces.vecadd(&restraint_forces, lambda);
+        Ok(lambda * energy)
+    }
+}
+// f077f343 ends here
+
+// [[file:../optim.note::cbf928ab][cbf928ab]]
+impl Restraint {
+    /// Return Hookean restraints with force constant `k` on all bonds in
+    /// `mol`, found from covalent radii. The threshold distance of each bond
+    /// is where atoms are no longer considered as bonded. Periodic images are
+    /// not considered.
+    pub fn hookean_bonds(mol: &gchemol::Molecule, k: f64) -> Vec<Self> {
+        use crate::redundant::{covalent_radius, BOND_FACTOR};
+
+        let positions = mol.positions().collect_vec();
+        if positions.len() < 2 {
+            return vec![];
+        }
+        let radii = mol.numbers().map(covalent_radius).collect_vec();
+        let rmax = radii.iter().copied().float_max();
+        crate::sparse::neighbor_pairs(&positions, 2.0 * rmax * BOND_FACTOR)
+            .into_iter()
+            .filter_map(|(i, j)| {
+                let rt = BOND_FACTOR * (radii[i] + radii[j]);
+                let r = crate::internals::distance(positions[i], positions[j]);
+                (r < rt).then_some(Self::Hookean { i, j, k, rt })
+            })
+            .collect()
+    }
+}
+// cbf928ab ends here
+
+// [[file:../optim.note::99529c99][99529c99]]
+/// Output of potential with restraints.
+#[derive(Debug, Clone)]
+pub struct Restrained<U> {
+    /// The restraint energy included in the total energy.
+    pub restraint_energy: f64,
+    /// The instantaneous lambda scaling the restraints.
+    pub lambda: f64,
+    /// Extra data from the wrapped potential.
+    pub extra: U,
+}
+
+/// A potential wrapper adding restraint energy and forces on top of any
+/// `EvaluatePotential`.
+///
+/// Each evaluation counts as a step in lambda schedule of restraints.
+pub struct RestrainedPotential<P> {
+    potential: P,
+    restraints: Restraints,
+    step: usize,
+}
+
+impl<P> RestrainedPotential<P> {
+    /// Restrain `potential` using `restraints`.
+    pub fn new(potential: P, restraints: Restraints) -> Self {
+        Self {
+            potential,
+            restraints,
+            step: 0,
+        }
+    }
+}
+
+impl<U, P> EvaluatePotential<Restrained<U>> for RestrainedPotential<P>
+where
+    P: EvaluatePotential<U>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<Restrained<U>> {
+        let extra = self.potential.evaluate(position, output)?;
+        let step = self.step;
+        let restraint_energy = self.restraints.apply(step, position, &mut output.force)?;
+        output.energy += restraint_energy;
+        self.step += 1;
+
+        Ok(Restrained {
+            restraint_energy,
+            lambda: self.restraints.lambda(step),
+            extra,
+        })
+    }
+}
+// 99529c99 ends here
@@ -0,0 +1,122 @@
+// [[file:../optim.note::6cbbef57][6cbbef57]]
+use super::*;
+
+use vecfx::nalgebra as na;
+// 6cbbef57 ends here
+
+// [[file:../optim.note::efcdb106][efcdb106]]
+// step for differentiating rotation matrix numerically
+const ROTATION_DELTA: f64 = 1e-6;
+
+#[derive(Debug, Clone)]
+struct RigidFragment {
+    // atom indices from 0
+    atoms: Vec<usize>,
+    // positions relative to the center in reference orientation
+    body: Vec<Vector3f>,
+}
+
+/// Rigid fragments moving only by translation of center and rotation, with
+/// their internal geometry frozen.
+///
+/// Each fragment is parameterized by 6 variables: the geometric center, and
+/// the rotation vector (axis times angle) of the unit quaternion rotating
+/// the fragment from its reference orientation.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RigidFragments {
+    fragments: Vec<RigidFragment>,
+}
+
+fn rotation(theta: &[f64]) -> na::Matrix3<f64> {
+    let q = na::UnitQuaternion::from_scaled_axis(Vector3f::new(theta[0], theta[1], theta[2]));
+    q.to_rotation_matrix().into_inner()
+}
+
+impl RigidFragments {
+    /// Construct rigid fragments of atoms in `fragments` (indexed from 0),
+    /// with reference geometry taken from flattened `positions`.
+    pub fn new(fragments: &[Vec<usize>], positions: &[f64]) -> Self {
+        let p = positions.as_3d();
+        let fragments = fragments
+            .iter()
+            .map(|atoms| {
+                assert!(atoms.len() > 1, "invalid rigid fragment: {atoms:?}");
+                let center = center(atoms.iter().map(|&i| p[i]));
+                let body = atoms.iter().map(|&i| Vector3f::from(p[i]) - center).collect();
+                RigidFragment {
+                    atoms: atoms.clone(),
+                    body,
+                }
+            })
+            .collect();
+        Self { fragments }
+    }
+
+    /// Return true if there is no rigid fragment.
+    pub fn is_empty(&self) -> bool {
+        self.fragments.is_empty()
+    }
+
+    /// Return indices of all atoms in rigid fragments.
+    pub fn atoms(&self) -> Vec<usize> {
+        self.fragments.iter().flat_map(|f| f.atoms.iter().copied()).collect()
+    }
+
+    /// Return the number of variables of all fragments.
+    pub fn nparams(&self) -> usize {
+        6 * self.fragments.len()
+    }
+
+    /// Return variables of fragments in reference geometry.
+    pub fn initial_params(&self, positions: &[f64]) -> Vec<f64> {
+        let p = positions.as_3d();
+        self.fragments
+            .iter()
+            .flat_map(|f| {
+                let c = center(f.atoms.iter().map(|&i| p[i]));
+                [c.x, c.y, c.z, 0.0, 0.0, 0.0]
+            })
+            .collect()
+    }
+
+    /// Place atoms of fragments in flattened `positions` using `params`.
+    pub fn place(&self, params: &[f64], positions: &mut [f64]) {
+        let p = positions.as_mut_3d();
+        for (f, x) in self.fragments.iter().zip(params.chunks(6)) {
+            let center = Vector3f::new(x[0], x[1], x[2]);
+            let r = rotation(&x[3..]);
+            for (&i, d) in f.atoms.iter().zip(&f.body) {
+                p[i] = (center + r * d).into();
+            }
+        }
+    }
+
+    /// Return the gradient with respect to `params` from the Cartesian
+    /// gradient `gx` of all atoms.
+    pub fn gradient(&self, params: &[f64], gx: &[f64]) -> Vec<f64> {
+        let g = gx.as_3d();
+        let mut grad = vec![];
+        for (f, x) in self.fragments.iter().zip(params.chunks(6)) {
+            let gi = |i: usize| Vector3f::from(g[i]);
+            let gc: Vector3f = f.atoms.iter().map(|&i| gi(i)).sum();
+            grad.extend_from_slice(gc.as_slice());
+            for k in 0..3 {
+                let mut theta = [x[3], x[4], x[5]];
+                theta[k] += ROTATION_DELTA;
+                let r_plus = rotation(&theta);
+                theta[k] -= 2.0 * ROTATION_DELTA;
+                let r_minus = rotation(&theta);
+                let dr = (r_plus - r_minus) / (2.0 * ROTATION_DELTA);
+                let gk: f64 = f.atoms.iter().zip(&f.body).map(|(&i, d)| gi(i).dot(&(dr * d))).sum();
+                grad.push(gk);
+            }
+        }
+        grad
+    }
+}
+
+fn center(points: impl Iterator<Item = [f64; 3]>) -> Vector3f {
+    let (sum, n) = points.fold((Vector3f::zeros(), 0), |(s, n), p| (s + Vector3f::from(p), n + 1));
+    sum / n as f64
+}
+// efcdb106 ends here
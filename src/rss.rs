@@ -0,0 +1,252 @@
+// [[file:../optim.note::6f4291a7][6f4291a7]]
+use super::*;
+
+use crate::cell::cell_matrix;
+use crate::hopping::Minimum;
+use crate::redundant::covalent_radius;
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+use vecfx::nalgebra as na;
+// 6f4291a7 ends here
+
+// [[file:../optim.note::ba72a21d][ba72a21d]]
+/// Final result of `RandomSearch`.
+#[derive(Debug, Clone)]
+pub struct RandomSearched {
+    /// Distinct minima relaxed from random structures, in ascending order of
+    /// energy, with the number of hits in `nvisits`.
+    pub minima: Vec<Minimum>,
+    /// The number of random structures relaxed with forces converged.
+    pub nconverged: usize,
+    /// The number of random structures failed in relaxation or not converged.
+    pub nfailed: usize,
+}
+
+impl RandomSearched {
+    /// Return the lowest minimum found.
+    pub fn best(&self) -> Option<&Minimum> {
+        self.minima.first()
+    }
+}
+
+/// Random structure search (RSS) in the spirit of AIRSS: generate random
+/// starting structures with the composition of a template molecule, relax
+/// each of them using `Optimizer`, and collect distinct minima identified by
+/// energies.
+///
+/// Atoms are placed in turn at uniformly random positions, rejecting those
+/// too close to placed atoms. For periodic template, positions are random in
+/// its unit cell with distances in minimum image convention; otherwise in a
+/// cubic box centered at origin.
+///
+/// # Examples
+///
+/// ```ignore
+/// let rss = RandomSearch::new(Optimizer::new(0.01, 500), &template).min_distance(1.5);
+/// let searched = rss.run(&mut model, 100)?;
+/// println!("lowest energy: {:?}", searched.best().map(|m| m.energy));
+/// ```
+///
+/// # References
+///
+/// - Pickard, C. J.; Needs, R. J. J. Phys.: Condens. Matter 2011, 23, 053201.
+pub struct RandomSearch {
+    optimizer: Optimizer,
+    template: Molecule,
+    // fixed min distance between atoms, or scaled sum of covalent radii
+    min_distance: Option<f64>,
+    radius_scale: f64,
+    box_size: Option<f64>,
+    max_attempts: usize,
+    energy_tolerance: f64,
+    seed: u64,
+}
+
+impl RandomSearch {
+    /// Relax random structures with the same atoms and lattice as `template`
+    /// using `optimizer`.
+    pub fn new(optimizer: Optimizer, template: &Molecule) -> Self {
+        Self {
+            optimizer,
+            template: template.clone(),
+            min_distance: None,
+            radius_scale: 0.7,
+            box_size: None,
+            max_attempts: 1000,
+            energy_tolerance: 1e-3,
+            seed: 0,
+        }
+    }
+
+    /// Reject random positions with distance to other atoms below
+    /// `distance` in Å, instead of the default 0.7 times the sum of their
+    /// covalent radii.
+    pub fn min_distance(mut self, distance: f64) -> Self {
+        assert!(distance > 0.0, "invalid min distance: {distance}");
+        self.min_distance = Some(distance);
+        self
+    }
+
+    /// Place atoms of non-periodic template in a cubic box with edge length
+    /// `size` in Å. By default the box holds each atom in a volume of cube of
+    /// twice its min distance to others.
+    pub fn box_size(mut self, size: f64) -> Self {
+        assert!(size > 0.0, "invalid box size: {size}");
+        self.box_size = Some(size);
+        self
+    }
+
+    /// Treat minima with energy difference below `tolerance` in eV as the
+    /// same one.
+    pub fn energy_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.energy_tolerance = tolerance;
+        self
+    }
+
+    /// Set `seed` for generating random structures.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Return the `i`-th random structure generated from the template. Atoms
+    /// with freezing flags in the template are kept in place, such as slab
+    /// atoms in surface structure search.
+    pub fn random_structure(&self, i: usize) -> Result<Molecule> {
+        let mut rng = crate::random::Rng::new(self.seed.wrapping_add(i as u64));
+        let numbers = self.template.atoms().map(|(_, a)| a.number()).collect_vec();
+        let fixed = self
+            .template
+            .atoms()
+            .map(|(_, a)| a.freezing().contains(&true))
+            .collect_vec();
+        let cell = cell_matrix(&self.template);
+        let box_size = self.box_size.unwrap_or_else(|| {
+            let d = (0..numbers.len())
+                .map(|k| self.min_distance_of(&numbers, k, k))
+                .float_max();
+            2.0 * d * (numbers.len() as f64).cbrt()
+        });
+        // placed atoms, starting from fixed ones
+        let mut positions = self.template.positions().collect_vec();
+        let mut placed = fixed.clone();
+        for k in (0..numbers.len()).filter(|&k| !fixed[k]) {
+            for _ in 0..self.max_attempts {
+                let r = [rng.uniform(), rng.uniform(), rng.uniform()];
+                let p: [f64; 3] = match cell {
+                    Some(cell) => (cell * na::Vector3::from(r)).into(),
+                    None => r.map(|x| (x - 0.5) * box_size),
+                };
+                let too_close = (0..numbers.len())
+                    .filter(|&j| placed[j])
+                    .any(|j| self.distance(p, positions[j]) < self.min_distance_of(&numbers, j, k));
+                if !too_close {
+                    positions[k] = p;
+                    placed[k] = true;
+                    break;
+                }
+            }
+            ensure!(
+                placed[k],
+                "failed to place atom {} after {} attempts: too large min distance?",
+                k + 1,
+                self.max_attempts
+            );
+        }
+        let mut mol = self.template.clone();
+        mol.update_positions(positions);
+        Ok(mol)
+    }
+
+    /// Return true if no atoms in `mol` are closer than min distance, in
+    /// minimum image convention with its own lattice if periodic.
+    pub(crate) fn is_sensible(&self, mol: &Molecule) -> bool {
+        let numbers = self.template.atoms().map(|(_, a)| a.number()).collect_vec();
+        let positions = mol.positions().collect_vec();
+        let cell = cell_matrix(mol);
+        (0..positions.len()).tuple_combinations().all(|(i, j)| {
+            image_distance(cell.as_ref(), positions[i], positions[j]) >= self.min_distance_of(&numbers, i, j)
+        })
+    }
+
+    /// Return the optimizer for relaxing structures.
+    pub(crate) fn optimizer(&self) -> &Optimizer {
+        &self.optimizer
+    }
+
+    /// Return the template molecule.
+    pub(crate) fn template(&self) -> &Molecule {
+        &self.template
+    }
+
+    /// Return distance between `p` and `q`, in minimum image convention for
+    /// periodic template.
+    pub(crate) fn distance(&self, p: [f64; 3], q: [f64; 3]) -> f64 {
+        image_distance(cell_matrix(&self.template).as_ref(), p, q)
+    }
+
+    // Min distance between atoms `i` and `j` with atomic `numbers`.
+    fn min_distance_of(&self, numbers: &[usize], i: usize, j: usize) -> f64 {
+        self.min_distance
+            .unwrap_or_else(|| self.radius_scale * (covalent_radius(numbers[i]) + covalent_radius(numbers[j])))
+    }
+
+    /// Relax `n` random structures in potential provided by `model`.
+    /// Structures failed in relaxation are skipped with a warning.
+    pub fn run<M: ChemicalModel>(&self, model: &mut M, n: usize) -> Result<RandomSearched> {
+        let mut minima: Vec<Minimum> = vec![];
+        let mut nconverged = 0;
+        let mut nfailed = 0;
+        for i in 0..n {
+            let mut mol = self.random_structure(i)?;
+            let energy = match self.optimizer.optimize_geometry(&mut mol, model) {
+                Ok(optimized) if optimized.fmax < self.optimizer.fmax() => optimized.computed.get_energy(),
+                Ok(optimized) => {
+                    warn!("random structure {i} not converged: fmax = {}", optimized.fmax);
+                    None
+                }
+                Err(e) => {
+                    warn!("random structure {i} failed in relaxation: {e:?}");
+                    None
+                }
+            };
+            let Some(energy) = energy else {
+                nfailed += 1;
+                continue;
+            };
+            nconverged += 1;
+            info!("random structure {i}: relaxed to E = {energy:-16.6}");
+            match minima
+                .iter_mut()
+                .find(|m| (m.energy - energy).abs() < self.energy_tolerance)
+            {
+                Some(m) => m.nvisits += 1,
+                None => minima.push(Minimum {
+                    energy,
+                    molecule: mol,
+                    nvisits: 1,
+                }),
+            }
+        }
+        minima.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+
+        Ok(RandomSearched {
+            minima,
+            nconverged,
+            nfailed,
+        })
+    }
+}
+
+// Distance between `p` and `q`, in minimum image convention if `cell` of
+// lattice vectors in columns exists.
+pub(crate) fn image_distance(cell: Option<&na::Matrix3<f64>>, p: [f64; 3], q: [f64; 3]) -> f64 {
+    let mut d = na::Vector3::from(p) - na::Vector3::from(q);
+    if let Some(cell) = cell {
+        let inv = cell.try_inverse().expect("invalid cell");
+        d = cell * (inv * d).map(|f| f - f.round());
+    }
+    d.norm()
+}
+// ba72a21d ends here
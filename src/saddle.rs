@@ -0,0 +1,605 @@
+// [[file:../optim.note::1e0202d0][1e0202d0]]
+use super::*;
+
+use serde::*;
+use std::f64::consts::FRAC_PI_2;
+// 1e0202d0 ends here
+
+// [[file:../optim.note::30e6659a][30e6659a]]
+/// Algorithms for rotating the dimer toward the lowest curvature mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DimerRotation {
+    /// Polak–Ribière conjugate gradient on rotational forces.
+    ConjugateGradient,
+    /// L-BFGS on rotational forces, with history kept within one translation.
+    Lbfgs,
+}
+
+/// Settings of dimer method for saddle point search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DimerConfig {
+    /// The algorithm for dimer rotation.
+    pub rotation: DimerRotation,
+    /// Max number of rotations before each translation.
+    pub max_rotations: usize,
+    /// Stop rotating when the rotational force drops below this value.
+    pub rotation_fmax: f64,
+    /// The distance between dimer center and its endpoint.
+    pub separation: f64,
+    /// Max displacement of dimer center in each translation.
+    pub max_translation: f64,
+    /// Extrapolate forces at the rotated dimer endpoint from previous
+    /// evaluations instead of recomputing them, which saves one potential
+    /// evaluation per rotation.
+    pub extrapolate_forces: bool,
+}
+
+impl Default for DimerConfig {
+    fn default() -> Self {
+        Self {
+            rotation: DimerRotation::ConjugateGradient,
+            max_rotations: 4,
+            rotation_fmax: 0.1,
+            separation: 0.01,
+            max_translation: 0.1,
+            extrapolate_forces: true,
+        }
+    }
+}
+// 30e6659a ends here
+
+// [[file:../optim.note::c122a892][c122a892]]
+/// Information on dimer search in each translation.
+#[derive(Debug, Clone)]
+pub struct DimerProgress {
+    /// The number of translations.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Energy at dimer center.
+    pub energy: f64,
+    /// Current fmax criterion of forces at dimer center.
+    pub fmax: f64,
+    /// The curvature along dimer, which is negative near a first-order
+    /// saddle point.
+    pub curvature: f64,
+    /// The lowest curvature mode estimated by dimer, as a unit vector.
+    pub mode: Vec<f64>,
+}
+
+/// Saddle point search using the dimer method with forward difference, which
+/// needs only one endpoint of the dimer evaluated. With force extrapolation,
+/// a translation with one rotation costs three evaluations, or two when the
+/// dimer is already aligned with the lowest mode.
+///
+/// # References
+///
+/// - Henkelman, G.; Jónsson, H. J. Chem. Phys. 1999, 111, 7010.
+/// - Heyden, A.; Bell, A. T.; Keil, F. J. J. Chem. Phys. 2005, 123, 224101.
+/// - Kästner, J.; Sherwood, P. J. Chem. Phys. 2008, 128, 014106.
+#[derive(Debug, Clone, Default)]
+pub struct DimerSearch {
+    config: DimerConfig,
+}
+
+impl DimerSearch {
+    /// Construct dimer search using settings in `config`.
+    pub fn new(config: DimerConfig) -> Self {
+        assert!(
+            config.separation > 0.0,
+            "invalid dimer separation: {}",
+            config.separation
+        );
+        assert!(
+            config.max_translation > 0.0,
+            "invalid max translation: {}",
+            config.max_translation
+        );
+        Self { config }
+    }
+
+    /// Search for a first-order saddle point starting from current position
+    /// of `dynamics` with initial dimer orientation along `mode`. The search
+    /// stops when fmax of forces is below `fmax` with negative curvature
+    /// along dimer, or `nmax` translations reached. The position of
+    /// `dynamics` is updated in place.
+    pub fn search<U>(&self, dynamics: &mut Dynamics<U>, mode: &[f64], fmax: f64, nmax: usize) -> Result<DimerProgress> {
+        let mut last = None;
+        for progress in self.search_iter(dynamics, mode)?.take(nmax) {
+            let progress = progress?;
+            debug!(
+                "{:5} E = {:-16.6} fmax = {:-10.4} curvature = {:-10.4}",
+                progress.niter, progress.energy, progress.fmax, progress.curvature
+            );
+            if progress.fmax < fmax && progress.curvature < 0.0 {
+                return Ok(progress);
+            }
+            last = Some(progress);
+        }
+        warn!("dimer search not converged in {nmax} translations");
+        last.ok_or(format_err!("no translation of dimer: nmax = {nmax}"))
+    }
+
+    /// Return an iterator over dimer translations starting from current
+    /// position of `dynamics` with initial dimer orientation along `mode`.
+    /// Each item reports the curvature and the lowest mode estimated by
+    /// dimer, for monitoring whether the search is converging to a
+    /// first-order saddle point.
+    pub fn search_iter<'a, U: 'a>(
+        &'a self,
+        dynamics: &'a mut Dynamics<U>,
+        mode: &[f64],
+    ) -> Result<Box<dyn Iterator<Item = Result<DimerProgress>> + 'a>> {
+        let n = dynamics.position().len();
+        ensure!(mode.len() == n, "invalid dimension of dimer mode: {}", mode.len());
+        let norm = mode.vec2norm();
+        ensure!(norm > 0.0, "invalid dimer mode");
+        let mut mode: Vec<f64> = mode.iter().map(|x| x / norm).collect();
+        let dr = self.config.separation;
+
+        let mut x = dynamics.position().to_vec();
+        // previous position and effective force for step size estimation
+        let mut last: Option<(Vec<f64>, Vec<f64>)> = None;
+        let mut step: Option<Vec<f64>> = None;
+        let mut translate = move |niter: usize| -> Result<DimerProgress> {
+            if let Some(step) = step.take() {
+                x.vecadd(&step, 1.0);
+            }
+            // forces at dimer endpoint first, then at the center
+            let mut f1 = force_at(dynamics, &endpoint(&x, &mode, dr))?;
+            dynamics.set_position(&x);
+            let energy = dynamics.get_energy()?;
+            let f0 = dynamics.get_force()?.to_vec();
+
+            self.rotate(dynamics, &x, &f0, &mut f1, &mut mode)?;
+            dynamics.set_position(&x);
+            let c = curvature(&f0, &f1, &mode, dr);
+
+            // invert the force component along dimer, or go uphill only
+            // along dimer in convex region
+            let f_par = f0.vecdot(&mode);
+            let f_eff: Vec<f64> = if c < 0.0 {
+                f0.iter().zip(&mode).map(|(f, m)| f - 2.0 * f_par * m).collect()
+            } else {
+                mode.iter().map(|m| -f_par * m).collect()
+            };
+            let alpha = barzilai_borwein(&x, &f_eff, last.as_ref()).unwrap_or(1.0 / c.abs());
+            let mut dx: Vec<f64> = f_eff.iter().map(|f| alpha * f).collect();
+            let dx_norm = dx.vec2norm();
+            ensure!(dx_norm.is_finite(), "invalid dimer translation step: {dx_norm}");
+            cap_norm(&mut dx, self.config.max_translation);
+            last = Some((x.clone(), f_eff));
+            step = Some(dx);
+
+            Ok(DimerProgress {
+                niter,
+                ncalls: dynamics.ncalls(),
+                energy,
+                fmax: fmax_(&f0),
+                curvature: c,
+                mode: mode.clone(),
+            })
+        };
+        Ok(Box::new((1..).map(translate)))
+    }
+
+    // Rotate dimer `mode` toward the lowest curvature mode at center `x`
+    // with forces `f0`. Forces `f1` at dimer endpoint are updated
+    // accordingly.
+    fn rotate<U>(
+        &self,
+        dynamics: &mut Dynamics<U>,
+        x: &[f64],
+        f0: &[f64],
+        f1: &mut Vec<f64>,
+        mode: &mut Vec<f64>,
+    ) -> Result<()> {
+        let dr = self.config.separation;
+        let mut rotator = Rotator::new(self.config.rotation);
+        for _ in 0..self.config.max_rotations {
+            let c = curvature(f0, f1, mode, dr);
+            // gradient of curvature perpendicular to mode
+            let mut g: Vec<f64> = f1.iter().zip(f0).map(|(a, b)| -2.0 * (a - b) / dr).collect();
+            project_out(&mut g, mode);
+            // the rotational force in units of force
+            let f_rot = g.vec2norm() * dr / 2.0;
+            if f_rot < self.config.rotation_fmax {
+                break;
+            }
+            let theta = rotator.direction(mode, &g);
+            let dc = g.vecdot(&theta);
+
+            // trial rotation with estimated optimal angle
+            let phi1 = -0.5 * (dc / (2.0 * c.abs())).atan();
+            if phi1.abs() < 1e-3 {
+                break;
+            }
+            let mode1 = rotated(mode, &theta, phi1);
+            let f1_trial = force_at(dynamics, &endpoint(x, &mode1, dr))?;
+            let c1 = curvature(f0, &f1_trial, &mode1, dr);
+
+            // fit curvature as C(phi) = a0/2 + a1 cos(2 phi) + b1 sin(2 phi)
+            let b1 = 0.5 * dc;
+            let a1 = (c - c1 + b1 * (2.0 * phi1).sin()) / (1.0 - (2.0 * phi1).cos());
+            let a0 = 2.0 * (c - a1);
+            let fit = |phi: f64| 0.5 * a0 + a1 * (2.0 * phi).cos() + b1 * (2.0 * phi).sin();
+            let mut phi = 0.5 * (b1 / a1).atan();
+            if fit(phi) > c {
+                phi += FRAC_PI_2;
+            }
+            *mode = rotated(mode, &theta, phi);
+            *f1 = if self.config.extrapolate_forces {
+                // interpolate forces from unrotated and trial endpoints
+                let (s1, sp, cp) = (phi1.sin(), phi.sin(), phi.cos());
+                let (w, w1, w0) = ((phi1 - phi).sin() / s1, sp / s1, 1.0 - cp - sp * (0.5 * phi1).tan());
+                f1.iter()
+                    .zip(&f1_trial)
+                    .zip(f0)
+                    .map(|((a, b), c)| w * a + w1 * b + w0 * c)
+                    .collect()
+            } else {
+                force_at(dynamics, &endpoint(x, mode, dr))?
+            };
+        }
+        Ok(())
+    }
+}
+// c122a892 ends here
+
+// [[file:../optim.note::cf709b6f][cf709b6f]]
+// Search direction for dimer rotation.
+struct Rotator {
+    algorithm: DimerRotation,
+    // previous gradient and direction for conjugate gradient
+    last: Option<(Vec<f64>, Vec<f64>)>,
+    // previous mode and gradient, and history of (s, y) pairs for L-BFGS
+    previous: Option<(Vec<f64>, Vec<f64>)>,
+    history: Vec<(Vec<f64>, Vec<f64>)>,
+}
+
+impl Rotator {
+    const MEMORY: usize = 5;
+
+    fn new(algorithm: DimerRotation) -> Self {
+        Self {
+            algorithm,
+            last: None,
+            previous: None,
+            history: vec![],
+        }
+    }
+
+    // Return unit rotation direction perpendicular to `mode` from gradient
+    // `g` of curvature.
+    fn direction(&mut self, mode: &[f64], g: &[f64]) -> Vec<f64> {
+        let mut d = match self.algorithm {
+            DimerRotation::ConjugateGradient => {
+                let mut d: Vec<f64> = g.iter().map(|x| -x).collect();
+                if let Some((g_last, d_last)) = &self.last {
+                    let gamma = g.iter().zip(g_last).map(|(a, b)| a * (a - b)).sum::<f64>() / g_last.vecdot(g_last);
+                    if gamma > 0.0 {
+                        d.vecadd(d_last, gamma);
+                    }
+                }
+                self.last = Some((g.to_vec(), d.clone()));
+                d
+            }
+            DimerRotation::Lbfgs => {
+                if let Some((mode_last, g_last)) = &self.previous {
+                    let s = mode.iter().zip(mode_last).map(|(a, b)| a - b).collect_vec();
+                    let y = g.iter().zip(g_last).map(|(a, b)| a - b).collect_vec();
+                    if s.vecdot(&y) > 0.0 {
+                        self.history.push((s, y));
+                        if self.history.len() > Self::MEMORY {
+                            self.history.remove(0);
+                        }
+                    }
+                }
+                self.previous = Some((mode.to_vec(), g.to_vec()));
+                lbfgs_direction(&self.history, g)
+            }
+        };
+        project_out(&mut d, mode);
+        let norm = d.vec2norm();
+        d.iter_mut().for_each(|x| *x /= norm);
+        d
+    }
+}
+
+// The L-BFGS two-loop recursion for search direction from gradient `g`.
+fn lbfgs_direction(history: &[(Vec<f64>, Vec<f64>)], g: &[f64]) -> Vec<f64> {
+    let mut q = g.to_vec();
+    let mut alphas = vec![];
+    for (s, y) in history.iter().rev() {
+        let rho = 1.0 / y.vecdot(s);
+        let alpha = rho * s.vecdot(&q);
+        q.vecadd(y, -alpha);
+        alphas.push((rho, alpha));
+    }
+    if let Some((s, y)) = history.last() {
+        let gamma = s.vecdot(y) / y.vecdot(y);
+        q.iter_mut().for_each(|x| *x *= gamma);
+    }
+    for ((s, y), (rho, alpha)) in history.iter().zip(alphas.into_iter().rev()) {
+        let beta = rho * y.vecdot(&q);
+        q.vecadd(s, alpha - beta);
+    }
+    q.iter().map(|x| -x).collect()
+}
+
+// Curvature along `mode` from forces at dimer center and endpoint.
+fn curvature(f0: &[f64], f1: &[f64], mode: &[f64], dr: f64) -> f64 {
+    -f1.iter().zip(f0).zip(mode).map(|((a, b), m)| (a - b) * m).sum::<f64>() / dr
+}
+
+fn endpoint(x: &[f64], mode: &[f64], dr: f64) -> Vec<f64> {
+    x.iter().zip(mode).map(|(a, m)| a + dr * m).collect()
+}
+
+// Rotate unit vector `mode` by angle `phi` in the plane spanned with unit
+// vector `theta` perpendicular to it.
+fn rotated(mode: &[f64], theta: &[f64], phi: f64) -> Vec<f64> {
+    let (s, c) = phi.sin_cos();
+    let v: Vec<f64> = mode.iter().zip(theta).map(|(m, t)| m * c + t * s).collect();
+    let norm = v.vec2norm();
+    v.iter().map(|x| x / norm).collect()
+}
+
+fn project_out(v: &mut [f64], mode: &[f64]) {
+    let p = v.vecdot(mode);
+    v.vecadd(mode, -p);
+}
+
+// Barzilai–Borwein long step size from current and `last` pair of position and
+// effective force.
+fn barzilai_borwein(x: &[f64], f: &[f64], last: Option<&(Vec<f64>, Vec<f64>)>) -> Option<f64> {
+    let (x_last, f_last) = last?;
+    let s = x.iter().zip(x_last).map(|(a, b)| a - b).collect_vec();
+    // change of gradient, the negative of force
+    let y = f_last.iter().zip(f).map(|(a, b)| a - b).collect_vec();
+    crate::sd::bb_step_size(StepSizeRule::BB1, &s, &y, None)
+}
+
+fn force_at<U>(dynamics: &mut Dynamics<U>, x: &[f64]) -> Result<Vec<f64>> {
+    dynamics.set_position(x);
+    Ok(dynamics.get_force()?.to_vec())
+}
+// cf709b6f ends here
+
+// [[file:../optim.note::99731626][99731626]]
+/// Settings of activation–relaxation technique (ART nouveau).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtConfig {
+    /// The size of initial displacement from the minimum.
+    pub initial_step: f64,
+    /// The size of push along the escape direction in the basin.
+    pub push_step: f64,
+    /// The basin is left when the lowest eigenvalue drops below this value.
+    pub eigen_threshold: f64,
+    /// The number of Lanczos iterations for the lowest mode.
+    pub lanczos_steps: usize,
+    /// Displacement for finite difference Hessian-vector products.
+    pub fd_step: f64,
+    /// The initial step size for relaxation in the hyperplane, which is
+    /// later adjusted using Barzilai–Borwein method.
+    pub relax_step: f64,
+    /// Max displacement in each step.
+    pub max_step: f64,
+    /// Seed for random initial displacement.
+    pub seed: u64,
+}
+
+impl Default for ArtConfig {
+    fn default() -> Self {
+        Self {
+            initial_step: 0.1,
+            push_step: 0.05,
+            eigen_threshold: 0.0,
+            lanczos_steps: 10,
+            fd_step: 1e-3,
+            relax_step: 1e-3,
+            max_step: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Final result of ART saddle search.
+#[derive(Debug, Clone)]
+pub struct ArtSaddle {
+    /// The number of steps.
+    pub niter: usize,
+    /// The number of calls for potential evaluation.
+    pub ncalls: usize,
+    /// Final energy.
+    pub energy: f64,
+    /// Final fmax criterion of forces.
+    pub fmax: f64,
+    /// The lowest eigenvalue of Hessian estimated by Lanczos method.
+    pub eigenvalue: f64,
+    /// The lowest mode as a unit vector.
+    pub mode: Vec<f64>,
+    /// True if converged to a saddle point.
+    pub converged: bool,
+}
+
+/// Saddle point search from a minimum using activation–relaxation technique
+/// (ART nouveau), for exploring reaction events without a known product.
+/// The structure is displaced away from the minimum, pushed uphill along the
+/// escape direction until the lowest curvature becomes negative, and then
+/// converged to the saddle point by following the lowest mode obtained from
+/// Lanczos method, relaxing in the perpendicular hyperplane at each step.
+///
+/// # Reference
+///
+/// Malek, R.; Mousseau, N. Phys. Rev. E 2000, 62, 7723.
+#[derive(Debug, Clone, Default)]
+pub struct ArtSearch {
+    config: ArtConfig,
+}
+
+impl ArtSearch {
+    /// Construct ART search using settings in `config`.
+    pub fn new(config: ArtConfig) -> Self {
+        assert!(config.fd_step > 0.0, "invalid fd step: {}", config.fd_step);
+        assert!(config.max_step > 0.0, "invalid max step: {}", config.max_step);
+        assert!(config.lanczos_steps > 0, "invalid number of Lanczos steps");
+        Self { config }
+    }
+
+    /// Search for a saddle point from the minimum at current position of
+    /// `dynamics`, with initial displacement along `direction`, or a random
+    /// direction if None. Stop when fmax of forces is below `fmax` with
+    /// negative lowest eigenvalue, or `nmax` steps reached.
+    pub fn search<U>(
+        &self,
+        dynamics: &mut Dynamics<U>,
+        direction: Option<&[f64]>,
+        fmax: f64,
+        nmax: usize,
+    ) -> Result<ArtSaddle> {
+        let n = dynamics.position().len();
+        let mut escape = match direction {
+            Some(d) => {
+                ensure!(d.len() == n, "invalid dimension of direction: {}", d.len());
+                let norm = d.vec2norm();
+                ensure!(norm > 0.0, "invalid direction");
+                d.iter().map(|x| x / norm).collect_vec()
+            }
+            None => crate::random::Rng::new(self.config.seed).unit_vector(n),
+        };
+        let x0 = dynamics.position().to_vec();
+        let mut x = x0.clone();
+        x.vecadd(&escape, self.config.initial_step);
+
+        let mut mode = escape.clone();
+        let mut last: Option<(Vec<f64>, Vec<f64>)> = None;
+        let mut activated = false;
+        let mut result = None;
+        for niter in 1..=nmax {
+            dynamics.set_position(&x);
+            let energy = dynamics.get_energy()?;
+            let force = dynamics.get_force()?.to_vec();
+            let (eigenvalue, v) = self.lowest_mode(dynamics, &x, &force, &mode)?;
+            mode = v;
+            dynamics.set_position(&x);
+            let progress = ArtSaddle {
+                niter,
+                ncalls: dynamics.ncalls(),
+                energy,
+                fmax: fmax_(&force),
+                eigenvalue,
+                mode: mode.clone(),
+                converged: false,
+            };
+            debug!(
+                "{niter:5} E = {energy:-16.6} fmax = {:-10.4} eigenvalue = {eigenvalue:-10.4}",
+                progress.fmax
+            );
+            if progress.fmax < fmax && eigenvalue < 0.0 {
+                return Ok(ArtSaddle {
+                    converged: true,
+                    ..progress
+                });
+            }
+            result = Some(progress);
+
+            let mut dx = if eigenvalue < self.config.eigen_threshold {
+                if !activated {
+                    info!("left harmonic basin at step {niter}");
+                    activated = true;
+                    last = None;
+                }
+                // uphill along the lowest mode, downhill in the others
+                let f_par = force.vecdot(&mode);
+                let f_eff = force.iter().zip(&mode).map(|(f, m)| f - 2.0 * f_par * m).collect_vec();
+                let alpha = barzilai_borwein(&x, &f_eff, last.as_ref()).unwrap_or(1.0 / eigenvalue.abs());
+                let dx = f_eff.iter().map(|f| alpha * f).collect_vec();
+                last = Some((x.clone(), f_eff));
+                dx
+            } else {
+                // push away from the minimum, relaxing perpendicularly
+                escape = x.iter().zip(&x0).map(|(a, b)| a - b).collect_vec();
+                let norm = escape.vec2norm();
+                escape.iter_mut().for_each(|e| *e /= norm);
+                let mut f_perp = force.clone();
+                project_out(&mut f_perp, &escape);
+                let alpha = barzilai_borwein(&x, &f_perp, last.as_ref()).unwrap_or(self.config.relax_step);
+                let mut dx = f_perp.iter().map(|f| alpha * f).collect_vec();
+                cap_norm(&mut dx, self.config.max_step);
+                dx.vecadd(&escape, self.config.push_step);
+                last = Some((x.clone(), f_perp));
+                dx
+            };
+            cap_norm(&mut dx, self.config.max_step);
+            x.vecadd(&dx, 1.0);
+        }
+        warn!("ART search not converged in {nmax} steps");
+        result.ok_or(format_err!("no step in ART search: nmax = {nmax}"))
+    }
+
+    // Estimate the lowest eigenvalue and eigenvector of Hessian at `x` using
+    // Lanczos method, starting from `guess`. Hessian-vector products are
+    // approximated using forward differences of forces.
+    fn lowest_mode<U>(
+        &self,
+        dynamics: &mut Dynamics<U>,
+        x: &[f64],
+        force: &[f64],
+        guess: &[f64],
+    ) -> Result<(f64, Vec<f64>)> {
+        use vecfx::nalgebra as na;
+
+        let h = self.config.fd_step;
+        let nsteps = self.config.lanczos_steps.min(x.len());
+        let mut q: Vec<Vec<f64>> = vec![guess.iter().map(|v| v / guess.vec2norm()).collect()];
+        let (mut alphas, mut betas) = (vec![], vec![]);
+        for j in 0..nsteps {
+            let displaced = x.iter().zip(&q[j]).map(|(a, b)| a + h * b).collect_vec();
+            let fd = force_at(dynamics, &displaced)?;
+            let mut w = fd.iter().zip(force).map(|(a, b)| -(a - b) / h).collect_vec();
+            alphas.push(w.vecdot(&q[j]));
+            // full reorthogonalization for numerical stability
+            for qk in q.iter() {
+                let p = w.vecdot(qk);
+                w.vecadd(qk, -p);
+            }
+            let beta = w.vec2norm();
+            if j + 1 == nsteps || beta < 1e-8 {
+                break;
+            }
+            betas.push(beta);
+            q.push(w.iter().map(|v| v / beta).collect());
+        }
+        let k = alphas.len();
+        let t = na::DMatrix::from_fn(k, k, |i, j| {
+            if i == j {
+                alphas[i]
+            } else if i + 1 == j {
+                betas[i]
+            } else if j + 1 == i {
+                betas[j]
+            } else {
+                0.0
+            }
+        });
+        let eigen = t.symmetric_eigen();
+        let (imin, &theta) = eigen
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .expect("no Lanczos step");
+        let y = eigen.eigenvectors.column(imin);
+        let mut v = vec![0.0; x.len()];
+        for (qi, yi) in q.iter().zip(y.iter()) {
+            v.vecadd(qi, *yi);
+        }
+        let norm = v.vec2norm();
+        v.iter_mut().for_each(|x| *x /= norm);
+        Ok((theta, v))
+    }
+}
+// 99731626 ends here
@@ -0,0 +1,248 @@
+// [[file:../optim.note::ded5a70d][ded5a70d]]
+use super::*;
+// ded5a70d ends here
+
+// [[file:../optim.note::45029a45][45029a45]]
+/// Schedules for switching restraints or bias terms on or off smoothly over
+/// steps, by scaling them with a coupling parameter lambda.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LambdaSchedule {
+    /// Constant lambda.
+    Constant(f64),
+    /// Switch lambda linearly from `from` to `to` in `nsteps` steps beginning
+    /// at step `start`.
+    Linear {
+        from: f64,
+        to: f64,
+        start: usize,
+        nsteps: usize,
+    },
+    /// Switch lambda sigmoidally from `from` to `to` in `nsteps` steps
+    /// beginning at step `start`. The switching function has vanishing first
+    /// and second derivatives at both ends, which avoids sudden jumps in
+    /// forces.
+    Sigmoid {
+        from: f64,
+        to: f64,
+        start: usize,
+        nsteps: usize,
+    },
+    /// Ramp lambda exponentially (geometrically) from `from` to `to` in
+    /// `nsteps` steps beginning at step `start`, such as increasing force
+    /// constants by orders of magnitude. Both `from` and `to` must be
+    /// positive.
+    Exponential {
+        from: f64,
+        to: f64,
+        start: usize,
+        nsteps: usize,
+    },
+}
+
+impl Default for LambdaSchedule {
+    fn default() -> Self {
+        Self::Constant(1.0)
+    }
+}
+
+impl LambdaSchedule {
+    /// Turn on linearly in `nsteps` steps.
+    pub fn linear_on(nsteps: usize) -> Self {
+        Self::Linear {
+            from: 0.0,
+            to: 1.0,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Turn off linearly in `nsteps` steps.
+    pub fn linear_off(nsteps: usize) -> Self {
+        Self::Linear {
+            from: 1.0,
+            to: 0.0,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Turn on sigmoidally in `nsteps` steps.
+    pub fn sigmoid_on(nsteps: usize) -> Self {
+        Self::Sigmoid {
+            from: 0.0,
+            to: 1.0,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Turn off sigmoidally in `nsteps` steps.
+    pub fn sigmoid_off(nsteps: usize) -> Self {
+        Self::Sigmoid {
+            from: 1.0,
+            to: 0.0,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Ramp up exponentially from `from` to `to` in `nsteps` steps.
+    pub fn exponential(from: f64, to: f64, nsteps: usize) -> Self {
+        assert!(
+            from > 0.0 && to > 0.0,
+            "invalid range for exponential ramp: {from}, {to}"
+        );
+        Self::Exponential {
+            from,
+            to,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Return the lambda value at `step`.
+    pub fn lambda(&self, step: usize) -> f64 {
+        // the progress of switching in [0, 1]
+        let progress = |start: usize, nsteps: usize| {
+            if step <= start {
+                0.0
+            } else if nsteps == 0 || step >= start + nsteps {
+                1.0
+            } else {
+                (step - start) as f64 / nsteps as f64
+            }
+        };
+        match *self {
+            Self::Constant(x) => x,
+            Self::Linear {
+                from,
+                to,
+                start,
+                nsteps,
+            } => {
+                let t = progress(start, nsteps);
+                from + (to - from) * t
+            }
+            Self::Sigmoid {
+                from,
+                to,
+                start,
+                nsteps,
+            } => {
+                let t = progress(start, nsteps);
+                let s = t * t * t * (t * (6.0 * t - 15.0) + 10.0);
+                from + (to - from) * s
+            }
+            Self::Exponential {
+                from,
+                to,
+                start,
+                nsteps,
+            } => {
+                let t = progress(start, nsteps);
+                from * (to / from).powf(t)
+            }
+        }
+    }
+}
+// 45029a45 ends here
+
+// [[file:../optim.note::6c11bd75][6c11bd75]]
+/// Schedules of target temperature in molecular dynamics, applied to the
+/// active thermostat, for simulated annealing protocols.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemperatureSchedule {
+    /// Ramp temperature linearly from `from` to `to` in `nsteps` steps
+    /// beginning at step `start`.
+    Linear {
+        from: f64,
+        to: f64,
+        start: usize,
+        nsteps: usize,
+    },
+    /// Cool exponentially (geometrically) from `from` to `to` in `nsteps`
+    /// steps beginning at step `start`. Both `from` and `to` must be
+    /// positive.
+    Exponential {
+        from: f64,
+        to: f64,
+        start: usize,
+        nsteps: usize,
+    },
+    /// Stepped plateaus of temperatures, each held for its number of steps
+    /// in turn. The last temperature is held afterwards.
+    Plateaus(Vec<(f64, usize)>),
+}
+
+impl TemperatureSchedule {
+    /// Ramp linearly from `from` to `to` in `nsteps` steps.
+    pub fn linear(from: f64, to: f64, nsteps: usize) -> Self {
+        Self::Linear {
+            from,
+            to,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Cool exponentially from `from` to `to` in `nsteps` steps.
+    pub fn exponential(from: f64, to: f64, nsteps: usize) -> Self {
+        assert!(
+            from > 0.0 && to > 0.0,
+            "invalid range for exponential cooling: {from}, {to}"
+        );
+        Self::Exponential {
+            from,
+            to,
+            start: 0,
+            nsteps,
+        }
+    }
+
+    /// Hold each temperature in `plateaus` for its number of steps.
+    pub fn plateaus(plateaus: &[(f64, usize)]) -> Self {
+        assert!(!plateaus.is_empty(), "no temperature plateaus");
+        Self::Plateaus(plateaus.to_vec())
+    }
+
+    /// Return the target temperature at `step`.
+    pub fn temperature(&self, step: usize) -> f64 {
+        match *self {
+            Self::Linear {
+                from,
+                to,
+                start,
+                nsteps,
+            } => LambdaSchedule::Linear {
+                from,
+                to,
+                start,
+                nsteps,
+            }
+            .lambda(step),
+            Self::Exponential {
+                from,
+                to,
+                start,
+                nsteps,
+            } => LambdaSchedule::Exponential {
+                from,
+                to,
+                start,
+                nsteps,
+            }
+            .lambda(step),
+            Self::Plateaus(ref plateaus) => {
+                let mut end = 0;
+                for &(t, n) in plateaus.iter() {
+                    end += n;
+                    if step < end {
+                        return t;
+                    }
+                }
+                plateaus.last().map_or(0.0, |p| p.0)
+            }
+        }
+    }
+}
+// 6c11bd75 ends here
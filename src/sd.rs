@@ -0,0 +1,128 @@
+// [[file:../optim.note::1787446e][1787446e]]
+use super::*;
+
+use serde::*;
+// 1787446e ends here
+
+// [[file:../optim.note::51e69529][51e69529]]
+/// Rules of step size for steepest descent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum StepSizeRule {
+    /// Fixed step size.
+    Fixed,
+    /// Barzilai–Borwein long step: α = s·s / s·y.
+    BB1,
+    /// Barzilai–Borwein short step: α = s·y / y·y.
+    BB2,
+}
+
+impl Default for StepSizeRule {
+    fn default() -> Self {
+        Self::BB2
+    }
+}
+
+// Step sizes are safeguarded within this factor of the initial step size
+const STEP_SIZE_RANGE: f64 = 1e3;
+
+/// Steepest descent with optional diagonal preconditioner, using two-point
+/// step sizes of Barzilai and Borwein (IMA J. Numer. Anal. 1988, 8, 141).
+///
+/// The step size is kept when curvature along the last step is not positive,
+/// and clamped within a range around the initial step size. No line search is
+/// performed.
+pub(crate) struct GradientDescent {
+    rule: StepSizeRule,
+    // initial or fixed step size
+    alpha0: f64,
+    // max displacement of any atom in a step
+    max_step: f64,
+    // inverse of diagonal preconditioner
+    precond: Option<Vec<f64>>,
+    // position, gradient and step size in last step
+    last: Option<(Vec<f64>, Vec<f64>, f64)>,
+}
+
+impl GradientDescent {
+    pub fn new(rule: StepSizeRule, alpha0: f64, max_step: f64) -> Self {
+        assert!(alpha0 > 0.0, "invalid step size: {alpha0}");
+        Self {
+            rule,
+            alpha0,
+            max_step,
+            precond: None,
+            last: None,
+        }
+    }
+
+    /// Precondition gradients using `inv_diag`, the inverse of a diagonal
+    /// approximation of Hessian.
+    pub fn with_preconditioner(mut self, inv_diag: Vec<f64>) -> Self {
+        assert!(inv_diag.iter().all(|&x| x > 0.0), "invalid preconditioner");
+        self.precond = Some(inv_diag);
+        self
+    }
+
+    fn precondition(&self, g: &[f64]) -> Vec<f64> {
+        precondition(g, self.precond.as_deref())
+    }
+
+    fn step_size(&self, x: &[f64], g: &[f64]) -> f64 {
+        let Some((x_last, g_last, alpha_last)) = &self.last else {
+            return self.alpha0;
+        };
+        if self.rule == StepSizeRule::Fixed {
+            return self.alpha0;
+        }
+        let s = x.iter().zip(x_last).map(|(a, b)| a - b).collect_vec();
+        let y = g.iter().zip(g_last).map(|(a, b)| a - b).collect_vec();
+        match bb_step_size(self.rule, &s, &y, self.precond.as_deref()) {
+            Some(alpha) => alpha.clamp(self.alpha0 / STEP_SIZE_RANGE, self.alpha0 * STEP_SIZE_RANGE),
+            None => {
+                debug!("negative curvature along last step, keep step size");
+                *alpha_last
+            }
+        }
+    }
+
+    /// Return new position stepping from `x` with gradient `g`.
+    pub fn step(&mut self, x: &[f64], g: &[f64]) -> Vec<f64> {
+        let alpha = self.step_size(x, g);
+        let mut d = self.precondition(g);
+        d.iter_mut().for_each(|v| *v *= -alpha);
+        let dmax = d.chunks(3).map(|v| v.vec2norm()).float_max();
+        if dmax > self.max_step {
+            d.iter_mut().for_each(|v| *v *= self.max_step / dmax);
+        }
+        self.last = Some((x.to_vec(), g.to_vec(), alpha));
+        let mut x_new = x.to_vec();
+        x_new.vecadd(&d, 1.0);
+        x_new
+    }
+}
+
+fn precondition(g: &[f64], precond: Option<&[f64]>) -> Vec<f64> {
+    match precond {
+        Some(p) => g.iter().zip(p).map(|(g, p)| g * p).collect(),
+        None => g.to_vec(),
+    }
+}
+
+/// Return two-point step size of Barzilai and Borwein following `rule` from
+/// the change of position `s` and of gradient `y` in the last step, with
+/// `precond` as the inverse of diagonal preconditioner if any. Return None
+/// for the fixed rule, or non-positive curvature along the last step.
+pub(crate) fn bb_step_size(rule: StepSizeRule, s: &[f64], y: &[f64], precond: Option<&[f64]>) -> Option<f64> {
+    let sy = s.vecdot(y);
+    let alpha = match rule {
+        StepSizeRule::Fixed => return None,
+        // s^T P^-1 s / s^T y
+        StepSizeRule::BB1 => match precond {
+            Some(p) => s.iter().zip(p).map(|(s, p)| s * s / p).sum::<f64>() / sy,
+            None => s.vecdot(s) / sy,
+        },
+        StepSizeRule::BB2 => sy / y.vecdot(&precondition(y, precond)),
+    };
+    (sy > 0.0 && alpha.is_finite()).then_some(alpha)
+}
+// 51e69529 ends here
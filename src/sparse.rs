@@ -0,0 +1,260 @@
+// [[file:../optim.note::cbec37f0][cbec37f0]]
+use super::*;
+
+use std::collections::HashMap;
+use vecfx::nalgebra as na;
+// cbec37f0 ends here
+
+// [[file:../optim.note::203e8092][203e8092]]
+/// Return all atom pairs (i, j) with i < j within `cutoff` distance, using
+/// cell lists for linear scaling with system size.
+pub(crate) fn neighbor_pairs(positions: &[[f64; 3]], cutoff: f64) -> Vec<(usize, usize)> {
+    assert!(cutoff > 0.0, "invalid cutoff: {cutoff}");
+    let cell_of = |p: &[f64; 3]| p.map(|x| (x / cutoff).floor() as i64);
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for (i, p) in positions.iter().enumerate() {
+        cells.entry(cell_of(p)).or_default().push(i);
+    }
+
+    let mut pairs = vec![];
+    for (i, p) in positions.iter().enumerate() {
+        let [a, b, c] = cell_of(p);
+        for (da, db, dc) in itertools::iproduct!(-1..=1, -1..=1, -1..=1) {
+            let Some(atoms) = cells.get(&[a + da, b + db, c + dc]) else {
+                continue;
+            };
+            for &j in atoms.iter().filter(|&&j| j > i) {
+                if crate::internals::distance(*p, positions[j]) < cutoff {
+                    pairs.push((i, j));
+                }
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+// 203e8092 ends here
+
+// [[file:../optim.note::78a56a03][78a56a03]]
+/// A symmetric Hessian matrix in compressed sparse row format, for systems
+/// too large for dense storage.
+#[derive(Debug, Clone)]
+pub struct SparseHessian {
+    n: usize,
+    row_ptr: Vec<usize>,
+    col_idx: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl SparseHessian {
+    /// Construct an `n` x `n` matrix from (row, col, value) triplets.
+    /// Duplicated entries will be summed up.
+    pub fn from_triplets(n: usize, triplets: impl IntoIterator<Item = (usize, usize, f64)>) -> Self {
+        let mut rows: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+        for (i, j, v) in triplets {
+            assert!(i < n && j < n, "invalid entry: ({i}, {j})");
+            *rows[i].entry(j).or_default() += v;
+        }
+
+        let mut row_ptr = vec![0];
+        let mut col_idx = vec![];
+        let mut values = vec![];
+        for row in rows {
+            let row = row.into_iter().sorted_by_key(|x| x.0).collect_vec();
+            for (j, v) in row {
+                col_idx.push(j);
+                values.push(v);
+            }
+            row_ptr.push(col_idx.len());
+        }
+        Self {
+            n,
+            row_ptr,
+            col_idx,
+            values,
+        }
+    }
+
+    /// Return the dimension of the matrix.
+    pub fn dim(&self) -> usize {
+        self.n
+    }
+
+    /// Return the number of stored entries.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Return the entry at row `i` and column `j`.
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        let (cols, values) = self.row(i);
+        cols.binary_search(&j).map(|k| values[k]).unwrap_or(0.0)
+    }
+
+    fn row(&self, i: usize) -> (&[usize], &[f64]) {
+        let range = self.row_ptr[i]..self.row_ptr[i + 1];
+        (&self.col_idx[range.clone()], &self.values[range])
+    }
+
+    /// Return the diagonal entries.
+    pub fn diagonal(&self) -> Vec<f64> {
+        (0..self.n).map(|i| self.get(i, i)).collect()
+    }
+
+    /// Return the product of the matrix with vector `x`.
+    pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.n, "invalid size of vector");
+        (0..self.n)
+            .map(|i| {
+                let (cols, values) = self.row(i);
+                cols.iter().zip(values).map(|(&j, v)| v * x[j]).sum()
+            })
+            .collect()
+    }
+
+    /// Return the dense matrix in row major.
+    pub fn to_dense(&self) -> Vec<f64> {
+        let n = self.n;
+        let mut dense = vec![0.0; n * n];
+        for i in 0..n {
+            let (cols, values) = self.row(i);
+            for (&j, &v) in cols.iter().zip(values) {
+                dense[i * n + j] = v;
+            }
+        }
+        dense
+    }
+
+    /// Solve (H + shift I) x = b using conjugate gradient with Jacobi
+    /// preconditioner. A positive `shift` regularizes the zero modes of
+    /// translation and rotation.
+    ///
+    /// For a Newton step, pass the negative gradient as `b`.
+    pub fn solve(&self, b: &[f64], shift: f64) -> Result<Vec<f64>> {
+        assert_eq!(b.len(), self.n, "invalid size of vector");
+        let mul = |x: &[f64]| {
+            let mut y = self.mul_vec(x);
+            y.vecadd(x, shift);
+            y
+        };
+        let precond = self
+            .diagonal()
+            .into_iter()
+            .map(|d| if d + shift > 0.0 { 1.0 / (d + shift) } else { 1.0 })
+            .collect_vec();
+        let apply_precond = |r: &[f64]| r.iter().zip(&precond).map(|(r, m)| r * m).collect_vec();
+
+        let bnorm = b.vec2norm();
+        let mut x = vec![0.0; self.n];
+        if bnorm == 0.0 {
+            return Ok(x);
+        }
+        let mut r = b.to_vec();
+        let mut z = apply_precond(&r);
+        let mut p = z.clone();
+        let mut rz = r.vecdot(&z);
+        for _ in 0..self.n.max(100) {
+            let ap = mul(&p);
+            let pap = p.vecdot(&ap);
+            ensure!(pap > 0.0, "matrix is not positive definite");
+            let alpha = rz / pap;
+            x.vecadd(&p, alpha);
+            r.vecadd(&ap, -alpha);
+            if r.vec2norm() < 1e-8 * bnorm {
+                return Ok(x);
+            }
+            z = apply_precond(&r);
+            let rz_new = r.vecdot(&z);
+            let beta = rz_new / rz;
+            rz = rz_new;
+            for (pi, zi) in p.iter_mut().zip(&z) {
+                *pi = zi + beta * *pi;
+            }
+        }
+        bail!("conjugate gradient not converged");
+    }
+
+    /// Return the lowest `k` eigenvalues with eigenvectors in ascending order.
+    /// Eigenpairs are found one by one using Lanczos iterations deflated
+    /// against those already found, which handles degenerate eigenvalues
+    /// such as zero modes of translation and rotation.
+    pub fn lowest_eigenpairs(&self, k: usize) -> Result<Vec<(f64, Vec<f64>)>> {
+        ensure!(k > 0 && k <= self.n, "invalid number of eigenpairs: {k}");
+        let mut pairs: Vec<(f64, Vec<f64>)> = vec![];
+        for seed in 0..k {
+            let locked = pairs.iter().map(|p| p.1.clone()).collect_vec();
+            let pair = self.lanczos_lowest(&locked, seed);
+            pairs.push(pair);
+        }
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("found invalid float numbers"));
+        Ok(pairs)
+    }
+
+    // Find the lowest eigenpair in the orthogonal complement of `locked`
+    // vectors, with full reorthogonalization.
+    fn lanczos_lowest(&self, locked: &[Vec<f64>], seed: usize) -> (f64, Vec<f64>) {
+        let n = self.n;
+        let m = (n - locked.len()).min(100);
+        let orthogonalize = |w: &mut Vec<f64>, basis: &[Vec<f64>]| {
+            // twice is enough
+            for _ in 0..2 {
+                for u in locked.iter().chain(basis) {
+                    let c = w.vecdot(u);
+                    w.vecadd(u, -c);
+                }
+            }
+        };
+
+        // deterministic starting vector
+        let mut v = (0..n)
+            .map(|i| 1.0 + 0.5 * (i as f64 * (seed + 1) as f64).sin())
+            .collect_vec();
+        orthogonalize(&mut v, &[]);
+        let norm = v.vec2norm();
+        v.iter_mut().for_each(|x| *x /= norm);
+
+        let mut basis: Vec<Vec<f64>> = vec![];
+        let mut alpha = vec![];
+        let mut beta: Vec<f64> = vec![];
+        for j in 0..m {
+            let mut w = self.mul_vec(&v);
+            alpha.push(w.vecdot(&v));
+            basis.push(v);
+            orthogonalize(&mut w, &basis);
+            let b = w.vec2norm();
+            if j + 1 == m || b < 1e-10 {
+                break;
+            }
+            beta.push(b);
+            v = w.into_iter().map(|x| x / b).collect();
+        }
+
+        let nb = alpha.len();
+        let t = na::DMatrix::from_fn(nb, nb, |i, j| {
+            if i == j {
+                alpha[i]
+            } else if i == j + 1 {
+                beta[j]
+            } else if j == i + 1 {
+                beta[i]
+            } else {
+                0.0
+            }
+        });
+        let eigen = t.symmetric_eigen();
+        let (i, &e) = eigen
+            .eigenvalues
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.partial_cmp(b.1).expect("found invalid float numbers"))
+            .expect("no Lanczos vectors");
+        let mut x = vec![0.0; n];
+        for (u, c) in basis.iter().zip(eigen.eigenvectors.column(i).iter()) {
+            x.vecadd(u, *c);
+        }
+        let norm = x.vec2norm();
+        x.iter_mut().for_each(|v| *v /= norm);
+        (e, x)
+    }
+}
+// 78a56a03 ends here
@@ -0,0 +1,111 @@
+// [[file:../optim.note::0c740bf7][0c740bf7]]
+use super::*;
+
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+// 0c740bf7 ends here
+
+// [[file:../optim.note::bafca704][bafca704]]
+/// A multi-stage optimization protocol, running a sequence of phases on the
+/// same molecule, each with its own optimizer settings such as freezing mask
+/// and convergence criteria.
+///
+/// # Examples
+///
+/// ```ignore
+/// // optimize adsorbate with frozen slab, release top layers, then relax all
+/// let staged = StagedOptimizer::default()
+///     .stage("adsorbate", Optimizer::new(0.1, 100).freeze_atoms(&slab))
+///     .stage("top layers", Optimizer::new(0.05, 200).freeze_atoms(&bottom_layers))
+///     .stage("full", Optimizer::new(0.02, 500));
+/// let optimized = staged.optimize_geometry(&mut mol, &mut model)?;
+/// println!("{optimized}");
+/// ```
+#[derive(Default)]
+pub struct StagedOptimizer {
+    stages: Vec<(String, Optimizer)>,
+}
+
+impl StagedOptimizer {
+    /// Append a stage named as `name` using `optimizer`.
+    pub fn stage(mut self, name: &str, optimizer: Optimizer) -> Self {
+        self.stages.push((name.into(), optimizer));
+        self
+    }
+
+    /// Optimize geometry of `mol` in potential provided by `model` through
+    /// all stages in turn. Each stage starts from the geometry of the last
+    /// one, regardless of its convergence.
+    pub fn optimize_geometry<M: ChemicalModel>(&self, mol: &mut Molecule, model: &mut M) -> Result<StagedOptimized> {
+        ensure!(!self.stages.is_empty(), "no stage for optimization");
+        let mut stages = vec![];
+        for (i, (name, optimizer)) in self.stages.iter().enumerate() {
+            info!("optimization stage {}/{}: {name}", i + 1, self.stages.len());
+            let optimized = optimizer
+                .optimize_geometry(mol, model)
+                .with_context(|| format!("optimization failed in stage {name}"))?;
+            let converged = optimized.fmax < optimizer.fmax();
+            if !converged {
+                warn!("forces not converged in stage {name}: fmax = {}", optimized.fmax);
+            }
+            stages.push(Stage {
+                name: name.to_owned(),
+                converged,
+                optimized,
+            });
+        }
+        Ok(StagedOptimized { stages })
+    }
+}
+
+/// Result of one stage in `StagedOptimizer`.
+pub struct Stage {
+    /// The name of stage.
+    pub name: String,
+    /// True if forces converged in this stage.
+    pub converged: bool,
+    pub optimized: Optimized,
+}
+
+/// Combined results of all stages in `StagedOptimizer`.
+pub struct StagedOptimized {
+    pub stages: Vec<Stage>,
+}
+
+impl StagedOptimized {
+    /// The total number of iterations in all stages.
+    pub fn niter(&self) -> usize {
+        self.stages.iter().map(|s| s.optimized.niter).sum()
+    }
+
+    /// Return true if forces converged in the final stage.
+    pub fn converged(&self) -> bool {
+        self.stages.last().map_or(false, |s| s.converged)
+    }
+
+    /// Return results of the final stage.
+    pub fn last(&self) -> &Optimized {
+        &self.stages.last().expect("no stage").optimized
+    }
+}
+
+impl std::fmt::Display for StagedOptimized {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>6} {:>10} {:>16} {:>10}",
+            "stage", "niter", "fmax", "energy", "converged"
+        )?;
+        for s in self.stages.iter() {
+            let energy = s.optimized.computed.get_energy().unwrap_or(std::f64::NAN);
+            writeln!(
+                f,
+                "{:<20} {:>6} {:>10.4} {:>16.6} {:>10}",
+                s.name, s.optimized.niter, s.optimized.fmax, energy, s.converged
+            )?;
+        }
+        writeln!(f, "total iterations: {}", self.niter())?;
+        write!(f, "final stage report:\n{}", self.last().report)
+    }
+}
+// bafca704 ends here
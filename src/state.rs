@@ -0,0 +1,108 @@
+// [[file:../optim.note::0b59eb70][0b59eb70]]
+use super::*;
+
+use serde::de::DeserializeOwned;
+use serde::*;
+use serde_json::Value;
+// 0b59eb70 ends here
+
+// [[file:../optim.note::0f0cb42b][0f0cb42b]]
+/// The envelope for all serialized state (checkpoints, restart files,
+/// histories) written by gosh-optim.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Envelope {
+    /// The kind of serialized state.
+    kind: String,
+    /// The format version of serialized state.
+    version: u32,
+    /// The version of gosh-optim writing the state.
+    #[serde(default)]
+    writer: String,
+    /// The serialized state in raw form.
+    state: Value,
+}
+
+/// Trait for state that can be persisted and resumed across gosh-optim
+/// releases.
+///
+/// Data written without a version tag (by gosh-optim before versioned
+/// formats) is treated as version 0. When reading, old data will be upgraded
+/// step by step using `migrate` until reaching the current `VERSION`.
+pub trait VersionedState: Serialize + DeserializeOwned {
+    /// A unique name for the kind of state.
+    const KIND: &'static str;
+
+    /// The current format version.
+    const VERSION: u32;
+
+    /// Upgrade raw `state` in format `version` to format `version + 1`.
+    ///
+    /// The default implementation refuses any migration.
+    fn migrate(version: u32, state: Value) -> Result<Value> {
+        let _ = state;
+        bail!("no migration path for {} state from version {version}", Self::KIND)
+    }
+
+    /// Serialize into a version tagged json string.
+    fn to_versioned_json(&self) -> Result<String> {
+        let envelope = Envelope {
+            kind: Self::KIND.into(),
+            version: Self::VERSION,
+            writer: env!("CARGO_PKG_VERSION").into(),
+            state: serde_json::to_value(self)?,
+        };
+        let s = serde_json::to_string_pretty(&envelope)?;
+        Ok(s)
+    }
+
+    /// Deserialize from json string written by any gosh-optim version,
+    /// migrating old formats when necessary.
+    fn from_versioned_json(s: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(s).context("invalid json for versioned state")?;
+        let (mut version, mut state) = match serde_json::from_value::<Envelope>(value.clone()) {
+            Ok(envelope) => {
+                ensure!(
+                    envelope.kind == Self::KIND,
+                    "expect {} state, but found {}",
+                    Self::KIND,
+                    envelope.kind
+                );
+                (envelope.version, envelope.state)
+            }
+            // untagged legacy data
+            Err(_) => (0, value),
+        };
+
+        ensure!(
+            version <= Self::VERSION,
+            "{} state in format version {version} is newer than supported version {}",
+            Self::KIND,
+            Self::VERSION
+        );
+        while version < Self::VERSION {
+            info!("migrating {} state from format version {version}", Self::KIND);
+            state = Self::migrate(version, state)
+                .with_context(|| format!("migrate {} state from version {version}", Self::KIND))?;
+            version += 1;
+        }
+        let state = serde_json::from_value(state).with_context(|| format!("invalid {} state", Self::KIND))?;
+
+        Ok(state)
+    }
+
+    /// Save state into file in `path`.
+    fn save_to_file(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let path = path.as_ref();
+        let s = self.to_versioned_json()?;
+        std::fs::write(path, s).with_context(|| format!("write {} state to {path:?}", Self::KIND))?;
+        Ok(())
+    }
+
+    /// Load state from file in `path`.
+    fn load_from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path).with_context(|| format!("read {} state from {path:?}", Self::KIND))?;
+        Self::from_versioned_json(&s)
+    }
+}
+// 0f0cb42b ends here
@@ -0,0 +1,420 @@
+// [[file:../optim.note::f6f8d024][f6f8d024]]
+use super::*;
+
+use crate::cell::{cell_matrix, set_cell};
+use crate::hopping::Minimum;
+use crate::random::Rng;
+use gchemol::Molecule;
+use gosh_model::ChemicalModel;
+use vecfx::nalgebra as na;
+// f6f8d024 ends here
+
+// [[file:../optim.note::600b0197][600b0197]]
+// Max number of trials for relaxing a new structure.
+const MAX_SPAWN_ATTEMPTS: usize = 20;
+// Max number of halving velocity for a sensible move.
+const MAX_MOVE_ATTEMPTS: usize = 5;
+
+/// Final result of `SwarmSearch`.
+#[derive(Debug, Clone)]
+pub struct SwarmSearched {
+    /// Distinct minima relaxed during search, in ascending order of energy,
+    /// with the number of hits in `nvisits`.
+    pub minima: Vec<Minimum>,
+    /// The number of generations evolved.
+    pub ngenerations: usize,
+    /// The number of structures relaxed, including failed ones.
+    pub nrelaxed: usize,
+}
+
+impl SwarmSearched {
+    /// Return the lowest minimum found.
+    pub fn best(&self) -> Option<&Minimum> {
+        self.minima.first()
+    }
+}
+
+// A relaxed structure in swarm, with its velocity and personal best.
+struct Particle {
+    state: Vec<f64>,
+    velocity: Vec<f64>,
+    energy: f64,
+    best_state: Vec<f64>,
+    best_energy: f64,
+}
+
+impl Particle {
+    fn new(state: Vec<f64>, energy: f64) -> Self {
+        Self {
+            velocity: vec![0.0; state.len()],
+            best_state: state.clone(),
+            state,
+            energy,
+            best_energy: energy,
+        }
+    }
+}
+
+// Mutable bookkeeping in swarm search.
+struct Progress {
+    rng: Rng,
+    // the number of structures taken from `RandomSearch`
+    nseeds: usize,
+    nrelaxed: usize,
+    minima: Vec<Minimum>,
+}
+
+/// Particle swarm optimization (PSO) for global structure search in the
+/// spirit of CALYPSO.
+///
+/// Each particle is a locally relaxed structure, moving with velocity toward
+/// its personal best and the global best structure of the swarm, and relaxed
+/// again after each move. Particles are moved in Cartesian coordinates for
+/// clusters, or in fractional coordinates for periodic structures, together
+/// with lattice vectors if the cell is variable. In each generation the worst
+/// particles are renewed with new structures, some of which have random
+/// rotational symmetry around z axis for clusters. Random structures are
+/// generated and relaxed using `RandomSearch`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let search = RandomSearch::new(Optimizer::new(0.01, 500), &template).min_distance(2.0);
+/// let pso = SwarmSearch::new(search).particles(20).generations(30);
+/// let searched = pso.run(&mut model)?;
+/// println!("lowest energy: {:?}", searched.best().map(|m| m.energy));
+/// ```
+///
+/// # References
+///
+/// - Wang, Y.; Lv, J.; Zhu, L.; Ma, Y. Phys. Rev. B 2010, 82, 094116.
+pub struct SwarmSearch {
+    search: RandomSearch,
+    nparticles: usize,
+    generations: usize,
+    // inertia weight decreasing linearly from the first to the second
+    inertia: (f64, f64),
+    // acceleration toward personal and global best
+    acceleration: (f64, f64),
+    max_velocity: f64,
+    renewal: f64,
+    symmetric: f64,
+    variable_cell: bool,
+    energy_tolerance: f64,
+    seed: u64,
+}
+
+impl SwarmSearch {
+    /// Evolve swarm of structures generated and relaxed using `search`.
+    pub fn new(search: RandomSearch) -> Self {
+        Self {
+            search,
+            nparticles: 10,
+            generations: 20,
+            inertia: (0.9, 0.4),
+            acceleration: (2.0, 2.0),
+            max_velocity: 0.5,
+            renewal: 0.4,
+            symmetric: 0.5,
+            variable_cell: false,
+            energy_tolerance: 1e-3,
+            seed: 0,
+        }
+    }
+
+    /// Set the number of particles in swarm.
+    pub fn particles(mut self, n: usize) -> Self {
+        assert!(n > 0, "invalid number of particles: {n}");
+        self.nparticles = n;
+        self
+    }
+
+    /// Set the number of generations to evolve.
+    pub fn generations(mut self, n: usize) -> Self {
+        self.generations = n;
+        self
+    }
+
+    /// Set inertia weight of velocity, decreasing linearly from `start` in
+    /// the first generation to `end` in the last one.
+    pub fn inertia(mut self, start: f64, end: f64) -> Self {
+        assert!(start >= 0.0 && end >= 0.0, "invalid inertia weights: {start}, {end}");
+        self.inertia = (start, end);
+        self
+    }
+
+    /// Set acceleration coefficients toward personal best `c1` and global
+    /// best `c2`.
+    pub fn acceleration(mut self, c1: f64, c2: f64) -> Self {
+        assert!(c1 >= 0.0 && c2 >= 0.0, "invalid acceleration coefficients: {c1}, {c2}");
+        self.acceleration = (c1, c2);
+        self
+    }
+
+    /// Limit each component of velocity to `vmax`, in Å for Cartesian
+    /// coordinates or lattice vectors, and in fractional coordinates for
+    /// periodic structures.
+    pub fn max_velocity(mut self, vmax: f64) -> Self {
+        assert!(vmax > 0.0, "invalid max velocity: {vmax}");
+        self.max_velocity = vmax;
+        self
+    }
+
+    /// Replace the worst `fraction` of particles with new structures in each
+    /// generation.
+    pub fn renewal(mut self, fraction: f64) -> Self {
+        assert!((0.0..1.0).contains(&fraction), "invalid renewal fraction: {fraction}");
+        self.renewal = fraction;
+        self
+    }
+
+    /// Seed `fraction` of new cluster structures with random rotational
+    /// symmetry.
+    pub fn symmetric(mut self, fraction: f64) -> Self {
+        assert!((0.0..=1.0).contains(&fraction), "invalid fraction: {fraction}");
+        self.symmetric = fraction;
+        self
+    }
+
+    /// Move lattice vectors of periodic structures together with atoms. The
+    /// optimizer of `RandomSearch` should also relax the cell, such as using
+    /// `Optimizer::variable_cell`.
+    pub fn variable_cell(mut self) -> Self {
+        self.variable_cell = true;
+        self
+    }
+
+    /// Treat minima with energy difference below `tolerance` in eV as the
+    /// same one.
+    pub fn energy_tolerance(mut self, tolerance: f64) -> Self {
+        assert!(tolerance > 0.0, "invalid tolerance: {tolerance}");
+        self.energy_tolerance = tolerance;
+        self
+    }
+
+    /// Set `seed` for random numbers in particle moves and symmetry seeding.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Evolve swarm in potential provided by `model`.
+    pub fn run<M: ChemicalModel>(&self, model: &mut M) -> Result<SwarmSearched> {
+        let mut progress = Progress {
+            rng: Rng::new(self.seed),
+            nseeds: 0,
+            nrelaxed: 0,
+            minima: vec![],
+        };
+        let mut particles = vec![];
+        for _ in 0..self.nparticles {
+            let (state, energy) = self.spawn(model, &mut progress)?;
+            particles.push(Particle::new(state, energy));
+        }
+
+        let nrenew = (self.renewal * self.nparticles as f64) as usize;
+        for g in 0..self.generations {
+            let t = g as f64 / (self.generations.max(2) - 1) as f64;
+            let w = self.inertia.0 + t * (self.inertia.1 - self.inertia.0);
+            let gbest = particles
+                .iter()
+                .min_by(|a, b| a.best_energy.total_cmp(&b.best_energy))
+                .map(|p| p.best_state.clone())
+                .expect("no particle");
+            // renew the worst particles, and move the others
+            particles.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+            for (i, particle) in particles.iter_mut().enumerate() {
+                if i >= self.nparticles - nrenew {
+                    let (state, energy) = self.spawn(model, &mut progress)?;
+                    *particle = Particle::new(state, energy);
+                } else {
+                    self.fly(particle, &gbest, w, model, &mut progress);
+                }
+            }
+            let best = particles.iter().map(|p| p.best_energy).fold(f64::INFINITY, f64::min);
+            info!("generation {}: lowest E = {best:-16.6}", g + 1);
+        }
+        progress.minima.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+
+        Ok(SwarmSearched {
+            minima: progress.minima,
+            ngenerations: self.generations,
+            nrelaxed: progress.nrelaxed,
+        })
+    }
+
+    // Update velocity of `particle` toward its personal best and global best
+    // `gbest`, move and relax it. The particle stays if failed.
+    fn fly<M: ChemicalModel>(
+        &self,
+        particle: &mut Particle,
+        gbest: &[f64],
+        w: f64,
+        model: &mut M,
+        progress: &mut Progress,
+    ) {
+        let (c1, c2) = self.acceleration;
+        let vmax = self.max_velocity;
+        for i in 0..particle.state.len() {
+            let x = particle.state[i];
+            let v = w * particle.velocity[i]
+                + c1 * progress.rng.uniform() * (particle.best_state[i] - x)
+                + c2 * progress.rng.uniform() * (gbest[i] - x);
+            particle.velocity[i] = v.clamp(-vmax, vmax);
+        }
+        let mut moved = None;
+        for _ in 0..MAX_MOVE_ATTEMPTS {
+            let state = particle
+                .state
+                .iter()
+                .zip(&particle.velocity)
+                .map(|(x, v)| x + v)
+                .collect_vec();
+            let mol = self.molecule_of(&state);
+            if self.search.is_sensible(&mol) {
+                moved = Some(mol);
+                break;
+            }
+            particle.velocity.iter_mut().for_each(|v| *v *= 0.5);
+        }
+        match moved.and_then(|mol| self.relax(mol, model, progress)) {
+            Some((state, energy)) => {
+                if energy < particle.best_energy {
+                    particle.best_state = state.clone();
+                    particle.best_energy = energy;
+                }
+                particle.state = state;
+                particle.energy = energy;
+            }
+            None => particle.velocity.iter_mut().for_each(|v| *v = 0.0),
+        }
+    }
+
+    // Relax new structures until one succeeds.
+    fn spawn<M: ChemicalModel>(&self, model: &mut M, progress: &mut Progress) -> Result<(Vec<f64>, f64)> {
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let mut mol = self.search.random_structure(progress.nseeds)?;
+            progress.nseeds += 1;
+            if progress.rng.uniform() < self.symmetric {
+                if let Some(sym) = self.symmetrize(&mol, &mut progress.rng) {
+                    mol = sym;
+                }
+            }
+            if let Some(relaxed) = self.relax(mol, model, progress) {
+                return Ok(relaxed);
+            }
+        }
+        bail!("failed to relax new structures after {MAX_SPAWN_ATTEMPTS} attempts");
+    }
+
+    // Relax `mol` locally, and record the minimum. Return its state and
+    // energy, or None if not converged.
+    fn relax<M: ChemicalModel>(
+        &self,
+        mut mol: Molecule,
+        model: &mut M,
+        progress: &mut Progress,
+    ) -> Option<(Vec<f64>, f64)> {
+        progress.nrelaxed += 1;
+        let optimizer = self.search.optimizer();
+        let energy = match optimizer.optimize_geometry(&mut mol, model) {
+            Ok(optimized) if optimized.fmax < optimizer.fmax() => optimized.computed.get_energy()?,
+            Ok(optimized) => {
+                warn!("structure not converged in relaxation: fmax = {}", optimized.fmax);
+                return None;
+            }
+            Err(e) => {
+                warn!("structure failed in relaxation: {e:?}");
+                return None;
+            }
+        };
+        let state = self.state_of(&mol);
+        match progress
+            .minima
+            .iter_mut()
+            .find(|m| (m.energy - energy).abs() < self.energy_tolerance)
+        {
+            Some(m) => m.nvisits += 1,
+            None => progress.minima.push(Minimum {
+                energy,
+                molecule: mol,
+                nvisits: 1,
+            }),
+        }
+        Some((state, energy))
+    }
+
+    // Replicate part of atoms in cluster `mol` by a random n-fold rotation
+    // around z axis, keeping the number of atoms of each element. Return None
+    // for periodic structures or with fixed atoms.
+    fn symmetrize(&self, mol: &Molecule, rng: &mut Rng) -> Option<Molecule> {
+        if mol.lattice.is_some() || mol.atoms().any(|(_, a)| a.freezing().contains(&true)) {
+            return None;
+        }
+        let numbers = mol.atoms().map(|(_, a)| a.number()).collect_vec();
+        let counts = numbers.iter().counts();
+        let orders = (2..=6).filter(|n| counts.values().all(|c| c % n == 0)).collect_vec();
+        if orders.is_empty() {
+            return None;
+        }
+        let n = orders[rng.index(orders.len())];
+        let positions = mol.positions().collect_vec();
+        let mut symmetric = positions.clone();
+        for z in numbers.iter().unique() {
+            let atoms = (0..numbers.len()).filter(|&i| numbers[i] == *z).collect_vec();
+            let m = atoms.len() / n;
+            for (k, &i) in atoms.iter().enumerate() {
+                let p = positions[atoms[k % m]];
+                let angle = 2.0 * std::f64::consts::PI * (k / m) as f64 / n as f64;
+                let (sin, cos) = angle.sin_cos();
+                symmetric[i] = [cos * p[0] - sin * p[1], sin * p[0] + cos * p[1], p[2]];
+            }
+        }
+        let mut mol = mol.clone();
+        mol.update_positions(symmetric);
+        self.search.is_sensible(&mol).then_some(mol)
+    }
+
+    // Coordinates of `mol` for particle moves: Cartesian positions for
+    // clusters, or fractional coordinates for periodic structures followed
+    // by lattice vectors if the cell is variable.
+    fn state_of(&self, mol: &Molecule) -> Vec<f64> {
+        let positions = mol.positions().flatten().collect_vec();
+        let Some(cell) = cell_matrix(mol) else {
+            return positions;
+        };
+        let inv = cell.try_inverse().expect("invalid cell");
+        let mut state = positions
+            .chunks(3)
+            .flat_map(|p| (inv * na::Vector3::from_column_slice(p)).iter().copied().collect_vec())
+            .collect_vec();
+        if self.variable_cell {
+            state.extend(cell.iter().copied());
+        }
+        state
+    }
+
+    // Structure from coordinates in `state`, see `state_of`.
+    fn molecule_of(&self, state: &[f64]) -> Molecule {
+        let mut mol = self.search.template().clone();
+        let n = 3 * mol.natoms();
+        match cell_matrix(&mol) {
+            None => mol.update_positions(state.as_3d().to_vec()),
+            Some(cell) => {
+                let cell = if self.variable_cell {
+                    na::Matrix3::from_column_slice(&state[n..])
+                } else {
+                    cell
+                };
+                set_cell(&mut mol, &cell);
+                let positions: Vec<[f64; 3]> = state[..n]
+                    .chunks(3)
+                    .map(|f| (cell * na::Vector3::from_column_slice(f)).into())
+                    .collect();
+                mol.update_positions(positions);
+            }
+        }
+        mol
+    }
+}
+// 600b0197 ends here
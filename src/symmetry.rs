@@ -0,0 +1,274 @@
+// [[file:../optim.note::8c4ce991][8c4ce991]]
+use super::*;
+
+use gchemol::Molecule;
+use vecfx::nalgebra as na;
+// 8c4ce991 ends here
+
+// [[file:../optim.note::ce5d9e3a][ce5d9e3a]]
+/// A symmetry operation mapping atom i onto atom `perm[i]`:
+///
+/// x[perm[i]] = R x[i] + t (modulo lattice translations)
+#[derive(Debug, Clone)]
+struct SymmetryOp {
+    rotation: na::Matrix3<f64>,
+    translation: Vector3f,
+    perm: Vec<usize>,
+}
+
+/// Symmetry operations of a structure in Cartesian coordinates, for keeping
+/// point or space group symmetry during optimization.
+///
+/// As a constraint, forces and steps are symmetrized by averaging over all
+/// operations, so that the structure keeps its symmetry in each iteration.
+#[derive(Debug, Clone)]
+pub struct Symmetry {
+    ops: Vec<SymmetryOp>,
+    // lattice vectors as columns with its inverse
+    cell: Option<(na::Matrix3<f64>, na::Matrix3<f64>)>,
+}
+
+impl Symmetry {
+    /// Detect symmetry operations of `mol` with tolerance `symprec` in
+    /// distance. Space group operations are detected for periodic molecule,
+    /// and point group operations otherwise. For linear molecules, only the
+    /// inversion is considered beyond the identity.
+    pub fn detect(mol: &Molecule, symprec: f64) -> Result<Self> {
+        ensure!(symprec > 0.0, "invalid symprec: {symprec}");
+        let positions = mol.positions().map(Vector3f::from).collect_vec();
+        let numbers = mol.numbers().collect_vec();
+        let cell = match crate::cell::cell_matrix(mol) {
+            Some(m) => Some((m, m.try_inverse().ok_or(format_err!("invalid cell"))?)),
+            None => None,
+        };
+        let mut sym = Self { ops: vec![], cell };
+        let candidates = match &sym.cell {
+            Some((m, inv)) => lattice_candidates(m, inv, &positions, &numbers, symprec),
+            None => point_candidates(&positions, &numbers, symprec),
+        };
+        for (rotation, translation) in candidates {
+            if let Some(perm) = sym.find_permutation(&positions, &numbers, &rotation, &translation, symprec) {
+                sym.push(SymmetryOp {
+                    rotation,
+                    translation,
+                    perm,
+                });
+            }
+        }
+        ensure!(!sym.ops.is_empty(), "no symmetry operation found");
+        Ok(sym)
+    }
+
+    /// Accept symmetry operations `ops` of `mol` given as rotation matrix and
+    /// translation vector in Cartesian coordinates, with tolerance `symprec`
+    /// for mapping atoms.
+    pub fn from_operations(mol: &Molecule, ops: &[([[f64; 3]; 3], [f64; 3])], symprec: f64) -> Result<Self> {
+        let positions = mol.positions().map(Vector3f::from).collect_vec();
+        let numbers = mol.numbers().collect_vec();
+        let cell = match crate::cell::cell_matrix(mol) {
+            Some(m) => Some((m, m.try_inverse().ok_or(format_err!("invalid cell"))?)),
+            None => None,
+        };
+        let mut sym = Self { ops: vec![], cell };
+        for (r, t) in ops {
+            let rotation = na::Matrix3::from_fn(|i, j| r[i][j]);
+            let translation = Vector3f::from(*t);
+            let perm = sym
+                .find_permutation(&positions, &numbers, &rotation, &translation, symprec)
+                .ok_or(format_err!("structure is not invariant under operation: {r:?}, {t:?}"))?;
+            sym.push(SymmetryOp {
+                rotation,
+                translation,
+                perm,
+            });
+        }
+        Ok(sym)
+    }
+
+    /// Return the number of symmetry operations.
+    pub fn nops(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Symmetrize flattened vectors on atoms in place, such as forces or
+    /// displacements.
+    pub fn symmetrize_vectors(&self, vectors: &mut [f64]) {
+        let old = vectors.as_3d().iter().map(|v| Vector3f::from(*v)).collect_vec();
+        let n = self.ops.len() as f64;
+        for (i, v) in vectors.as_mut_3d().iter_mut().enumerate() {
+            let sum: Vector3f = self
+                .ops
+                .iter()
+                .map(|op| op.rotation.transpose() * old[op.perm[i]])
+                .sum();
+            *v = (sum / n).into();
+        }
+    }
+
+    /// Symmetrize flattened `positions` in place, removing small symmetry
+    /// breaking within the tolerance.
+    pub fn symmetrize_positions(&self, positions: &mut [f64]) {
+        let old = positions.as_3d().iter().map(|v| Vector3f::from(*v)).collect_vec();
+        let n = self.ops.len() as f64;
+        for (i, p) in positions.as_mut_3d().iter_mut().enumerate() {
+            let sum: Vector3f = self
+                .ops
+                .iter()
+                .map(|op| {
+                    let d = self.min_image(old[op.perm[i]] - (op.rotation * old[i] + op.translation));
+                    op.rotation.transpose() * d
+                })
+                .sum();
+            *p = (old[i] + sum / n).into();
+        }
+    }
+
+    fn min_image(&self, d: Vector3f) -> Vector3f {
+        match &self.cell {
+            Some((m, inv)) => m * (inv * d).map(|f| f - f.round()),
+            None => d,
+        }
+    }
+
+    // Find the mapping of atoms under operation, if the structure is invariant.
+    fn find_permutation(
+        &self,
+        positions: &[Vector3f],
+        numbers: &[usize],
+        rotation: &na::Matrix3<f64>,
+        translation: &Vector3f,
+        symprec: f64,
+    ) -> Option<Vec<usize>> {
+        let mut used = vec![false; positions.len()];
+        let mut perm = vec![];
+        for (p, n) in positions.iter().zip(numbers) {
+            let image = rotation * p + translation;
+            let k = (0..positions.len())
+                .find(|&k| !used[k] && numbers[k] == *n && self.min_image(image - positions[k]).norm() < symprec)?;
+            used[k] = true;
+            perm.push(k);
+        }
+        Some(perm)
+    }
+
+    // Add operation if not found yet.
+    fn push(&mut self, op: SymmetryOp) {
+        let found = self
+            .ops
+            .iter()
+            .any(|x| x.perm == op.perm && (x.rotation - op.rotation).amax() < 1e-6);
+        if !found {
+            self.ops.push(op);
+        }
+    }
+}
+
+impl EnforceConstraint for Symmetry {
+    fn project_forces(&self, _positions: &[f64], forces: &mut [f64]) -> Result<()> {
+        self.symmetrize_vectors(forces);
+        Ok(())
+    }
+
+    fn adjust_step(&self, _positions: &[f64], step: &mut [f64]) -> Result<()> {
+        self.symmetrize_vectors(step);
+        Ok(())
+    }
+}
+// ce5d9e3a ends here
+
+// [[file:../optim.note::7efd6a70][7efd6a70]]
+// Return the nearest orthogonal matrix from polar decomposition.
+fn orthogonalize(m: na::Matrix3<f64>) -> Option<na::Matrix3<f64>> {
+    let svd = m.svd(true, true);
+    Some(svd.u? * svd.v_t?)
+}
+
+// Candidate point group operations from mapping two reference atoms onto
+// equivalent atoms with the same distances to the center.
+fn point_candidates(positions: &[Vector3f], numbers: &[usize], symprec: f64) -> Vec<(na::Matrix3<f64>, Vector3f)> {
+    // center weighted by atomic numbers, invariant under any operation
+    let total: f64 = numbers.iter().map(|&n| n as f64).sum();
+    let center: Vector3f = positions
+        .iter()
+        .zip(numbers)
+        .map(|(p, &n)| p * n as f64)
+        .sum::<Vector3f>()
+        / total;
+    let v = positions.iter().map(|p| p - center).collect_vec();
+    let identity = na::Matrix3::identity();
+    let translation = |r: &na::Matrix3<f64>| center - r * center;
+
+    let Some(a) = (0..v.len()).max_by(|&i, &j| v[i].norm().partial_cmp(&v[j].norm()).unwrap()) else {
+        return vec![];
+    };
+    let b = (0..v.len()).max_by(|&i, &j| {
+        let ci = v[a].cross(&v[i]).norm();
+        let cj = v[a].cross(&v[j]).norm();
+        ci.partial_cmp(&cj).unwrap()
+    });
+    // single atom or linear molecule
+    let b = match b {
+        Some(b) if v[a].norm() > symprec && v[a].cross(&v[b]).norm() > symprec * v[a].norm() => b,
+        _ => return [identity, -identity].map(|r| (r, translation(&r))).to_vec(),
+    };
+
+    let frame = na::Matrix3::from_columns(&[v[a], v[b], v[a].cross(&v[b])]);
+    let Some(frame_inv) = frame.try_inverse() else {
+        return vec![(identity, translation(&identity))];
+    };
+    let (ra, rb, dab) = (v[a].norm(), v[b].norm(), v[a].dot(&v[b]));
+    let mut candidates = vec![];
+    for i in (0..v.len()).filter(|&i| numbers[i] == numbers[a] && (v[i].norm() - ra).abs() < symprec) {
+        for j in (0..v.len()).filter(|&j| numbers[j] == numbers[b] && (v[j].norm() - rb).abs() < symprec) {
+            if (v[i].dot(&v[j]) - dab).abs() > symprec * (ra + rb) {
+                continue;
+            }
+            // proper and improper rotations
+            for s in [1.0, -1.0] {
+                let image = na::Matrix3::from_columns(&[v[i], v[j], v[i].cross(&v[j]) * s]);
+                if let Some(r) = orthogonalize(image * frame_inv) {
+                    candidates.push((r, translation(&r)));
+                }
+            }
+        }
+    }
+    candidates
+}
+
+// Candidate space group operations: rotations preserving the lattice metric,
+// combined with translations mapping the first atom onto equivalent atoms.
+fn lattice_candidates(
+    m: &na::Matrix3<f64>,
+    inv: &na::Matrix3<f64>,
+    positions: &[Vector3f],
+    numbers: &[usize],
+    symprec: f64,
+) -> Vec<(na::Matrix3<f64>, Vector3f)> {
+    let scale = (0..3).map(|k| m.column(k).norm()).fold(f64::INFINITY, f64::min);
+    let tol = symprec / scale;
+    let mut candidates = vec![];
+    // all integer matrices with entries in -1, 0, 1
+    for code in 0..3usize.pow(9) {
+        let w = na::Matrix3::from_fn(|i, j| ((code / 3usize.pow((i * 3 + j) as u32)) % 3) as f64 - 1.0);
+        if (w.determinant().abs() - 1.0).abs() > 1e-6 {
+            continue;
+        }
+        // rotation in Cartesian coordinates
+        let r = m * w * inv;
+        if (r.transpose() * r - na::Matrix3::identity()).amax() > tol {
+            continue;
+        }
+        let Some(r) = orthogonalize(r) else {
+            continue;
+        };
+        if positions.is_empty() {
+            candidates.push((r, Vector3f::zeros()));
+            continue;
+        }
+        for j in (0..positions.len()).filter(|&j| numbers[j] == numbers[0]) {
+            candidates.push((r, positions[j] - r * positions[0]));
+        }
+    }
+    candidates
+}
+// 7efd6a70 ends here
@@ -0,0 +1,185 @@
+// [[file:../optim.note::1ff23542][1ff23542]]
+use super::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+// 1ff23542 ends here
+
+// [[file:../optim.note::60efc76c][60efc76c]]
+/// Error for potential evaluation not finished in time.
+#[derive(Debug, Clone)]
+pub struct Timeout {
+    /// The time limit for each evaluation.
+    pub limit: Duration,
+}
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "potential evaluation timed out after {:?}", self.limit)
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Error for potential evaluation cancelled by `Canceller`.
+#[derive(Debug, Clone)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "potential evaluation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A handle for cancelling pending evaluations of `TimeoutPotential` from
+/// other threads.
+#[derive(Debug, Clone)]
+pub struct Canceller {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Canceller {
+    /// Cancel pending and future evaluations.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+type Job = (Vec<f64>, PotentialOutput);
+type Done<U> = Result<(PotentialOutput, U)>;
+
+struct Worker<U> {
+    jobs: mpsc::Sender<Job>,
+    done: mpsc::Receiver<Done<U>>,
+}
+
+/// A potential wrapper running evaluations in a worker thread with a time
+/// limit for each evaluation, so that a hung call of external model fails
+/// with `Timeout` error instead of blocking the whole workflow.
+///
+/// A hung call cannot be interrupted safely, so the worker thread is
+/// abandoned on timeout, and a new one is spawned with a fresh potential
+/// for the next evaluation, e.g. on retry set by `Dynamics::set_retry`.
+/// Cancellation applies to all further evaluations. The errors could be
+/// checked by downcasting, e.g. `err.is::<Timeout>()`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut dynamics = Dynamics::with_timeout(&x, || model.clone(), Duration::from_secs(3600));
+/// match dynamics.get_energy() {
+///     Err(e) if e.is::<Timeout>() => { /* handle hung job */ }
+///     ...
+/// }
+/// ```
+pub struct TimeoutPotential<U> {
+    limit: Option<Duration>,
+    spawn: Box<dyn FnMut() -> Worker<U> + Send>,
+    worker: Option<Worker<U>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<U: Send + 'static> TimeoutPotential<U> {
+    /// Evaluate potential created by `make` in a worker thread, with time
+    /// `limit` for each evaluation. `make` is called again for a new worker
+    /// after the last one is abandoned on timeout.
+    pub fn new<P>(mut make: impl FnMut() -> P + Send + 'static, limit: Duration) -> Self
+    where
+        P: EvaluatePotential<U> + Send + 'static,
+    {
+        let spawn = move || {
+            let mut potential = make();
+            let (jobs, jobs_rx) = mpsc::channel::<Job>();
+            let (done_tx, done) = mpsc::channel();
+            std::thread::spawn(move || {
+                for (position, mut output) in jobs_rx {
+                    let result = potential.evaluate(&position, &mut output).map(|extra| (output, extra));
+                    if done_tx.send(result).is_err() {
+                        break;
+                    }
+                }
+            });
+            Worker { jobs, done }
+        };
+        Self {
+            limit: Some(limit),
+            spawn: Box::new(spawn),
+            worker: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Return a handle for cancelling evaluations.
+    pub fn canceller(&self) -> Canceller {
+        Canceller {
+            cancelled: self.cancelled.clone(),
+        }
+    }
+
+    // Wait for result in short slices for checking cancellation.
+    fn wait(&self, done: &mpsc::Receiver<Done<U>>) -> Result<Done<U>> {
+        let start = Instant::now();
+        loop {
+            if self.cancelled.load(Ordering::SeqCst) {
+                return Err(Cancelled.into());
+            }
+            let mut slice = Duration::from_millis(100);
+            if let Some(limit) = self.limit {
+                let elapsed = start.elapsed();
+                if elapsed >= limit {
+                    return Err(Timeout { limit }.into());
+                }
+                slice = slice.min(limit - elapsed);
+            }
+            match done.recv_timeout(slice) {
+                Ok(done) => return Ok(done),
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => bail!("worker thread for potential evaluation panicked"),
+            }
+        }
+    }
+}
+
+impl<U: Send + 'static> EvaluatePotential<U> for TimeoutPotential<U> {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<U> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(Cancelled.into());
+        }
+        let worker = match self.worker.take() {
+            Some(worker) => worker,
+            None => (self.spawn)(),
+        };
+        worker
+            .jobs
+            .send((position.to_vec(), output.clone()))
+            .map_err(|_| format_err!("worker thread for potential evaluation panicked"))?;
+        // the worker is abandoned on error
+        let done = self.wait(&worker.done)?;
+        self.worker = Some(worker);
+        let (evaluated, extra) = done?;
+        *output = evaluated;
+        Ok(extra)
+    }
+
+    fn set_time_limit(&mut self, limit: Option<Duration>) -> bool {
+        self.limit = limit;
+        true
+    }
+}
+
+impl<'a, U: Send + 'static> Dynamics<'a, U> {
+    /// Construct a `Dynamics` as in `new`, with potential created by `make`
+    /// evaluated in a worker thread under time `limit` for each evaluation.
+    /// See also `TimeoutPotential` and `Dynamics::set_timeout`.
+    pub fn with_timeout<P>(x: &[f64], make: impl FnMut() -> P + Send + 'static, limit: Duration) -> Self
+    where
+        P: EvaluatePotential<U> + Send + 'static,
+    {
+        Self::new(x, TimeoutPotential::new(make, limit))
+    }
+}
+// 60efc76c ends here
@@ -0,0 +1,261 @@
+// [[file:../optim.note::166ab6f8][166ab6f8]]
+use super::*;
+// 166ab6f8 ends here
+
+// [[file:../optim.note::573f00bb][573f00bb]]
+/// The two dimensional Müller–Brown potential with three minima and two
+/// saddle points.
+///
+/// # Reference
+///
+/// Müller, K.; Brown, L. D. Theor. Chim. Acta 1979, 53, 75.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MullerBrown;
+
+impl MullerBrown {
+    // parameters in A exp(a (x - x0)^2 + b (x - x0)(y - y0) + c (y - y0)^2)
+    const A: [f64; 4] = [-200.0, -100.0, -170.0, 15.0];
+    const AX: [f64; 4] = [-1.0, -1.0, -6.5, 0.7];
+    const BXY: [f64; 4] = [0.0, 0.0, 11.0, 0.6];
+    const CY: [f64; 4] = [-10.0, -10.0, -6.5, 0.7];
+    const X0: [f64; 4] = [1.0, 0.0, -0.5, -1.0];
+    const Y0: [f64; 4] = [0.0, 0.5, 1.5, 1.0];
+
+    /// Return approximate positions of the three minima.
+    pub fn minima() -> [[f64; 2]; 3] {
+        [[-0.558, 1.442], [0.623, 0.028], [-0.050, 0.467]]
+    }
+
+    /// Return approximate positions of the two saddle points.
+    pub fn saddles() -> [[f64; 2]; 2] {
+        [[-0.822, 0.624], [0.212, 0.293]]
+    }
+}
+
+impl EvaluatePotential<()> for MullerBrown {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        ensure!(position.len() == 2, "Müller–Brown potential requires 2 coordinates");
+        let (x, y) = (position[0], position[1]);
+        let mut energy = 0.0;
+        let mut gradient = [0.0; 2];
+        for k in 0..4 {
+            let (dx, dy) = (x - Self::X0[k], y - Self::Y0[k]);
+            let (a, b, c) = (Self::AX[k], Self::BXY[k], Self::CY[k]);
+            let e = Self::A[k] * (a * dx * dx + b * dx * dy + c * dy * dy).exp();
+            energy += e;
+            gradient[0] += e * (2.0 * a * dx + b * dy);
+            gradient[1] += e * (b * dx + 2.0 * c * dy);
+        }
+        output.energy = energy;
+        output.force[0] = -gradient[0];
+        output.force[1] = -gradient[1];
+        Ok(())
+    }
+}
+// 573f00bb ends here
+
+// [[file:../optim.note::148c2b0b][148c2b0b]]
+/// The LEPS potential for collinear reaction A + BC -> AB + C, coupled to a
+/// harmonic oscillator. The two coordinates are the AB distance and the
+/// oscillator coordinate x, giving two minima separated by a saddle point.
+///
+/// # Reference
+///
+/// Jónsson, H.; Mills, G.; Jacobsen, K. W. In Classical and Quantum Dynamics
+/// in Condensed Phase Simulations; World Scientific, 1998; p 385.
+#[derive(Debug, Clone, Copy)]
+pub struct LepsHarmonic {
+    /// The fixed distance between A and C.
+    pub r_ac: f64,
+    /// The force constant of harmonic coupling.
+    pub kc: f64,
+    /// The coupling parameter to the oscillator.
+    pub c: f64,
+}
+
+impl Default for LepsHarmonic {
+    fn default() -> Self {
+        Self {
+            r_ac: 3.742,
+            kc: 0.2025,
+            c: 1.154,
+        }
+    }
+}
+
+// Sato parameters and Morse parameters for the three pairs: AB, BC, AC
+const LEPS_SATO: [f64; 3] = [0.05, 0.30, 0.05];
+const LEPS_D: [f64; 3] = [4.746, 4.746, 3.445];
+const LEPS_R0: f64 = 0.742;
+const LEPS_ALPHA: f64 = 1.942;
+
+/// Return LEPS energy with its derivatives with respect to distances of AB,
+/// BC and AC.
+fn leps(r: [f64; 3]) -> (f64, [f64; 3]) {
+    let mut q = [0.0; 3];
+    let mut dq = [0.0; 3];
+    let mut j = [0.0; 3];
+    let mut dj = [0.0; 3];
+    for i in 0..3 {
+        let (d, s) = (LEPS_D[i], 1.0 + LEPS_SATO[i]);
+        let e1 = (-LEPS_ALPHA * (r[i] - LEPS_R0)).exp();
+        let e2 = e1 * e1;
+        q[i] = d / 2.0 * (1.5 * e2 - e1) / s;
+        dq[i] = d / 2.0 * LEPS_ALPHA * (e1 - 3.0 * e2) / s;
+        j[i] = d / 4.0 * (e2 - 6.0 * e1) / s;
+        dj[i] = d / 4.0 * LEPS_ALPHA * (6.0 * e1 - 2.0 * e2) / s;
+    }
+    let sum = j[0] * j[0] + j[1] * j[1] + j[2] * j[2] - j[0] * j[1] - j[1] * j[2] - j[0] * j[2];
+    let sqrt = sum.sqrt();
+    let ds = [
+        2.0 * j[0] - j[1] - j[2],
+        2.0 * j[1] - j[0] - j[2],
+        2.0 * j[2] - j[0] - j[1],
+    ];
+    let energy = q.iter().sum::<f64>() - sqrt;
+    let gradient = [0, 1, 2].map(|i| dq[i] - ds[i] * dj[i] / (2.0 * sqrt));
+    (energy, gradient)
+}
+
+impl EvaluatePotential<()> for LepsHarmonic {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        ensure!(position.len() == 2, "LEPS-harmonic potential requires 2 coordinates");
+        let (r_ab, x) = (position[0], position[1]);
+        let (e, g) = leps([r_ab, self.r_ac - r_ab, self.r_ac]);
+        // B atom bound to the oscillator
+        let u = r_ab - (self.r_ac / 2.0 - x / self.c);
+        output.energy = e + 2.0 * self.kc * u * u;
+        output.force[0] = -(g[0] - g[1] + 4.0 * self.kc * u);
+        output.force[1] = -(4.0 * self.kc * u / self.c);
+        Ok(())
+    }
+}
+// 148c2b0b ends here
+
+// [[file:../optim.note::06ccc47f][06ccc47f]]
+/// The N dimensional Rosenbrock function with a narrow curved valley, having
+/// its global minimum at (a, a, ...) for the default b = 100.
+#[derive(Debug, Clone, Copy)]
+pub struct Rosenbrock {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Default for Rosenbrock {
+    fn default() -> Self {
+        Self { a: 1.0, b: 100.0 }
+    }
+}
+
+impl EvaluatePotential<()> for Rosenbrock {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        ensure!(
+            position.len() >= 2,
+            "Rosenbrock function requires at least 2 coordinates"
+        );
+        let (a, b) = (self.a, self.b);
+        output.force.iter_mut().for_each(|f| *f = 0.0);
+        let mut energy = 0.0;
+        for (i, w) in position.windows(2).enumerate() {
+            let (x, y) = (w[0], w[1]);
+            let u = y - x * x;
+            energy += b * u * u + (a - x).powi(2);
+            output.force[i] -= -4.0 * b * u * x - 2.0 * (a - x);
+            output.force[i + 1] -= 2.0 * b * u;
+        }
+        output.energy = energy;
+        Ok(())
+    }
+}
+
+/// The Eckart barrier along the first coordinate, with harmonic wells of
+/// force constant `k` in remaining coordinates:
+///
+/// V = A y / (1 + y) + B y / (1 + y)^2 + k/2 Σ x_i^2, y = exp(x_0 / l)
+///
+/// `a` is the reaction energy, and the barrier is symmetric when `a` is zero
+/// with height of `b`/4.
+#[derive(Debug, Clone, Copy)]
+pub struct EckartBarrier {
+    pub a: f64,
+    pub b: f64,
+    pub l: f64,
+    pub k: f64,
+}
+
+impl Default for EckartBarrier {
+    fn default() -> Self {
+        Self {
+            a: 0.0,
+            b: 4.0,
+            l: 0.5,
+            k: 1.0,
+        }
+    }
+}
+
+impl EvaluatePotential<()> for EckartBarrier {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        ensure!(!position.is_empty(), "Eckart barrier requires at least 1 coordinate");
+        let y = (position[0] / self.l).exp();
+        let z = 1.0 + y;
+        let mut energy = self.a * y / z + self.b * y / (z * z);
+        output.force[0] = -y / self.l * (self.a / (z * z) + self.b * (1.0 - y) / (z * z * z));
+        for (f, x) in output.force.iter_mut().zip(position).skip(1) {
+            energy += 0.5 * self.k * x * x;
+            *f = -self.k * x;
+        }
+        output.energy = energy;
+        Ok(())
+    }
+}
+
+/// Atoms connected by harmonic springs to neighbors within a cutoff in a
+/// reference structure, such as a crystal lattice. The minimum is at the
+/// reference structure, with zero modes of translation and rotation.
+#[derive(Debug, Clone)]
+pub struct HarmonicLattice {
+    // atom pairs with rest lengths
+    springs: Vec<(usize, usize, f64)>,
+    k: f64,
+}
+
+impl HarmonicLattice {
+    /// Construct springs with force constant `k` between atoms within
+    /// `cutoff` in `reference` positions.
+    pub fn new(reference: &[[f64; 3]], k: f64, cutoff: f64) -> Self {
+        let springs = crate::sparse::neighbor_pairs(reference, cutoff)
+            .into_iter()
+            .map(|(i, j)| (i, j, crate::internals::distance(reference[i], reference[j])))
+            .collect();
+        Self { springs, k }
+    }
+
+    /// Return the number of springs.
+    pub fn nsprings(&self) -> usize {
+        self.springs.len()
+    }
+}
+
+impl EvaluatePotential<()> for HarmonicLattice {
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<()> {
+        let positions = position.as_3d();
+        let forces = output.force.as_mut_3d();
+        forces.iter_mut().for_each(|f| *f = [0.0; 3]);
+        let mut energy = 0.0;
+        for &(i, j, r0) in self.springs.iter() {
+            ensure!(j < positions.len(), "invalid atom index in spring: {j}");
+            let r = crate::internals::distance(positions[i], positions[j]);
+            energy += 0.5 * self.k * (r - r0).powi(2);
+            let c = self.k * (r - r0) / r;
+            for k in 0..3 {
+                let d = positions[j][k] - positions[i][k];
+                forces[i][k] += c * d;
+                forces[j][k] -= c * d;
+            }
+        }
+        output.energy = energy;
+        Ok(())
+    }
+}
+// 06ccc47f ends here
@@ -0,0 +1,232 @@
+// [[file:../optim.note::2456a032][2456a032]]
+use super::*;
+
+use vecfx::nalgebra as na;
+// 2456a032 ends here
+
+// [[file:../optim.note::a7e2e17d][a7e2e17d]]
+/// Settings for transition state refinement.
+#[derive(Debug, Clone)]
+pub struct TsConfig {
+    /// Displacement for finite difference Hessian.
+    pub fd_step: f64,
+    /// Max length of each step.
+    pub trust_radius: f64,
+    /// Indices of coordinates for the partial Hessian computed at the initial
+    /// guess. All coordinates are used if None. The other diagonal elements
+    /// are approximated by the mean of the computed ones.
+    pub coords: Option<Vec<usize>>,
+    /// Eigenvalues of final Hessian below the negative of this value are
+    /// counted as imaginary modes.
+    pub imaginary_tolerance: f64,
+}
+
+impl Default for TsConfig {
+    fn default() -> Self {
+        Self {
+            fd_step: 1e-3,
+            trust_radius: 0.1,
+            coords: None,
+            imaginary_tolerance: 1e-4,
+        }
+    }
+}
+
+/// Final result of transition state refinement.
+#[derive(Debug, Clone)]
+pub struct TsRefined {
+    /// The number of P-RFO iterations.
+    pub niter: usize,
+    /// The number of calls for potential evaluation, including those for
+    /// finite difference Hessians.
+    pub ncalls: usize,
+    /// Final fmax criterion of forces.
+    pub fmax: f64,
+    /// Final energy.
+    pub energy: f64,
+    /// Eigenvalues of finite difference Hessian at final geometry, in
+    /// ascending order.
+    pub eigenvalues: Vec<f64>,
+    /// The number of imaginary modes (negative eigenvalues).
+    pub nimaginary: usize,
+    /// The eigenvector of the lowest eigenvalue at final geometry.
+    pub mode: Vec<f64>,
+}
+
+impl TsRefined {
+    /// Return true if refined structure has exactly one imaginary mode.
+    pub fn is_first_order(&self) -> bool {
+        self.nimaginary == 1
+    }
+}
+
+/// Refine transition state from the guess at current position of `dynamics`:
+/// compute finite difference Hessian (partial if `coords` set in `config`),
+/// follow the lowest mode uphill using P-RFO steps with Bofill Hessian update
+/// until fmax below `fmax` or `nmax` iterations reached, and verify the
+/// number of imaginary modes using finite difference Hessian at the final
+/// geometry.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut dynamics = Dynamics::new(&guess, potential);
+/// let refined = gosh_optim::refine_ts(&mut dynamics, &TsConfig::default(), 0.01, 100)?;
+/// assert!(refined.is_first_order());
+/// ```
+///
+/// # References
+///
+/// - Baker, J. J. Comput. Chem. 1986, 7, 385.
+/// - Bofill, J. M. J. Comput. Chem. 1994, 15, 1.
+pub fn refine<U>(dynamics: &mut Dynamics<U>, config: &TsConfig, fmax: f64, nmax: usize) -> Result<TsRefined> {
+    let n = dynamics.position().len();
+    let all = (0..n).collect_vec();
+    let coords = config.coords.as_deref().unwrap_or(&all);
+    ensure!(coords.iter().all(|&i| i < n), "invalid coordinate indices: {coords:?}");
+
+    let mut x = dynamics.position().to_vec();
+    let mut hessian = fd_hessian(dynamics, config.fd_step, coords)?;
+    let mut energy = dynamics.get_energy()?;
+    let mut force = dynamics.get_force()?.to_vec();
+    let mut mode: Option<Vec<f64>> = None;
+    let mut niter = 0;
+    while fmax_(&force) >= fmax && niter < nmax {
+        niter += 1;
+        let (mut step, ts_mode) = prfo_step(&hessian, &force, mode.as_deref());
+        cap_norm(&mut step, config.trust_radius);
+        let x_new = x.iter().zip(&step).map(|(a, b)| a + b).collect_vec();
+        dynamics.set_position(&x_new);
+        energy = dynamics.get_energy()?;
+        let force_new = dynamics.get_force()?.to_vec();
+        // gradient difference
+        let y = na::DVector::from_iterator(n, force.iter().zip(&force_new).map(|(a, b)| a - b));
+        bofill_update(&mut hessian, &na::DVector::from_vec(step), &y);
+        x = x_new;
+        force = force_new;
+        mode = Some(ts_mode);
+        debug!("{niter:5} E = {energy:-16.6} fmax = {:-10.4}", fmax_(&force));
+    }
+
+    // verify with full Hessian at final geometry
+    let hessian = fd_hessian(dynamics, config.fd_step, &all)?;
+    let (eigenvalues, vectors) = sorted_eigen(&hessian);
+    let nimaginary = eigenvalues.iter().filter(|&&e| e < -config.imaginary_tolerance).count();
+    if nimaginary != 1 {
+        warn!("found {nimaginary} imaginary modes in refined transition state");
+    }
+    Ok(TsRefined {
+        niter,
+        ncalls: dynamics.ncalls(),
+        fmax: fmax_(&force),
+        energy,
+        eigenvalues,
+        nimaginary,
+        mode: vectors[0].clone(),
+    })
+}
+// a7e2e17d ends here
+
+// [[file:../optim.note::6db27537][6db27537]]
+// Compute Hessian at current position of `dynamics` using central difference
+// of forces displaced along `coords`.
+fn fd_hessian<U>(dynamics: &mut Dynamics<U>, step: f64, coords: &[usize]) -> Result<na::DMatrix<f64>> {
+    let n = dynamics.position().len();
+    let mut hessian = na::DMatrix::zeros(n, n);
+    let columns = dynamics.fd_columns(step, coords)?;
+    for (&j, column) in coords.iter().zip(&columns) {
+        hessian.set_column(j, &na::DVector::from_column_slice(column));
+    }
+    // symmetrize computed columns, and guess diagonal of the others
+    let computed = (0..n).map(|i| coords.contains(&i)).collect_vec();
+    let diagonal = coords.iter().map(|&i| hessian[(i, i)]).collect_vec();
+    let guess = if diagonal.is_empty() {
+        1.0
+    } else {
+        diagonal.iter().sum::<f64>() / diagonal.len() as f64
+    };
+    for i in 0..n {
+        for j in 0..i {
+            let h = match (computed[i], computed[j]) {
+                (true, true) => 0.5 * (hessian[(i, j)] + hessian[(j, i)]),
+                (true, false) => hessian[(j, i)],
+                (false, true) => hessian[(i, j)],
+                (false, false) => 0.0,
+            };
+            hessian[(i, j)] = h;
+            hessian[(j, i)] = h;
+        }
+        if !computed[i] {
+            hessian[(i, i)] = guess;
+        }
+    }
+    Ok(hessian)
+}
+
+// Eigenvalues in ascending order with eigenvectors.
+fn sorted_eigen(hessian: &na::DMatrix<f64>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let eigen = hessian.clone().symmetric_eigen();
+    eigen
+        .eigenvalues
+        .iter()
+        .zip(eigen.eigenvectors.column_iter())
+        .map(|(&e, v)| (e, v.iter().copied().collect_vec()))
+        .sorted_by(|a, b| a.0.total_cmp(&b.0))
+        .unzip()
+}
+
+// P-RFO step maximizing along the transition mode and minimizing along all
+// others. The transition mode is the lowest one, or the one overlapping most
+// with `last_mode` if any. Return the step and the transition mode.
+fn prfo_step(hessian: &na::DMatrix<f64>, force: &[f64], last_mode: Option<&[f64]>) -> (Vec<f64>, Vec<f64>) {
+    let (b, v) = sorted_eigen(hessian);
+    let k = match last_mode {
+        Some(m) => (0..b.len())
+            .max_by(|&i, &j| v[i].vecdot(m).abs().total_cmp(&v[j].vecdot(m).abs()))
+            .unwrap(),
+        None => 0,
+    };
+    // gradient components along eigenvectors
+    let g = v.iter().map(|vi| -vi.vecdot(force)).collect_vec();
+    let lambda_p = 0.5 * b[k] + (0.25 * b[k] * b[k] + g[k] * g[k]).sqrt();
+    // the lowest eigenvalue of the augmented Hessian for the other modes
+    let others = (0..b.len()).filter(|&i| i != k).collect_vec();
+    let m = others.len();
+    let mut aug = na::DMatrix::zeros(m + 1, m + 1);
+    for (p, &i) in others.iter().enumerate() {
+        aug[(p, p)] = b[i];
+        aug[(p, m)] = g[i];
+        aug[(m, p)] = g[i];
+    }
+    let lambda_n = aug
+        .symmetric_eigen()
+        .eigenvalues
+        .iter()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+
+    let mut step = vec![0.0; force.len()];
+    step.vecadd(&v[k], -g[k] / (b[k] - lambda_p));
+    for &i in &others {
+        step.vecadd(&v[i], -g[i] / (b[i] - lambda_n));
+    }
+    (step, v[k].clone())
+}
+
+// Bofill update of Hessian from step `s` and gradient change `y`, suitable
+// for transition states.
+fn bofill_update(hessian: &mut na::DMatrix<f64>, s: &na::DVector<f64>, y: &na::DVector<f64>) {
+    let xi = y - &*hessian * s;
+    let (xs, ss, xx) = (xi.dot(s), s.dot(s), xi.dot(&xi));
+    if ss < 1e-12 || xx < 1e-12 {
+        return;
+    }
+    // symmetric rank one (Murtagh–Sargent) and Powell symmetric Broyden
+    let phi = xs * xs / (xx * ss);
+    if xs.abs() > 1e-12 {
+        *hessian += phi * &xi * xi.transpose() / xs;
+    }
+    let psb = (&xi * s.transpose() + s * xi.transpose()) / ss - xs * s * s.transpose() / (ss * ss);
+    *hessian += (1.0 - phi) * psb;
+}
+// 6db27537 ends here
@@ -0,0 +1,173 @@
+// [[file:../optim.note::167c4352][167c4352]]
+use super::*;
+
+use std::path::Path;
+// 167c4352 ends here
+
+// [[file:../optim.note::f2edeb81][f2edeb81]]
+/// Umbrella sampling along a collective variable using molecular dynamics,
+/// with a harmonic restraint E = k/2 (q - target)^2 in each window. Windows
+/// are sampled in turn, each starting from the last configuration of the
+/// previous one.
+///
+/// For angular coordinates, targets and sampled values are in degree, and
+/// force constants in energy per radian squared, as in `Restraint::Harmonic`.
+///
+/// # Examples
+///
+/// ```ignore
+/// let umbrella = UmbrellaSampling::new(Coordinate::Distance(0, 1))
+///     .windows(1.0, 3.0, 21, 10.0)
+///     .equilibration(1000)
+///     .sampling(10000);
+/// let series = umbrella.run(&mut md, "umbrella")?;
+/// // then run WHAM using "umbrella/metadata.dat"
+/// ```
+#[derive(Debug, Clone)]
+pub struct UmbrellaSampling {
+    coord: Coordinate,
+    windows: Vec<UmbrellaWindow>,
+    nequil: usize,
+    nsample: usize,
+    nevery: usize,
+}
+
+/// One window in umbrella sampling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UmbrellaWindow {
+    /// The target value of collective variable.
+    pub target: f64,
+    /// The force constant of harmonic restraint.
+    pub k: f64,
+}
+
+/// Time series of collective variable sampled in one umbrella window.
+#[derive(Debug, Clone)]
+pub struct CvSeries {
+    pub window: UmbrellaWindow,
+    /// Simulation time in fs of each sample.
+    pub time: Vec<f64>,
+    /// Sampled values of collective variable.
+    pub values: Vec<f64>,
+}
+
+impl CvSeries {
+    /// Return the mean value of collective variable.
+    pub fn mean(&self) -> f64 {
+        self.values.iter().sum::<f64>() / self.values.len() as f64
+    }
+}
+
+impl UmbrellaSampling {
+    /// Umbrella sampling along `coord`, with 1000 steps of equilibration and
+    /// 5000 steps of sampling in each window by default.
+    pub fn new(coord: Coordinate) -> Self {
+        Self {
+            coord,
+            windows: vec![],
+            nequil: 1000,
+            nsample: 5000,
+            nevery: 1,
+        }
+    }
+
+    /// Add a window restraining at `target` with force constant `k`.
+    pub fn window(mut self, target: f64, k: f64) -> Self {
+        assert!(k > 0.0, "invalid force constant: {k}");
+        self.windows.push(UmbrellaWindow { target, k });
+        self
+    }
+
+    /// Add `n` windows evenly spaced from `from` to `to`, with the same
+    /// force constant `k`.
+    pub fn windows(mut self, from: f64, to: f64, n: usize, k: f64) -> Self {
+        for i in 0..n {
+            let t = if n > 1 { i as f64 / (n - 1) as f64 } else { 0.0 };
+            self = self.window(from + t * (to - from), k);
+        }
+        self
+    }
+
+    /// Set the number of equilibration steps in each window.
+    pub fn equilibration(mut self, nsteps: usize) -> Self {
+        self.nequil = nsteps;
+        self
+    }
+
+    /// Set the number of sampling steps in each window.
+    pub fn sampling(mut self, nsteps: usize) -> Self {
+        self.nsample = nsteps;
+        self
+    }
+
+    /// Record collective variable every `nevery` steps in sampling.
+    pub fn every(mut self, nevery: usize) -> Self {
+        assert!(nevery > 0, "invalid interval: {nevery}");
+        self.nevery = nevery;
+        self
+    }
+
+    /// Run umbrella sampling in all windows using `md`, whose restraints
+    /// will be replaced in each window. Time series of collective variable
+    /// are written into directory `dir` as "window-000.dat", ..., with a
+    /// "metadata.dat" file listing data file, target and force constant of
+    /// all windows, which is suitable for WHAM or MBAR post-processing.
+    pub fn run<U>(&self, md: &mut MoleculeDynamics<U>, dir: impl AsRef<Path>) -> Result<Vec<CvSeries>> {
+        use std::io::Write;
+
+        ensure!(!self.windows.is_empty(), "no umbrella window");
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir).with_context(|| format!("create directory {dir:?}"))?;
+        let mut metadata = vec![];
+        let mut all = vec![];
+        for (i, &window) in self.windows.iter().enumerate() {
+            info!(
+                "umbrella window {}/{}: target = {}",
+                i + 1,
+                self.windows.len(),
+                window.target
+            );
+            let mut restraints = Restraints::default();
+            restraints.add(Restraint::Harmonic {
+                coord: self.coord,
+                k: window.k,
+                target: window.target,
+            });
+            md.set_restraints(restraints);
+            md.propagate(self.nequil)?;
+
+            let mut series = CvSeries {
+                window,
+                time: vec![],
+                values: vec![],
+            };
+            for _ in 0..self.nsample / self.nevery {
+                md.propagate(self.nevery)?;
+                let p = md.progress()?;
+                series.time.push(p.time);
+                series.values.push(self.value(md.position()));
+            }
+            let name = format!("window-{i:03}.dat");
+            let mut f = std::fs::File::create(dir.join(&name)).with_context(|| format!("create file {name}"))?;
+            for (t, q) in series.time.iter().zip(&series.values) {
+                writeln!(f, "{t:-16.4} {q:-16.8}")?;
+            }
+            metadata.push(format!("{name} {} {}", window.target, window.k));
+            all.push(series);
+        }
+        md.set_restraints(Restraints::default());
+        std::fs::write(dir.join("metadata.dat"), metadata.join("\n") + "\n")?;
+        Ok(all)
+    }
+
+    // Return the value of collective variable at flattened `positions`.
+    fn value(&self, positions: &[f64]) -> f64 {
+        let q = self.coord.value(positions.as_3d());
+        if self.coord.is_angular() {
+            q.to_degrees()
+        } else {
+            q
+        }
+    }
+}
+// f2edeb81 ends here
@@ -0,0 +1,151 @@
+// [[file:../optim.note::4d08e85c][4d08e85c]]
+use super::*;
+// 4d08e85c ends here
+
+// [[file:../optim.note::5b9d1ee0][5b9d1ee0]]
+/// Hartree in eV
+const HARTREE: f64 = 27.211386245988;
+/// Bohr in Å
+const BOHR: f64 = 0.529177210903;
+/// kcal/mol in eV
+const KCAL_PER_MOL: f64 = 0.0433641043;
+/// kJ/mol in eV
+const KJ_PER_MOL: f64 = 0.0103642688;
+
+/// Units of energy and length used by a model or in optimization.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnitSystem {
+    /// eV and Å, the units used in this crate.
+    #[default]
+    EvAngstrom,
+    /// Hartree and Bohr (atomic units).
+    HartreeBohr,
+    /// Hartree and Å.
+    HartreeAngstrom,
+    /// kcal/mol and Å.
+    KcalMolAngstrom,
+    /// kJ/mol and Å.
+    KjMolAngstrom,
+    /// User defined units of energy in eV and length in Å.
+    Custom { energy: f64, length: f64 },
+}
+
+impl UnitSystem {
+    /// The energy unit in eV.
+    fn energy(&self) -> f64 {
+        match self {
+            Self::EvAngstrom => 1.0,
+            Self::HartreeBohr | Self::HartreeAngstrom => HARTREE,
+            Self::KcalMolAngstrom => KCAL_PER_MOL,
+            Self::KjMolAngstrom => KJ_PER_MOL,
+            Self::Custom { energy, .. } => *energy,
+        }
+    }
+
+    /// The length unit in Å.
+    fn length(&self) -> f64 {
+        match self {
+            Self::HartreeBohr => BOHR,
+            Self::Custom { length, .. } => *length,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Conversion of energy, forces and positions between units of a model and
+/// the internal units declared for optimization, which are eV and Å by
+/// default.
+///
+/// # Examples
+///
+/// ```ignore
+/// // a model working in atomic units
+/// let units = Units::new(UnitSystem::HartreeBohr);
+/// let mut dynamics = Dynamics::new(&x_angstrom, units.wrap(potential));
+/// // fmax given in Hartree/Bohr
+/// let fmax = units.force(0.00045);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    pub(crate) model: UnitSystem,
+    pub(crate) internal: UnitSystem,
+}
+
+impl Units {
+    /// Convert from `model` units into eV and Å.
+    pub fn new(model: UnitSystem) -> Self {
+        Self {
+            model,
+            internal: UnitSystem::EvAngstrom,
+        }
+    }
+
+    /// Declare `internal` units instead of eV and Å.
+    pub fn internal(mut self, internal: UnitSystem) -> Self {
+        self.internal = internal;
+        self
+    }
+
+    // factors for energy and length from model to internal units
+    fn factors(&self) -> (f64, f64) {
+        (
+            self.model.energy() / self.internal.energy(),
+            self.model.length() / self.internal.length(),
+        )
+    }
+
+    /// Convert `energy` from model units into internal units.
+    pub fn energy(&self, energy: f64) -> f64 {
+        energy * self.factors().0
+    }
+
+    /// Convert `length` from model units into internal units.
+    pub fn length(&self, length: f64) -> f64 {
+        length * self.factors().1
+    }
+
+    /// Convert `force` (energy/length) from model units into internal units,
+    /// e.g. for thresholds like `fmax` known in model units.
+    pub fn force(&self, force: f64) -> f64 {
+        let (fe, fl) = self.factors();
+        force * fe / fl
+    }
+
+    /// Convert `stress` (energy/length^3) from model units into internal
+    /// units.
+    pub fn stress(&self, stress: f64) -> f64 {
+        let (fe, fl) = self.factors();
+        stress * fe / fl.powi(3)
+    }
+
+    /// Wrap `potential` evaluated in model units, so that positions are
+    /// passed in and results are returned in internal units.
+    pub fn wrap<P>(self, potential: P) -> UnitsPotential<P> {
+        UnitsPotential { potential, units: self }
+    }
+}
+
+/// A potential wrapper converting between model units and internal units,
+/// created by `Units::wrap`.
+pub struct UnitsPotential<P> {
+    potential: P,
+    units: Units,
+}
+
+impl<U, P> EvaluatePotential<U> for UnitsPotential<P>
+where
+    P: EvaluatePotential<U>,
+{
+    fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<U> {
+        let units = self.units;
+        let position = position.iter().map(|&x| x / units.length(1.0)).collect_vec();
+        let extra = self.potential.evaluate(&position, output)?;
+        output.energy = units.energy(output.energy);
+        output.force.iter_mut().for_each(|f| *f = units.force(*f));
+        if let Some(stress) = output.stress.as_mut() {
+            stress.iter_mut().for_each(|s| *s = units.stress(*s));
+        }
+        Ok(extra)
+    }
+}
+// 5b9d1ee0 ends here
@@ -17,6 +17,17 @@ pub struct Vars {
     pub max_evaluations: usize,
 
     pub algorithm: String,
+
+    /// Scale the initial L-BFGS step size using the mean curvature of the
+    /// Lindh model Hessian, or precondition steepest descent using its
+    /// diagonal. The inverse Hessian of L-BFGS is not seeded. BFGS in
+    /// internal coordinates is always seeded from the Lindh model Hessian
+    /// for non-periodic molecules, regardless of this option.
+    #[serde(alias = "model_hessian")]
+    pub model_hessian_scaling: bool,
+
+    /// The step size rule for steepest descent ("SD" algorithm).
+    pub step_size_rule: crate::sd::StepSizeRule,
 }
 
 impl Default for Vars {
@@ -27,6 +38,8 @@ impl Default for Vars {
             max_linesearch: 1,
             max_evaluations: 0,
             algorithm: "LBFGS".into(),
+            model_hessian_scaling: false,
+            step_size_rule: crate::sd::StepSizeRule::default(),
         }
     }
 }
@@ -0,0 +1,83 @@
+// [[file:../optim.note::28493e3c][28493e3c]]
+use super::*;
+
+use gchemol::Molecule;
+use serde::*;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+// 28493e3c ends here
+
+// [[file:../optim.note::58a07a0f][58a07a0f]]
+/// A frame of structure streamed to the viewer, one json object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewerFrame {
+    /// The iteration step in optimization or MD.
+    pub step: usize,
+    /// The energy of current structure, if available.
+    pub energy: Option<f64>,
+    /// Element symbols of atoms.
+    pub symbols: Vec<String>,
+    /// Cartesian positions of atoms.
+    pub positions: Vec<[f64; 3]>,
+    /// Lattice vectors for periodic structure.
+    pub cell: Option<[[f64; 3]; 3]>,
+}
+
+impl ViewerFrame {
+    /// Construct a frame from molecule `mol` at `step`.
+    pub fn new(step: usize, mol: &Molecule, energy: Option<f64>) -> Self {
+        let cell = mol.lattice.as_ref().map(|lat| {
+            let mat = lat.matrix();
+            [0, 1, 2].map(|i| [mat[(0, i)], mat[(1, i)], mat[(2, i)]])
+        });
+        Self {
+            step,
+            energy,
+            symbols: mol.symbols().map(|s| s.to_string()).collect(),
+            positions: mol.positions().collect(),
+            cell,
+        }
+    }
+}
+// 58a07a0f ends here
+
+// [[file:../optim.note::c05147dd][c05147dd]]
+/// An observer streaming current geometries over a local socket to a molecular
+/// viewer, so that optimization or MD can be displayed live.
+///
+/// Frames are sent as newline delimited json in the schema of `ViewerFrame`.
+#[derive(Debug)]
+pub struct LiveViewer {
+    stream: TcpStream,
+    // send a frame every N steps
+    every: usize,
+}
+
+impl LiveViewer {
+    /// Connect to the viewer listening on `addr`, such as "127.0.0.1:6666".
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).context("connect to live viewer")?;
+        Ok(Self { stream, every: 1 })
+    }
+
+    /// Send a frame every `n` steps.
+    pub fn every(mut self, n: usize) -> Self {
+        assert!(n > 0, "invalid number of steps: {n}");
+        self.every = n;
+        self
+    }
+
+    /// Send structure in `mol` at `step` to the viewer if required.
+    pub fn observe(&self, step: usize, mol: &Molecule, energy: Option<f64>) -> Result<()> {
+        if step % self.every == 0 {
+            let frame = ViewerFrame::new(step, mol, energy);
+            let mut line = serde_json::to_string(&frame)?;
+            line.push('\n');
+            (&self.stream)
+                .write_all(line.as_bytes())
+                .context("send frame to viewer")?;
+        }
+        Ok(())
+    }
+}
+// c05147dd ends here
@@ -0,0 +1,28 @@
+// [[file:../optim.note::01e56699][01e56699]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_benchmark() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Backend, Benchmark};
+
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let table = Benchmark::new(0.01, 200)
+        .backend(Backend::Lbfgs)
+        .backend(Backend::Fire)
+        .case("LJ3", Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?)
+        .case("LJ38r", Molecule::from_file("tests/files/LennardJones/LJ38r.xyz")?)
+        .run(&mut lj);
+    assert_eq!(table.records.len(), 4);
+    let summary = table.summary();
+    assert_eq!(summary.len(), 2);
+    assert_eq!(summary[0].backend, Backend::Lbfgs);
+    assert!(summary[0].success_rate > 0.0);
+    println!("{table}");
+
+    Ok(())
+}
+// 01e56699 ends here
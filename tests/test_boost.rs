@@ -0,0 +1,74 @@
+// [[file:../optim.note::c8b613eb][c8b613eb]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::{BondBoost, Dynamics};
+
+#[test]
+fn test_bond_boost() -> Result<()> {
+    use vecfx::approx::*;
+
+    // a flat potential
+    let f = |_: &[f64], f: &mut [f64]| {
+        f.iter_mut().for_each(|x| *x = 0.0);
+        Ok(0.0)
+    };
+    let reference = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+    let boost = BondBoost::new(f, &[(0, 1), (1, 2)], &reference).with_max_boost(0.3);
+    let x = [0.0, 0.0, 0.0, 1.05, 0.02, 0.0, 0.98, 1.03, 0.01];
+    let mut pot = Dynamics::new(&x, boost);
+
+    // maximum boost at the reference
+    let e0 = pot.get_energy()?;
+    let boosted = pot.get_extra()?;
+    assert_relative_eq!(boosted.boost_energy, e0, epsilon = 1e-8);
+    assert!(boosted.boost_factor > 1.0);
+    assert!(e0 < 0.3);
+
+    // compare forces with finite difference
+    let force = pot.get_force()?.to_vec();
+    let h = 1e-5;
+    for i in 0..x.len() {
+        let mut d = [0.0; 9];
+        d[i] = h;
+        pot.set_position(&x);
+        pot.step_toward(&d);
+        let ep = pot.get_energy()?;
+        d[i] = -2.0 * h;
+        pot.step_toward(&d);
+        let em = pot.get_energy()?;
+        let fd = -(ep - em) / (2.0 * h);
+        assert_relative_eq!(force[i], fd, epsilon = 1e-5);
+    }
+
+    Ok(())
+}
+// c8b613eb ends here
+
+// [[file:../optim.note::c23ed640][c23ed640]]
+#[test]
+fn test_hyperdynamics() -> Result<()> {
+    use gosh_optim::{HarmonicLattice, HyperDynamics, MoleculeDynamics};
+    use vecfx::approx::*;
+
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let position = reference.iter().flatten().copied().collect_vec();
+    let boost = BondBoost::new(lattice, &[(0, 1), (0, 2), (1, 3), (2, 3)], &position).with_temperature(300.0);
+    let md = MoleculeDynamics::new(Dynamics::new(&position, boost), &[1.0, 2.0, 3.0, 4.0])
+        .timestep(0.5)
+        .nvt(300.0)
+        .seed(1);
+    let mut hmd = HyperDynamics::new(md);
+    hmd.propagate(200)?;
+
+    let clock = hmd.clock();
+    assert_relative_eq!(clock.md_time(), 100.0, epsilon = 1e-8);
+    assert!(clock.hyper_time() > clock.md_time());
+    assert!(clock.average_boost() > 1.0);
+    assert_eq!(hmd.md().nstep(), 200);
+    assert!(hmd.boost_energy()? >= 0.0);
+
+    Ok(())
+}
+// c23ed640 ends here
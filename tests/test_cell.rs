@@ -0,0 +1,25 @@
+// [[file:../optim.note::76422fea][76422fea]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_niggli_reduce() -> Result<()> {
+    use vecfx::approx::*;
+    use vecfx::nalgebra as na;
+
+    // a skewed setting of the simple cubic lattice, vectors in columns
+    let cell = na::Matrix3::new(1.0, 5.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0) * 3.0;
+    let (reduced, m) = gosh_optim::niggli_reduce(cell)?;
+    assert_relative_eq!(reduced, cell * m, epsilon = 1e-8);
+    assert_relative_eq!(m.determinant(), 1.0, epsilon = 1e-8);
+    for i in 0..3 {
+        assert_relative_eq!(reduced.column(i).norm(), 3.0, epsilon = 1e-8);
+        // integer transformation
+        for j in 0..3 {
+            assert_relative_eq!(m[(i, j)], m[(i, j)].round(), epsilon = 1e-8);
+        }
+    }
+
+    Ok(())
+}
+// 76422fea ends here
@@ -0,0 +1,159 @@
+// [[file:../optim.note::ce2866a5][ce2866a5]]
+use gosh_core::*;
+use gosh_optim::{Constraint, Constraints, EnforceConstraint};
+use gut::prelude::*;
+
+#[test]
+fn test_constraint_adjust_step() -> Result<()> {
+    use vecfx::approx::*;
+
+    let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+    let mut constraints = Constraints::default();
+    constraints.add(Constraint::Bond(0, 1, 1.0));
+    constraints.add(Constraint::FixedPlane(2, [0.0, 0.0, 1.0]));
+
+    let mut step = [0.0, 0.0, 0.1, 0.2, 0.1, 0.0, 0.1, 0.1, 0.3];
+    constraints.adjust_step(&positions, &mut step)?;
+    let x: Vec<_> = positions.iter().zip(step.iter()).map(|(a, b)| a + b).collect();
+    let d = ((x[3] - x[0]).powi(2) + (x[4] - x[1]).powi(2) + (x[5] - x[2]).powi(2)).sqrt();
+    assert_relative_eq!(d, 1.0, epsilon = 1e-5);
+    assert_relative_eq!(step[8], 0.0, epsilon = 1e-8);
+
+    // projected forces have no component along the bond or plane normal
+    let mut forces = [1.0, 0.0, 0.5, -1.0, 0.2, 0.0, 0.3, 0.4, 0.5];
+    constraints.project_forces(&positions, &mut forces)?;
+    assert_relative_eq!(forces[3] - forces[0], 0.0, epsilon = 1e-8);
+    assert_relative_eq!(forces[8], 0.0, epsilon = 1e-8);
+
+    Ok(())
+}
+// ce2866a5 ends here
+
+// [[file:../optim.note::f97d466d][f97d466d]]
+#[test]
+fn test_constraint_fixed_com() -> Result<()> {
+    use gosh_optim::FixedCenterOfMass;
+    use vecfx::approx::*;
+
+    let masses = [1.0, 16.0, 1.0];
+    let com = FixedCenterOfMass::new(&masses);
+    let positions = [0.0; 9];
+
+    // no net force
+    let mut forces = [1.0, 0.0, 0.5, -0.5, 0.2, 0.0, 0.3, 0.4, 0.5];
+    com.project_forces(&positions, &mut forces)?;
+    for k in 0..3 {
+        let net: f64 = (0..3).map(|i| forces[3 * i + k]).sum();
+        assert_relative_eq!(net, 0.0, epsilon = 1e-12);
+    }
+
+    // no displacement of center of mass
+    let mut step = [0.1, 0.0, 0.0, 0.2, 0.1, 0.0, 0.1, 0.1, 0.3];
+    com.adjust_step(&positions, &mut step)?;
+    for k in 0..3 {
+        let shift: f64 = (0..3).map(|i| masses[i] * step[3 * i + k]).sum();
+        assert_relative_eq!(shift, 0.0, epsilon = 1e-12);
+    }
+
+    // no momentum of center of mass
+    let mut velocities = [1.0, 0.0, 0.5, -0.5, 0.2, 0.0, 0.3, 0.4, 0.5];
+    com.project_velocities(&positions, &mut velocities)?;
+    for k in 0..3 {
+        let momentum: f64 = (0..3).map(|i| masses[i] * velocities[3 * i + k]).sum();
+        assert_relative_eq!(momentum, 0.0, epsilon = 1e-12);
+    }
+
+    // counted together with built-in constraints
+    let mut constraints = Constraints::default();
+    constraints.add(Constraint::Bond(0, 1, 1.0));
+    constraints.add_custom(com);
+    assert_eq!(constraints.ndof_removed(), 4);
+
+    Ok(())
+}
+// f97d466d ends here
+
+// [[file:../optim.note::33f34cb7][33f34cb7]]
+#[test]
+fn test_constraint_fixed_line() -> Result<()> {
+    use vecfx::approx::*;
+
+    let positions = [0.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+    let line = Constraint::FixedLine(1, [1.0, 1.0, 0.0]);
+    assert_eq!(line.ndof_removed(), 2);
+
+    // force along the line kept, and perpendicular force removed
+    let mut forces = [0.1, 0.2, 0.3, 1.0, 1.0, 0.0];
+    line.project_forces(&positions, &mut forces)?;
+    assert_eq!(&forces[..3], &[0.1, 0.2, 0.3]);
+    assert_relative_eq!(forces[3], 1.0, epsilon = 1e-12);
+    assert_relative_eq!(forces[4], 1.0, epsilon = 1e-12);
+    assert_relative_eq!(forces[5], 0.0, epsilon = 1e-12);
+    let mut forces = [0.1, 0.2, 0.3, 1.0, -1.0, 0.5];
+    line.project_forces(&positions, &mut forces)?;
+    assert_eq!(&forces[..3], &[0.1, 0.2, 0.3]);
+    for x in &forces[3..] {
+        assert_relative_eq!(*x, 0.0, epsilon = 1e-12);
+    }
+    let mut forces = [0.0, 0.0, 0.0, 1.0, 0.0, 0.5];
+    line.project_forces(&positions, &mut forces)?;
+    assert_relative_eq!(forces[3], 0.5, epsilon = 1e-12);
+    assert_relative_eq!(forces[4], 0.5, epsilon = 1e-12);
+    assert_relative_eq!(forces[5], 0.0, epsilon = 1e-12);
+
+    // the atom stays on the line through its position
+    let mut step = [0.1, 0.0, 0.0, 0.3, -0.1, 0.2];
+    line.adjust_step(&positions, &mut step)?;
+    assert_eq!(&step[..3], &[0.1, 0.0, 0.0]);
+    assert_relative_eq!(step[3], 0.1, epsilon = 1e-12);
+    assert_relative_eq!(step[4], 0.1, epsilon = 1e-12);
+    assert_relative_eq!(step[5], 0.0, epsilon = 1e-12);
+
+    Ok(())
+}
+
+#[test]
+fn test_constraint_fixed_position() -> Result<()> {
+    use vecfx::approx::*;
+
+    let tol = 0.05;
+    let initial = [1.0, 0.0, 0.0];
+    let anchor = Constraint::FixedPosition(1, initial, tol);
+    assert_eq!(anchor.ndof_removed(), 0);
+
+    // small steps within tolerance are kept
+    let mut positions = vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+    let mut step = [0.0, 0.0, 0.0, 0.01, 0.02, 0.0];
+    anchor.adjust_step(&positions, &mut step)?;
+    for (x, y) in step.iter().zip([0.0, 0.0, 0.0, 0.01, 0.02, 0.0]) {
+        assert_relative_eq!(*x, y, epsilon = 1e-12);
+    }
+
+    // the atom never leaves the sphere in repeated steps
+    for k in 0..10 {
+        let mut step = [0.1, 0.0, 0.0, 0.03, -0.02 * k as f64, 0.04];
+        anchor.adjust_step(&positions, &mut step)?;
+        positions.iter_mut().zip(&step).for_each(|(x, s)| *x += s);
+        let r = positions[3..]
+            .iter()
+            .zip(&initial)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(r <= tol * (1.0 + 1e-12), "atom moved out of tolerance: {r}");
+    }
+    assert_relative_eq!(positions[0], 1.0, epsilon = 1e-12);
+
+    // outward force removed on the boundary, and inward force kept
+    let positions = [0.0, 0.0, 0.0, 1.0 + tol, 0.0, 0.0];
+    let mut forces = [0.0, 0.0, 0.0, 1.0, 0.5, 0.0];
+    anchor.project_forces(&positions, &mut forces)?;
+    assert_relative_eq!(forces[3], 0.0, epsilon = 1e-12);
+    assert_relative_eq!(forces[4], 0.5, epsilon = 1e-12);
+    let mut forces = [0.0, 0.0, 0.0, -1.0, 0.5, 0.0];
+    anchor.project_forces(&positions, &mut forces)?;
+    assert_eq!(&forces[3..], &[-1.0, 0.5, 0.0]);
+
+    Ok(())
+}
+// 33f34cb7 ends here
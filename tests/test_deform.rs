@@ -0,0 +1,129 @@
+// [[file:../optim.note::45888459][45888459]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use vecfx::approx::*;
+
+#[test]
+fn test_deform_gradient() -> Result<()> {
+    let tension = Deformation::Tension { axis: 0, rate: 1e-3 };
+    let f = tension.gradient(100.0)?;
+    assert_relative_eq!(f[(0, 0)], 1.1, epsilon = 1e-12);
+    assert_eq!(f[(1, 1)], 1.0);
+    assert_eq!(f[(2, 2)], 1.0);
+    // engineering strain grows linearly in time
+    assert_relative_eq!(tension.strain(100.0), 0.1, epsilon = 1e-12);
+    assert_relative_eq!(tension.strain(200.0), 0.2, epsilon = 1e-12);
+
+    // the c vector tilts along x, volume is preserved
+    let shear = Deformation::Shear {
+        axis: 0,
+        normal: 2,
+        rate: 1e-3,
+    };
+    let f = shear.gradient(100.0)?;
+    assert_relative_eq!(f[(0, 2)], 0.1, epsilon = 1e-12);
+    assert_relative_eq!(f.determinant(), 1.0, epsilon = 1e-12);
+
+    // invalid axes
+    let invalid = Deformation::Shear {
+        axis: 1,
+        normal: 1,
+        rate: 1e-3,
+    };
+    assert!(invalid.gradient(1.0).is_err());
+    assert!(Deformation::Tension { axis: 3, rate: 1e-3 }.gradient(1.0).is_err());
+
+    // the cell cannot be compressed to zero length
+    let compression = Deformation::Tension { axis: 0, rate: -0.1 };
+    assert!(compression.gradient(5.0).is_ok());
+    assert!(compression.gradient(10.0).is_err());
+
+    Ok(())
+}
+// 45888459 ends here
+
+// [[file:../optim.note::a2f201e5][a2f201e5]]
+use gchemol::prelude::*;
+use gchemol::{Lattice, Molecule};
+
+// a toy model with energy depending only on volume: E = k/2 (V - V0)^2
+struct VolumeModel;
+impl OptimizeMolecule<()> for VolumeModel {
+    fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+        let (k, v0) = (1e-5, 729.0);
+        let v = mol.lattice.as_ref().unwrap().volume();
+        out.energy = Some(0.5 * k * (v - v0).powi(2));
+        out.forces = Some(vec![[0.0; 3]; mol.natoms()]);
+        let s = k * (v - v0);
+        out.stress = Some([s, s, s, 0.0, 0.0, 0.0]);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_deform_tension() -> Result<()> {
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    mol.set_lattice(Lattice::new([[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]]));
+    let x0 = mol.positions().next().unwrap();
+    let mut model = VolumeModel;
+    let mut md = MoleculeDynamics::from_model(&mut model, mol)?.timestep(0.5);
+
+    let rate = 1e-4;
+    let n = 100;
+    let records = md.deform(Deformation::Tension { axis: 0, rate }, n)?;
+    assert_eq!(records.len(), n);
+    assert_eq!(records[n - 1].step, n);
+    // engineering strain grows linearly in time
+    let strain = rate * 0.5 * n as f64;
+    assert_relative_eq!(records[n - 1].strain, strain, epsilon = 1e-12);
+    assert_relative_eq!(records[n / 2 - 1].strain, strain / 2.0, epsilon = 1e-12);
+
+    // cell and atoms are stretched along x only
+    let mol = md.molecule().unwrap();
+    let [a, b, c] = mol.lattice.as_ref().unwrap().lengths();
+    assert_relative_eq!(a, 9.0 * (1.0 + strain), epsilon = 1e-9);
+    assert_relative_eq!(b, 9.0, epsilon = 1e-9);
+    assert_relative_eq!(c, 9.0, epsilon = 1e-9);
+    let x1 = mol.positions().next().unwrap();
+    assert_relative_eq!(x1[0], x0[0] * (1.0 + strain), epsilon = 1e-9);
+
+    // volume grows, so does the stress
+    assert!(records[n - 1].stress[0] > records[0].stress[0]);
+    assert!(records[0].stress[0] > 0.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_deform_shear() -> Result<()> {
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    mol.set_lattice(Lattice::new([[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]]));
+    let mut model = VolumeModel;
+    let mut md = MoleculeDynamics::from_model(&mut model, mol)?.timestep(0.5);
+
+    let shear = Deformation::Shear {
+        axis: 0,
+        normal: 2,
+        rate: 1e-3,
+    };
+    let records = md.deform(shear, 10)?;
+    assert_relative_eq!(records[9].strain, 10.0 * 1e-3 * 0.5, epsilon = 1e-12);
+    // the c vector tilts along x, volume is preserved
+    let lattice = md.molecule().unwrap().lattice.unwrap();
+    // lattice vectors as columns
+    let mat = lattice.matrix();
+    assert_relative_eq!(mat[(0, 2)], 9.0 * records[9].strain, epsilon = 1e-9);
+    assert_relative_eq!(lattice.volume(), 729.0, epsilon = 1e-9);
+
+    let invalid = Deformation::Shear {
+        axis: 1,
+        normal: 1,
+        rate: 1e-3,
+    };
+    assert!(md.deform(invalid, 1).is_err());
+
+    Ok(())
+}
+// a2f201e5 ends here
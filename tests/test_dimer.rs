@@ -0,0 +1,89 @@
+// [[file:../optim.note::a8568707][a8568707]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use vecfx::approx::*;
+use vecfx::*;
+
+#[test]
+fn test_dimer_search() -> Result<()> {
+    // converge onto the lower saddle point of Müller–Brown potential from
+    // nearby, with dimer initially along the reaction path
+    let [_, b, c] = MullerBrown::minima();
+    let mode = [b[0] - c[0], b[1] - c[1]];
+    let [x, y] = MullerBrown::saddles()[1];
+
+    for rotation in [DimerRotation::ConjugateGradient, DimerRotation::Lbfgs] {
+        let config = DimerConfig {
+            rotation,
+            max_translation: 0.02,
+            ..Default::default()
+        };
+        let dimer = DimerSearch::new(config);
+        let mut dynamics = Dynamics::new(&[x + 0.05, y - 0.03], MullerBrown);
+        let progress = dimer.search(&mut dynamics, &mode, 0.1, 500)?;
+        assert!(progress.fmax < 0.1);
+        assert_eq!(progress.ncalls, dynamics.ncalls());
+        let position = dynamics.position();
+        assert_relative_eq!(position[0], x, epsilon = 1e-2);
+        assert_relative_eq!(position[1], y, epsilon = 1e-2);
+    }
+
+    // force extrapolation saves evaluations in rotations
+    let mut ncalls = vec![];
+    for extrapolate_forces in [true, false] {
+        let config = DimerConfig {
+            max_translation: 0.02,
+            extrapolate_forces,
+            ..Default::default()
+        };
+        let mut dynamics = Dynamics::new(&[x + 0.05, y - 0.03], MullerBrown);
+        let progress = DimerSearch::new(config).search(&mut dynamics, &mode, 0.1, 500)?;
+        assert!(progress.fmax < 0.1);
+        ncalls.push(progress.ncalls as f64 / progress.niter as f64);
+    }
+    assert!(ncalls[0] < ncalls[1]);
+
+    // lowest mode converges along with the curvature
+    let mut dynamics = Dynamics::new(&[x + 0.05, y - 0.03], MullerBrown);
+    let dimer = DimerSearch::default();
+    let last = dimer.search_iter(&mut dynamics, &mode)?.take(50).last().unwrap()?;
+    assert!(last.curvature < 0.0);
+    assert_relative_eq!(last.mode.vec2norm(), 1.0, epsilon = 1e-8);
+    // the lowest mode is roughly along the reaction path
+    let cosine = (last.mode[0] * mode[0] + last.mode[1] * mode[1]) / mode.vec2norm();
+    assert!(cosine.abs() > 0.7);
+
+    Ok(())
+}
+// a8568707 ends here
+
+// [[file:../optim.note::7ad9c16c][7ad9c16c]]
+#[test]
+fn test_art_search() -> Result<()> {
+    // escape from the intermediate minimum of Müller–Brown potential toward
+    // the global minimum
+    let [_, b, c] = MullerBrown::minima();
+    let direction = [b[0] - c[0], b[1] - c[1]];
+    let config = ArtConfig {
+        initial_step: 0.05,
+        push_step: 0.01,
+        max_step: 0.02,
+        ..Default::default()
+    };
+    let art = ArtSearch::new(config);
+    let mut dynamics = Dynamics::new(&c, MullerBrown);
+    let saddle = art.search(&mut dynamics, Some(&direction), 0.1, 1000)?;
+    assert!(saddle.converged);
+    assert!(saddle.eigenvalue < 0.0);
+    assert_eq!(saddle.ncalls, dynamics.ncalls());
+
+    let [x, y] = MullerBrown::saddles()[1];
+    let position = dynamics.position();
+    assert_relative_eq!(position[0], x, epsilon = 1e-2);
+    assert_relative_eq!(position[1], y, epsilon = 1e-2);
+
+    Ok(())
+}
+// 7ad9c16c ends here
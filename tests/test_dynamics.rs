@@ -0,0 +1,646 @@
+// [[file:../optim.note::ffad7879][ffad7879]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use vecfx::approx::*;
+
+#[test]
+fn test_molecule_dynamics_nve() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+    position[4] -= 0.1;
+    let dynamics = Dynamics::new(&position, lattice);
+    let mut md = MoleculeDynamics::new(dynamics, &[1.0, 2.0, 3.0, 4.0]).timestep(0.5);
+    assert_eq!(md.temperature(), 0.0);
+    let e0 = md.get_energy()?;
+
+    md.propagate(200)?;
+    assert_eq!(md.nstep(), 200);
+    assert!(md.kinetic_energy() > 0.0);
+    let e1 = md.get_energy()? + md.kinetic_energy();
+    assert_relative_eq!(e0, e1, max_relative = 1e-3);
+    let progress = md.progress()?;
+    assert_eq!(progress.time, 100.0);
+    assert_eq!(progress.total_energy, e1);
+    assert_eq!(progress.temperature, md.temperature());
+    assert!(progress.pressure.is_none());
+
+    // no net momentum from internal forces
+    let momentum: f64 = md.velocities().chunks(3).zip(md.masses()).map(|(v, m)| m * v[0]).sum();
+    assert_relative_eq!(momentum, 0.0, epsilon = 1e-8);
+
+    Ok(())
+}
+// ffad7879 ends here
+
+// [[file:../optim.note::76dc5a69][76dc5a69]]
+#[test]
+fn test_molecule_dynamics_langevin() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let position = reference.iter().flatten().copied().collect_vec();
+    let dynamics = Dynamics::new(&position, lattice);
+    let thermostat = Thermostat::Langevin {
+        temperature: 300.0,
+        friction: 0.05,
+    };
+    let mut md = MoleculeDynamics::new(dynamics, &[1.0, 2.0, 3.0, 4.0])
+        .timestep(0.5)
+        .thermostat(thermostat)
+        .seed(1);
+
+    // equilibration
+    md.propagate(1000)?;
+    let n = 3000;
+    let mut t_sum = 0.0;
+    for _ in 0..n {
+        md.propagate(1)?;
+        t_sum += md.temperature();
+    }
+    assert_relative_eq!(t_sum / n as f64, 300.0, max_relative = 0.15);
+
+    Ok(())
+}
+// 76dc5a69 ends here
+
+// [[file:../optim.note::a580dc0e][a580dc0e]]
+#[test]
+fn test_molecule_dynamics_rescaling() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+    position[4] -= 0.1;
+
+    let thermostats = [
+        Thermostat::Berendsen {
+            temperature: 300.0,
+            tau: 20.0,
+        },
+        Thermostat::Bussi {
+            temperature: 300.0,
+            tau: 5.0,
+        },
+    ];
+    for thermostat in thermostats {
+        let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+        let dynamics = Dynamics::new(&position, lattice);
+        let mut md = MoleculeDynamics::new(dynamics, &[1.0, 2.0, 3.0, 4.0])
+            .timestep(0.5)
+            .thermostat(thermostat)
+            .seed(1);
+        md.propagate(1000)?;
+        let n = 5000;
+        let mut t_sum = 0.0;
+        for _ in 0..n {
+            md.propagate(1)?;
+            t_sum += md.temperature();
+        }
+        assert_relative_eq!(t_sum / n as f64, 300.0, max_relative = 0.15);
+    }
+
+    Ok(())
+}
+// a580dc0e ends here
+
+// [[file:../optim.note::e1a3a209][e1a3a209]]
+#[test]
+fn test_molecule_dynamics_npt() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Lattice, Molecule};
+
+    // a toy model with energy depending only on volume: E = k/2 (V - V0)^2
+    struct VolumeModel;
+    impl OptimizeMolecule<()> for VolumeModel {
+        fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+            let (k, v0) = (1e-5, 1000.0);
+            let v = mol.lattice.as_ref().unwrap().volume();
+            out.energy = Some(0.5 * k * (v - v0).powi(2));
+            out.forces = Some(vec![[0.0; 3]; mol.natoms()]);
+            let s = k * (v - v0);
+            out.stress = Some([s, s, s, 0.0, 0.0, 0.0]);
+            Ok(())
+        }
+    }
+
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    mol.set_lattice(Lattice::new([[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]]));
+    let mut model = VolumeModel;
+    let barostat = Barostat::Berendsen {
+        pressure: 0.0,
+        tau: 10.0,
+        compressibility: 0.05,
+    };
+    let mut md = MoleculeDynamics::from_model(&mut model, mol)?.barostat(barostat);
+    assert!(md.pressure()? > 0.0);
+    md.propagate(2000)?;
+    let v = md.molecule().unwrap().lattice.unwrap().volume();
+    assert_relative_eq!(v, 1000.0, epsilon = 1.0);
+    assert_relative_eq!(md.pressure()?, 0.0, epsilon = 1e-3);
+    assert_eq!(md.progress()?.pressure, Some(md.pressure()?));
+
+    // NPT dynamics requires a periodic molecule
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let mut lj = gosh_model::LennardJones::default();
+    let mut md = MoleculeDynamics::from_chemical_model(&mut lj, mol)?.barostat(barostat);
+    assert!(md.propagate(1).is_err());
+
+    Ok(())
+}
+// e1a3a209 ends here
+
+// [[file:../optim.note::384d91ea][384d91ea]]
+#[test]
+fn test_molecule_dynamics_constrained() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[6] += 0.1;
+    position[7] += 0.1;
+    let dynamics = Dynamics::new(&position, lattice);
+    let mut md = MoleculeDynamics::new(dynamics, &[16.0, 1.0, 1.0])
+        .timestep(1.0)
+        .constrain(Constraint::Bond(0, 1, 1.0));
+    let e0 = md.get_energy()?;
+    md.propagate(500)?;
+
+    let x = md.position();
+    let r01 = x[..3]
+        .iter()
+        .zip(&x[3..6])
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    assert_relative_eq!(r01, 1.0, epsilon = 1e-5);
+    // no relative velocity along the constrained bond
+    let v = md.velocities();
+    let vr: f64 = (0..3).map(|k| (v[3 + k] - v[k]) * (x[3 + k] - x[k])).sum();
+    assert_relative_eq!(vr, 0.0, epsilon = 1e-8);
+    let e1 = md.get_energy()? + md.kinetic_energy();
+    assert_relative_eq!(e0, e1, max_relative = 1e-3);
+
+    Ok(())
+}
+// 384d91ea ends here
+
+// [[file:../optim.note::5a594460][5a594460]]
+#[test]
+fn test_molecule_dynamics_net_motion() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let masses = [1.0, 2.0, 3.0, 4.0];
+    let momentum = |md: &MoleculeDynamics<()>| {
+        let mut p = [0.0; 3];
+        for (v, m) in md.velocities().chunks(3).zip(md.masses()) {
+            (0..3).for_each(|k| p[k] += m * v[k]);
+        }
+        p.vec2norm()
+    };
+
+    // inconsistent forces with a small net force on all atoms
+    let mut lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let f = move |x: &[f64], force: &mut [f64]| {
+        let mut out = PotentialOutput {
+            energy: 0.0,
+            force: vec![0.0; x.len()],
+            stress: None,
+        };
+        lattice.evaluate(x, &mut out)?;
+        force.iter_mut().zip(&out.force).for_each(|(f, f0)| *f = f0 + 0.01);
+        Ok(out.energy)
+    };
+    let position = reference.iter().flatten().copied().collect_vec();
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, f), &masses).remove_translation(1);
+    md.propagate(100)?;
+    assert_relative_eq!(momentum(&md), 0.0, epsilon = 1e-10);
+
+    // spinning cluster
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .remove_translation(1)
+        .remove_rotation(1);
+    let velocities = reference
+        .iter()
+        .flat_map(|r| [-0.01 * r[1], 0.01 * r[0], 0.0])
+        .collect_vec();
+    md.set_velocities(&velocities);
+    md.propagate(1)?;
+    assert_relative_eq!(md.kinetic_energy(), 0.0, epsilon = 1e-4);
+    md.propagate(10)?;
+    assert_relative_eq!(momentum(&md), 0.0, epsilon = 1e-10);
+
+    // linear molecule has 3N - 5 degrees of freedom
+    let linear = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]];
+    let position = linear.iter().flatten().copied().collect_vec();
+    let lattice = HarmonicLattice::new(&linear, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0; 3])
+        .remove_translation(1)
+        .remove_rotation(1);
+    md.set_velocities(&[0.01, 0.0, 0.0, -0.02, 0.0, 0.0, 0.01, 0.0, 0.0]);
+    let kb = 8.617333262e-5;
+    assert_relative_eq!(
+        md.temperature(),
+        2.0 * md.kinetic_energy() / (4.0 * kb),
+        max_relative = 1e-9
+    );
+
+    Ok(())
+}
+// 5a594460 ends here
+
+// [[file:../optim.note::7a17b865][7a17b865]]
+#[test]
+fn test_molecule_dynamics_trajectory() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let reference = mol.positions().collect_vec();
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+
+    let dir = std::env::temp_dir().join("gosh-optim-test-md");
+    std::fs::create_dir_all(&dir)?;
+    let writer = TrajectoryWriter::new(dir.join("md.xyz"), &mol).every(10).rotate(5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0; 3]).trajectory(writer);
+    md.propagate(100)?;
+
+    for (i, file) in ["md-0000.xyz", "md-0001.xyz"].iter().enumerate() {
+        let s = std::fs::read_to_string(dir.join(file))?;
+        let lines = s.lines().collect_vec();
+        assert_eq!(lines.len(), 5 * 5);
+        assert!(lines[1].contains("vel:R:3:forces:R:3"));
+        assert!(lines[1].contains(&format!("step={}", 50 * i + 10)));
+        assert_eq!(lines[2].split_whitespace().count(), 10);
+    }
+    assert!(!dir.join("md-0002.xyz").exists());
+
+    Ok(())
+}
+// 7a17b865 ends here
+
+// [[file:../optim.note::ba8214d6][ba8214d6]]
+#[test]
+fn test_molecule_dynamics_restart() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let position = reference.iter().flatten().copied().collect_vec();
+    let masses = [1.0, 2.0, 3.0, 4.0];
+    let new_md = |seed| {
+        let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+        MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+            .nvt(300.0)
+            .seed(seed)
+    };
+
+    let mut md = new_md(1);
+    md.propagate(50)?;
+    let file = std::env::temp_dir().join("gosh-optim-test-md-restart.json");
+    md.restart().save(&file)?;
+    md.propagate(50)?;
+
+    let restart = MdRestart::load(&file)?;
+    assert_eq!(restart.nstep(), 50);
+    let value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+    assert_eq!(value["kind"], "md-restart");
+    std::fs::remove_file(&file)?;
+    let mut restored = new_md(2);
+    restored.restore(&restart)?;
+    restored.propagate(50)?;
+    assert_eq!(restored.nstep(), 100);
+    assert_eq!(restored.position(), md.position());
+    assert_eq!(restored.velocities(), md.velocities());
+
+    Ok(())
+}
+// ba8214d6 ends here
+
+// [[file:../optim.note::6133ad95][6133ad95]]
+#[test]
+fn test_molecule_dynamics_annealing() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let position = reference.iter().flatten().copied().collect_vec();
+    let schedule = TemperatureSchedule::plateaus(&[(1000.0, 2000), (0.0, 1)]);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0])
+        .timestep(0.5)
+        .thermostat(Thermostat::Langevin {
+            temperature: 300.0,
+            friction: 0.05,
+        })
+        .temperature_schedule(schedule);
+    md.propagate(2000)?;
+    assert!(md.temperature() > 100.0);
+    // quenched
+    md.propagate(2000)?;
+    assert!(md.temperature() < 1.0);
+
+    Ok(())
+}
+// 6133ad95 ends here
+
+// [[file:../optim.note::3f0e512e][3f0e512e]]
+#[test]
+fn test_molecule_dynamics_integrators() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+    position[4] -= 0.1;
+    let masses = [1.0, 2.0, 3.0, 4.0];
+
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut vv = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .timestep(0.5)
+        .integrator(VelocityVerlet);
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut leapfrog = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .timestep(0.5)
+        .integrator(Leapfrog);
+    // initial velocities at half step before for leapfrog
+    leapfrog.kick(-0.25)?;
+    vv.propagate(100)?;
+    leapfrog.propagate(100)?;
+    // the same trajectory
+    for (a, b) in vv.position().iter().zip(leapfrog.position()) {
+        assert_relative_eq!(a, b, epsilon = 1e-8);
+    }
+
+    // a user defined integrator: position Verlet
+    struct PositionVerlet;
+    impl<U> Integrator<U> for PositionVerlet {
+        fn step(&mut self, md: &mut MoleculeDynamics<U>, dt: f64) -> Result<()> {
+            md.drift(0.5 * dt)?;
+            md.kick(dt)?;
+            md.drift(0.5 * dt)
+        }
+    }
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .timestep(0.5)
+        .integrator(PositionVerlet);
+    let e0 = md.get_energy()?;
+    md.propagate(200)?;
+    let e1 = md.get_energy()? + md.kinetic_energy();
+    assert_relative_eq!(e0, e1, max_relative = 1e-3);
+
+    Ok(())
+}
+// 3f0e512e ends here
+
+// [[file:../optim.note::e2a139ba][e2a139ba]]
+#[test]
+fn test_molecule_dynamics_respa() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+    position[4] -= 0.1;
+
+    // fast: stiff springs; slow: weak harmonic well around origin
+    let ks = 0.01;
+    let slow_energy = |x: &[f64]| 0.5 * ks * x.iter().map(|a| a * a).sum::<f64>();
+    let ncalls = std::cell::Cell::new(0);
+    let slow = |x: &[f64], force: &mut [f64]| -> Result<f64> {
+        ncalls.set(ncalls.get() + 1);
+        force.iter_mut().zip(x).for_each(|(f, a)| *f = -ks * a);
+        Ok(slow_energy(x))
+    };
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0])
+        .timestep(2.0)
+        .integrator(Respa::new(slow, 4));
+    let e0 = md.get_energy()? + slow_energy(md.position());
+    md.propagate(50)?;
+    let e1 = md.get_energy()? + slow_energy(md.position()) + md.kinetic_energy();
+    assert_relative_eq!(e0, e1, max_relative = 1e-3);
+    assert_eq!(ncalls.get(), 51);
+    assert_eq!(md.dynamics().ncalls(), 201);
+
+    Ok(())
+}
+// e2a139ba ends here
+
+// [[file:../optim.note::b64dad8e][b64dad8e]]
+#[test]
+fn test_molecule_dynamics_run_iter() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+
+    let mut observed = vec![];
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0])
+        .timestep(0.5)
+        .observe(10, |p| observed.push(p.nstep));
+    let steps: Vec<_> = md.run_iter(50).collect::<Result<_>>()?;
+    assert_eq!(steps.len(), 50);
+    assert_eq!(steps[0].nstep, 1);
+    assert_eq!(steps[49].time, 25.0);
+    let e0 = steps[0].total_energy;
+    assert!(steps.iter().all(|p| (p.total_energy - e0).abs() < 1e-2 * e0.abs()));
+    // stream until equilibrated in a workflow
+    let hot = md
+        .run_iter(100)
+        .find(|p| p.as_ref().map_or(true, |p| p.temperature > 1.0));
+    assert!(hot.is_some());
+    drop(md);
+    assert_eq!(observed[..5], [10, 20, 30, 40, 50]);
+
+    Ok(())
+}
+// b64dad8e ends here
+
+// [[file:../optim.note::bea86bc6][bea86bc6]]
+#[test]
+fn test_umbrella_sampling() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let position = reference.iter().flatten().copied().collect_vec();
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 1.0])
+        .timestep(0.5)
+        .nvt(300.0)
+        .seed(1);
+
+    let dir = std::env::temp_dir().join("gosh-optim-test-umbrella");
+    let umbrella = UmbrellaSampling::new(Coordinate::Distance(0, 1))
+        .windows(0.9, 1.1, 2, 10.0)
+        .equilibration(500)
+        .sampling(2000)
+        .every(2);
+    let series = umbrella.run(&mut md, &dir)?;
+    assert_eq!(series.len(), 2);
+    assert_eq!(series[0].values.len(), 1000);
+    // the minimum of combined springs
+    for s in series.iter() {
+        let q0 = (1.0 + 10.0 * s.window.target) / 11.0;
+        assert_relative_eq!(s.mean(), q0, epsilon = 0.02);
+    }
+
+    let metadata = std::fs::read_to_string(dir.join("metadata.dat"))?;
+    assert_eq!(metadata.lines().next(), Some("window-000.dat 0.9 10"));
+    let data = std::fs::read_to_string(dir.join("window-001.dat"))?;
+    assert_eq!(data.lines().count(), 1000);
+
+    Ok(())
+}
+// bea86bc6 ends here
+
+// [[file:../optim.note::f07e8d92][f07e8d92]]
+#[test]
+fn test_molecule_dynamics_drift() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 0.1;
+    let masses = [1.0, 2.0, 3.0, 4.0];
+
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .timestep(0.5)
+        .drift_tolerance(1e-4, DriftPolicy::Abort);
+    md.propagate(200)?;
+
+    // unstable with too large timestep
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &masses)
+        .timestep(20.0)
+        .drift_tolerance(1e-4, DriftPolicy::Abort);
+    assert!(md.propagate(200).is_err());
+    assert!(md.nstep() < 200);
+
+    Ok(())
+}
+// f07e8d92 ends here
+
+// [[file:../optim.note::91e2321c][91e2321c]]
+#[test]
+fn test_molecule_dynamics_small_steps() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[0] += 1e-6;
+
+    // starting from rest near the minimum, the first steps are far below the
+    // default epsilon of Dynamics
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0]).timestep(0.5);
+    let e0 = md.get_energy()?;
+    md.propagate(200)?;
+    assert_ne!(md.position(), position.as_slice());
+    let e1 = md.get_energy()? + md.kinetic_energy();
+    assert_relative_eq!(e0, e1, max_relative = 1e-3);
+
+    Ok(())
+}
+// 91e2321c ends here
+
+// [[file:../optim.note::408d1e70][408d1e70]]
+#[test]
+fn test_molecule_dynamics_isotope() -> Result<()> {
+    use gchemol::{Atom, Molecule};
+    use gosh_model::LennardJones;
+
+    let mol = Molecule::from_atoms([Atom::new("H", [0.0; 3]), Atom::new("H", [1.2, 0.0, 0.0])]);
+    let mut lj = LennardJones::default();
+    let mut md = MoleculeDynamics::from_chemical_model(&mut lj, mol)?
+        .isotope(&[1], DEUTERIUM_MASS)
+        .timestep(0.5);
+    assert_relative_eq!(md.masses()[0], 1.008, epsilon = 1e-2);
+    assert_eq!(md.masses()[1], DEUTERIUM_MASS);
+
+    // momentum conserved with substituted mass
+    md.propagate(100)?;
+    let momentum: f64 = md.velocities().chunks(3).zip(md.masses()).map(|(v, m)| m * v[0]).sum();
+    assert_relative_eq!(momentum, 0.0, epsilon = 1e-8);
+    assert!(md.velocities()[0].abs() > md.velocities()[3].abs());
+
+    Ok(())
+}
+// 408d1e70 ends here
+
+// [[file:../optim.note::1821bed8][1821bed8]]
+#[test]
+fn test_molecule_dynamics_frozen() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    let mut position = reference.iter().flatten().copied().collect_vec();
+    position[3] += 0.1;
+    position[4] -= 0.1;
+    let dynamics = Dynamics::new(&position, lattice);
+    let mut md = MoleculeDynamics::new(dynamics, &[1.0, 2.0, 3.0, 4.0])
+        .freeze_atoms(&[1])?
+        .freeze_coords(&[
+            false, false, false, false, false, false, false, false, true, false, false, true,
+        ])?
+        .timestep(0.5)
+        .nvt(300.0)
+        .seed(1);
+    md.set_velocities(&[0.1; 12]);
+    assert_eq!(&md.velocities()[..3], &[0.0; 3]);
+
+    md.propagate(200)?;
+    let x = md.position();
+    assert_eq!(&x[..3], &position[..3]);
+    assert_eq!(x[8], position[8]);
+    assert_eq!(x[11], position[11]);
+    assert!(x[3..8].iter().zip(&position[3..8]).any(|(a, b)| a != b));
+    assert_eq!(md.velocities()[0], 0.0);
+    assert_eq!(md.velocities()[11], 0.0);
+
+    Ok(())
+}
+// 1821bed8 ends here
+
+// [[file:../optim.note::0154c5ba][0154c5ba]]
+#[test]
+fn test_replica_exchange() -> Result<()> {
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let position = reference.iter().flatten().copied().collect_vec();
+    let temperatures = [280.0, 300.0];
+    let replicas = (0..2)
+        .map(|i| {
+            let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+            let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0])
+                .timestep(0.5)
+                .nvt(300.0)
+                .seed(i);
+            md.randomize_velocities(300.0);
+            md
+        })
+        .collect_vec();
+    let mut remd = ReplicaExchange::new(replicas, &temperatures).exchange_every(50).seed(1);
+    remd.run(20)?;
+
+    // only even pairs attempted with two replicas
+    let stats = remd.stats();
+    assert_eq!(stats.attempts, vec![10]);
+    assert!(stats.accepted[0] > 0);
+    assert!(stats.acceptance(0) <= 1.0);
+    assert!(stats.to_string().contains("280.00"));
+    assert!(remd.replicas().iter().all(|md| md.nstep() == 1000));
+
+    // propagated in parallel by workers, with the same result
+    let workers = (0..2)
+        .map(|i| {
+            let position = position.clone();
+            ReplicaWorker::spawn(move |server| {
+                let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+                let mut md = MoleculeDynamics::new(Dynamics::new(&position, lattice), &[1.0, 2.0, 3.0, 4.0])
+                    .timestep(0.5)
+                    .nvt(300.0)
+                    .seed(i);
+                md.randomize_velocities(300.0);
+                server.serve(md)
+            })
+        })
+        .collect_vec();
+    let mut remd_parallel = ReplicaExchange::new(workers, &temperatures).exchange_every(50).seed(1);
+    remd_parallel.run_parallel(20)?;
+    assert_eq!(remd_parallel.stats().accepted, remd.stats().accepted);
+
+    // errors in worker are reported
+    let worker = ReplicaWorker::spawn(|_| bail!("no model"));
+    let mut remd = ReplicaExchange::new(vec![worker], &[300.0]);
+    assert!(remd.run_parallel(1).is_err());
+
+    Ok(())
+}
+// 0154c5ba ends here
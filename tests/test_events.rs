@@ -0,0 +1,46 @@
+// [[file:../optim.note::fa34179f][fa34179f]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_reaction_events() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_optim::{detect_reactions, ReactionDetector};
+
+    // a bonded He trimer after shrinking
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let positions: Vec<_> = mol.positions().map(|p| p.map(|x| x * 0.5)).collect();
+    mol.update_positions(positions.clone());
+
+    // the third atom leaves
+    let mut frames = vec![];
+    for step in 0..20 {
+        let mut frame = mol.clone();
+        if step >= 10 {
+            let mut p = positions.clone();
+            p[2] = p[2].map(|x| x * 10.0);
+            frame.update_positions(p);
+        }
+        // transient change filtered out
+        if step == 5 {
+            let mut p = positions.clone();
+            p[0] = p[0].map(|x| x * 10.0);
+            frame.update_positions(p);
+        }
+        frames.push((step, frame));
+    }
+
+    let detector = ReactionDetector::default().with_persistence(3);
+    let events = detect_reactions(frames.iter().map(|(i, m)| (*i, m)), detector);
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.step_reactant, 9);
+    assert_eq!(event.step_product, 10);
+    assert_eq!(event.broken, vec![(0, 2), (1, 2)]);
+    assert!(event.formed.is_empty());
+    assert_eq!(event.atoms, vec![0, 1, 2]);
+
+    Ok(())
+}
+// fa34179f ends here
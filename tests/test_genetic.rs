@@ -0,0 +1,30 @@
+// [[file:../optim.note::55f0a96b][55f0a96b]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_genetic_search() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{GeneticSearch, Optimizer, RandomSearch};
+    use vecfx::approx::*;
+
+    let template = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let search = RandomSearch::new(Optimizer::new(0.01, 500), &template)
+        .min_distance(0.9)
+        .box_size(1.6);
+    let ga = GeneticSearch::new(search).population(3).generations(2).seed(1);
+    let mut lj = LennardJones::default();
+    let searched = ga.run(&mut lj)?;
+    assert_eq!(searched.ngenerations, 2);
+    assert!(searched.nrelaxed > 3);
+    assert!(searched.population.len() <= 3);
+
+    // the only minimum found again and again
+    let best = searched.best().expect("no structure");
+    assert_relative_eq!(best.energy, -3.0, epsilon = 1e-2);
+    assert!(best.nvisits > 1);
+
+    Ok(())
+}
+// 55f0a96b ends here
@@ -0,0 +1,180 @@
+// [[file:../optim.note::7f5f4b70][7f5f4b70]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_lindh_hessian() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use vecfx::approx::*;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ38.xyz")?;
+    let h = gosh_optim::lindh_hessian(&mol);
+    let n = 3 * mol.natoms();
+    assert_eq!(h.len(), n * n);
+
+    for i in 0..n {
+        // symmetric
+        for j in 0..i {
+            assert_relative_eq!(h[i * n + j], h[j * n + i], epsilon = 1e-8);
+        }
+        // invariant to overall translation
+        for p in 0..3 {
+            let s: f64 = (0..n / 3).map(|j| h[i * n + 3 * j + p]).sum();
+            assert_relative_eq!(s, 0.0, epsilon = 1e-6);
+        }
+        assert!(h[i * n + i] >= 0.0);
+    }
+
+    Ok(())
+}
+// 7f5f4b70 ends here
+
+// [[file:../optim.note::276677a1][276677a1]]
+#[test]
+fn test_lindh_hessian_sparse() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use vecfx::approx::*;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ38.xyz")?;
+    let dense = gosh_optim::lindh_hessian(&mol);
+    let sparse = gosh_optim::lindh_hessian_sparse(&mol);
+    let n = sparse.dim();
+    assert_eq!(n, 3 * mol.natoms());
+    assert!(sparse.nnz() <= n * n);
+    for (a, b) in sparse.to_dense().iter().zip(dense.iter()) {
+        assert_relative_eq!(a, b, epsilon = 1e-8);
+    }
+
+    // Newton step with shift for zero modes
+    let b: Vec<_> = (0..n).map(|i| (i as f64 * 0.3).cos()).collect();
+    let x = sparse.solve(&b, 0.1)?;
+    let hx = sparse.mul_vec(&x);
+    for i in 0..n {
+        assert_relative_eq!(hx[i] + 0.1 * x[i], b[i], epsilon = 1e-5);
+    }
+
+    // translations are zero modes
+    let pairs = sparse.lowest_eigenpairs(3)?;
+    assert_eq!(pairs.len(), 3);
+    for (e, _) in pairs {
+        assert_relative_eq!(e, 0.0, epsilon = 1e-6);
+    }
+
+    Ok(())
+}
+// 276677a1 ends here
+
+// [[file:../optim.note::895958df][895958df]]
+#[test]
+fn test_normal_modes() {
+    use gosh_optim::normal_modes;
+    use vecfx::approx::*;
+
+    // a diatomic spring with force constant k along x
+    let (k, m1, m2) = (2.0, 1.0, 4.0);
+    let n = 6;
+    let mut h = vec![0.0; n * n];
+    h[0] = k;
+    h[3 * n + 3] = k;
+    h[3] = -k;
+    h[3 * n] = -k;
+    let modes = normal_modes(&h, &[m1, m2]);
+    assert_eq!(modes.len(), 6);
+    // only the stretching mode has nonzero eigenvalue: k / μ
+    assert_relative_eq!(modes[5].0, k * (1.0 / m1 + 1.0 / m2), epsilon = 1e-8);
+    for (e, _) in modes.iter().take(5) {
+        assert_relative_eq!(*e, 0.0, epsilon = 1e-8);
+    }
+}
+// 895958df ends here
+
+// [[file:../optim.note::54aef7b0][54aef7b0]]
+#[test]
+fn test_numerical_hessian() -> Result<()> {
+    use gosh_optim::Dynamics;
+    use vecfx::approx::*;
+
+    // f(x1, x2, x3) = x1^2 + 3 x2^2 + x1 x2 + x2 x3^2
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -(2.0 * x[0] + x[1]);
+        f[1] = -(6.0 * x[1] + x[0] + x[2].powi(2));
+        f[2] = -(2.0 * x[1] * x[2]);
+        let fx: f64 = x[0].powi(2) + 3.0 * x[1].powi(2) + x[0] * x[1] + x[1] * x[2].powi(2);
+        Ok(fx)
+    };
+    let mut dynamics = Dynamics::new(&[0.1, 0.2, 0.3], f);
+    let energy = dynamics.get_energy()?;
+    assert_eq!(dynamics.ncalls(), 1);
+
+    let h = dynamics.numerical_hessian(1e-4)?;
+    assert_eq!(h.ncalls, 6);
+    #[rustfmt::skip]
+    let expected = [
+        2.0, 1.0, 0.0,
+        1.0, 6.0, 0.6,
+        0.0, 0.6, 0.4,
+    ];
+    for (a, b) in h.hessian.iter().zip(&expected) {
+        assert_relative_eq!(a, b, epsilon = 1e-6);
+    }
+    // position and evaluated results restored
+    assert_eq!(dynamics.position(), &[0.1, 0.2, 0.3]);
+    assert_eq!(dynamics.get_energy()?, energy);
+    assert_eq!(dynamics.ncalls(), 7);
+
+    // skip frozen coordinate
+    let h = dynamics.numerical_hessian_masked(1e-4, &[false, false, true])?;
+    assert_eq!(h.ncalls, 4);
+    assert_relative_eq!(h.hessian[4], 6.0, epsilon = 1e-6);
+    assert_eq!(h.hessian[5], 0.0);
+    assert_eq!(h.hessian[7], 0.0);
+    assert_eq!(h.hessian[8], 0.0);
+
+    Ok(())
+}
+// 54aef7b0 ends here
+
+// [[file:../optim.note::08ab5271][08ab5271]]
+#[test]
+fn test_finite_difference_parallel() -> Result<()> {
+    use gosh_optim::{Dynamics, NumericalForces, NumericalHessian, SerialBatch};
+    use vecfx::approx::*;
+
+    // f(x1, x2, x3) = x1^2 + 3 x2^2 + x1 x2 + x2 x3^2
+    let e = |x: &[f64]| {
+        let fx: f64 = x[0].powi(2) + 3.0 * x[1].powi(2) + x[0] * x[1] + x[1] * x[2].powi(2);
+        Ok(fx)
+    };
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -(2.0 * x[0] + x[1]);
+        f[1] = -(6.0 * x[1] + x[0] + x[2].powi(2));
+        f[2] = -(2.0 * x[1] * x[2]);
+        e(x)
+    };
+    let x = [0.1, 0.2, 0.3];
+
+    let mut pot = Dynamics::new(&x, NumericalForces::new(e).displacement(1e-4).parallelism(4));
+    assert_relative_eq!(pot.get_energy()?, 0.168, epsilon = 1e-8);
+    let forces = pot.get_force()?;
+    assert_relative_eq!(forces[0], -0.4, epsilon = 1e-6);
+    assert_relative_eq!(forces[1], -1.39, epsilon = 1e-6);
+    assert_relative_eq!(forces[2], -0.12, epsilon = 1e-6);
+
+    // the same as serial results
+    let serial = Dynamics::new(&x, f).numerical_hessian(1e-4)?;
+    let parallel = NumericalHessian::parallel(&mut vec![f; 3], &x, 1e-4, None)?;
+    assert_eq!(serial.ncalls, parallel.ncalls);
+    for (a, b) in serial.hessian.iter().zip(&parallel.hessian) {
+        assert_relative_eq!(a, b, epsilon = 1e-10);
+    }
+    let batch = NumericalHessian::batch(&mut SerialBatch(f), &x, 1e-4, None)?;
+    assert_eq!(parallel.hessian, batch.hessian);
+    let h = NumericalHessian::parallel(&mut [f], &x, 1e-4, Some(&[false, false, true]))?;
+    assert_eq!(h.ncalls, 4);
+    assert_eq!(h.hessian[8], 0.0);
+
+    Ok(())
+}
+// 08ab5271 ends here
@@ -0,0 +1,28 @@
+// [[file:../optim.note::2236c813][2236c813]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_minima_hopping() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{MinimaHopping, Optimizer};
+    use vecfx::approx::*;
+
+    // LJ3 has only one minimum, which can not be escaped at low temperature
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let mut lj = LennardJones::default();
+    let hopping = MinimaHopping::new(Optimizer::new(0.01, 500))
+        .temperature(100.0)
+        .mdmin(2, 200)
+        .seed(1);
+    let hopped = hopping.run(&mol, &mut lj, 3)?;
+    assert_eq!(hopped.nescapes, 3);
+    assert_eq!(hopped.minima.len(), 1);
+    assert_eq!(hopped.naccepted, 0);
+    assert_relative_eq!(hopped.best().energy, -3.0, epsilon = 1e-3);
+    assert!(hopped.temperature > 100.0);
+
+    Ok(())
+}
+// 2236c813 ends here
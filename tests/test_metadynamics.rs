@@ -0,0 +1,51 @@
+// [[file:../optim.note::d7d9b18d][d7d9b18d]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::{Dynamics, HillStore, Metadynamics};
+use vecfx::approx::*;
+
+#[test]
+fn test_metadynamics_walkers() -> Result<()> {
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        Ok(x[0] * x[0])
+    };
+    // CV as the coordinate itself
+    let cv = |x: &[f64]| (x[0], vec![1.0]);
+    let (height, width) = (0.5, 0.2);
+
+    let store = HillStore::default();
+    let walker = |i| Metadynamics::new(f, height).cv(cv, width).stride(1).walker(i, &store);
+    let mut walker0 = Dynamics::new(&[0.0], walker(0));
+    let mut walker1 = Dynamics::new(&[0.1], walker(1));
+
+    // no bias before any hill
+    assert_eq!(walker0.get_energy()?, 0.0);
+    assert_eq!(walker0.get_extra()?.bias_energy, 0.0);
+    assert_eq!(store.len(), 1);
+
+    // the hill from walker 0 biases walker 1
+    let e = height * (-0.01 / (2.0 * width * width)).exp();
+    let extra = walker1.get_extra()?;
+    assert_relative_eq!(extra.bias_energy, e, epsilon = 1e-12);
+    assert_relative_eq!(extra.physical_energy, 0.01, epsilon = 1e-12);
+    assert_eq!(extra.cv, vec![0.1]);
+    let force = walker1.get_force()?[0];
+    assert_relative_eq!(force, -0.2 + e * 0.1 / (width * width), epsilon = 1e-12);
+
+    let hills = store.hills();
+    assert_eq!(hills.len(), 2);
+    assert_eq!(hills[1].walker, 1);
+    assert_eq!(hills[1].center, vec![0.1]);
+
+    let metad = walker(2);
+    assert_relative_eq!(
+        metad.free_energy(&[0.0]),
+        -height * (1.0 + (-0.01 / (2.0 * width * width)).exp()),
+        epsilon = 1e-12
+    );
+
+    Ok(())
+}
+// d7d9b18d ends here
@@ -0,0 +1,64 @@
+// [[file:../optim.note::2656f331][2656f331]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_minima_db() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_optim::MinimaDb;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let positions = mol.positions().collect_vec();
+    let mut db = MinimaDb::default();
+    assert_eq!(db.insert(-3.0, &mol), (0, true));
+
+    // rotated by 90 degree around z axis, translated and permuted
+    let mut copy = mol.clone();
+    let moved = [2, 0, 1].map(|i| {
+        let [x, y, z] = positions[i];
+        [-y + 1.0, x, z]
+    });
+    copy.update_positions(moved);
+    assert_eq!(db.insert(-3.0, &copy), (0, false));
+    assert_eq!(db.minima()[0].nvisits, 2);
+
+    // same geometry with a different element
+    let mut mixed = mol.clone();
+    mixed.get_atom_mut(1).unwrap().set_symbol("Ne");
+    assert_eq!(db.find(-3.0, &mixed), None);
+
+    // a different structure
+    let mut other = mol.clone();
+    let stretched = positions.iter().map(|p| p.map(|x| x * 1.5)).collect_vec();
+    other.update_positions(stretched);
+    assert_eq!(db.find(-3.0, &other), None);
+    assert_eq!(db.insert(-1.0, &other), (1, true));
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.sorted()[0].energy, -3.0);
+
+    Ok(())
+}
+// 2656f331 ends here
+
+// [[file:../optim.note::d7522cd2][d7522cd2]]
+#[test]
+fn test_kick_search() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{KickSearch, Optimizer};
+    use vecfx::approx::*;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let mut lj = LennardJones::default();
+    let kicked = KickSearch::new(Optimizer::new(0.01, 500), 0.1)
+        .seed(1)
+        .run(&mol, &mut lj, 3)?;
+    assert_relative_eq!(kicked.reference().energy, -3.0, epsilon = 1e-3);
+    assert_eq!(kicked.nfailed, 0);
+    assert!(kicked.is_stable());
+    assert_eq!(kicked.reference().nvisits, 4);
+    assert!(kicked.lower().is_empty());
+
+    Ok(())
+}
+// d7522cd2 ends here
@@ -0,0 +1,270 @@
+// [[file:../optim.note::6b28066e][6b28066e]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use vecfx::approx::*;
+
+#[test]
+fn test_climbing_image_neb() -> Result<()> {
+    // the path between two minima of Müller–Brown potential, passing
+    // through the lower saddle point
+    let [_, b, c] = MullerBrown::minima();
+    let mut path = Path::interpolate(&c, &b, 9);
+    assert_eq!(path.nimages(), 9);
+
+    let neb = NudgedElasticBand::default()
+        .spring(10.0)
+        .max_step(0.01)
+        .climbing_after(20.0);
+    let optimized = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 3000)?;
+    assert!(optimized.fmax < 0.5);
+    let ci = optimized.climbing_image.expect("no climbing image");
+    assert_eq!(ci, optimized.highest_image().0);
+
+    let [x, y] = MullerBrown::saddles()[1];
+    let saddle = &path.images()[ci];
+    assert_relative_eq!(saddle[0], x, epsilon = 1e-2);
+    assert_relative_eq!(saddle[1], y, epsilon = 1e-2);
+    // end points are fixed
+    assert_eq!(path.images()[0], c);
+    assert_eq!(path.images()[8], b);
+
+    Ok(())
+}
+// 6b28066e ends here
+
+// [[file:../optim.note::c755fb40][c755fb40]]
+#[test]
+fn test_neb_variable_spring() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let mut path = Path::interpolate(&c, &b, 7);
+    let neb = NudgedElasticBand::default()
+        .variable_spring(5.0, 50.0)
+        .max_step(0.01)
+        .climbing_after(20.0);
+    let optimized = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 3000)?;
+    assert!(optimized.fmax < 0.5);
+
+    let ci = optimized.climbing_image.expect("no climbing image");
+    let [x, y] = MullerBrown::saddles()[1];
+    assert_relative_eq!(path.images()[ci][0], x, epsilon = 1e-2);
+    assert_relative_eq!(path.images()[ci][1], y, epsilon = 1e-2);
+
+    Ok(())
+}
+// c755fb40 ends here
+
+// [[file:../optim.note::2962e412][2962e412]]
+#[test]
+fn test_growing_string() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let mut path = Path::new(vec![c.to_vec(), b.to_vec()]);
+    let neb = NudgedElasticBand::default()
+        .spring(10.0)
+        .max_step(0.01)
+        .climbing_after(20.0);
+    let optimized = neb.grow_path(&mut path, 7, &mut MullerBrown, 0.5, 3000)?;
+    assert_eq!(path.nimages(), 7);
+    assert!(optimized.fmax < 0.5);
+
+    let ci = optimized.climbing_image.expect("no climbing image");
+    let [x, y] = MullerBrown::saddles()[1];
+    assert_relative_eq!(path.images()[ci][0], x, epsilon = 1e-2);
+    assert_relative_eq!(path.images()[ci][1], y, epsilon = 1e-2);
+
+    Ok(())
+}
+// 2962e412 ends here
+
+// [[file:../optim.note::7dd1f576][7dd1f576]]
+#[test]
+fn test_idpp_interpolation() -> Result<()> {
+    // rotate a diatomic molecule by 90 degrees
+    let reactant = [0.0, 0.0, 0.0, 1.4, 0.0, 0.0];
+    let product = [0.0, 0.0, 0.0, 0.0, 1.4, 0.0];
+    let bond = |x: &[f64]| (x[3] - x[0]).hypot(x[4] - x[1]);
+
+    let linear = Path::interpolate(&reactant, &product, 5);
+    let path = Path::interpolate_idpp(&reactant, &product, 5)?;
+    assert_eq!(path.nimages(), 5);
+    assert_eq!(path.images()[0], reactant);
+    assert_eq!(path.images()[4], product);
+    // linear interpolation shortens the bond in the middle
+    assert!(bond(&linear.images()[2]) < 1.0);
+    assert!(bond(&path.images()[2]) > 1.2);
+
+    Ok(())
+}
+// 7dd1f576 ends here
+
+// [[file:../optim.note::f04b0eb5][f04b0eb5]]
+#[test]
+fn test_neb_parallel() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let neb = NudgedElasticBand::default().spring(10.0).max_step(0.01).climbing();
+
+    let mut path = Path::interpolate(&c, &b, 7);
+    let serial = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 3000)?;
+    let mut path_parallel = Path::interpolate(&c, &b, 7);
+    let parallel = neb.optimize_path_parallel(&mut path_parallel, &mut [MullerBrown, MullerBrown], 0.5, 3000)?;
+    assert_eq!(serial.niter, parallel.niter);
+    assert_eq!(serial.ncalls, parallel.ncalls);
+    assert_eq!(serial.energies, parallel.energies);
+    assert_eq!(path.images(), path_parallel.images());
+
+    Ok(())
+}
+// f04b0eb5 ends here
+
+// [[file:../optim.note::567d0c15][567d0c15]]
+#[test]
+fn test_neb_checkpoint() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let file = std::env::temp_dir().join("gosh-optim-test-neb.json");
+    let neb = NudgedElasticBand::default()
+        .spring(10.0)
+        .max_step(0.01)
+        .climbing()
+        .checkpoint(&file);
+
+    // stop early as interrupted
+    let mut path = Path::interpolate(&c, &b, 7);
+    let optimized = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 20)?;
+    assert!(optimized.fmax > 0.5);
+
+    // checkpoint is version tagged
+    let value: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+    assert_eq!(value["kind"], "neb-checkpoint");
+    // untagged checkpoint written by older versions can still be loaded
+    std::fs::write(&file, value["state"].to_string())?;
+
+    let ckpt = NebCheckpoint::load(&file)?;
+    assert_eq!(ckpt.niter(), optimized.niter);
+    assert_eq!(ckpt.path().images(), path.images());
+    let (path, resumed) = ckpt.resume(&mut MullerBrown, 0.5, 3000)?;
+    assert!(resumed.niter > optimized.niter);
+    assert!(resumed.ncalls > optimized.ncalls);
+    assert!(resumed.fmax < 0.5);
+    let ci = resumed.climbing_image.expect("no climbing image");
+    let [x, y] = MullerBrown::saddles()[1];
+    assert_relative_eq!(path.images()[ci][0], x, epsilon = 1e-2);
+    assert_relative_eq!(path.images()[ci][1], y, epsilon = 1e-2);
+    // checkpoint updated by resumed run
+    assert_eq!(NebCheckpoint::load(&file)?.niter(), resumed.niter);
+    std::fs::remove_file(&file)?;
+
+    Ok(())
+}
+// 567d0c15 ends here
+
+// [[file:../optim.note::13d61290][13d61290]]
+#[test]
+fn test_band_writer() -> Result<()> {
+    use gchemol::{Atom, Molecule};
+
+    // rotate a diatomic molecule, with harmonic bond
+    let reactant = [0.0, 0.0, 0.0, 1.4, 0.0, 0.0];
+    let product = [0.0, 0.0, 0.0, 0.0, 1.4, 0.0];
+    let mol = Molecule::from_atoms([Atom::new("H", [0.0; 3]), Atom::new("H", [1.4, 0.0, 0.0])]);
+    let mut bond = |x: &[f64], f: &mut [f64]| -> Result<f64> {
+        let r = [x[3] - x[0], x[4] - x[1], x[5] - x[2]];
+        let d = r.iter().map(|v| v * v).sum::<f64>().sqrt();
+        for k in 0..3 {
+            f[k] = 2.0 * (d - 1.4) * r[k] / d;
+            f[3 + k] = -f[k];
+        }
+        Ok((d - 1.4).powi(2))
+    };
+
+    let dir = std::env::temp_dir().join("gosh-optim-test-band");
+    for layout in [BandLayout::PerIteration, BandLayout::PerImage] {
+        let _ = std::fs::remove_dir_all(&dir);
+        let writer = BandWriter::new(&dir, &mol, layout)?;
+        let neb = NudgedElasticBand::default().band_writer(writer);
+        let mut path = Path::interpolate(&reactant, &product, 5);
+        let optimized = neb.optimize_path(&mut path, &mut bond, 1e-3, 5)?;
+
+        let energies = std::fs::read_to_string(dir.join("energies.dat"))?;
+        assert_eq!(energies.lines().count(), optimized.niter);
+        let xyz = match layout {
+            BandLayout::PerIteration => dir.join(format!("band-{:05}.xyz", optimized.niter)),
+            BandLayout::PerImage => dir.join("image-02.xyz"),
+        };
+        let frames = std::fs::read_to_string(xyz)?;
+        let nframes = match layout {
+            BandLayout::PerIteration => 5,
+            BandLayout::PerImage => optimized.niter,
+        };
+        assert_eq!(frames.lines().count(), nframes * 4);
+        assert!(frames.lines().nth(1).unwrap().contains("energy="));
+    }
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+// 13d61290 ends here
+
+// [[file:../optim.note::b448173b][b448173b]]
+#[test]
+fn test_neb_batch() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let neb = NudgedElasticBand::default().spring(10.0).max_step(0.01).climbing();
+
+    let mut path = Path::interpolate(&c, &b, 7);
+    let serial = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 3000)?;
+    let mut path_batch = Path::interpolate(&c, &b, 7);
+    let batch = neb.optimize_path_batch(&mut path_batch, &mut SerialBatch(MullerBrown), 0.5, 3000)?;
+    assert_eq!(serial.ncalls, batch.ncalls);
+    assert_eq!(serial.energies, batch.energies);
+    assert_eq!(path.images(), path_batch.images());
+
+    // batch closure recording sizes of batches
+    let mut sizes = vec![];
+    let mut potential = |positions: &[&[f64]], forces: &mut [Vec<f64>]| {
+        sizes.push(positions.len());
+        let mut out = PotentialOutput {
+            energy: 0.0,
+            force: vec![0.0; 2],
+            stress: None,
+        };
+        let mut energies = vec![];
+        for (x, f) in positions.iter().zip(forces) {
+            MullerBrown.evaluate(x, &mut out)?;
+            f.copy_from_slice(&out.force);
+            energies.push(out.energy);
+        }
+        Ok(energies)
+    };
+    let mut path_batch = Path::interpolate(&c, &b, 7);
+    let batch = neb.optimize_path_batch(&mut path_batch, &mut potential, 0.5, 3000)?;
+    assert_eq!(serial.energies, batch.energies);
+    // the end points first, then all intermediate images
+    assert_eq!(sizes[0], 2);
+    assert!(sizes[1..].iter().all(|&n| n == path.nimages() - 2));
+    assert_eq!(sizes.iter().sum::<usize>(), batch.ncalls);
+
+    Ok(())
+}
+// b448173b ends here
+
+// [[file:../optim.note::6d217540][6d217540]]
+#[test]
+fn test_neb_parallel_dynamics() -> Result<()> {
+    let [_, b, c] = MullerBrown::minima();
+    let neb = NudgedElasticBand::default().spring(10.0).max_step(0.01).climbing();
+
+    let mut path = Path::interpolate(&c, &b, 7);
+    let serial = neb.optimize_path(&mut path, &mut MullerBrown, 0.5, 3000)?;
+    // thread-safe Dynamics driven from worker threads
+    let mut workers = (0..2).map(|_| DynamicsSync::new_sync(&c, MullerBrown)).collect_vec();
+    let mut path_parallel = Path::interpolate(&c, &b, 7);
+    let parallel = neb.optimize_path_parallel(&mut path_parallel, &mut workers, 0.5, 3000)?;
+    assert_eq!(serial.energies, parallel.energies);
+    assert_eq!(path.images(), path_parallel.images());
+    let ncalls: usize = workers.iter().map(|w| w.ncalls()).sum();
+    assert_eq!(ncalls, parallel.ncalls);
+
+    Ok(())
+}
+// 6d217540 ends here
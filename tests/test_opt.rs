@@ -14,10 +14,17 @@ fn test_opt() -> Result<()> {
     let mut lj = LennardJones::default();
     lj.derivative_order = 1;
 
-    let _ = Optimizer::default().optimize_geometry(&mut mol, &mut lj)?;
+    let optimized = Optimizer::default().optimize_geometry(&mut mol, &mut lj)?;
+    let report = &optimized.report;
+    assert_eq!(report.forces.worst_atoms.len(), 5);
+    assert!((report.forces.max - optimized.fmax).abs() < 1e-8);
+    assert!(report.forces.rms <= report.forces.max);
+    let evaluations = &report.evaluations;
+    assert!(evaluations.ncalls >= optimized.niter);
+    assert!(evaluations.min <= evaluations.mean() && evaluations.mean() <= evaluations.max);
 
     // iterator interface
-    let steps = optimize_geometry_iter(&mut mol, &mut lj);
+    let steps = optimize_geometry_iter(&mut mol, &mut lj)?;
     for p in steps.take(10) {
         dbg!(p.fmax, p.ncalls);
     }
@@ -25,3 +32,553 @@ fn test_opt() -> Result<()> {
     Ok(())
 }
 // f15831bf ends here
+
+// [[file:../optim.note::8250a6f2][8250a6f2]]
+#[test]
+fn test_opt_constrained() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Constraint, Optimizer};
+    use vecfx::approx::*;
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    let _ = Optimizer::new(0.1, 50)
+        .constrain(Constraint::Bond(0, 1, 1.2))
+        .optimize_geometry(&mut mol, &mut lj)?;
+    let positions = mol.positions().collect_vec();
+    let d = (0..3)
+        .map(|k| (positions[0][k] - positions[1][k]).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    assert_relative_eq!(d, 1.2, epsilon = 1e-4);
+
+    Ok(())
+}
+// 8250a6f2 ends here
+
+// [[file:../optim.note::a6b92536][a6b92536]]
+#[test]
+fn test_opt_constrained_angles() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Constraint, Coordinate, Optimizer};
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    // absolute deviation in radians of coordinate in `mol` from `target` in degree
+    let deviation = |mol: &Molecule, coord: Coordinate, target: f64| {
+        let positions = mol.positions().collect_vec();
+        coord.difference(coord.value(&positions), target.to_radians()).abs()
+    };
+
+    let mut mol = Molecule::from_file(filename)?;
+    let _ = Optimizer::new(0.1, 50)
+        .constrain(Constraint::Angle(0, 1, 2, 100.0))
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert!(deviation(&mol, Coordinate::Angle(0, 1, 2), 100.0) < 1e-4);
+
+    let mut mol = Molecule::from_file(filename)?;
+    let _ = Optimizer::new(0.1, 50)
+        .constrain(Constraint::Dihedral(0, 1, 2, 3, 60.0))
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert!(deviation(&mol, Coordinate::Dihedral(0, 1, 2, 3), 60.0) < 1e-4);
+
+    // trans dihedral close to the wrap-around at ±180°
+    for target in [180.0, -180.0, 179.5, -179.5] {
+        let mut mol = Molecule::from_file(filename)?;
+        let _ = Optimizer::new(0.1, 50)
+            .constrain(Constraint::Dihedral(0, 1, 2, 3, target))
+            .optimize_geometry(&mut mol, &mut lj)?;
+        assert!(
+            deviation(&mol, Coordinate::Dihedral(0, 1, 2, 3), target) < 1e-4,
+            "{target}"
+        );
+    }
+
+    Ok(())
+}
+// a6b92536 ends here
+
+// [[file:../optim.note::47a263bb][47a263bb]]
+#[test]
+fn test_opt_redundant() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{CoordinateSystem, Optimizer, RedundantInternals};
+
+    let filename = "tests/files/LennardJones/LJ3.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let internals = RedundantInternals::from_molecule(&mol);
+    // two bonds and one angle for a connected trimer at least
+    assert!(internals.coords().len() >= 3);
+
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let optimized = Optimizer::new(0.01, 100)
+        .coordinate_system(CoordinateSystem::Redundant)
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert!(optimized.fmax < 0.01);
+
+    Ok(())
+}
+// 47a263bb ends here
+
+// [[file:../optim.note::a7f81c06][a7f81c06]]
+#[test]
+fn test_opt_delocalized() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{CoordinateSystem, DelocalizedInternals, Optimizer, RedundantInternals};
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let positions = mol.positions().collect_vec().concat();
+    let internals = RedundantInternals::from_molecule(&mol);
+    let dlc = DelocalizedInternals::new(internals, &positions)?;
+    // 3N - 6 degrees of freedom at most
+    assert!(dlc.dim() <= 3 * mol.natoms() - 6);
+
+    // freezing atoms are never displaced
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let p1 = mol.positions().next().unwrap();
+    let _ = Optimizer::new(0.1, 20)
+        .coordinate_system(CoordinateSystem::Delocalized)
+        .freeze_atoms(&[1])
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert_eq!(mol.positions().next().unwrap(), p1);
+
+    Ok(())
+}
+// a7f81c06 ends here
+
+// [[file:../optim.note::57c85198][57c85198]]
+#[test]
+fn test_opt_rigid() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::Optimizer;
+    use vecfx::approx::*;
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    let distances = |mol: &Molecule| {
+        let p = mol.positions().take(3).collect_vec();
+        [(0, 1), (0, 2), (1, 2)].map(|(i, j)| (0..3).map(|k| (p[i][k] - p[j][k]).powi(2)).sum::<f64>().sqrt())
+    };
+    let d0 = distances(&mol);
+    let p0 = mol.positions().next().unwrap();
+    let _ = Optimizer::new(0.1, 50)
+        .rigid_fragment(&[1, 2, 3])
+        .optimize_geometry(&mut mol, &mut lj)?;
+    let d1 = distances(&mol);
+    for k in 0..3 {
+        assert_relative_eq!(d0[k], d1[k], epsilon = 1e-8);
+    }
+    // the fragment is still free to move
+    assert_ne!(mol.positions().next().unwrap(), p0);
+
+    Ok(())
+}
+// 57c85198 ends here
+
+// [[file:../optim.note::67b345ef][67b345ef]]
+#[test]
+fn test_opt_variable_cell() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Lattice, Molecule};
+    use gosh_optim::{OptimizeMolecule, Optimizer, Output};
+    use vecfx::approx::*;
+
+    // a toy model with energy depending only on volume: E = k/2 (V - V0)^2
+    struct VolumeModel;
+    impl OptimizeMolecule<()> for VolumeModel {
+        fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+            let (k, v0) = (0.01, 1000.0);
+            let v = mol.lattice.as_ref().unwrap().volume();
+            out.energy = Some(0.5 * k * (v - v0).powi(2));
+            out.forces = Some(vec![[0.0; 3]; mol.natoms()]);
+            let s = k * (v - v0);
+            out.stress = Some([s, s, s, 0.0, 0.0, 0.0]);
+            Ok(())
+        }
+    }
+
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    mol.set_lattice(Lattice::new([[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]]));
+    let mut model = VolumeModel;
+    let steps = Optimizer::new(1e-4, 200)
+        .variable_cell(0.0)
+        .optimize_geometry_iter(&mut mol, &mut model)?;
+    let last = steps.take(200).take_while(|p| p.fmax > 1e-4).last();
+    assert!(last.is_some());
+    let v = mol.lattice.as_ref().unwrap().volume();
+    assert_relative_eq!(v, 1000.0, epsilon = 1.0);
+
+    // variable cell requires a periodic molecule
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let opt = Optimizer::new(1e-4, 200).variable_cell(0.0);
+    assert!(opt.optimize_geometry_iter(&mut mol, &mut model).is_err());
+    assert!(opt
+        .optimize_geometry(&mut mol, &mut gosh_model::LennardJones::default())
+        .is_err());
+
+    Ok(())
+}
+// 67b345ef ends here
+
+// [[file:../optim.note::e4552721][e4552721]]
+#[test]
+fn test_opt_variable_cell_constrained() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Lattice, Molecule};
+    use gosh_optim::{CellConstraint, OptimizeMolecule, Optimizer, Output};
+    use vecfx::approx::*;
+
+    // a toy model with energy depending only on volume: E = k/2 (V - V0)^2
+    struct VolumeModel;
+    impl OptimizeMolecule<()> for VolumeModel {
+        fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+            let (k, v0) = (0.01, 1000.0);
+            let v = mol.lattice.as_ref().unwrap().volume();
+            out.energy = Some(0.5 * k * (v - v0).powi(2));
+            out.forces = Some(vec![[0.0; 3]; mol.natoms()]);
+            let s = k * (v - v0);
+            out.stress = Some([s, s, s, 0.0, 0.0, 0.0]);
+            Ok(())
+        }
+    }
+
+    // relax only the c-axis
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    mol.set_lattice(Lattice::new([[9.0, 0.0, 0.0], [0.0, 9.0, 0.0], [0.0, 0.0, 9.0]]));
+    let mut model = VolumeModel;
+    let steps = Optimizer::new(1e-4, 200)
+        .variable_cell(0.0)
+        .constrain_cell(CellConstraint::Axes([false, false, true]))
+        .optimize_geometry_iter(&mut mol, &mut model)?;
+    let last = steps.take(200).take_while(|p| p.fmax > 1e-4).last();
+    assert!(last.is_some());
+    let [a, b, c] = mol.lattice.as_ref().unwrap().lengths();
+    assert_relative_eq!(a, 9.0, epsilon = 1e-6);
+    assert_relative_eq!(b, 9.0, epsilon = 1e-6);
+    assert_relative_eq!(c, 1000.0 / 81.0, epsilon = 1e-2);
+
+    Ok(())
+}
+// e4552721 ends here
+
+// [[file:../optim.note::c645414e][c645414e]]
+#[test]
+fn test_opt_fractional() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Atom, Lattice, Molecule};
+    use gosh_optim::{OptimizeMolecule, Optimizer, Output};
+
+    // a periodic toy model tethering atoms to the origin in minimum image
+    struct TetherModel;
+    impl OptimizeMolecule<()> for TetherModel {
+        fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+            let (k, l) = (1.0, 10.0);
+            let forces = mol
+                .positions()
+                .map(|p| p.map(|x| -k * (x - l * (x / l).round())))
+                .collect_vec();
+            let energy: f64 = forces.iter().flatten().map(|f| 0.5 * f * f / k).sum();
+            out.energy = Some(energy);
+            out.forces = Some(forces);
+            Ok(())
+        }
+    }
+
+    // atoms across periodic boundaries
+    let atoms = [[-0.3, 0.2, 0.1], [9.8, -0.4, 0.3], [0.5, 9.6, -0.2]].map(|p| Atom::new("Ar", p));
+    let mut mol = Molecule::from_atoms(atoms);
+    mol.set_lattice(Lattice::new([[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]]));
+    let mut model = TetherModel;
+    let steps = Optimizer::new(1e-4, 200)
+        .fractional_coords()
+        .optimize_geometry_iter(&mut mol, &mut model)?;
+    let last = steps.take(200).take_while(|p| p.fmax > 1e-4).last();
+    assert!(last.is_some());
+    // all atoms wrapped into the cell
+    for x in mol.positions().flatten() {
+        assert!((0.0..10.0).contains(&x), "atom not wrapped: {x}");
+    }
+
+    // fractional coords require a periodic molecule
+    let mut mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let opt = Optimizer::new(1e-4, 200).fractional_coords();
+    assert!(opt.optimize_geometry_iter(&mut mol, &mut model).is_err());
+
+    Ok(())
+}
+// c645414e ends here
+
+// [[file:../optim.note::ec834c4e][ec834c4e]]
+#[test]
+fn test_opt_staged() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Optimizer, StagedOptimizer};
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    // relax outer atoms with the core frozen, then all atoms
+    let core = (1..=20).collect_vec();
+    let staged = StagedOptimizer::default()
+        .stage("outer", Optimizer::new(0.1, 100).freeze_atoms(&core))
+        .stage("full", Optimizer::new(0.05, 200));
+    let optimized = staged.optimize_geometry(&mut mol, &mut lj)?;
+    assert_eq!(optimized.stages.len(), 2);
+    assert_eq!(optimized.stages[0].name, "outer");
+    assert!(optimized.converged());
+    assert!(optimized.last().fmax < 0.05);
+    println!("{optimized}");
+
+    Ok(())
+}
+// ec834c4e ends here
+
+// [[file:../optim.note::41110b5f][41110b5f]]
+#[test]
+fn test_opt_mass_weighted() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::Optimizer;
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    let optimized = Optimizer::new(0.05, 500)
+        .mass_weighted()
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert!(optimized.fmax < 0.05);
+
+    Ok(())
+}
+// 41110b5f ends here
+
+// [[file:../optim.note::cfd198c1][cfd198c1]]
+#[test]
+fn test_opt_multi_fidelity() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Atom, Molecule};
+    use gosh_optim::{MultiFidelity, OptimizeMolecule, Optimizer, Output, Switchover};
+    use vecfx::approx::*;
+
+    // a harmonic bond between two atoms with equilibrium length `r0`, and
+    // constant energy offset
+    struct BondModel(f64, f64);
+    impl OptimizeMolecule<()> for BondModel {
+        fn evaluate(&mut self, mol: &Molecule, out: &mut Output) -> Result<()> {
+            let p = mol.positions().collect_vec();
+            let d = [p[1][0] - p[0][0], p[1][1] - p[0][1], p[1][2] - p[0][2]];
+            let r = d.iter().map(|x| x * x).sum::<f64>().sqrt();
+            let g = (r - self.0) / r;
+            out.energy = Some(0.5 * (r - self.0).powi(2) + self.1);
+            out.forces = Some(vec![d.map(|x| g * x), d.map(|x| -g * x)]);
+            Ok(())
+        }
+    }
+    let bond_length = |mol: &Molecule| {
+        let p = mol.positions().collect_vec();
+        (0..3).map(|i| (p[1][i] - p[0][i]).powi(2)).sum::<f64>().sqrt()
+    };
+
+    let mut mol = Molecule::from_atoms([Atom::new("H", [0.0; 3]), Atom::new("H", [1.4, 0.0, 0.0])]);
+    let mut model = MultiFidelity::default()
+        .model("cheap", BondModel(0.8, 0.0), Switchover::Fmax(0.05))
+        .model("accurate", BondModel(0.74, 0.0), Switchover::Fmax(0.0));
+    assert_eq!(model.current(), "cheap");
+    let last = Optimizer::new(1e-4, 200)
+        .optimize_geometry_iter(&mut mol, &mut model)?
+        .take(200)
+        .take_while(|p| p.fmax > 1e-4)
+        .last();
+    assert!(last.is_some());
+    assert!(model.is_final());
+    assert_eq!(model.current(), "accurate");
+    let stats = model.stats().map(|(_, s)| s.ncalls).collect_vec();
+    assert!(stats.iter().all(|&n| n > 0), "{stats:?}");
+    assert_relative_eq!(bond_length(&mol), 0.74, epsilon = 1e-3);
+
+    model.reset();
+    assert_eq!(model.current(), "cheap");
+
+    // L-BFGS history is restarted after switching to a model with large
+    // energy offset
+    let mut mol = Molecule::from_atoms([Atom::new("H", [0.0; 3]), Atom::new("H", [1.4, 0.0, 0.0])]);
+    let mut model = MultiFidelity::default()
+        .model("cheap", BondModel(0.8, 0.0), Switchover::Fmax(0.05))
+        .model("accurate", BondModel(0.74, -1e4), Switchover::Fmax(0.0));
+    let optimized = Optimizer::new(1e-4, 200).optimize_geometry_iter(&mut mol, &mut model)?;
+    let last = optimized.take(200).find(|p| p.fmax < 1e-4).expect("not converged");
+    assert!(model.is_final());
+    assert_relative_eq!(last.energy, -1e4, epsilon = 1e-6);
+    assert_relative_eq!(bond_length(&mol), 0.74, epsilon = 1e-3);
+
+    Ok(())
+}
+// cfd198c1 ends here
+
+// [[file:../optim.note::40bf6fb0][40bf6fb0]]
+#[test]
+fn test_opt_units() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Optimizer, UnitSystem, Units};
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    // fmax given in kcal/mol/Å
+    let units = Units::new(UnitSystem::EvAngstrom).internal(UnitSystem::KcalMolAngstrom);
+    let mut mol = Molecule::from_file(filename)?;
+    let optimized = Optimizer::new(1.0, 500)
+        .units(units)
+        .optimize_geometry(&mut mol, &mut lj)?;
+    // reported in eV/Å
+    assert!(optimized.fmax < 0.0434);
+
+    // declaring units again does not convert fmax twice
+    let mut mol = Molecule::from_file(filename)?;
+    let optimized_again = Optimizer::new(1.0, 500)
+        .units(units)
+        .units(units)
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert_eq!(optimized_again.niter, optimized.niter);
+
+    Ok(())
+}
+// 40bf6fb0 ends here
+
+// [[file:../optim.note::f8200f09][f8200f09]]
+#[test]
+fn test_opt_freezing() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::Optimizer;
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    // invalid selections are reported as errors
+    let opt = Optimizer::new(0.1, 50).freeze_atoms(&[mol.natoms() + 1]);
+    assert!(opt.optimize_geometry_iter(&mut mol, &mut lj).is_err());
+    let opt = Optimizer::new(0.1, 50).freeze_coords(&[true; 3]);
+    assert!(opt.optimize_geometry_iter(&mut mol, &mut lj).is_err());
+
+    // freeze atom with serial number 2, and z coordinate of the first atom and
+    // x coordinate of the third atom in separate masks, which are merged
+    let mut mask1 = vec![false; 3 * mol.natoms()];
+    mask1[2] = true;
+    let mut mask2 = vec![false; 3 * mol.natoms()];
+    mask2[6] = true;
+    let p0 = mol.positions().collect_vec();
+    let steps = Optimizer::new(0.1, 50)
+        .freeze_atoms(&[2])
+        .freeze_coords(&mask1)
+        .freeze_coords(&mask2)
+        .optimize_geometry_iter(&mut mol, &mut lj)?;
+    let last = steps.take(50).take_while(|p| p.fmax > 0.1).last();
+    assert!(last.is_some());
+
+    let p = mol.positions().collect_vec();
+    assert_eq!(p[1], p0[1]);
+    assert_eq!(p[0][2], p0[0][2]);
+    assert_eq!(p[2][0], p0[2][0]);
+    assert_ne!(p[0], p0[0]);
+    assert_ne!(p[2], p0[2]);
+
+    Ok(())
+}
+// f8200f09 ends here
+
+// [[file:../optim.note::5f123c61][5f123c61]]
+#[test]
+fn test_opt_restart_signature() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_database::{CheckpointDb, DbConnection};
+    use gosh_model::LennardJones;
+    use gosh_optim::Optimizer;
+
+    let filename = "tests/files/LennardJones/LJ3.xyz";
+    let dbfile = std::env::temp_dir().join("gosh-optim-test-restart.db");
+    let _ = std::fs::remove_file(&dbfile);
+    let ckpt = || -> Result<CheckpointDb> {
+        let db = DbConnection::connect(&dbfile.to_string_lossy())?;
+        Ok(CheckpointDb::new(db))
+    };
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    let mut mol = Molecule::from_file(filename)?;
+    Optimizer::new(0.1, 5)
+        .freeze_atoms(&[1])
+        .checkpoint(ckpt()?)
+        .optimize_geometry(&mut mol, &mut lj)?;
+
+    // resumed with the same settings
+    let mut mol = Molecule::from_file(filename)?;
+    Optimizer::new(0.1, 5)
+        .freeze_atoms(&[1])
+        .checkpoint(ckpt()?)
+        .optimize_geometry(&mut mol, &mut lj)?;
+
+    // freezing mask changed
+    let mut mol = Molecule::from_file(filename)?;
+    let err = Optimizer::new(0.1, 5)
+        .freeze_atoms(&[2])
+        .checkpoint(ckpt()?)
+        .optimize_geometry(&mut mol, &mut lj)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("inconsistent restart"), "{err}");
+    assert!(err.contains("freezing coords of atom 1 changed: [true, true, true] => [false, false, false]"));
+    assert!(err.contains("freezing coords of atom 2 changed: [false, false, false] => [true, true, true]"));
+
+    // element changed
+    let mut mol = Molecule::from_file(filename)?;
+    mol.get_atom_mut(1).unwrap().set_symbol("Ne");
+    let err = Optimizer::new(0.1, 5)
+        .freeze_atoms(&[1])
+        .checkpoint(ckpt()?)
+        .optimize_geometry(&mut mol, &mut lj)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("element of atom 1 changed: He => Ne"), "{err}");
+    assert!(!err.contains("freezing coords"), "{err}");
+
+    let _ = std::fs::remove_file(&dbfile);
+
+    Ok(())
+}
+// 5f123c61 ends here
@@ -47,3 +47,906 @@ fn test_dynamics() -> Result<()> {
     Ok(())
 }
 // aba130a2 ends here
+
+// [[file:../optim.note::c83dda69][c83dda69]]
+#[test]
+fn test_optimize_fold() -> Result<()> {
+    use gosh_optim::{optimize_fold, EvaluatePotential};
+
+    // harmonic potential returning squared distance to origin as user data
+    struct Harmonic;
+    impl EvaluatePotential<f64> for Harmonic {
+        fn evaluate(&mut self, position: &[f64], output: &mut PotentialOutput) -> Result<f64> {
+            let r2: f64 = position.iter().map(|x| x * x).sum();
+            for (f, x) in output.force.iter_mut().zip(position) {
+                *f = -2.0 * x;
+            }
+            output.energy = r2;
+            Ok(r2)
+        }
+    }
+
+    let x = [1.0, 0.5, -0.3];
+    let mut pot = Dynamics::new(&x, Harmonic);
+    let folded = optimize_fold(&mut pot, 0.01, 100, (0, 0.0), |(n, sum), r2| (n + 1, sum + r2))?;
+    let (n, sum) = folded.aggregate;
+    assert!(n >= folded.niter);
+    assert!(folded.fmax < 0.01);
+    assert!(sum >= folded.energy);
+
+    Ok(())
+}
+// c83dda69 ends here
+
+// [[file:../optim.note::1082a24e][1082a24e]]
+#[test]
+fn test_dynamics_snapshot() -> Result<()> {
+    let f = |x: &[f64], f: &mut [f64]| {
+        for i in 0..x.len() {
+            f[i] = -2.0 * x[i];
+        }
+        let fx: f64 = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+
+    let mut pot = Dynamics::new(&[1.0, 2.0], f);
+    let shared = pot.share();
+    assert_eq!(shared.latest().energy(), None);
+
+    let _ = pot.get_energy()?;
+    let snapshot = pot.snapshot();
+    assert_eq!(snapshot.energy(), Some(5.0));
+    assert_eq!(snapshot.force(), Some(&[-2.0, -4.0][..]));
+
+    // read the latest state from another thread
+    pot.step_toward(&[1.0, 1.0]);
+    let _ = pot.get_force()?;
+    let reader = shared.clone();
+    let (position, energy) = std::thread::spawn(move || {
+        let latest = reader.latest();
+        (latest.position().to_vec(), latest.energy())
+    })
+    .join()
+    .unwrap();
+    assert_eq!(position, [2.0, 3.0]);
+    assert_eq!(energy, Some(13.0));
+    // old snapshot is not affected
+    assert_eq!(snapshot.position(), &[1.0, 2.0]);
+
+    Ok(())
+}
+// 1082a24e ends here
+
+// [[file:../optim.note::920fdfb4][920fdfb4]]
+#[test]
+fn test_optimize_constrained() -> Result<()> {
+    use gosh_optim::optimize_constrained;
+    use vecfx::approx::*;
+
+    // E = (x - 2)^2 + y^2 on the unit circle, minimum at (1, 0)
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * (x[0] - 2.0);
+        f[1] = -2.0 * x[1];
+        let fx: f64 = (x[0] - 2.0).powi(2) + x[1].powi(2);
+        Ok(fx)
+    };
+    let circle = |x: &[f64], g: &mut [f64]| {
+        g[0] = 2.0 * x[0];
+        g[1] = 2.0 * x[1];
+        x[0].powi(2) + x[1].powi(2) - 1.0
+    };
+
+    let mut pot = Dynamics::new(&[0.5, 0.5], f);
+    let optimized = optimize_constrained(&mut pot, &[&circle], 1e-4, 1000)?;
+    assert!(optimized.violation < 1e-5);
+    let x = pot.position();
+    assert_relative_eq!(x[0], 1.0, epsilon = 1e-3);
+    assert_relative_eq!(x[1], 0.0, epsilon = 1e-3);
+    // ∇E = λ ∇c at (1, 0): -2 = 2 λ
+    assert_relative_eq!(optimized.multipliers[0], -1.0, epsilon = 1e-2);
+
+    Ok(())
+}
+// 920fdfb4 ends here
+
+// [[file:../optim.note::1762c589][1762c589]]
+#[test]
+fn test_optimize_mecp() -> Result<()> {
+    use gosh_optim::optimize_mecp;
+    use vecfx::approx::*;
+
+    // two shifted paraboloids crossing on the plane x = -0.125
+    let f1 = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * (x[0] - 1.0);
+        f[1] = -2.0 * x[1];
+        let fx: f64 = (x[0] - 1.0).powi(2) + x[1].powi(2);
+        Ok(fx)
+    };
+    let f2 = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * (x[0] + 1.0);
+        f[1] = -2.0 * x[1];
+        let fx: f64 = (x[0] + 1.0).powi(2) + x[1].powi(2) + 0.5;
+        Ok(fx)
+    };
+
+    let mut state1 = Dynamics::new(&[0.5, 0.5], f1);
+    let mut state2 = Dynamics::new(&[0.5, 0.5], f2);
+    let crossing = optimize_mecp(&mut state1, &mut state2, 1e-4, 500)?;
+    assert_relative_eq!(crossing.energy1, crossing.energy2, epsilon = 1e-3);
+    assert_relative_eq!(crossing.position[0], -0.125, epsilon = 1e-3);
+    assert_relative_eq!(crossing.position[1], 0.0, epsilon = 1e-3);
+    assert_relative_eq!(crossing.energy1, 1.265625, epsilon = 1e-3);
+
+    Ok(())
+}
+// 1762c589 ends here
+
+// [[file:../optim.note::34e2418d][34e2418d]]
+#[test]
+fn test_numerical_forces() -> Result<()> {
+    use gosh_optim::{optimize, NumericalForces};
+    use vecfx::approx::*;
+
+    // f(x1, x2) = (x1 - 1)^2 + 2 x2^2 + x1 x2, energy only
+    let f = |x: &[f64]| {
+        let fx: f64 = (x[0] - 1.0).powi(2) + 2.0 * x[1].powi(2) + x[0] * x[1];
+        Ok(fx)
+    };
+    let pot = NumericalForces::new(f).displacement(1e-4);
+    let mut dynamics = Dynamics::new(&[0.5, 0.5], pot);
+    let energy = dynamics.get_energy()?;
+    assert_relative_eq!(energy, 1.0, epsilon = 1e-8);
+    let force = dynamics.get_force()?.to_vec();
+    assert_relative_eq!(force[0], 0.5, epsilon = 1e-6);
+    assert_relative_eq!(force[1], -2.5, epsilon = 1e-6);
+
+    // the minimum at (8/7, -2/7)
+    let last = optimize(&mut dynamics).take_while(|p| p.fmax > 1e-5).take(200).last();
+    assert!(last.is_some());
+    let x = dynamics.position();
+    assert_relative_eq!(x[0], 8.0 / 7.0, epsilon = 1e-3);
+    assert_relative_eq!(x[1], -2.0 / 7.0, epsilon = 1e-3);
+
+    Ok(())
+}
+// 34e2418d ends here
+
+// [[file:../optim.note::52c09a13][52c09a13]]
+#[test]
+fn test_dynamics_cache() -> Result<()> {
+    // f(x1, x2) = x1^2 + x2^2
+    let f = |x: &[f64], f: &mut [f64]| {
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+
+    let mut pot = Dynamics::new(&[0.0, 0.0], f);
+    pot.set_cache(2);
+    let e0 = pot.get_energy()?;
+    pot.set_position(&[1.0, 0.0]);
+    let e1 = pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 2);
+
+    // revisit nearly identical points
+    pot.set_position(&[1e-9, 0.0]);
+    assert_eq!(pot.get_energy()?, e0);
+    pot.set_position(&[1.0, 0.0]);
+    assert_eq!(pot.get_energy()?, e1);
+    assert_eq!(pot.get_force()?, &[-2.0, 0.0]);
+    assert_eq!(pot.ncalls(), 2);
+    assert_eq!(pot.cache_hits(), 2);
+
+    // the least recently used point is evicted
+    pot.set_position(&[2.0, 0.0]);
+    pot.get_energy()?;
+    pot.set_position(&[0.0, 0.0]);
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 4);
+    pot.set_position(&[1.0, 0.0]);
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 5);
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "zero eps")]
+fn test_dynamics_cache_zero_eps() {
+    let f = |x: &[f64], _f: &mut [f64]| Ok(x[0]);
+    let mut pot = Dynamics::new(&[0.0], f);
+    pot.set_cache(2);
+    pot.set_epsilon(0.0);
+}
+// 52c09a13 ends here
+
+// [[file:../optim.note::2a1ff557][2a1ff557]]
+#[test]
+fn test_dynamics_rollback() -> Result<()> {
+    // f(x1, x2) = x1^2 + x2^2
+    let f = |x: &[f64], f: &mut [f64]| {
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+
+    let mut pot = Dynamics::new(&[0.0, 0.0], f);
+    pot.set_history(3);
+    for i in 0..4 {
+        pot.set_position(&[i as f64, 1.0]);
+        pot.get_energy()?;
+    }
+    let energies = pot.history().filter_map(|s| s.energy()).collect_vec();
+    assert_eq!(energies, [2.0, 5.0, 10.0]);
+
+    // reject the last step
+    pot.rollback(1)?;
+    assert_eq!(pot.position(), &[2.0, 1.0]);
+    assert_eq!(pot.get_energy()?, 5.0);
+    assert_eq!(pot.get_force()?, &[-4.0, -2.0]);
+    assert_eq!(pot.ncalls(), 4);
+    assert_eq!(pot.history().count(), 2);
+    assert!(pot.rollback(2).is_err());
+
+    Ok(())
+}
+// 2a1ff557 ends here
+
+// [[file:../optim.note::56b401eb][56b401eb]]
+#[test]
+fn test_composite_potential() -> Result<()> {
+    use gosh_optim::{optimize, CompositePotential};
+    use vecfx::approx::*;
+
+    // a paraboloid with a linear correction
+    let f1 = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        f[1] = -2.0 * x[1];
+        let fx: f64 = x[0].powi(2) + x[1].powi(2);
+        Ok(fx)
+    };
+    let f2 = |x: &[f64], f: &mut [f64]| {
+        f[0] = -1.0;
+        f[1] = 0.0;
+        Ok(x[0])
+    };
+    let potential = CompositePotential::default().term("base", f1).term("linear", f2);
+    assert_eq!(potential.nterms(), 2);
+
+    let mut dynamics = Dynamics::new(&[1.0, 1.0], potential);
+    assert_eq!(dynamics.get_energy()?, 3.0);
+    assert_eq!(dynamics.get_force()?, &[-3.0, -2.0]);
+    let extra = dynamics.get_extra()?;
+    assert_eq!(extra.get("base"), Some(2.0));
+    assert_eq!(extra.get("linear"), Some(1.0));
+    assert_eq!(dynamics.get_stress()?, None);
+
+    // the minimum at (-0.5, 0)
+    let last = optimize(&mut dynamics).take_while(|p| p.fmax > 1e-5).take(100).last();
+    assert!(last.is_some());
+    assert_relative_eq!(dynamics.position()[0], -0.5, epsilon = 1e-4);
+    assert_relative_eq!(dynamics.position()[1], 0.0, epsilon = 1e-4);
+
+    Ok(())
+}
+// 56b401eb ends here
+
+// [[file:../optim.note::fa2424bb][fa2424bb]]
+#[test]
+fn test_delta_potential() -> Result<()> {
+    use gosh_optim::DeltaPotential;
+    use vecfx::approx::*;
+
+    // baseline f(x1, x2) = x1^2 + x2^2
+    let baseline = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        f[1] = -2.0 * x[1];
+        let fx: f64 = x[0].powi(2) + x[1].powi(2);
+        Ok(fx)
+    };
+    // linear correction g(x1, x2) = x1 - x2
+    let correction = |x: &[f64], f: &mut [f64]| {
+        f[0] = -1.0;
+        f[1] = 1.0;
+        Ok(x[0] - x[1])
+    };
+    let potential = DeltaPotential::new(baseline, correction).refresh_every(3);
+    let mut pot = Dynamics::new(&[1.0, 0.0], potential);
+    assert_eq!(pot.get_energy()?, 2.0);
+    assert_eq!(pot.get_force()?, &[-3.0, 1.0]);
+    assert!(pot.get_extra()?.refreshed);
+
+    // linear correction is extrapolated exactly
+    for i in 1..6 {
+        pot.set_position(&[1.0, i as f64]);
+        let energy = pot.get_energy()?;
+        let extra = pot.get_extra()?;
+        assert_relative_eq!(extra.correction_energy, 1.0 - i as f64, epsilon = 1e-10);
+        assert_relative_eq!(energy, extra.baseline_energy + extra.correction_energy, epsilon = 1e-10);
+        assert_eq!(extra.refreshed, i % 3 == 0);
+    }
+    let extra = pot.get_extra()?;
+    assert_eq!(extra.nbaseline, 6);
+    assert_eq!(extra.ncorrection, 2);
+
+    Ok(())
+}
+// fa2424bb ends here
+
+// [[file:../optim.note::c3e85bd3][c3e85bd3]]
+#[test]
+fn test_external_field() -> Result<()> {
+    use gosh_optim::{EvaluatePotential, ExternalField, NumericalForces};
+    use vecfx::approx::*;
+
+    let x = [0.0, 0.0, 0.0, 1.0, 0.5, 0.0];
+    // a dipole in uniform field along x
+    let mut field = ExternalField::new(&[0.5, -0.5]).uniform([0.2, 0.0, 0.0]);
+    let mut pot = Dynamics::new(&x, field.clone());
+    assert_relative_eq!(pot.get_energy()?, 0.1, epsilon = 1e-10);
+    assert_eq!(pot.get_force()?, &[0.1, 0.0, 0.0, -0.1, 0.0, 0.0]);
+
+    // with point charges, compared with numerical forces
+    field = field
+        .point_charge(1.0, [3.0, 0.0, 0.0])
+        .point_charge(-2.0, [0.0, 2.0, 1.0]);
+    let mut pot = Dynamics::new(&x, field.clone());
+    let energy = pot.get_energy()?;
+    let force = pot.get_force()?.to_vec();
+    let e = |x: &[f64]| -> Result<f64> {
+        let mut out = PotentialOutput {
+            energy: 0.0,
+            force: vec![0.0; 6],
+            stress: None,
+        };
+        field.clone().evaluate(x, &mut out)?;
+        Ok(out.energy)
+    };
+    let mut pot = Dynamics::new(&x, NumericalForces::new(e).displacement(1e-5));
+    assert_relative_eq!(pot.get_energy()?, energy, epsilon = 1e-10);
+    for (a, b) in pot.get_force()?.iter().zip(&force) {
+        assert_relative_eq!(a, b, epsilon = 1e-5);
+    }
+
+    Ok(())
+}
+// c3e85bd3 ends here
+
+// [[file:../optim.note::5634c923][5634c923]]
+#[test]
+fn test_dynamics_retry() -> Result<()> {
+    use gosh_optim::RetryPolicy;
+
+    // f(x1, x2) = x1^2 + x2^2, failed on every third call
+    let mut ncalls = 0;
+    let f = |x: &[f64], f: &mut [f64]| {
+        ncalls += 1;
+        ensure!(ncalls % 3 != 1, "transient failure");
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+    let mut pot = Dynamics::new(&[1.0, 1.0], f);
+    let policy = RetryPolicy {
+        max_retries: 2,
+        backoff: std::time::Duration::ZERO,
+        jitter: 0.01,
+        ..Default::default()
+    };
+    pot.set_retry(policy);
+    let energy = pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 1);
+    assert_eq!(pot.stats().nretries, 1);
+    // evaluated at a jittered copy on retry
+    assert_eq!(pot.position(), &[1.0, 1.0]);
+    assert_ne!(energy, 2.0);
+    assert!((energy - 2.0).abs() < 0.5);
+
+    // give up without retry
+    pot.set_retry(None);
+    pot.set_position(&[2.0, 2.0]);
+    pot.get_energy()?;
+    pot.set_position(&[3.0, 3.0]);
+    assert!(pot.get_energy().is_err());
+    assert_eq!(pot.ncalls(), 2);
+    // no partial results kept
+    assert!(pot.get_energy().is_ok());
+    assert_eq!(pot.get_force()?, &[-6.0, -6.0]);
+
+    Ok(())
+}
+// 5634c923 ends here
+
+// [[file:../optim.note::7283fa15][7283fa15]]
+#[test]
+fn test_dynamics_timeout() -> Result<()> {
+    use gosh_optim::{Cancelled, EvaluatePotential, RetryPolicy, Timeout, TimeoutPotential};
+    use std::time::Duration;
+
+    // f(x1, x2) = x1^2 + x2^2, hung when x1 is negative
+    let f = |x: &[f64], f: &mut [f64]| {
+        if x[0] < 0.0 {
+            std::thread::sleep(Duration::from_secs(2));
+        }
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+    let mut pot = Dynamics::with_timeout(&[1.0, 1.0], move || f, Duration::from_millis(200));
+    assert_eq!(pot.get_energy()?, 2.0);
+    assert_eq!(pot.get_force()?, &[-2.0, -2.0]);
+    pot.set_position(&[-1.0, 1.0]);
+    let err = pot.get_energy().unwrap_err();
+    assert!(err.is::<Timeout>());
+    // the hung worker is replaced with a new one
+    pot.set_position(&[1.0, 2.0]);
+    assert_eq!(pot.get_energy()?, 5.0);
+
+    // recover from a hung call by retrying
+    let nhung = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let make = move || {
+        let nhung = nhung.clone();
+        move |x: &[f64], force: &mut [f64]| {
+            if nhung.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                std::thread::sleep(Duration::from_secs(2));
+            }
+            f(x, force)
+        }
+    };
+    let mut pot = Dynamics::with_timeout(&[1.0, 1.0], make, Duration::from_millis(200));
+    pot.set_retry(RetryPolicy {
+        backoff: Duration::ZERO,
+        ..Default::default()
+    });
+    assert_eq!(pot.get_energy()?, 2.0);
+    assert_eq!(pot.stats().nretries, 1);
+
+    // time limit on a borrowed potential, checked after evaluation
+    let mut ncalls = 0;
+    let g = |x: &[f64], force: &mut [f64]| {
+        ncalls += 1;
+        if x[0] < 0.0 {
+            std::thread::sleep(Duration::from_millis(300));
+        }
+        f(x, force)
+    };
+    let mut pot = Dynamics::new(&[1.0, 1.0], g);
+    pot.set_timeout(Duration::from_millis(100));
+    assert_eq!(pot.get_energy()?, 2.0);
+    pot.set_position(&[-1.0, 1.0]);
+    assert!(pot.get_energy().unwrap_err().is::<Timeout>());
+    pot.set_timeout(None);
+    assert_eq!(pot.get_energy()?, 2.0);
+    drop(pot);
+    assert_eq!(ncalls, 3);
+
+    let mut potential = TimeoutPotential::new(move || f, Duration::from_secs(1));
+    let mut output = PotentialOutput {
+        energy: 0.0,
+        force: vec![0.0; 2],
+        stress: None,
+    };
+    potential.evaluate(&[1.0, 2.0], &mut output)?;
+    assert_eq!(output.energy, 5.0);
+    potential.canceller().cancel();
+    let err = potential.evaluate(&[1.0, 2.0], &mut output).unwrap_err();
+    assert!(err.is::<Cancelled>());
+
+    Ok(())
+}
+// 7283fa15 ends here
+
+// [[file:../optim.note::a9a50879][a9a50879]]
+// A minimal executor polling `future` to completion in current thread.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+
+    struct ThreadWaker(std::thread::Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+#[test]
+fn test_optimize_async() -> Result<()> {
+    use gosh_optim::optimize_async;
+    use vecfx::approx::*;
+
+    // f(x1, x2) = (x1 - 1)^2 + 2 x2^2, as if computed remotely
+    let mut remote = |x: Vec<f64>| async move {
+        let energy = (x[0] - 1.0).powi(2) + 2.0 * x[1].powi(2);
+        let force = vec![-2.0 * (x[0] - 1.0), -4.0 * x[1]];
+        Result::<(f64, Vec<f64>)>::Ok((energy, force))
+    };
+    let mut x = [0.0, 0.5];
+    let mut nsteps = 0;
+    let last = block_on(optimize_async(&mut remote, &mut x, 1e-5, 200, |_| nsteps += 1))?;
+    assert!(last.fmax < 1e-5);
+    assert_eq!(last.ncalls, nsteps);
+    assert_relative_eq!(x[0], 1.0, epsilon = 1e-4);
+    assert_relative_eq!(x[1], 0.0, epsilon = 1e-4);
+    assert_relative_eq!(last.energy, 0.0, epsilon = 1e-8);
+
+    Ok(())
+}
+// a9a50879 ends here
+
+// [[file:../optim.note::23c1e118][23c1e118]]
+#[test]
+fn test_dynamics_stats() -> Result<()> {
+    use std::time::Duration;
+
+    // f(x1, x2) = x1^2 + x2^2, slow to evaluate
+    let f = |x: &[f64], f: &mut [f64]| {
+        std::thread::sleep(Duration::from_millis(5));
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+    let mut pot = Dynamics::new(&[0.0, 0.0], f);
+    pot.set_cache(4);
+    for x in [[1.0, 0.0], [2.0, 0.0], [1.0, 0.0]] {
+        pot.set_position(&x);
+        pot.get_energy()?;
+    }
+    let stats = pot.stats();
+    assert_eq!(stats.ncalls, 2);
+    assert_eq!(stats.ncached, 1);
+    assert!(stats.min >= Duration::from_millis(5));
+    assert!(stats.min <= stats.mean() && stats.mean() <= stats.max);
+    assert!(stats.to_string().contains("2 calls (1 cached, 0 retried)"));
+
+    Ok(())
+}
+// 23c1e118 ends here
+
+// [[file:../optim.note::1c2fb842][1c2fb842]]
+#[test]
+fn test_check_forces() -> Result<()> {
+    use gosh_optim::Rosenbrock;
+
+    let mut pot = Dynamics::new(&[0.3, 0.7], Rosenbrock::default());
+    let energy = pot.get_energy()?;
+    let errors = pot.check_forces(1e-5, 5)?;
+    assert_eq!(errors.len(), 5);
+    assert!(errors.iter().all(|&e| e < 1e-6), "{errors:?}");
+    // restored without extra evaluation
+    assert_eq!(pot.position(), &[0.3, 0.7]);
+    assert_eq!(pot.get_energy()?, energy);
+    assert_eq!(pot.ncalls(), 21);
+
+    // forces with a wrong factor
+    let f = |x: &[f64], f: &mut [f64]| {
+        for i in 0..2 {
+            f[i] = -x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+    let mut pot = Dynamics::new(&[0.3, 0.7], f);
+    let errors = pot.check_forces(1e-5, 3)?;
+    assert!(errors.iter().all(|&e| e > 0.4), "{errors:?}");
+
+    Ok(())
+}
+// 1c2fb842 ends here
+
+// [[file:../optim.note::447c6b55][447c6b55]]
+#[test]
+fn test_units_potential() -> Result<()> {
+    use gosh_optim::{UnitSystem, Units};
+    use vecfx::approx::*;
+
+    let units = Units::new(UnitSystem::HartreeBohr);
+    // harmonic potential in atomic units: E = x^2 Hartree, x in Bohr
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        Ok(x[0] * x[0])
+    };
+    let bohr = 0.529177210903;
+    let hartree = 27.211386245988;
+    let mut pot = Dynamics::new(&[bohr], units.wrap(f));
+    assert_relative_eq!(pot.get_energy()?, hartree, epsilon = 1e-8);
+    assert_relative_eq!(pot.get_force()?[0], -2.0 * hartree / bohr, epsilon = 1e-8);
+    // thresholds known in model units
+    assert_relative_eq!(units.force(1.0), hartree / bohr, epsilon = 1e-8);
+    assert_relative_eq!(units.length(1.0), bohr, epsilon = 1e-12);
+
+    // declared internal units other than eV/Å
+    let units = Units::new(UnitSystem::HartreeBohr).internal(UnitSystem::KcalMolAngstrom);
+    assert_relative_eq!(units.energy(1.0), 627.5094740631, epsilon = 1e-3);
+    let units = Units::new(UnitSystem::EvAngstrom).internal(UnitSystem::EvAngstrom);
+    assert_eq!(units.stress(0.1), 0.1);
+
+    Ok(())
+}
+// 447c6b55 ends here
+
+// [[file:../optim.note::46cf19b5][46cf19b5]]
+#[test]
+fn test_committee_potential() -> Result<()> {
+    use gosh_optim::{optimize, CommitteePotential, Uncertain};
+    use vecfx::approx::*;
+
+    // harmonic potentials with different force constants
+    let member = |k: f64| {
+        move |x: &[f64], f: &mut [f64]| {
+            f.iter_mut().zip(x).for_each(|(f, x)| *f = -2.0 * k * x);
+            let fx: f64 = k * x.iter().map(|v| v * v).sum::<f64>();
+            Ok(fx)
+        }
+    };
+    let committee = || {
+        CommitteePotential::new()
+            .member(member(0.9))
+            .member(member(1.0))
+            .member(member(1.1))
+    };
+    assert_eq!(committee().nmembers(), 3);
+
+    let mut dynamics = Dynamics::new(&[1.0, 0.0, 0.0], committee());
+    assert_relative_eq!(dynamics.get_energy()?, 1.0, epsilon = 1e-12);
+    assert_relative_eq!(dynamics.get_force()?[0], -2.0, epsilon = 1e-12);
+    let extra = dynamics.get_extra()?;
+    assert_eq!(extra.energies.len(), 3);
+    assert_relative_eq!(extra.energy_std, (0.02f64 / 3.0).sqrt(), epsilon = 1e-12);
+    assert_relative_eq!(extra.uncertainty(), 2.0 * (0.02f64 / 3.0).sqrt(), epsilon = 1e-12);
+    assert!(!extra.switched());
+
+    // the uncertainty vanishes at the minimum
+    let last = optimize(&mut dynamics).take_while(|p| p.fmax > 1e-5).take(100).last();
+    assert!(last.is_some());
+    assert!(dynamics.get_extra()?.uncertainty() < 1e-4);
+
+    // abort on high uncertainty
+    let mut dynamics = Dynamics::new(&[1.0, 0.0, 0.0], committee().max_uncertainty(0.1));
+    let e = dynamics.get_energy().unwrap_err();
+    assert!(e.downcast_ref::<Uncertain>().is_some());
+    let mut dynamics = Dynamics::new(&[0.1, 0.0, 0.0], committee().max_uncertainty(0.1));
+    assert!(dynamics.get_energy().is_ok());
+
+    // switch to fallback model on high uncertainty
+    let potential = committee().max_uncertainty(0.1).fallback(member(2.0));
+    let mut dynamics = Dynamics::new(&[1.0, 0.0, 0.0], potential);
+    assert_eq!(dynamics.get_energy()?, 2.0);
+    assert_eq!(dynamics.get_force()?, &[-4.0, 0.0, 0.0]);
+    assert!(dynamics.get_extra()?.switched());
+
+    Ok(())
+}
+// 46cf19b5 ends here
+
+// [[file:../optim.note::ac8fcb05][ac8fcb05]]
+#[test]
+fn test_dynamics_restore() -> Result<()> {
+    use gosh_optim::DynamicsSnapshot;
+
+    let f = |x: &[f64], f: &mut [f64]| {
+        for i in 0..2 {
+            f[i] = -2.0 * x[i];
+        }
+        let fx = x.iter().map(|v| v.powi(2)).sum();
+        Ok(fx)
+    };
+    let mut pot = Dynamics::new(&[1.0, 2.0], f);
+    pot.get_energy()?;
+    pot.set_position(&[0.5, 1.0]);
+    let energy = pot.get_energy()?;
+    let file = std::env::temp_dir().join("gosh-optim-test-snapshot.json");
+    pot.snapshot().save_to_file(&file)?;
+
+    // resume in a new run without extra evaluation
+    let snapshot = DynamicsSnapshot::load_from_file(&file)?;
+    std::fs::remove_file(&file)?;
+    let mut pot = Dynamics::new(&[0.0, 0.0], f);
+    pot.restore(&snapshot)?;
+    assert_eq!(pot.position(), &[0.5, 1.0]);
+    assert_eq!(pot.ncalls(), 2);
+    assert_eq!(pot.get_energy()?, energy);
+    assert_eq!(pot.get_force()?, &[-1.0, -2.0]);
+    assert_eq!(pot.ncalls(), 2);
+
+    let mut pot = Dynamics::new(&[0.0; 3], f);
+    assert!(pot.restore(&snapshot).is_err());
+
+    Ok(())
+}
+// ac8fcb05 ends here
+
+// [[file:../optim.note::f4e8f85a][f4e8f85a]]
+#[test]
+fn test_dynamics_small_step() -> Result<()> {
+    use gosh_optim::SmallStep;
+
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        Ok(x[0] * x[0])
+    };
+    let mut pot = Dynamics::new(&[1.0], f);
+    pot.get_energy()?;
+    // ignored by default
+    pot.step_toward(&[1e-10]);
+    assert_eq!(pot.position(), &[1.0]);
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 1);
+
+    pot.set_small_step(SmallStep::ForceReevaluate);
+    pot.step_toward(&[1e-10]);
+    assert_eq!(pot.position(), &[1.0 + 1e-10]);
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 2);
+
+    pot.set_small_step(SmallStep::Error);
+    pot.set_position(&[1.0]);
+    assert!(pot.get_energy().is_err());
+    assert_eq!(pot.position(), &[1.0 + 1e-10]);
+    // normal steps are not affected
+    pot.set_position(&[0.5]);
+    assert_eq!(pot.get_energy()?, 0.25);
+
+    // discard the last evaluation explicitly
+    pot.force_invalidate();
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 4);
+
+    Ok(())
+}
+// f4e8f85a ends here
+
+// [[file:../optim.note::b0f3e701][b0f3e701]]
+#[test]
+fn test_dynamics_fmax() -> Result<()> {
+    use vecfx::approx::*;
+
+    let f = |x: &[f64], f: &mut [f64]| {
+        f.iter_mut().zip(x).for_each(|(f, x)| *f = -2.0 * x);
+        Ok(x.iter().map(|v| v * v).sum())
+    };
+    let mut pot = Dynamics::new(&[1.5, 0.0, 0.0, 1.0, 1.0, 1.0], f);
+    assert_eq!(pot.fmax()?, 3.0);
+    assert_relative_eq!(pot.f3max()?, 12f64.sqrt(), epsilon = 1e-12);
+    let norms = pot.force_norms()?;
+    assert_eq!(norms.len(), 2);
+    assert_relative_eq!(norms[0], 3.0, epsilon = 1e-12);
+    assert_eq!(pot.ncalls(), 1);
+
+    let mut pot = Dynamics::new(&[1.0, 1.0], f);
+    assert_eq!(pot.fmax()?, 2.0);
+    assert!(pot.force_norms().is_err());
+
+    Ok(())
+}
+// b0f3e701 ends here
+
+// [[file:../optim.note::c3d347cf][c3d347cf]]
+#[test]
+fn test_dynamics_hook() -> Result<()> {
+    use std::sync::mpsc;
+
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        Ok(x[0] * x[0])
+    };
+    let (tx, rx) = mpsc::channel();
+    let mut pot = Dynamics::new(&[1.0], f);
+    pot.on_evaluated(move |x, e, f, ncalls| {
+        tx.send((x.to_vec(), e, f.to_vec(), ncalls)).unwrap();
+    });
+    pot.get_energy()?;
+    pot.get_force()?;
+    pot.set_position(&[2.0]);
+    pot.get_force()?;
+    drop(pot);
+
+    let records = rx.iter().collect_vec();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0], (vec![1.0], 1.0, vec![-2.0], 1));
+    assert_eq!(records[1], (vec![2.0], 4.0, vec![-4.0], 2));
+
+    // hooks of plain Dynamics need not be thread safe
+    let energies = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+    let mut pot = Dynamics::new(&[1.0], f);
+    let saved = energies.clone();
+    pot.on_evaluated(move |_, e, _, _| saved.borrow_mut().push(e));
+    pot.get_energy()?;
+    drop(pot);
+    assert_eq!(*energies.borrow(), [1.0]);
+
+    // hooks of DynamicsSync are moved into worker threads
+    let (tx, rx) = mpsc::channel();
+    let mut pot = gosh_optim::DynamicsSync::new_sync(&[3.0], f);
+    pot.on_evaluated(move |_, e, _, _| tx.send(e).unwrap());
+    std::thread::spawn(move || pot.get_energy().map(|_| ()))
+        .join()
+        .unwrap()?;
+    assert_eq!(rx.iter().collect_vec(), [9.0]);
+
+    Ok(())
+}
+// c3d347cf ends here
+
+// [[file:../optim.note::6b6d1986][6b6d1986]]
+#[test]
+fn test_dynamics_averaging() -> Result<()> {
+    use vecfx::approx::*;
+
+    // alternating noise of +/- 0.1 on energy and force
+    let mut sign = 1.0;
+    let f = move |x: &[f64], f: &mut [f64]| {
+        sign = -sign;
+        f[0] = -2.0 * x[0] + 0.1 * sign;
+        Ok(x[0] * x[0] + 0.1 * sign)
+    };
+    let mut pot = Dynamics::new(&[1.0], f);
+    pot.set_averaging(4);
+    assert_relative_eq!(pot.get_energy()?, 1.0, epsilon = 1e-12);
+    assert_relative_eq!(pot.get_force()?[0], -2.0, epsilon = 1e-12);
+    assert_eq!(pot.ncalls(), 4);
+    let noise = pot.noise().unwrap();
+    assert_eq!(noise.nsamples, 4);
+    assert_relative_eq!(noise.energy_variance, 0.04 / 3.0, epsilon = 1e-12);
+    assert_relative_eq!(noise.force_variance[0], 0.04 / 3.0, epsilon = 1e-12);
+    assert_relative_eq!(noise.energy_error(), 0.1 / 3f64.sqrt(), epsilon = 1e-12);
+
+    pot.set_averaging(1);
+    pot.set_position(&[2.0]);
+    pot.get_energy()?;
+    assert_eq!(pot.ncalls(), 5);
+    assert!(pot.noise().is_none());
+
+    Ok(())
+}
+// 6b6d1986 ends here
+
+// [[file:../optim.note::afefadd7][afefadd7]]
+#[test]
+fn test_dynamics_shared_molecule() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{optimize, Freezing};
+    use vecfx::approx::*;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let first = mol.positions().next().unwrap();
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let freezing = Freezing::default().freeze_atoms(&[1]);
+    let (mut dynamics, shared) = Dynamics::from_chemical_model_shared(&mut lj, mol, &freezing)?;
+    let last = optimize(&mut dynamics).take_while(|p| p.fmax > 1e-3).take(200).last();
+    assert!(last.is_some());
+
+    // the working molecule follows the evaluated positions
+    let mol = shared.get();
+    let positions = mol.positions().collect_vec();
+    assert_eq!(positions[0], first);
+    for (a, b) in positions[1..].iter().flatten().zip(dynamics.position()) {
+        assert_relative_eq!(a, b, epsilon = 1e-8);
+    }
+
+    Ok(())
+}
+// afefadd7 ends here
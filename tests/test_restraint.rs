@@ -0,0 +1,240 @@
+// [[file:../optim.note::db68ba83][db68ba83]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::{Dynamics, Restrained, RestrainedPotential, Restraint, Restraints};
+use vecfx::approx::*;
+
+// evaluate energy and forces of restraints only on a flat potential
+fn evaluate_restraints(restraints: Restraints, x: &[f64]) -> Result<(f64, Vec<f64>)> {
+    let flat = |_: &[f64], f: &mut [f64]| -> Result<f64> {
+        f.iter_mut().for_each(|x| *x = 0.0);
+        Ok(0.0)
+    };
+    let mut pot: Dynamics<Restrained<()>> = Dynamics::new(x, RestrainedPotential::new(flat, restraints));
+    let energy = pot.get_energy()?;
+    let forces = pot.get_force()?.to_vec();
+    Ok((energy, forces))
+}
+
+#[test]
+fn test_restraint_walls() -> Result<()> {
+    // the second atom is 1 Å outside the sphere
+    let mut restraints = Restraints::default();
+    restraints.add(Restraint::SphericalWall {
+        center: [0.0; 3],
+        radius: 2.0,
+        k: 2.0,
+    });
+    let (energy, forces) = evaluate_restraints(restraints, &[1.0, 0.0, 0.0, 0.0, 3.0, 0.0])?;
+    assert_relative_eq!(energy, 1.0);
+    assert_relative_eq!(forces.as_slice(), [0.0, 0.0, 0.0, 0.0, -2.0, 0.0].as_slice());
+
+    // outside the box along x and z
+    let mut restraints = Restraints::default();
+    restraints.add(Restraint::BoxWall {
+        lower: [0.0; 3],
+        upper: [5.0; 3],
+        k: 1.0,
+    });
+    let (energy, forces) = evaluate_restraints(restraints, &[-1.0, 2.0, 6.0])?;
+    assert_relative_eq!(energy, 1.0);
+    assert_relative_eq!(forces.as_slice(), [1.0, 0.0, -1.0].as_slice());
+
+    Ok(())
+}
+// db68ba83 ends here
+
+// [[file:../optim.note::2a449dda][2a449dda]]
+#[test]
+fn test_restraint_harmonic() -> Result<()> {
+    use gosh_optim::Coordinate;
+
+    // stretched by 1 Å
+    let mut restraints = Restraints::default();
+    restraints.add(Restraint::Harmonic {
+        coord: Coordinate::Distance(0, 1),
+        k: 2.0,
+        target: 1.0,
+    });
+    let (energy, forces) = evaluate_restraints(restraints, &[0.0, 0.0, 0.0, 2.0, 0.0, 0.0])?;
+    assert_relative_eq!(energy, 1.0);
+    assert_relative_eq!(forces.as_slice(), [2.0, 0.0, 0.0, -2.0, 0.0, 0.0].as_slice());
+
+    // right angle restrained to 60 degree
+    let mut restraints = Restraints::default();
+    restraints.add(Restraint::Harmonic {
+        coord: Coordinate::Angle(0, 1, 2),
+        k: 2.0,
+        target: 60.0,
+    });
+    let x = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+    let (energy, _) = evaluate_restraints(restraints.clone(), &x)?;
+    assert_relative_eq!(energy, 30f64.to_radians().powi(2), epsilon = 1e-10);
+    let flat = |_: &[f64], f: &mut [f64]| -> Result<f64> {
+        f.iter_mut().for_each(|x| *x = 0.0);
+        Ok(0.0)
+    };
+    let mut pot = Dynamics::new(&x, RestrainedPotential::new(flat, restraints));
+    assert!(pot.check_forces(1e-4, 5)?.iter().all(|&e| e < 1e-5));
+
+    // dihedral of ±179 degree is 1 degree away from trans
+    for phi in [179f64, -179.0] {
+        let mut restraints = Restraints::default();
+        restraints.add(Restraint::Harmonic {
+            coord: Coordinate::Dihedral(0, 1, 2, 3),
+            k: 2.0,
+            target: 180.0,
+        });
+        let (s, c) = phi.to_radians().sin_cos();
+        let x = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, c, s, 1.0];
+        let (energy, forces) = evaluate_restraints(restraints, &x)?;
+        assert_relative_eq!(energy, 1f64.to_radians().powi(2), epsilon = 1e-10);
+        assert!(forces.iter().all(|f| f.abs() < 0.1), "{phi}: {forces:?}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_opt_restrained() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Coordinate, Optimizer};
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+
+    // stiff restraints hold the bond and the angle close to targets
+    let bond = Coordinate::Distance(0, 1);
+    let angle = Coordinate::Angle(0, 1, 2);
+    let _ = Optimizer::new(0.05, 500)
+        .restrain(Restraint::Harmonic {
+            coord: bond,
+            k: 1000.0,
+            target: 1.5,
+        })
+        .restrain(Restraint::Harmonic {
+            coord: angle,
+            k: 1000.0,
+            target: 90.0,
+        })
+        .optimize_geometry(&mut mol, &mut lj)?;
+    let positions = mol.positions().collect_vec();
+    assert_relative_eq!(bond.value(&positions), 1.5, epsilon = 1e-2);
+    assert_relative_eq!(angle.value(&positions), 90f64.to_radians(), epsilon = 1e-2);
+
+    Ok(())
+}
+// 2a449dda ends here
+
+// [[file:../optim.note::846e5dcb][846e5dcb]]
+#[test]
+fn test_restraint_hookean() -> Result<()> {
+    use gchemol::{Atom, Molecule};
+
+    // zero below threshold distance
+    let hookean = Restraint::Hookean {
+        i: 0,
+        j: 1,
+        k: 2.0,
+        rt: 1.5,
+    };
+    let mut restraints = Restraints::default();
+    restraints.add(hookean.clone());
+    let (energy, forces) = evaluate_restraints(restraints, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0])?;
+    assert_eq!(energy, 0.0);
+    assert!(forces.iter().all(|&f| f == 0.0));
+
+    // harmonic beyond
+    let mut restraints = Restraints::default();
+    restraints.add(hookean);
+    let (energy, forces) = evaluate_restraints(restraints, &[0.0, 0.0, 0.0, 2.0, 0.0, 0.0])?;
+    assert_relative_eq!(energy, 0.25);
+    assert_relative_eq!(forces.as_slice(), [1.0, 0.0, 0.0, -1.0, 0.0, 0.0].as_slice());
+
+    // the bond in H2 but not between two molecules
+    let positions = [[0.0, 0.0, 0.0], [0.74, 0.0, 0.0], [5.0, 0.0, 0.0], [5.74, 0.0, 0.0]];
+    let mol = Molecule::from_atoms(positions.map(|p| Atom::new("H", p)));
+    let restraints = Restraint::hookean_bonds(&mol, 5.0);
+    assert_eq!(restraints.len(), 2);
+
+    Ok(())
+}
+// 846e5dcb ends here
+
+// [[file:../optim.note::b3451b6b][b3451b6b]]
+#[test]
+fn test_restraint_ramped() -> Result<()> {
+    use gosh_optim::{EvaluatePotential, LambdaSchedule, PotentialOutput};
+
+    // force constant ramped linearly from 0 to 2 over 2 evaluations
+    let mut restraints = Restraints::default();
+    restraints.add_ramped(
+        Restraint::SphericalWall {
+            center: [0.0; 3],
+            radius: 1.0,
+            k: 2.0,
+        },
+        LambdaSchedule::linear_on(2),
+    );
+    let x = [2.0, 0.0, 0.0];
+    let mut pot = RestrainedPotential::new(
+        |_: &[f64], f: &mut [f64]| -> Result<f64> {
+            f.iter_mut().for_each(|x| *x = 0.0);
+            Ok(0.0)
+        },
+        restraints,
+    );
+    let mut out = PotentialOutput {
+        energy: 0.0,
+        force: vec![0.0; 3],
+        stress: None,
+    };
+    let energies: Vec<_> = (0..3)
+        .map(|_| pot.evaluate(&x, &mut out).map(|_| out.energy))
+        .try_collect()?;
+    assert_relative_eq!(energies.as_slice(), [0.0, 0.5, 1.0].as_slice());
+    assert_relative_eq!(out.force[0], -2.0);
+
+    Ok(())
+}
+// b3451b6b ends here
+
+// [[file:../optim.note::e3f8e089][e3f8e089]]
+#[test]
+fn test_biased_potential() -> Result<()> {
+    use gosh_optim::{optimize, BiasedPotential};
+
+    // f(x1, x2) = x1^2 + x2^2
+    let f = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * x[0];
+        f[1] = -2.0 * x[1];
+        let fx: f64 = x[0].powi(2) + x[1].powi(2);
+        Ok(fx)
+    };
+    // harmonic bias pulling x1 toward 1.0
+    let bias = |x: &[f64], f: &mut [f64]| {
+        f[0] = -2.0 * (x[0] - 1.0);
+        Ok((x[0] - 1.0).powi(2))
+    };
+    let mut pot = Dynamics::new(&[2.0, 1.0], BiasedPotential::new(f, bias));
+    assert_relative_eq!(pot.get_energy()?, 6.0, epsilon = 1e-8);
+    assert_eq!(pot.get_force()?, &[-6.0, -2.0]);
+    let extra = pot.get_extra()?;
+    assert_eq!(extra.bias_energy, 1.0);
+    assert_eq!(extra.physical_energy, 5.0);
+
+    // the minimum at (0.5, 0) with bias energy of 0.25
+    let last = optimize(&mut pot).take_while(|p| p.fmax > 1e-5).take(100).last();
+    let last = last.unwrap();
+    assert_relative_eq!(pot.position()[0], 0.5, epsilon = 1e-4);
+    assert_relative_eq!(last.extra.bias_energy, 0.25, epsilon = 1e-3);
+    assert_relative_eq!(last.extra.physical_energy, 0.25, epsilon = 1e-3);
+
+    Ok(())
+}
+// e3f8e089 ends here
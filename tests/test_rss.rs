@@ -0,0 +1,48 @@
+// [[file:../optim.note::4acf0465][4acf0465]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_random_search() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Lattice, Molecule};
+    use gosh_model::LennardJones;
+    use gosh_optim::{Optimizer, RandomSearch};
+    use vecfx::approx::*;
+
+    let template = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let rss = RandomSearch::new(Optimizer::new(0.01, 500), &template)
+        .min_distance(0.9)
+        .box_size(1.6)
+        .seed(1);
+
+    // atoms not too close in random structures
+    let mol = rss.random_structure(0)?;
+    let positions = mol.positions().collect_vec();
+    for (i, j) in [(0, 1), (0, 2), (1, 2)] {
+        let d = (0..3)
+            .map(|k| (positions[i][k] - positions[j][k]).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(d >= 0.9);
+        assert!(positions[i].iter().all(|x| x.abs() <= 0.8));
+    }
+    assert_ne!(rss.random_structure(1)?.positions().collect_vec(), positions);
+
+    let mut lj = LennardJones::default();
+    let searched = rss.run(&mut lj, 4)?;
+    assert_eq!(searched.nconverged + searched.nfailed, 4);
+    let best = searched.best().expect("no minimum");
+    assert_relative_eq!(best.energy, -3.0, epsilon = 1e-2);
+
+    // periodic structure in cell
+    let mut template = template.clone();
+    template.set_lattice(Lattice::new([[3.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 3.0]]));
+    let rss = RandomSearch::new(Optimizer::new(0.01, 500), &template).min_distance(1.4);
+    let mol = rss.random_structure(0)?;
+    assert!(mol.lattice.is_some());
+    assert!(mol.positions().flatten().all(|x| (0.0..3.0).contains(&x)));
+
+    Ok(())
+}
+// 4acf0465 ends here
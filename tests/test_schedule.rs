@@ -0,0 +1,49 @@
+// [[file:../optim.note::adfcd5cd][adfcd5cd]]
+use gosh_optim::{LambdaSchedule, TemperatureSchedule};
+
+#[test]
+fn test_lambda_schedule() {
+    use vecfx::approx::*;
+
+    let s = LambdaSchedule::linear_on(10);
+    assert_eq!(s.lambda(0), 0.0);
+    assert_relative_eq!(s.lambda(5), 0.5);
+    assert_eq!(s.lambda(10), 1.0);
+    assert_eq!(s.lambda(20), 1.0);
+
+    let s = LambdaSchedule::sigmoid_off(10);
+    assert_eq!(s.lambda(0), 1.0);
+    assert_relative_eq!(s.lambda(5), 0.5);
+    assert!(s.lambda(1) > 0.99);
+    assert_eq!(s.lambda(10), 0.0);
+
+    let s = LambdaSchedule::exponential(0.1, 10.0, 10);
+    assert_relative_eq!(s.lambda(0), 0.1);
+    assert_relative_eq!(s.lambda(5), 1.0);
+    assert_relative_eq!(s.lambda(10), 10.0);
+}
+// adfcd5cd ends here
+
+// [[file:../optim.note::0094cdeb][0094cdeb]]
+#[test]
+fn test_temperature_schedule() {
+    use vecfx::approx::*;
+
+    let s = TemperatureSchedule::linear(300.0, 100.0, 100);
+    assert_eq!(s.temperature(0), 300.0);
+    assert_relative_eq!(s.temperature(50), 200.0);
+    assert_eq!(s.temperature(200), 100.0);
+
+    let s = TemperatureSchedule::exponential(1000.0, 10.0, 100);
+    assert_relative_eq!(s.temperature(50), 100.0);
+    assert_relative_eq!(s.temperature(100), 10.0);
+
+    // anneal at 1000 K, then quench to 0 K
+    let s = TemperatureSchedule::plateaus(&[(1000.0, 10), (300.0, 5), (0.0, 1)]);
+    assert_eq!(s.temperature(0), 1000.0);
+    assert_eq!(s.temperature(9), 1000.0);
+    assert_eq!(s.temperature(10), 300.0);
+    assert_eq!(s.temperature(15), 0.0);
+    assert_eq!(s.temperature(100), 0.0);
+}
+// 0094cdeb ends here
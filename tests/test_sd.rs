@@ -0,0 +1,26 @@
+// [[file:../optim.note::9e739b16][9e739b16]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_opt_sd_bb() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::Optimizer;
+
+    // the only test in this file, as env vars are shared in process
+    std::env::set_var("GOSH_OPTIM_ALGORITHM", "SD");
+    std::env::set_var("GOSH_OPTIM_STEP_SIZE_RULE", "BB1");
+    std::env::set_var("GOSH_OPTIM_MODEL_HESSIAN_SCALING", "true");
+
+    let filename = "tests/files/LennardJones/LJ38r.xyz";
+    let mut mol = Molecule::from_file(filename)?;
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let optimized = Optimizer::new(0.1, 500).optimize_geometry(&mut mol, &mut lj)?;
+    assert!(optimized.fmax < 0.1);
+
+    Ok(())
+}
+// 9e739b16 ends here
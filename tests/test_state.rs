@@ -0,0 +1,48 @@
+// [[file:../optim.note::e06aaeab][e06aaeab]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::VersionedState;
+use serde::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Walker {
+    position: Vec<f64>,
+    ncalls: usize,
+}
+
+impl VersionedState for Walker {
+    const KIND: &'static str = "walker";
+    const VERSION: u32 = 1;
+
+    // version 0 stores `x` instead of `position`, and has no `ncalls`
+    fn migrate(version: u32, state: serde_json::Value) -> Result<serde_json::Value> {
+        assert_eq!(version, 0);
+        let x = state["x"].clone();
+        Ok(serde_json::json!({"position": x, "ncalls": 0}))
+    }
+}
+
+#[test]
+fn test_versioned_state() -> Result<()> {
+    let walker = Walker {
+        position: vec![1.0, 2.0],
+        ncalls: 3,
+    };
+    let s = walker.to_versioned_json()?;
+    let walker = Walker::from_versioned_json(&s)?;
+    assert_eq!(walker.position, [1.0, 2.0]);
+    assert_eq!(walker.ncalls, 3);
+
+    // untagged legacy format
+    let walker = Walker::from_versioned_json(r#"{"x": [0.5, 0.1]}"#)?;
+    assert_eq!(walker.position, [0.5, 0.1]);
+    assert_eq!(walker.ncalls, 0);
+
+    // newer format is rejected
+    let s = r#"{"kind": "walker", "version": 2, "state": {"position": [], "ncalls": 0}}"#;
+    assert!(Walker::from_versioned_json(s).is_err());
+
+    Ok(())
+}
+// e06aaeab ends here
@@ -0,0 +1,32 @@
+// [[file:../optim.note::46e717a4][46e717a4]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_swarm_search() -> Result<()> {
+    use gchemol::Molecule;
+    use gosh_model::LennardJones;
+    use gosh_optim::{Optimizer, RandomSearch, SwarmSearch};
+    use vecfx::approx::*;
+
+    let template = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+    let search = RandomSearch::new(Optimizer::new(0.01, 500), &template)
+        .min_distance(0.9)
+        .box_size(1.6);
+    let pso = SwarmSearch::new(search)
+        .particles(3)
+        .generations(2)
+        .symmetric(1.0)
+        .seed(1);
+    let mut lj = LennardJones::default();
+    let searched = pso.run(&mut lj)?;
+    assert_eq!(searched.ngenerations, 2);
+    assert!(searched.nrelaxed >= 3);
+
+    let best = searched.best().expect("no minimum");
+    assert_relative_eq!(best.energy, -3.0, epsilon = 1e-2);
+    assert!(searched.minima.windows(2).all(|m| m[0].energy <= m[1].energy));
+
+    Ok(())
+}
+// 46e717a4 ends here
@@ -0,0 +1,51 @@
+// [[file:../optim.note::5b500874][5b500874]]
+use gosh_core::*;
+use gut::prelude::*;
+
+#[test]
+fn test_symmetry() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::{Atom, Lattice, Molecule};
+    use gosh_model::LennardJones;
+    use gosh_optim::{Optimizer, Symmetry};
+    use vecfx::approx::*;
+
+    // simple cubic lattice
+    let mut mol = Molecule::from_atoms([Atom::new("Ar", [0.0; 3])]);
+    mol.set_lattice(Lattice::new([[3.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 3.0]]));
+    assert_eq!(Symmetry::detect(&mol, 1e-3)?.nops(), 48);
+
+    // a square in D4h symmetry, slightly broken out of plane
+    let positions = [[1.3, 0.0, 0.0], [0.0, 1.3, 0.0], [-1.3, 0.0, 0.0], [0.0, -1.3, 1e-4]];
+    let mut mol = Molecule::from_atoms(positions.map(|p| Atom::new("Ar", p)));
+    assert_eq!(Symmetry::detect(&mol, 1e-2)?.nops(), 16);
+
+    // the square is a saddle point of LJ4, kept by symmetry
+    let mut lj = LennardJones::default();
+    lj.derivative_order = 1;
+    let optimized = Optimizer::new(1e-3, 100)
+        .preserve_symmetry(1e-2)
+        .optimize_geometry(&mut mol, &mut lj)?;
+    assert!(optimized.fmax < 1e-3);
+    let positions = mol.positions().collect_vec();
+    for p in positions.iter() {
+        assert_relative_eq!(p[2], 0.0, epsilon = 1e-6);
+    }
+    let d = |i: usize, j: usize| {
+        (0..3)
+            .map(|k| (positions[i][k] - positions[j][k]).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+    assert_relative_eq!(d(0, 1), d(1, 2), epsilon = 1e-6);
+    assert_relative_eq!(d(0, 2), d(1, 3), epsilon = 1e-6);
+
+    // failed symmetry detection is reported as error
+    let optimized = Optimizer::new(1e-3, 100)
+        .preserve_symmetry(-1.0)
+        .optimize_geometry(&mut mol, &mut lj);
+    assert!(optimized.is_err());
+
+    Ok(())
+}
+// 5b500874 ends here
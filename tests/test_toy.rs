@@ -0,0 +1,55 @@
+// [[file:../optim.note::7b0e9ffe][7b0e9ffe]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use vecfx::approx::*;
+
+// check analytic forces against central finite differences
+fn check_forces(mut pot: impl EvaluatePotential<()>, x: &[f64]) -> Result<()> {
+    let n = x.len();
+    let mut eval = |x: &[f64]| -> Result<PotentialOutput> {
+        let mut out = PotentialOutput {
+            energy: 0.0,
+            force: vec![0.0; n],
+            stress: None,
+        };
+        pot.evaluate(x, &mut out)?;
+        Ok(out)
+    };
+    let forces = eval(x)?.force;
+    let h = 1e-5;
+    for i in 0..n {
+        let mut xp = x.to_vec();
+        let mut xm = x.to_vec();
+        xp[i] += h;
+        xm[i] -= h;
+        let f = -(eval(&xp)?.energy - eval(&xm)?.energy) / (2.0 * h);
+        assert_relative_eq!(forces[i], f, epsilon = 1e-4, max_relative = 1e-5);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_toy_potentials() -> Result<()> {
+    check_forces(MullerBrown, &[-0.3, 0.8])?;
+    check_forces(LepsHarmonic::default(), &[1.2, 0.3])?;
+    check_forces(Rosenbrock::default(), &[-1.2, 1.0, 0.5])?;
+    check_forces(EckartBarrier::default(), &[0.3, -0.2, 0.4])?;
+    let reference = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+    let lattice = HarmonicLattice::new(&reference, 1.0, 1.5);
+    assert_eq!(lattice.nsprings(), 6);
+    check_forces(lattice, &[0.1, 0.0, 0.0, 1.0, 0.2, 0.0, 0.0, 0.9, 0.1, 1.1, 1.0, 0.0])?;
+
+    // relax into the nearest minimum of Müller–Brown potential
+    let [x, y] = MullerBrown::minima()[1];
+    let mut pot = Dynamics::new(&[x + 0.05, y - 0.05], MullerBrown);
+    let last = optimize(&mut pot).take(200).take_while(|p| p.fmax > 1e-3).last();
+    assert!(last.is_some());
+    let position = pot.position();
+    assert_relative_eq!(position[0], x, epsilon = 1e-2);
+    assert_relative_eq!(position[1], y, epsilon = 1e-2);
+
+    Ok(())
+}
+// 7b0e9ffe ends here
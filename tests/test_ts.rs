@@ -0,0 +1,38 @@
+// [[file:../optim.note::2e40df61][2e40df61]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::*;
+use gosh_optim::{refine_ts, TsConfig};
+use vecfx::approx::*;
+
+#[test]
+fn test_ts_refine() -> Result<()> {
+    let [x, y] = MullerBrown::saddles()[1];
+    let configs = [
+        TsConfig {
+            trust_radius: 0.02,
+            ..Default::default()
+        },
+        // partial Hessian on the x coordinate only
+        TsConfig {
+            trust_radius: 0.02,
+            coords: Some(vec![0]),
+            ..Default::default()
+        },
+    ];
+    for config in configs {
+        let mut dynamics = Dynamics::new(&[x - 0.03, y + 0.03], MullerBrown);
+        let refined = refine_ts(&mut dynamics, &config, 1e-3, 100)?;
+        assert!(refined.fmax < 1e-3);
+        assert!(refined.is_first_order());
+        assert!(refined.eigenvalues[0] < 0.0 && refined.eigenvalues[1] > 0.0);
+        assert_eq!(refined.ncalls, dynamics.ncalls());
+        let position = dynamics.position();
+        assert_relative_eq!(position[0], x, epsilon = 1e-2);
+        assert_relative_eq!(position[1], y, epsilon = 1e-2);
+    }
+
+    Ok(())
+}
+// 2e40df61 ends here
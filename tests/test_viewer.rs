@@ -0,0 +1,36 @@
+// [[file:../optim.note::6e8f75d9][6e8f75d9]]
+use gosh_core::*;
+use gut::prelude::*;
+
+use gosh_optim::{LiveViewer, ViewerFrame};
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+
+#[test]
+fn test_live_viewer() -> Result<()> {
+    use gchemol::prelude::*;
+    use gchemol::Molecule;
+
+    let mol = Molecule::from_file("tests/files/LennardJones/LJ3.xyz")?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let viewer = LiveViewer::connect(listener.local_addr()?)?.every(2);
+    let (stream, _) = listener.accept()?;
+
+    // only the frame at step 2 is sent
+    viewer.observe(1, &mol, Some(1.0))?;
+    viewer.observe(2, &mol, Some(2.0))?;
+    drop(viewer);
+
+    let lines: Vec<_> = BufReader::new(stream).lines().collect::<std::io::Result<_>>()?;
+    assert_eq!(lines.len(), 1);
+    let frame: ViewerFrame = serde_json::from_str(&lines[0])?;
+    assert_eq!(frame.step, 2);
+    assert_eq!(frame.energy, Some(2.0));
+    assert_eq!(frame.symbols, ["He"; 3]);
+    assert_eq!(frame.positions, mol.positions().collect_vec());
+    assert!(frame.cell.is_none());
+
+    Ok(())
+}
+// 6e8f75d9 ends here